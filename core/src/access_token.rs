@@ -0,0 +1,97 @@
+//! Access tokens are only stored as salted hashes. The token itself is shown to the
+//! client once, when it is created. The format is `<version>$<salt>$<hex digest>`.
+use data_encoding::HEXLOWER;
+use ring::constant_time::verify_slices_are_equal;
+use ring::digest;
+
+use crate::utils::generate_random_string;
+
+pub const ACCESS_TOKEN_LENGTH: usize = 32;
+
+const SALT_LENGTH: usize = 16;
+
+/// SHA-256 of the salt and the token
+const VERSION_SHA256: &str = "1";
+
+/// A new random access token
+pub fn generate() -> String {
+    generate_random_string(ACCESS_TOKEN_LENGTH)
+}
+
+/// Salted hash of `token`, which is stored instead of the token
+pub fn hash(token: &str) -> String {
+    hash_with_salt(&generate_random_string(SALT_LENGTH), token)
+}
+
+fn hash_with_salt(salt: &str, token: &str) -> String {
+    format!("{}${}${}", VERSION_SHA256, salt, digest_hex(salt, token))
+}
+
+fn digest_hex(salt: &str, token: &str) -> String {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(salt.as_bytes());
+    ctx.update(token.as_bytes());
+
+    HEXLOWER.encode(ctx.finish().as_ref())
+}
+
+/// Compares `token` with the stored hash in constant time. Unhashed values never match.
+pub fn verify(stored: &str, token: &str) -> bool {
+    let parts: Vec<_> = stored.splitn(3, '$').collect();
+
+    match parts.as_slice() {
+        [VERSION_SHA256, salt, expected] => {
+            verify_slices_are_equal(expected.as_bytes(), digest_hex(salt, token).as_bytes()).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// Users without an access token, e.g. from before the tokens were introduced
+pub fn is_missing(stored: &str) -> bool {
+    let parts: Vec<_> = stored.splitn(3, '$').collect();
+
+    match parts.as_slice() {
+        [VERSION_SHA256, _, _] => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let token = generate();
+        let stored = hash(&token);
+
+        assert_eq!(ACCESS_TOKEN_LENGTH, token.len());
+        assert!(verify(&stored, &token));
+        assert!(!verify(&stored, "wrong"));
+        assert!(!stored.contains(&token));
+    }
+
+    #[test]
+    fn test_hashes_are_salted() {
+        assert_ne!(hash("token"), hash("token"));
+        assert_eq!(
+            hash_with_salt("salt", "token"),
+            hash_with_salt("salt", "token")
+        );
+    }
+
+    #[test]
+    fn test_plain_tokens_do_not_match() {
+        assert!(!verify("token", "token"));
+        assert!(!verify("init", "init"));
+        assert!(!verify("2$salt$digest", "token"));
+    }
+
+    #[test]
+    fn test_is_missing() {
+        assert!(is_missing(""));
+        assert!(is_missing("init"));
+        assert!(!is_missing(&hash(&generate())));
+    }
+}
@@ -1,8 +1,7 @@
-use actix_web::{error::ResponseError, HttpResponse};
+use actix_web::{error::ResponseError, http::header, http::StatusCode, HttpResponse};
 use derive_more::Display;
 use diesel::result::{DatabaseErrorKind, Error as DBError};
 use log::error;
-use serde::Serialize;
 use std::convert::From;
 use uuid::ParseError;
 
@@ -26,11 +25,20 @@ pub enum ServiceError {
     #[display(fmt = "Unauthorized")]
     Unauthorized,
 
+    /// Seconds until the client is allowed to retry
     #[display(fmt = "RateLimit was reached")]
-    RateLimit,
+    RateLimit(i64),
 
     #[display(fmt = "Resource does not exist")]
-    ResourceDoesNotExist
+    ResourceDoesNotExist,
+
+    /// The client is older than the minimum supported version
+    #[display(fmt = "Upgrade required. The minimum supported version is {}", _0)]
+    UpgradeRequired(String),
+
+    /// Cursor of the server, when the delta of the contacts is based on an outdated cursor
+    #[display(fmt = "Contacts are out of sync. Upload all contacts")]
+    SyncConflict(i64),
 }
 
 #[derive(Debug, Display, Serialize, PartialEq)]
@@ -57,28 +65,106 @@ pub enum InvalidUserInput {
     InvalidCode,
 }
 
-impl ResponseError for ServiceError {
-    fn error_response(&self) -> HttpResponse {
+/// Body of every error response. `code` is stable and can be used to localise the error.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    /// Set by the `RequestId` middleware
+    pub request_id: Option<String>,
+}
+
+impl ServiceError {
+    /// Machine-readable code of the error
+    pub fn code(&self) -> &'static str {
         match self {
-            ServiceError::InternalError => {
-                error!("InternalError");
-                HttpResponse::InternalServerError().json("Internal Server Error")
+            ServiceError::InternalError | ServiceError::InternalServerError(_) => {
+                "internal_error"
+            }
+            ServiceError::BadRequest(_) => "bad_request",
+            ServiceError::InvalidUserInput(err) => err.code(),
+            ServiceError::AlreadyExists => "already_exists",
+            ServiceError::Unauthorized => "unauthorized",
+            ServiceError::RateLimit(_) => "rate_limit",
+            ServiceError::ResourceDoesNotExist => "not_found",
+            ServiceError::UpgradeRequired(_) => "upgrade_required",
+            ServiceError::SyncConflict(_) => "sync_conflict",
+        }
+    }
+
+    /// Additional values for the client. Internal errors are not exposed.
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            ServiceError::InvalidUserInput(InvalidUserInput::InvalidPhoneNumber(value))
+            | ServiceError::InvalidUserInput(InvalidUserInput::InvalidCountry(value)) => {
+                Some(serde_json::json!({ "value": value }))
+            }
+            ServiceError::RateLimit(retry_after) => {
+                Some(serde_json::json!({ "retry_after": retry_after }))
             }
-            ServiceError::InternalServerError(err) => {
-                error!("{}", err);
-                HttpResponse::InternalServerError().json("Internal Server Error")
+            ServiceError::UpgradeRequired(min_version) => {
+                Some(serde_json::json!({ "min_version": min_version }))
+            }
+            ServiceError::SyncConflict(cursor) => Some(serde_json::json!({ "cursor": cursor })),
+            _ => None,
+        }
+    }
+
+    pub fn to_response(&self, request_id: Option<String>) -> ErrorResponse {
+        ErrorResponse {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            details: self.details(),
+            request_id,
+        }
+    }
+}
+
+impl InvalidUserInput {
+    pub fn code(&self) -> &'static str {
+        match self {
+            InvalidUserInput::InvalidPhoneNumber(_) => "invalid_phone_number",
+            InvalidUserInput::InvalidCountry(_) => "invalid_country",
+            InvalidUserInput::InvalidCode => "invalid_code",
+        }
+    }
+}
+
+impl ResponseError for ServiceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServiceError::InternalError | ServiceError::InternalServerError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
             }
-            ServiceError::InvalidUserInput(err) => {
-                error!("{}", err);
-                HttpResponse::BadRequest().json(err)
+            ServiceError::BadRequest(_) | ServiceError::InvalidUserInput(_) => {
+                StatusCode::BAD_REQUEST
             }
-            ServiceError::BadRequest(ref message) => HttpResponse::BadRequest().json(message),
-            ServiceError::AlreadyExists => HttpResponse::BadRequest().json("Entity already exists"),
-            ServiceError::Unauthorized => HttpResponse::Unauthorized().json("Unauthorized"),
-            ServiceError::RateLimit => HttpResponse::TooManyRequests().json("Too many requests"),
-            ServiceError::ResourceDoesNotExist => HttpResponse::NotFound().json("Resource does not exist"),
+            ServiceError::AlreadyExists | ServiceError::SyncConflict(_) => StatusCode::CONFLICT,
+            ServiceError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ServiceError::RateLimit(_) => StatusCode::TOO_MANY_REQUESTS,
+            ServiceError::ResourceDoesNotExist => StatusCode::NOT_FOUND,
+            ServiceError::UpgradeRequired(_) => StatusCode::UPGRADE_REQUIRED,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ServiceError::InternalError => error!("InternalError"),
+            ServiceError::InternalServerError(err) => error!("{}", err),
+            ServiceError::InvalidUserInput(err) => error!("{}", err),
+            _ => {}
+        }
+
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let ServiceError::RateLimit(retry_after) = self {
+            response.header(header::RETRY_AFTER, retry_after.to_string());
+        }
+
+        response.json(self.to_response(None))
+    }
 }
 
 impl From<ParseError> for ServiceError {
@@ -109,12 +195,65 @@ impl From<DBError> for ServiceError {
                     return ServiceError::AlreadyExists;
                 }
                 error!("kind {:?} info {:?}", kind, info);
-                ServiceError::InternalError
+                ServiceError::InternalServerError(InternalServerError::DatabaseError(
+                    info.message().to_string(),
+                ))
             }
+            DBError::NotFound => ServiceError::ResourceDoesNotExist,
             other => {
                 error!("{}", other);
-                ServiceError::InternalError
+                ServiceError::InternalServerError(InternalServerError::DatabaseError(
+                    other.to_string(),
+                ))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(StatusCode::NOT_FOUND, ServiceError::ResourceDoesNotExist.status_code());
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, ServiceError::RateLimit(5).status_code());
+        assert_eq!(StatusCode::CONFLICT, ServiceError::AlreadyExists.status_code());
+        assert_eq!(StatusCode::CONFLICT, ServiceError::SyncConflict(3).status_code());
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            ServiceError::InvalidUserInput(InvalidUserInput::InvalidCode).status_code()
+        );
+        assert_eq!(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ServiceError::InternalServerError(InternalServerError::NotificationError).status_code()
+        );
+    }
+
+    #[test]
+    fn test_envelope() {
+        let err = ServiceError::InvalidUserInput(InvalidUserInput::InvalidCountry("XY".into()));
+
+        assert_eq!(
+            ErrorResponse {
+                code: "invalid_country".to_string(),
+                message: "Wrong parameters (Invalid Country: XY)".to_string(),
+                details: Some(serde_json::json!({ "value": "XY" })),
+                request_id: Some("abc".to_string()),
+            },
+            err.to_response(Some("abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_internal_errors_are_hidden() {
+        let err = ServiceError::InternalServerError(InternalServerError::DatabaseError(
+            "relation users does not exist".into(),
+        ));
+
+        let response = err.to_response(None);
+        assert_eq!("internal_error", response.code);
+        assert_eq!("Internal Server Error", response.message);
+        assert_eq!(None, response.details);
+    }
+}
@@ -0,0 +1,116 @@
+//! Hashes of the phone numbers. Clients hash the numbers of their contacts with a plain
+//! SHA-256 (version 1). The server keys these hashes with a secret pepper (version 2), so
+//! the stored hashes cannot be reversed by enumerating all phone numbers.
+use data_encoding::{HEXLOWER, HEXUPPER};
+use lazy_static::lazy_static;
+use ring::{digest, hmac};
+use std::sync::RwLock;
+
+/// Plain SHA-256 of the number, which is computed by the clients
+pub const VERSION_CLIENT: u8 = 1;
+
+/// HMAC-SHA256 of the client's hash with the pepper
+pub const VERSION_PEPPERED: u8 = 2;
+
+/// Hashes are stored in `CHAR(64)`
+const HASH_LENGTH: usize = 64;
+
+lazy_static! {
+    static ref PEPPER: RwLock<Option<Vec<u8>>> = RwLock::new(None);
+}
+
+/// Sets the pepper of the server. Without a pepper the hashes of the clients are stored as they are.
+pub fn set_pepper(pepper: Option<&str>) {
+    let pepper = pepper
+        .filter(|w| !w.is_empty())
+        .map(|w| w.as_bytes().to_vec());
+
+    *PEPPER.write().expect("pepper lock poisoned") = pepper;
+}
+
+/// Whether the server keys the hashes of the clients
+pub fn is_peppered() -> bool {
+    PEPPER.read().expect("pepper lock poisoned").is_some()
+}
+
+/// Hash of a phone number in the international format, as the clients compute it
+pub fn client_hash(tele_num: &str) -> String {
+    HEXUPPER.encode(digest::digest(&digest::SHA256, tele_num.as_bytes()).as_ref())
+}
+
+/// Hash of a phone number in the international format, as the server stores it
+pub fn hash_tele_num(tele_num: &str) -> String {
+    from_client(&client_hash(tele_num))
+}
+
+/// Derives the stored hash of a hash, which was sent by a client
+pub fn from_client(hash: &str) -> String {
+    if version(hash) != VERSION_CLIENT {
+        return hash.to_string();
+    }
+
+    match *PEPPER.read().expect("pepper lock poisoned") {
+        Some(ref pepper) => pepper_hash(pepper, hash),
+        None => hash.to_string(),
+    }
+}
+
+/// Keys a version 1 hash with `pepper`
+pub fn pepper_hash(pepper: &[u8], hash: &str) -> String {
+    let key = hmac::SigningKey::new(&digest::SHA256, pepper);
+    let signature = hmac::sign(&key, hash.to_uppercase().as_bytes());
+
+    let prefix = format!("{}$", VERSION_PEPPERED);
+    let mut encoded = HEXLOWER.encode(signature.as_ref());
+    encoded.truncate(HASH_LENGTH - prefix.len());
+
+    prefix + &encoded
+}
+
+/// Version of a hash. Hashes without a `<version>$` prefix are version 1.
+pub fn version(hash: &str) -> u8 {
+    hash.find('$')
+        .and_then(|pos| hash[..pos].parse().ok())
+        .unwrap_or(VERSION_CLIENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_hash() {
+        assert_eq!(
+            "C775E7B757EDE630CD0AA1113BD102661AB38829CA52A6422AB782862F268646",
+            client_hash("1234567890")
+        );
+    }
+
+    #[test]
+    fn test_pepper_hash_fits_the_column() {
+        let hash = pepper_hash(b"pepper", &client_hash("+4365012345678"));
+
+        assert_eq!(HASH_LENGTH, hash.len());
+        assert_eq!(VERSION_PEPPERED, version(&hash));
+        assert!(hash.starts_with("2$"));
+    }
+
+    #[test]
+    fn test_pepper_hash_is_keyed() {
+        let hash = client_hash("+4365012345678");
+
+        assert_eq!(pepper_hash(b"pepper", &hash), pepper_hash(b"pepper", &hash));
+        assert_eq!(
+            pepper_hash(b"pepper", &hash),
+            pepper_hash(b"pepper", &hash.to_lowercase())
+        );
+        assert_ne!(pepper_hash(b"pepper", &hash), pepper_hash(b"salt", &hash));
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(VERSION_CLIENT, version(&client_hash("+4365012345678")));
+        assert_eq!(VERSION_PEPPERED, version("2$abc"));
+        assert_eq!(VERSION_CLIENT, version("x$abc"));
+    }
+}
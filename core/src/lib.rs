@@ -3,7 +3,10 @@ extern crate diesel;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod access_token;
+pub mod hashing;
 pub mod models;
+pub mod pseudonym;
 pub mod utils;
 pub mod errors;
 pub mod schema;
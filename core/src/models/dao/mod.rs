@@ -3,12 +3,9 @@ use crate::models::dto::*;
 use crate::schema::*;
 use crate::utils::phonenumber_to_international;
 
-use diesel::sql_types::{Nullable, Text, Uuid};
+use diesel::sql_types::{Array, Text, Uuid};
 use diesel::{Associations, Queryable, QueryableByName};
 
-use data_encoding::HEXUPPER;
-use ring::digest;
-
 #[derive(
     Debug,
     Serialize,
@@ -37,11 +34,15 @@ pub struct UserDao {
     pub hash_tele_num: HashedTeleNum,
     pub xp: i32,
     pub profile_picture: i32,
+    /// The led is switched off afterwards
+    pub led_expires_at: Option<chrono::NaiveDateTime>,
+    /// Group, which sees the led and the description. `None` is everyone.
+    pub led_audience: Option<i32>,
 }
 
 macro_rules! hash {
     ($col:expr) => {
-        HashedTeleNum(HEXUPPER.encode(digest::digest(&digest::SHA256, $col.as_bytes()).as_ref()))
+        HashedTeleNum(crate::hashing::hash_tele_num(&$col))
     };
 }
 
@@ -61,11 +62,29 @@ impl UserDao {
             access_token: access_token.to_string(),
             xp: 0,
             hash_tele_num: hash!(tele_num),
+            led_expires_at: None,
+            led_audience: None,
+        }
+    }
+
+    /// Expiry of the led after the update. A running expiry is kept, when the led stays on.
+    pub fn led_expiry(
+        &self,
+        user: &UpdateUserDto,
+        time: chrono::NaiveDateTime,
+    ) -> Option<chrono::NaiveDateTime> {
+        match (user.led, user.expires_in) {
+            (false, _) => None,
+            (true, Some(minutes)) => Some(time + chrono::Duration::minutes(minutes)),
+            (true, None) if self.led => self.led_expires_at,
+            (true, None) => None,
         }
     }
 
     pub fn apply_update(mut self, user: &UpdateUserDto, time: chrono::NaiveDateTime) -> Self {
+        self.led_expires_at = self.led_expiry(user, time);
         self.led = user.led;
+        self.led_audience = user.audience;
         self.description = user.description.clone();
         self.client_version = user.client_version.clone();
         self.changed_at = time;
@@ -85,11 +104,21 @@ impl UserDao {
             hash_tele_num: self.hash_tele_num.clone(),
             xp: self.xp,
             client_version: self.client_version,
-            access_token: Some(self.access_token),
+            // Only the hash is stored
+            access_token: None,
             firebase_token: self.firebase_token,
             session_token: None,
+            refresh_token: None,
+            led_expires_at: self.led_expires_at,
+            led_audience: self.led_audience,
         }
     }
+
+    /// Replaces the phone number in the analytics and the usage statistics. It is kept,
+    /// when the number changes.
+    pub fn pseudonym(&self) -> String {
+        crate::pseudonym::pseudonym(&self.id)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, Identifiable)]
@@ -146,7 +175,7 @@ impl fmt::Display for PhoneNumber {
 #[table_name = "analytics"]
 pub struct AnalyticDao {
     pub id: i32,
-    pub tele_num: String,
+    pub pseudonym: String,
     pub led: bool,
     pub description: String,
     pub created_at: chrono::NaiveDateTime,
@@ -155,7 +184,7 @@ pub struct AnalyticDao {
 #[derive(Debug, Serialize, Deserialize, Insertable)]
 #[table_name = "analytics"]
 pub struct InsertAnalyticDao {
-    pub tele_num: String,
+    pub pseudonym: String,
     pub led: bool,
     pub description: String,
     pub created_at: chrono::NaiveDateTime,
@@ -164,7 +193,7 @@ pub struct InsertAnalyticDao {
 impl AnalyticDao {
     pub fn my_from(user: &UserDao) -> InsertAnalyticDao {
         InsertAnalyticDao {
-            tele_num: user.tele_num.clone(),
+            pseudonym: user.pseudonym(),
             led: user.led,
             description: user.description.clone(),
             created_at: chrono::Local::now().naive_local(),
@@ -176,26 +205,36 @@ impl AnalyticDao {
 #[table_name = "usage_statistics"]
 pub struct UsageStatisticEntryDao {
     pub id: i32,
-    pub tele_num: String,
+    pub pseudonym: String,
     pub created_at: chrono::NaiveDateTime,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
 #[table_name = "usage_statistics"]
 pub struct InsertUsageStatisticEntryDao {
-    pub tele_num: String,
+    pub pseudonym: String,
     pub created_at: chrono::NaiveDateTime,
 }
 
 impl UsageStatisticEntryDao {
     pub fn my_from(user: &UserDao) -> InsertUsageStatisticEntryDao {
         InsertUsageStatisticEntryDao {
-            tele_num: user.tele_num.clone(),
+            pseudonym: user.pseudonym(),
             created_at: chrono::Local::now().naive_local(),
         }
     }
 }
 
+/// Usage statistics of a month, which were purged
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, PartialEq)]
+#[table_name = "usage_statistics_monthly"]
+pub struct UsageStatisticsMonthlyDao {
+    pub pseudonym: String,
+    pub yearly: i32,
+    pub monthly: i32,
+    pub count: i64,
+}
+
 #[derive(
     Debug, Serialize, Deserialize, Queryable, Clone, Associations, QueryableByName, Insertable,
 )]
@@ -207,6 +246,8 @@ pub struct ContactDao {
     pub created_at: chrono::NaiveDateTime,
     pub name: String,
     pub target_hash_tele_num: HashedTeleNum,
+    /// Last rename, or change of the blacklist or the audience between both users
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Clone, Insertable, Associations)]
@@ -218,6 +259,36 @@ pub struct ContactInsertDao {
     pub created_at: chrono::NaiveDateTime,
     pub name: String,
     pub target_hash_tele_num: HashedTeleNum,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+/// Contact of `from_id`, whose number doesn't belong to a user yet
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, PartialEq)]
+#[table_name = "pending_contacts"]
+pub struct PendingContactDao {
+    pub from_id: uuid::Uuid,
+    pub target_hash_tele_num: HashedTeleNum,
+    pub name: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Contact of `from_id`, which was removed. `hash_tele_num` is the hash of the client.
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, PartialEq)]
+#[table_name = "contact_tombstones"]
+pub struct ContactTombstoneDao {
+    pub from_id: uuid::Uuid,
+    pub target_hash_tele_num: HashedTeleNum,
+    pub hash_tele_num: HashedTeleNum,
+    pub removed_at: chrono::NaiveDateTime,
+}
+
+/// Cursor of the last upload of the contacts of `user_id`
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, PartialEq)]
+#[table_name = "contact_sync"]
+pub struct ContactSyncDao {
+    pub user_id: uuid::Uuid,
+    pub cursor: i64,
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 /// Database response for users which
@@ -228,10 +299,14 @@ pub struct ContactPushNotificationDao {
     pub from_id: uuid::Uuid,
     #[sql_type = "Text"]
     pub name: String,
-    #[sql_type = "Nullable<Text>"]
-    pub firebase_token: Option<String>,
+    /// Tokens of the active devices
+    #[sql_type = "Array<Text>"]
+    pub tokens: Vec<String>,
     #[sql_type = "Text"]
     pub target_hash_tele_num: HashedTeleNum,
+    /// Country of the recipient
+    #[sql_type = "Text"]
+    pub country_code: String,
 }
 
 #[derive(
@@ -376,6 +451,11 @@ pub struct InsertInvitationDao {
     pub updated_at: chrono::NaiveDateTime,
 }
 
+/// States of an `InvitationMemberDao`
+pub const INVITATION_STATE_UNDECIDED: i32 = 0;
+pub const INVITATION_STATE_ACCEPTED: i32 = 1;
+pub const INVITATION_STATE_DECLINED: i32 = 2;
+
 #[derive(Debug, Serialize, Deserialize, Associations, Insertable, Queryable, QueryableByName, Clone)]
 #[table_name = "invitation_members"]
 #[belongs_to(UserDao, foreign_key = "user_id")]
@@ -390,3 +470,193 @@ pub struct InvitationMemberDao {
 }
 
 
+
+/// Limits of the sms verification for a phone number or a client ip
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, AsChangeset, Clone, PartialEq)]
+#[table_name = "verification_limits"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct VerificationLimitDao {
+    pub limit_key: String,
+    /// Code requests since the last reset
+    pub requests: i32,
+    /// Wrong codes since the last lockout
+    pub failed_checks: i32,
+    /// No code can be requested before
+    pub blocked_until: Option<chrono::NaiveDateTime>,
+    /// No code can be requested or checked before
+    pub locked_until: Option<chrono::NaiveDateTime>,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl VerificationLimitDao {
+    pub fn my_from(limit_key: impl Into<String>, now: chrono::NaiveDateTime) -> Self {
+        VerificationLimitDao {
+            limit_key: limit_key.into(),
+            requests: 0,
+            failed_checks: 0,
+            blocked_until: None,
+            locked_until: None,
+            updated_at: now,
+        }
+    }
+}
+
+/// Status of a push notification in the outbox
+pub const OUTBOX_PENDING: &str = "pending";
+pub const OUTBOX_DELIVERED: &str = "delivered";
+/// Given up after too many attempts
+pub const OUTBOX_DEAD: &str = "dead";
+
+/// Push notification, which waits for delivery by the background worker
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone, PartialEq)]
+#[table_name = "notification_outbox"]
+pub struct NotificationOutboxDao {
+    pub id: i32,
+    pub name: String,
+    pub token: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: chrono::NaiveDateTime,
+    pub last_error: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub kind: String,
+    pub country_code: String,
+    pub text: String,
+    /// Json object
+    pub data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "notification_outbox"]
+pub struct InsertNotificationOutboxDao {
+    pub name: String,
+    pub token: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub kind: String,
+    pub country_code: String,
+    pub text: String,
+    pub data: String,
+}
+
+/// Installation of the app, which receives push notifications
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Associations, Clone, PartialEq)]
+#[table_name = "devices"]
+#[belongs_to(UserDao, foreign_key = "user_id")]
+pub struct DeviceDao {
+    pub id: i32,
+    pub user_id: uuid::Uuid,
+    pub token: String,
+    pub platform: String,
+    pub app_version: String,
+    pub last_seen: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "devices"]
+pub struct InsertDeviceDao {
+    pub user_id: uuid::Uuid,
+    pub token: String,
+    pub platform: String,
+    pub app_version: String,
+    pub last_seen: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Push notification preferences of a user. Users without a row receive everything.
+#[derive(
+    Debug, Serialize, Deserialize, Queryable, Insertable, AsChangeset, Identifiable, Clone, PartialEq,
+)]
+#[table_name = "notification_settings"]
+#[primary_key(user_id)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NotificationSettingsDao {
+    pub user_id: uuid::Uuid,
+    pub muted_until: Option<chrono::NaiveDateTime>,
+    pub quiet_hours_start: Option<chrono::NaiveTime>,
+    pub quiet_hours_end: Option<chrono::NaiveTime>,
+    /// IANA name, e.g. `Europe/Vienna`
+    pub timezone: String,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl NotificationSettingsDao {
+    pub fn default_for(user_id: uuid::Uuid, now: chrono::NaiveDateTime) -> Self {
+        Self {
+            user_id,
+            muted_until: None,
+            quiet_hours_start: None,
+            quiet_hours_end: None,
+            timezone: "UTC".to_string(),
+            updated_at: now,
+        }
+    }
+
+    pub fn into(self, muted_contacts: Vec<MutedContactDao>) -> NotificationSettingsDto {
+        NotificationSettingsDto {
+            muted_until: self.muted_until,
+            quiet_hours_start: self.quiet_hours_start,
+            quiet_hours_end: self.quiet_hours_end,
+            timezone: self.timezone,
+            muted_contacts: muted_contacts.into_iter().map(|w| w.hash_muted).collect(),
+        }
+    }
+}
+
+/// Contact `hash_muted`, which doesn't notify `user_id`
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, PartialEq)]
+#[table_name = "muted_contacts"]
+pub struct MutedContactDao {
+    pub user_id: uuid::Uuid,
+    pub hash_muted: HashedTeleNum,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Named group of the contacts of `owner_id`
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Associations, Clone, PartialEq)]
+#[table_name = "contact_groups"]
+#[belongs_to(UserDao, foreign_key = "owner_id")]
+pub struct ContactGroupDao {
+    pub id: i32,
+    pub owner_id: uuid::Uuid,
+    pub name: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "contact_groups"]
+pub struct InsertContactGroupDao {
+    pub owner_id: uuid::Uuid,
+    pub name: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Queryable, Insertable, Identifiable, Associations, Clone, PartialEq,
+)]
+#[table_name = "contact_group_members"]
+#[primary_key(group_id, hash_tele_num)]
+#[belongs_to(ContactGroupDao, foreign_key = "group_id")]
+pub struct ContactGroupMemberDao {
+    pub group_id: i32,
+    pub hash_tele_num: HashedTeleNum,
+}
+
+impl ContactGroupDao {
+    pub fn into(self, members: Vec<ContactGroupMemberDao>) -> ContactGroupDto {
+        ContactGroupDto {
+            id: self.id,
+            name: self.name,
+            members: members.into_iter().map(|w| w.hash_tele_num).collect(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
@@ -5,10 +5,47 @@ use diesel::types::FromSql;
 use std::io::Write;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, AsExpression, FromSqlRow, PartialEq, Eq, Hash)]
+#[macro_use]
+pub mod openapi;
+
+use openapi::{component_ref, ApiComponent, ApiSchema};
+
+#[derive(Debug, Clone, Serialize, AsExpression, FromSqlRow, PartialEq, Eq, Hash)]
 #[sql_type = "diesel::sql_types::Text"]
 pub struct HashedTeleNum(pub String);
 
+impl HashedTeleNum {
+    /// The stored hash of a hash, which was sent by a client
+    pub fn from_client(hash: &str) -> Self {
+        HashedTeleNum(crate::hashing::from_client(hash))
+    }
+
+    pub fn version(&self) -> u8 {
+        crate::hashing::version(&self.0)
+    }
+
+    /// The hash, which the clients compute for `tele_num`. Responses contain it instead of
+    /// the stored hash, so the clients can match it with their contacts.
+    pub fn for_client(&self, tele_num: &str) -> Self {
+        if crate::hashing::is_peppered() {
+            HashedTeleNum(crate::hashing::client_hash(tele_num))
+        } else {
+            self.clone()
+        }
+    }
+}
+
+/// Hashes of the clients are peppered, when they are received
+impl<'de> serde::Deserialize<'de> for HashedTeleNum {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        <String as serde::Deserialize>::deserialize(deserializer)
+            .map(|w| HashedTeleNum::from_client(&w))
+    }
+}
+
 impl<DB> ToSql<diesel::sql_types::Text, DB> for HashedTeleNum
 where
     DB: Backend,
@@ -39,179 +76,619 @@ impl fmt::Display for HashedTeleNum {
 
 //FIXME merge WrappedUserDto with UserDto
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WrappedUserDto {
-    pub hash_tele_num: HashedTeleNum,
-    pub name: String,
-    pub user: Option<UserDto>,
+api_dto! {
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct WrappedUserDto {
+        pub hash_tele_num: HashedTeleNum,
+        pub name: String,
+        pub user: Option<UserDto>,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-//FIXME `created_at` missing
-pub struct UserDto {
-    pub id: Uuid,
-    pub tele_num: String,
-    pub led: bool,
-    pub country_code: String,
-    pub description: String,
-    pub changed_at: chrono::NaiveDateTime,
-    pub profile_picture: String,
-    pub hash_tele_num: HashedTeleNum,
-    pub xp: i32,
-    pub client_version: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub access_token: Option<String>,
-    pub firebase_token: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub session_token: Option<String>,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    //FIXME `created_at` missing
+    pub struct UserDto {
+        pub id: Uuid,
+        pub tele_num: String,
+        pub led: bool,
+        pub country_code: String,
+        pub description: String,
+        pub changed_at: chrono::NaiveDateTime,
+        pub profile_picture: String,
+        pub hash_tele_num: HashedTeleNum,
+        pub xp: i32,
+        pub client_version: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub access_token: Option<String>,
+        /// Push token of the user. It is never shown to the contacts.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub firebase_token: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub session_token: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub refresh_token: Option<String>,
+        /// The led is switched off afterwards
+        #[serde(default)]
+        pub led_expires_at: Option<chrono::NaiveDateTime>,
+        /// Group, which sees the led and the description. It is only shown to the user.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub led_audience: Option<i32>,
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct PayloadNumbersDto {
-    pub numbers: Vec<PayloadUserDto>,
+impl UserDto {
+    /// Replaces the stored hash with the hash of the client
+    pub fn with_client_hash(mut self) -> Self {
+        self.hash_tele_num = self.hash_tele_num.for_client(&self.tele_num);
+        self
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct PayloadUserDto {
-    pub name: String,
-    pub hash_tele_num: HashedTeleNum,
+api_dto! {
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct PayloadNumbersDto {
+        pub numbers: Vec<PayloadUserDto>,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct PayloadUserDto {
+        pub name: String,
+        pub hash_tele_num: HashedTeleNum,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct PayloadContactsDeltaDto {
+        /// Cursor of the last upload
+        pub cursor: i64,
+        #[serde(default)]
+        pub added: Vec<PayloadUserDto>,
+        /// Contacts with a new name
+        #[serde(default)]
+        pub changed: Vec<PayloadUserDto>,
+        /// Hashes of the deleted contacts
+        #[serde(default)]
+        pub removed: Vec<HashedTeleNum>,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    pub struct ContactSyncDto {
+        /// Must be sent with the next delta
+        pub cursor: i64,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct DiscoverContactsDto {
+        /// Hashes of the numbers, which the client holds
+        pub hashes: Vec<String>,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    pub struct DiscoveredContactDto {
+        /// Hash, which was sent by the client
+        pub hash: String,
+        /// Hash of the user on the server
+        pub hash_tele_num: HashedTeleNum,
+    }
 }
 
 //TODO merge!
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PostUserDto {
-    pub tele_num: String,
-    pub country_code: String,
-    pub client_version: String,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct PostUserDto {
+        pub tele_num: String,
+        pub country_code: String,
+        pub client_version: String,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UpdateUserDto {
-    pub description: String,
-    pub led: bool,
-    pub client_version: String,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct UpdateUserDto {
+        pub description: String,
+        pub led: bool,
+        pub client_version: String,
+        /// Minutes until the led is switched off. A running expiry is kept, when the
+        /// led stays on.
+        #[serde(default)]
+        pub expires_in: Option<i64>,
+        /// Contact group, which sees the update. `None` is everyone.
+        #[serde(default)]
+        pub audience: Option<i32>,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct BlacklistDto {
+        pub id: uuid::Uuid,
+        pub created_at: chrono::NaiveDateTime,
+        pub hash_blocker: HashedTeleNum,
+        pub hash_blocked: HashedTeleNum,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BlacklistDto {
-    pub id: uuid::Uuid,
-    pub created_at: chrono::NaiveDateTime,
-    pub hash_blocker: HashedTeleNum,
-    pub hash_blocked: HashedTeleNum,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct AnalyticDto {
+        pub id: i32,
+        pub pseudonym: String,
+        pub led: bool,
+        pub description: String,
+        pub created_at: chrono::NaiveDateTime,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct UsageStatisticEntryDto {
+        pub id: i32,
+        pub pseudonym: String,
+        pub created_at: chrono::NaiveDateTime,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct AnalyticDto {
-    pub id: i32,
-    pub tele_num: String,
-    pub led: bool,
-    pub description: String,
-    pub created_at: chrono::NaiveDateTime,
+api_dto! {
+    /// Number of usages in a month, after the single usages were purged
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct UsageStatisticsMonthlyDto {
+        pub yearly: i32,
+        pub monthly: i32,
+        pub count: i64,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct UsageStatisticEntryDto {
-    pub id: i32,
-    pub tele_num: String,
-    pub created_at: chrono::NaiveDateTime,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct ContactDto {
+        pub user: UserDto,
+        pub name: String,
+        pub blocked: bool,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ContactDto {
-    pub user: UserDto,
-    pub name: String,
-    pub blocked: bool,
+api_dto! {
+    /// Contacts, which changed after `since`
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct ContactsDeltaDto {
+        /// New contacts and contacts with a new status
+        pub contacts: Vec<ContactDto>,
+        /// Hashes of the contacts, which were removed
+        pub removed: Vec<HashedTeleNum>,
+    }
 }
 
 impl ContactDto {
     pub fn new(name: impl Into<String>, blocked: bool, mut user: UserDto) -> Self {
         user.access_token = None;
         user.firebase_token = None;
+        user.session_token = None;
+        user.refresh_token = None;
+        user.led_audience = None;
         Self {
             name: name.into(),
             user,
             blocked,
         }
     }
+
+    pub fn with_client_hash(mut self) -> Self {
+        self.user = self.user.with_client_hash();
+        self
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct RequestRefreshSessionDto {
+        pub refresh_token: String,
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct RequestCodeDto {
-    pub tele_num: String,
-    pub country_code: String,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct SessionDto {
+        pub session_token: String,
+        pub refresh_token: String,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct AccessTokenDto {
+        /// It is only shown once
+        pub access_token: String,
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct RequestCheckCodeDto {
-    pub tele_num: String,
-    pub code: String,
-    pub country_code: String,
-    pub client_version: String,
+api_dto! {
+    #[derive(Debug, Deserialize)]
+    pub struct RequestCodeDto {
+        pub tele_num: String,
+        pub country_code: String,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Deserialize)]
+    pub struct RequestCheckCodeDto {
+        pub tele_num: String,
+        pub code: String,
+        pub country_code: String,
+        pub client_version: String,
+        /// Replaces the access token of an existing user, e.g. after a reinstallation
+        #[serde(default)]
+        pub new_access_token: bool,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
-pub struct EventDto {
-    pub name: String,
-    pub description: String,
-    pub opening: chrono::NaiveDateTime,
-    pub href: Option<String>,
-    pub id: i32,
+api_dto! {
+    /// New number of the user and the code, which was sent to it
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct ChangeTeleNumDto {
+        pub tele_num: String,
+        pub code: String,
+        pub country_code: String,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
-pub struct VoteDto {
-    pub hash_tele_num: HashedTeleNum,
-    pub event_id: i32,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+    pub struct EventDto {
+        pub name: String,
+        pub description: String,
+        pub opening: chrono::NaiveDateTime,
+        pub href: Option<String>,
+        pub id: i32,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
-pub struct ProfilePictureDto {
-    pub id: i32,
-    pub path: String
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+    pub struct VoteDto {
+        pub hash_tele_num: HashedTeleNum,
+        pub event_id: i32,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
-pub struct UpdateProfilePictureDto {
-    pub profile_id: i32,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+    pub struct ProfilePictureDto {
+        pub id: i32,
+        pub path: String
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BroadcastElementDto {
-    pub id: i32,
-    /// User for whom, it will be display
-    pub display_user: HashedTeleNum, 
-    /// User who created it
-    pub originator_user: ContactDto,
-    pub text: String,
-    pub created_at: chrono::NaiveDateTime,
-    pub updated_at: chrono::NaiveDateTime,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+    pub struct UpdateProfilePictureDto {
+        pub profile_id: i32,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct InvitationDto {
-    pub id: i32,
-    pub is_seen: bool,
-    pub state: i32, //0 is undecided, 1 is ok, 2 is declined
-    pub members: Vec<WrappedUserDto>,
-    pub originator: WrappedUserDto,
-    pub original_text: String,
-    pub edit_text: String,
-    pub original_time: chrono::NaiveDateTime,
-    pub edit_time: chrono::NaiveDateTime,
-    pub created_at: chrono::NaiveDateTime,
-    pub updated_at: chrono::NaiveDateTime,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct BroadcastElementDto {
+        pub id: i32,
+        /// User for whom, it will be display
+        pub display_user: HashedTeleNum,
+        /// User who created it
+        pub originator_user: ContactDto,
+        pub text: String,
+        pub created_at: chrono::NaiveDateTime,
+        pub updated_at: chrono::NaiveDateTime,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct RequestInvitationCreateDto {
-    pub text: String,
-    pub time: chrono::NaiveDateTime,
-    pub contacts: Vec<HashedTeleNum>
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct InvitationDto {
+        pub id: i32,
+        pub is_seen: bool,
+        pub state: i32, //0 is undecided, 1 is ok, 2 is declined
+        pub members: Vec<WrappedUserDto>,
+        pub originator: WrappedUserDto,
+        pub original_text: String,
+        pub edit_text: String,
+        pub original_time: chrono::NaiveDateTime,
+        pub edit_time: chrono::NaiveDateTime,
+        pub created_at: chrono::NaiveDateTime,
+        pub updated_at: chrono::NaiveDateTime,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct UpdateInvitationStateDto {
-    pub accept: bool,
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct RequestInvitationCreateDto {
+        pub text: String,
+        pub time: chrono::NaiveDateTime,
+        pub contacts: Vec<HashedTeleNum>,
+        /// Groups, whose members are invited as well
+        #[serde(default)]
+        pub groups: Vec<i32>,
+    }
 }
 
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct UpdateInvitationStateDto {
+        pub accept: bool,
+    }
+}
+
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct RequestInvitationAddMembersDto {
+        pub contacts: Vec<HashedTeleNum>
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct RegisterDeviceDto {
+        pub token: String,
+        /// `android`, `ios` or `web`
+        pub platform: String,
+        pub app_version: String,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct DeviceDto {
+        pub token: String,
+        pub platform: String,
+        pub app_version: String,
+        pub last_seen: chrono::NaiveDateTime,
+        pub created_at: chrono::NaiveDateTime,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct NotificationSettingsDto {
+        /// No push notifications are sent until then
+        pub muted_until: Option<chrono::NaiveDateTime>,
+        /// Local time of `timezone`
+        pub quiet_hours_start: Option<chrono::NaiveTime>,
+        pub quiet_hours_end: Option<chrono::NaiveTime>,
+        pub timezone: String,
+        pub muted_contacts: Vec<HashedTeleNum>,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct UpdateNotificationSettingsDto {
+        pub muted_until: Option<chrono::NaiveDateTime>,
+        /// Both or none of the quiet hours must be set
+        pub quiet_hours_start: Option<chrono::NaiveTime>,
+        pub quiet_hours_end: Option<chrono::NaiveTime>,
+        /// IANA name, e.g. `Europe/Vienna`
+        pub timezone: String,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct MuteContactDto {
+        pub hash_tele_num: HashedTeleNum,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct ContactGroupDto {
+        pub id: i32,
+        pub name: String,
+        pub members: Vec<HashedTeleNum>,
+        pub created_at: chrono::NaiveDateTime,
+        pub updated_at: chrono::NaiveDateTime,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct RequestContactGroupDto {
+        pub name: String,
+        /// Hashes of the contacts
+        pub members: Vec<HashedTeleNum>,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct ExportedContactDto {
+        pub name: String,
+        pub hash_tele_num: HashedTeleNum,
+        pub created_at: chrono::NaiveDateTime,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct ExportedBroadcastDto {
+        pub id: i32,
+        /// User for whom, it was displayed
+        pub display_user: HashedTeleNum,
+        pub text: String,
+        pub is_seen: bool,
+        pub created_at: chrono::NaiveDateTime,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct ExportedInvitationDto {
+        pub id: i32,
+        pub original_text: String,
+        pub original_time: chrono::NaiveDateTime,
+        pub edit_text: String,
+        pub edit_time: chrono::NaiveDateTime,
+        pub created_at: chrono::NaiveDateTime,
+    }
+}
+
+api_dto! {
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct ExportedInvitationMemberDto {
+        pub inv_id: i32,
+        /// 0 is undecided, 1 is accepted, 2 is declined
+        pub state: i32,
+        pub is_seen: bool,
+        pub created_at: chrono::NaiveDateTime,
+    }
+}
+
+api_dto! {
+    /// Everything, which is stored about a user
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct AccountExportDto {
+        pub user: UserDto,
+        pub contacts: Vec<ExportedContactDto>,
+        pub contact_groups: Vec<ContactGroupDto>,
+        /// Users, which were blocked by the user
+        pub blacklist: Vec<BlacklistDto>,
+        pub devices: Vec<DeviceDto>,
+        pub notification_settings: Option<NotificationSettingsDto>,
+        pub broadcasts: Vec<ExportedBroadcastDto>,
+        pub invitations: Vec<ExportedInvitationDto>,
+        /// Invitations, which the user received
+        pub invitation_memberships: Vec<ExportedInvitationMemberDto>,
+        pub votes: Vec<VoteDto>,
+        pub analytics: Vec<AnalyticDto>,
+        pub usage_statistics: Vec<UsageStatisticEntryDto>,
+        /// Usage statistics, which were purged
+        pub usage_statistics_monthly: Vec<UsageStatisticsMonthlyDto>,
+        pub exported_at: chrono::NaiveDateTime,
+    }
+}
+
+impl ApiSchema for HashedTeleNum {
+    fn schema() -> serde_json::Value {
+        component_ref(Self::NAME)
+    }
+}
+
+impl ApiComponent for HashedTeleNum {
+    const NAME: &'static str = "HashedTeleNum";
+
+    fn definition() -> serde_json::Value {
+        serde_json::json!({
+            "type": "string",
+            "description": "Hash of a phone number in the international format"
+        })
+    }
+}
+
+/// Schemas of all DTOs for the OpenAPI document
+pub fn api_components() -> serde_json::Map<String, serde_json::Value> {
+    api_components![
+        HashedTeleNum,
+        WrappedUserDto,
+        UserDto,
+        PayloadNumbersDto,
+        PayloadUserDto,
+        PayloadContactsDeltaDto,
+        ContactSyncDto,
+        DiscoverContactsDto,
+        DiscoveredContactDto,
+        PostUserDto,
+        UpdateUserDto,
+        BlacklistDto,
+        AnalyticDto,
+        UsageStatisticEntryDto,
+        UsageStatisticsMonthlyDto,
+        ContactDto,
+        ContactsDeltaDto,
+        RequestRefreshSessionDto,
+        SessionDto,
+        AccessTokenDto,
+        RequestCodeDto,
+        RequestCheckCodeDto,
+        ChangeTeleNumDto,
+        EventDto,
+        VoteDto,
+        ProfilePictureDto,
+        UpdateProfilePictureDto,
+        BroadcastElementDto,
+        InvitationDto,
+        RequestInvitationCreateDto,
+        UpdateInvitationStateDto,
+        RequestInvitationAddMembersDto,
+        RegisterDeviceDto,
+        DeviceDto,
+        NotificationSettingsDto,
+        UpdateNotificationSettingsDto,
+        MuteContactDto,
+        ContactGroupDto,
+        RequestContactGroupDto,
+        ExportedContactDto,
+        ExportedBroadcastDto,
+        ExportedInvitationDto,
+        ExportedInvitationMemberDto,
+        AccountExportDto,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dto_definition() {
+        let definition = UserDto::definition();
+
+        assert_eq!("object", definition["type"]);
+        assert_eq!("uuid", definition["properties"]["id"]["format"]);
+        assert_eq!(true, definition["properties"]["access_token"]["nullable"]);
+
+        let required = definition["required"].as_array().unwrap();
+        assert!(required.contains(&serde_json::json!("tele_num")));
+        assert!(!required.contains(&serde_json::json!("access_token")));
+    }
+
+    #[test]
+    fn test_dto_references() {
+        let definition = WrappedUserDto::definition();
+
+        assert_eq!(
+            "#/components/schemas/HashedTeleNum",
+            definition["properties"]["hash_tele_num"]["$ref"]
+        );
+        assert_eq!(
+            "#/components/schemas/UserDto",
+            definition["properties"]["user"]["allOf"][0]["$ref"]
+        );
+        assert_eq!(
+            "#/components/schemas/PayloadUserDto",
+            PayloadNumbersDto::definition()["properties"]["numbers"]["items"]["$ref"]
+        );
+    }
+
+    #[test]
+    fn test_contact_hides_private_fields() {
+        let mut user = crate::models::dao::UserDao::my_from("+4366412345678", "AT", "0.6.0", "");
+        user.firebase_token = Some("token".to_string());
+        user.led_audience = Some(1);
+
+        let contact = ContactDto::new("Alice", false, user.into("path".to_string()));
+        let json = serde_json::to_value(&contact).unwrap();
+
+        assert!(json["user"].get("access_token").is_none());
+        assert!(json["user"].get("firebase_token").is_none());
+        assert!(json["user"].get("led_audience").is_none());
+    }
+}
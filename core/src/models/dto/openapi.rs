@@ -0,0 +1,136 @@
+//! OpenAPI schemas of the DTOs. The structs are declared with `api_dto!`, which
+//! derives the schema from the fields, so the document cannot drift from the code.
+use serde_json::{json, Value};
+
+/// Schema of a type, when it is used as a field or as a body
+pub trait ApiSchema {
+    fn schema() -> Value;
+
+    /// `Option`s are not required
+    fn required() -> bool {
+        true
+    }
+}
+
+/// Named schema in `#/components/schemas`
+pub trait ApiComponent {
+    const NAME: &'static str;
+
+    fn definition() -> Value;
+}
+
+/// Reference to the component `name`
+pub fn component_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+macro_rules! primitive_schema {
+    ($($ty:ty => $schema:tt),* $(,)?) => {
+        $(
+            impl ApiSchema for $ty {
+                fn schema() -> Value {
+                    json!($schema)
+                }
+            }
+        )*
+    };
+}
+
+primitive_schema! {
+    String => { "type": "string" },
+    bool => { "type": "boolean" },
+    i32 => { "type": "integer", "format": "int32" },
+    i64 => { "type": "integer", "format": "int64" },
+    uuid::Uuid => { "type": "string", "format": "uuid" },
+    chrono::NaiveDateTime => { "type": "string", "example": "2020-07-20T12:00:00", "description": "Without offset" },
+    chrono::NaiveTime => { "type": "string", "example": "22:00:00" },
+}
+
+impl<T: ApiSchema> ApiSchema for Option<T> {
+    fn schema() -> Value {
+        let mut schema = T::schema();
+
+        match schema.get("$ref").cloned() {
+            // Siblings of `$ref` are ignored
+            Some(reference) => json!({ "allOf": [{ "$ref": reference }], "nullable": true }),
+            None => {
+                schema["nullable"] = json!(true);
+                schema
+            }
+        }
+    }
+
+    fn required() -> bool {
+        false
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+/// Declares a DTO and implements `ApiSchema` and `ApiComponent` from its fields.
+macro_rules! api_dto {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                pub $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[$field_attr])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl $crate::models::dto::openapi::ApiSchema for $name {
+            fn schema() -> serde_json::Value {
+                $crate::models::dto::openapi::component_ref(stringify!($name))
+            }
+        }
+
+        impl $crate::models::dto::openapi::ApiComponent for $name {
+            const NAME: &'static str = stringify!($name);
+
+            fn definition() -> serde_json::Value {
+                use $crate::models::dto::openapi::ApiSchema;
+
+                let mut properties = serde_json::Map::new();
+                let mut required: Vec<&str> = Vec::new();
+
+                $(
+                    properties.insert(stringify!($field).to_string(), <$ty>::schema());
+                    if <$ty>::required() {
+                        required.push(stringify!($field));
+                    }
+                )*
+
+                serde_json::json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                })
+            }
+        }
+    };
+}
+
+/// Map of the component names to their definitions
+macro_rules! api_components {
+    ($($name:ty),* $(,)?) => {{
+        use $crate::models::dto::openapi::ApiComponent;
+
+        let mut components = serde_json::Map::new();
+        $(
+            components.insert(<$name>::NAME.to_string(), <$name>::definition());
+        )*
+        components
+    }};
+}
@@ -16,18 +16,28 @@ impl Into<UsageStatisticEntryDto> for UsageStatisticEntryDao {
     fn into(self) -> UsageStatisticEntryDto {
         UsageStatisticEntryDto {
             id: self.id,
-            tele_num: self.tele_num.clone(),
+            pseudonym: self.pseudonym.clone(),
             created_at: self.created_at,
         }
     }
 }
 
+impl Into<UsageStatisticsMonthlyDto> for UsageStatisticsMonthlyDao {
+    fn into(self) -> UsageStatisticsMonthlyDto {
+        UsageStatisticsMonthlyDto {
+            yearly: self.yearly,
+            monthly: self.monthly,
+            count: self.count,
+        }
+    }
+}
+
 impl Into<AnalyticDto> for AnalyticDao {
     fn into(self) -> AnalyticDto {
         AnalyticDto {
             id: self.id,
             description: self.description.clone(),
-            tele_num: self.tele_num.clone(),
+            pseudonym: self.pseudonym.clone(),
             led: self.led,
             created_at: self.created_at,
         }
@@ -63,3 +73,71 @@ impl Into<ProfilePictureDto> for ProfilePictureDao {
         }
     }
 }
+
+impl Into<DeviceDto> for DeviceDao {
+    fn into(self) -> DeviceDto {
+        DeviceDto {
+            token: self.token,
+            platform: self.platform,
+            app_version: self.app_version,
+            last_seen: self.last_seen,
+            created_at: self.created_at,
+        }
+    }
+}
+
+impl Into<ExportedContactDto> for ContactDao {
+    fn into(self) -> ExportedContactDto {
+        ExportedContactDto {
+            name: self.name,
+            hash_tele_num: self.target_hash_tele_num,
+            created_at: self.created_at,
+        }
+    }
+}
+
+impl Into<ExportedContactDto> for PendingContactDao {
+    fn into(self) -> ExportedContactDto {
+        ExportedContactDto {
+            name: self.name,
+            hash_tele_num: self.target_hash_tele_num,
+            created_at: self.created_at,
+        }
+    }
+}
+
+impl Into<ExportedBroadcastDto> for BroadcastElementDao {
+    fn into(self) -> ExportedBroadcastDto {
+        ExportedBroadcastDto {
+            id: self.id,
+            display_user: self.display_user,
+            text: self.text,
+            is_seen: self.is_seen,
+            created_at: self.created_at,
+        }
+    }
+}
+
+impl Into<ExportedInvitationDto> for InvitationDao {
+    fn into(self) -> ExportedInvitationDto {
+        ExportedInvitationDto {
+            id: self.id,
+            original_text: self.original_text,
+            original_time: self.original_time,
+            edit_text: self.edit_text,
+            edit_time: self.edit_time,
+            created_at: self.created_at,
+        }
+    }
+}
+
+impl Into<ExportedInvitationMemberDto> for InvitationMemberDao {
+    fn into(self) -> ExportedInvitationMemberDto {
+        ExportedInvitationMemberDto {
+            inv_id: self.inv_id,
+            state: self.state,
+            is_seen: self.is_seen,
+            created_at: self.created_at,
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Pseudonyms of the users in the analytics and the usage statistics. The server keys
+//! them with a secret, so the rows cannot be joined with the users without it.
+use data_encoding::HEXLOWER;
+use lazy_static::lazy_static;
+use ring::{digest, hmac};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+lazy_static! {
+    static ref KEY: RwLock<Option<Vec<u8>>> = RwLock::new(None);
+}
+
+/// Sets the key of the server. Without a key the pseudonym is the id of the user.
+pub fn set_key(key: Option<&str>) {
+    let key = key.filter(|w| !w.is_empty()).map(|w| w.as_bytes().to_vec());
+
+    *KEY.write().expect("pseudonym key lock poisoned") = key;
+}
+
+/// Pseudonym of the user `id`, as the server stores it
+pub fn pseudonym(id: &Uuid) -> String {
+    match *KEY.read().expect("pseudonym key lock poisoned") {
+        Some(ref key) => keyed_pseudonym(key, &id.to_string()),
+        None => id.to_string(),
+    }
+}
+
+/// HMAC-SHA256 of the user `id` with `key`. The id is in its hyphenated form.
+pub fn keyed_pseudonym(key: &[u8], id: &str) -> String {
+    let key = hmac::SigningKey::new(&digest::SHA256, key);
+    let signature = hmac::sign(&key, id.as_bytes());
+
+    HEXLOWER.encode(signature.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyed_pseudonym() {
+        let id = Uuid::new_v4().to_string();
+
+        assert_eq!(keyed_pseudonym(b"key", &id), keyed_pseudonym(b"key", &id));
+        assert_ne!(keyed_pseudonym(b"key", &id), keyed_pseudonym(b"other", &id));
+        assert!(!keyed_pseudonym(b"key", &id).contains(&id));
+        // Fits `VARCHAR(100)`
+        assert_eq!(64, keyed_pseudonym(b"key", &id).len());
+    }
+}
@@ -1,7 +1,7 @@
 table! {
     analytics (id) {
         id -> Int4,
-        tele_num -> Varchar,
+        pseudonym -> Varchar,
         led -> Bool,
         description -> Text,
         created_at -> Timestamp,
@@ -29,12 +29,59 @@ table! {
     }
 }
 
+table! {
+    contact_group_members (group_id, hash_tele_num) {
+        group_id -> Int4,
+        hash_tele_num -> Bpchar,
+    }
+}
+
+table! {
+    contact_groups (id) {
+        id -> Int4,
+        owner_id -> Uuid,
+        name -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    contact_sync (user_id) {
+        user_id -> Uuid,
+        cursor -> Int8,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    contact_tombstones (from_id, target_hash_tele_num) {
+        from_id -> Uuid,
+        target_hash_tele_num -> Bpchar,
+        hash_tele_num -> Bpchar,
+        removed_at -> Timestamp,
+    }
+}
+
 table! {
     contacts (from_id, target_hash_tele_num) {
         from_id -> Uuid,
         created_at -> Timestamp,
         name -> Varchar,
         target_hash_tele_num -> Bpchar,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    devices (id) {
+        id -> Int4,
+        user_id -> Uuid,
+        token -> Varchar,
+        platform -> Varchar,
+        app_version -> Varchar,
+        last_seen -> Timestamp,
+        created_at -> Timestamp,
     }
 }
 
@@ -77,6 +124,52 @@ table! {
     }
 }
 
+table! {
+    muted_contacts (user_id, hash_muted) {
+        user_id -> Uuid,
+        hash_muted -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    notification_outbox (id) {
+        id -> Int4,
+        name -> Varchar,
+        token -> Varchar,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        kind -> Varchar,
+        country_code -> Varchar,
+        text -> Text,
+        data -> Text,
+    }
+}
+
+table! {
+    notification_settings (user_id) {
+        user_id -> Uuid,
+        muted_until -> Nullable<Timestamp>,
+        quiet_hours_start -> Nullable<Time>,
+        quiet_hours_end -> Nullable<Time>,
+        timezone -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    pending_contacts (from_id, target_hash_tele_num) {
+        from_id -> Uuid,
+        target_hash_tele_num -> Bpchar,
+        name -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     profile_pictures (id) {
         id -> Int4,
@@ -84,14 +177,31 @@ table! {
     }
 }
 
+table! {
+    tele_num_changes (old_hash_tele_num) {
+        old_hash_tele_num -> Bpchar,
+        hash_tele_num -> Bpchar,
+        changed_at -> Timestamp,
+    }
+}
+
 table! {
     usage_statistics (id) {
         id -> Int4,
-        tele_num -> Varchar,
+        pseudonym -> Varchar,
         created_at -> Timestamp,
     }
 }
 
+table! {
+    usage_statistics_monthly (pseudonym, yearly, monthly) {
+        pseudonym -> Varchar,
+        yearly -> Int4,
+        monthly -> Int4,
+        count -> Int8,
+    }
+}
+
 table! {
     users (id) {
         id -> Uuid,
@@ -107,6 +217,19 @@ table! {
         hash_tele_num -> Bpchar,
         xp -> Int4,
         profile_picture -> Int4,
+        led_expires_at -> Nullable<Timestamp>,
+        led_audience -> Nullable<Int4>,
+    }
+}
+
+table! {
+    verification_limits (limit_key) {
+        limit_key -> Varchar,
+        requests -> Int4,
+        failed_checks -> Int4,
+        blocked_until -> Nullable<Timestamp>,
+        locked_until -> Nullable<Timestamp>,
+        updated_at -> Timestamp,
     }
 }
 
@@ -117,9 +240,17 @@ table! {
     }
 }
 
+joinable!(contact_group_members -> contact_groups (group_id));
+joinable!(contact_groups -> users (owner_id));
+joinable!(contact_sync -> users (user_id));
+joinable!(contact_tombstones -> users (from_id));
+joinable!(devices -> users (user_id));
 joinable!(invitation -> users (originator_user_id));
 joinable!(invitation_members -> invitation (inv_id));
 joinable!(invitation_members -> users (user_id));
+joinable!(muted_contacts -> users (user_id));
+joinable!(notification_settings -> users (user_id));
+joinable!(pending_contacts -> users (from_id));
 joinable!(users -> profile_pictures (profile_picture));
 joinable!(votes -> events (event_id));
 
@@ -127,12 +258,24 @@ allow_tables_to_appear_in_same_query!(
     analytics,
     blacklist,
     broadcast,
+    contact_group_members,
+    contact_groups,
+    contact_sync,
+    contact_tombstones,
     contacts,
+    devices,
     events,
     invitation,
     invitation_members,
+    muted_contacts,
+    notification_outbox,
+    notification_settings,
+    pending_contacts,
     profile_pictures,
+    tele_num_changes,
     usage_statistics,
+    usage_statistics_monthly,
     users,
+    verification_limits,
     votes,
 );
@@ -0,0 +1,898 @@
+//! The configuration is read from a toml file (`GEHMA_CONFIG`, default `gehma.toml`).
+//! Every value can be overwritten by the environment.
+use crate::ratelimits::verification::VerificationLimitConfig;
+use crate::ratelimits::RateLimitConfig;
+use crate::services::led_expiry::LedExpiryConfig;
+use crate::services::number_registration::twilio::TwilioConfiguration;
+use crate::services::push_notifications::firebase::FirebaseConfiguration;
+use crate::services::push_notifications::one_signal::OneSignalConfiguration;
+use crate::services::push_notifications::outbox::OutboxConfig;
+use crate::services::retention::RetentionConfig;
+use crate::services::session::{REFRESH_TOKEN_DURATION, SESSION_DURATION};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::HttpResponse;
+use core::errors::ServiceError;
+use log::{debug, info};
+use std::fmt;
+use std::str::FromStr;
+use web_contrib::utils::Version;
+
+pub const DEFAULT_CONFIG_PATH: &str = "gehma.toml";
+
+/// All problems of the configuration
+#[derive(Debug, PartialEq)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join("\n"))
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub session: SessionConfig,
+    pub number_registration: NumberRegistrationConfig,
+    pub notification: NotificationConfig,
+    pub cors: CorsConfig,
+    pub client: ClientConfig,
+    pub limits: LimitsConfig,
+    pub led_expiry: LedExpiryConfig,
+    pub retention: RetentionConfig,
+    pub hashing: HashingConfig,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub addr: String,
+    pub port: u16,
+    pub database_url: String,
+    /// Sessions and refresh tokens are stored in redis
+    pub redis_url: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: "localhost".to_string(),
+            port: 3000,
+            database_url: String::new(),
+            redis_url: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub key: String,
+    /// Lifetime of a session token in seconds
+    pub duration: i64,
+    /// Lifetime of a refresh token in seconds
+    pub refresh_token_duration: i64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            key: String::new(),
+            duration: SESSION_DURATION,
+            refresh_token_duration: REFRESH_TOKEN_DURATION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumberRegistrationBackend {
+    Twilio,
+    Testing,
+}
+
+impl FromStr for NumberRegistrationBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "twilio" => Ok(NumberRegistrationBackend::Twilio),
+            "testing" => Ok(NumberRegistrationBackend::Testing),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct NumberRegistrationConfig {
+    pub backend: NumberRegistrationBackend,
+    pub twilio: TwilioConfiguration,
+}
+
+/// The testing backend accepts every code, so it must be chosen explicitly
+impl Default for NumberRegistrationConfig {
+    fn default() -> Self {
+        Self {
+            backend: NumberRegistrationBackend::Twilio,
+            twilio: TwilioConfiguration::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationBackend {
+    OneSignal,
+    Firebase,
+    Testing,
+}
+
+impl FromStr for NotificationBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "one_signal" => Ok(NotificationBackend::OneSignal),
+            "firebase" => Ok(NotificationBackend::Firebase),
+            "testing" => Ok(NotificationBackend::Testing),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub backend: NotificationBackend,
+    pub one_signal: OneSignalConfiguration,
+    pub firebase: FirebaseConfiguration,
+    pub outbox: OutboxConfig,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            backend: NotificationBackend::OneSignal,
+            one_signal: OneSignalConfiguration::default(),
+            firebase: FirebaseConfiguration::default(),
+            outbox: OutboxConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    pub origins: Vec<String>,
+    /// Seconds
+    pub max_age: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: vec![
+                "http://localhost:3000".to_string(),
+                "https://gehma.xyz".to_string(),
+            ],
+            max_age: 3600,
+        }
+    }
+}
+
+/// Response header with the recommended version for deprecated clients
+pub const UPGRADE_RECOMMENDED_HEADER: &str = "x-upgrade-recommended";
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    /// Older clients are rejected
+    pub min_version: String,
+    /// Older clients are deprecated and should upgrade
+    pub recommended_version: String,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            min_version: crate::MIN_CLIENT_VERSION.to_string(),
+            recommended_version: crate::MIN_CLIENT_VERSION.to_string(),
+        }
+    }
+}
+
+/// How a client version is supported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VersionSupport {
+    Supported,
+    Deprecated,
+}
+
+impl ClientConfig {
+    /// Fails with `ServiceError::UpgradeRequired` for unsupported clients
+    pub fn negotiate(&self, client_version: &str) -> Result<VersionSupport, ServiceError> {
+        let version = Version::new(client_version).map_err(|_| {
+            ServiceError::BadRequest(format!("Invalid client version {}", client_version))
+        })?;
+
+        // Both were checked by `Config::validate`
+        let min_version =
+            Version::new(self.min_version.as_str()).map_err(|_| ServiceError::InternalError)?;
+        let recommended_version = Version::new(self.recommended_version.as_str())
+            .map_err(|_| ServiceError::InternalError)?;
+
+        if version < min_version {
+            info!("Client version {} is not supported anymore", client_version);
+            return Err(ServiceError::UpgradeRequired(self.min_version.clone()));
+        }
+
+        if version < recommended_version {
+            debug!("Client version {} is deprecated", client_version);
+            return Ok(VersionSupport::Deprecated);
+        }
+
+        Ok(VersionSupport::Supported)
+    }
+
+    /// Recommends an upgrade to deprecated clients
+    pub fn set_response_headers(&self, support: VersionSupport, response: &mut HttpResponse) {
+        if support != VersionSupport::Deprecated {
+            return;
+        }
+
+        if let Ok(value) = HeaderValue::from_str(&self.recommended_version) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(UPGRADE_RECOMMENDED_HEADER), value);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitPolicyKind {
+    /// Counts the updates in the analytics table
+    Analytics,
+    /// Keeps the requests in memory
+    Memory,
+}
+
+impl FromStr for RateLimitPolicyKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "analytics" => Ok(RateLimitPolicyKind::Analytics),
+            "memory" => Ok(RateLimitPolicyKind::Memory),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub push_notification_contacts: usize,
+    pub policy: RateLimitPolicyKind,
+    pub rate_limits: RateLimitConfig,
+    pub verification: VerificationLimitConfig,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            push_notification_contacts: crate::LIMIT_PUSH_NOTIFICATION_CONTACTS,
+            policy: RateLimitPolicyKind::Analytics,
+            rate_limits: RateLimitConfig::default(),
+            verification: VerificationLimitConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HashingConfig {
+    /// Secret key of the stored phone number hashes. Empty keeps the hashes of the clients.
+    pub pepper: String,
+    /// Secret key of the pseudonyms in the statistics. Empty uses the ids of the users.
+    pub pseudonym_key: String,
+}
+
+type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn set_string(env: Env, key: &str, target: &mut String) {
+    if let Some(value) = env(key) {
+        *target = value;
+    }
+}
+
+/// Comma separated values
+fn set_list(env: Env, key: &str, target: &mut Vec<String>) {
+    if let Some(value) = env(key) {
+        *target = value
+            .split(',')
+            .map(|w| w.trim().to_string())
+            .filter(|w| !w.is_empty())
+            .collect();
+    }
+}
+
+fn set_parsed<T: FromStr>(env: Env, key: &str, target: &mut T, errors: &mut Vec<String>) {
+    if let Some(value) = env(key) {
+        match value.parse() {
+            Ok(parsed) => *target = parsed,
+            Err(_) => errors.push(format!("{} has an invalid value '{}'", key, value)),
+        }
+    }
+}
+
+fn require(value: &str, name: &str, errors: &mut Vec<String>) {
+    if value.is_empty() {
+        errors.push(format!("{} is missing", name));
+    }
+}
+
+fn require_positive(value: i64, name: &str, errors: &mut Vec<String>) {
+    if value <= 0 {
+        errors.push(format!("{} must be positive", name));
+    }
+}
+
+impl Config {
+    /// Loads the file and the environment and validates the result
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match std::env::var("GEHMA_CONFIG") {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) if std::path::Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(DEFAULT_CONFIG_PATH)?
+            }
+            Err(_) => Config::default(),
+        };
+
+        config.apply_env(&|key| std::env::var(key).ok())?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError(vec![format!("Cannot read {}: {}", path, err)]))?;
+
+        Self::from_toml(&content)
+            .map_err(|err| ConfigError(vec![format!("Cannot parse {}: {}", path, err)]))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        toml::from_str(content).map_err(|err| ConfigError(vec![err.to_string()]))
+    }
+
+    /// Overwrites the values, which are set in `env`
+    pub fn apply_env(&mut self, env: Env) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        set_string(env, "DATABASE_URL", &mut self.server.database_url);
+        set_string(env, "REDIS_URL", &mut self.server.redis_url);
+        set_string(env, "BINDING_ADDR", &mut self.server.addr);
+        set_parsed(env, "PORT", &mut self.server.port, &mut errors);
+
+        let session = &mut self.session;
+        set_string(env, "SESSION_KEY", &mut session.key);
+        set_parsed(env, "SESSION_DURATION", &mut session.duration, &mut errors);
+        set_parsed(
+            env,
+            "REFRESH_TOKEN_DURATION",
+            &mut session.refresh_token_duration,
+            &mut errors,
+        );
+
+        let number_registration = &mut self.number_registration;
+        set_parsed(
+            env,
+            "NUMBER_REGISTRATION_BACKEND",
+            &mut number_registration.backend,
+            &mut errors,
+        );
+        set_string(env, "TWILIO_PROJECT_ID", &mut number_registration.twilio.project_id);
+        set_string(env, "TWILIO_ACCOUNT_ID", &mut number_registration.twilio.account_id);
+        set_string(env, "TWILIO_AUTH_TOKEN", &mut number_registration.twilio.auth_token);
+
+        let notification = &mut self.notification;
+        set_parsed(env, "NOTIFICATION_BACKEND", &mut notification.backend, &mut errors);
+        set_string(env, "ONE_SIGNAL_ID", &mut notification.one_signal.id);
+        set_string(env, "ONE_SIGNAL_KEY", &mut notification.one_signal.key);
+        set_string(env, "FCM_TOKEN", &mut notification.firebase.fcm_token);
+
+        let outbox = &mut notification.outbox;
+        set_parsed(
+            env,
+            "NOTIFICATION_OUTBOX_POLL_INTERVAL",
+            &mut outbox.poll_interval,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "NOTIFICATION_OUTBOX_BATCH_SIZE",
+            &mut outbox.batch_size,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "NOTIFICATION_OUTBOX_MAX_ATTEMPTS",
+            &mut outbox.max_attempts,
+            &mut errors,
+        );
+        set_parsed(env, "NOTIFICATION_OUTBOX_BACKOFF", &mut outbox.backoff, &mut errors);
+        set_parsed(
+            env,
+            "NOTIFICATION_OUTBOX_MAX_BACKOFF",
+            &mut outbox.max_backoff,
+            &mut errors,
+        );
+        set_parsed(env, "NOTIFICATION_OUTBOX_LEASE", &mut outbox.lease, &mut errors);
+
+        set_list(env, "CORS_ORIGINS", &mut self.cors.origins);
+        set_string(env, "MIN_CLIENT_VERSION", &mut self.client.min_version);
+        set_string(
+            env,
+            "RECOMMENDED_CLIENT_VERSION",
+            &mut self.client.recommended_version,
+        );
+
+        let limits = &mut self.limits;
+        set_parsed(
+            env,
+            "LIMIT_PUSH_NOTIFICATION_CONTACTS",
+            &mut limits.push_notification_contacts,
+            &mut errors,
+        );
+        set_parsed(env, "RATE_LIMIT_POLICY", &mut limits.policy, &mut errors);
+
+        let rate_limits = &mut limits.rate_limits;
+        set_parsed(env, "RATE_LIMIT_WINDOW", &mut rate_limits.window, &mut errors);
+        set_parsed(env, "RATE_LIMIT_LED_UPDATES", &mut rate_limits.led_updates, &mut errors);
+        set_parsed(env, "RATE_LIMIT_XP", &mut rate_limits.xp, &mut errors);
+        set_parsed(
+            env,
+            "RATE_LIMIT_CODE_REQUESTS",
+            &mut rate_limits.code_requests,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "RATE_LIMIT_CONTACT_UPLOADS",
+            &mut rate_limits.contact_uploads,
+            &mut errors,
+        );
+
+        let verification = &mut limits.verification;
+        set_parsed(env, "VERIFICATION_BACKOFF", &mut verification.backoff, &mut errors);
+        set_parsed(
+            env,
+            "VERIFICATION_MAX_BACKOFF",
+            &mut verification.max_backoff,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "VERIFICATION_FREE_REQUESTS_NUMBER",
+            &mut verification.free_requests_number,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "VERIFICATION_FREE_REQUESTS_IP",
+            &mut verification.free_requests_ip,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "VERIFICATION_MAX_FAILED_CHECKS",
+            &mut verification.max_failed_checks,
+            &mut errors,
+        );
+        set_parsed(env, "VERIFICATION_LOCKOUT", &mut verification.lockout, &mut errors);
+        set_parsed(
+            env,
+            "VERIFICATION_RESET_AFTER",
+            &mut verification.reset_after,
+            &mut errors,
+        );
+        set_list(
+            env,
+            "VERIFICATION_TRUSTED_PROXIES",
+            &mut verification.trusted_proxies,
+        );
+
+        set_parsed(
+            env,
+            "LED_EXPIRY_POLL_INTERVAL",
+            &mut self.led_expiry.poll_interval,
+            &mut errors,
+        );
+
+        set_parsed(
+            env,
+            "RETENTION_POLL_INTERVAL",
+            &mut self.retention.poll_interval,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "RETENTION_ANALYTICS_DAYS",
+            &mut self.retention.analytics,
+            &mut errors,
+        );
+        set_parsed(
+            env,
+            "RETENTION_USAGE_STATISTICS_DAYS",
+            &mut self.retention.usage_statistics,
+            &mut errors,
+        );
+
+        set_string(env, "HASH_PEPPER", &mut self.hashing.pepper);
+        set_string(env, "PSEUDONYM_KEY", &mut self.hashing.pseudonym_key);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+
+    /// Reports every problem at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        require(&self.server.database_url, "server.database_url (DATABASE_URL)", &mut errors);
+        require(&self.server.redis_url, "server.redis_url (REDIS_URL)", &mut errors);
+        require(&self.session.key, "session.key (SESSION_KEY)", &mut errors);
+        require_positive(
+            self.session.duration,
+            "session.duration (SESSION_DURATION)",
+            &mut errors,
+        );
+        require_positive(
+            self.session.refresh_token_duration,
+            "session.refresh_token_duration (REFRESH_TOKEN_DURATION)",
+            &mut errors,
+        );
+
+        if self.number_registration.backend == NumberRegistrationBackend::Twilio {
+            let twilio = &self.number_registration.twilio;
+            require(
+                &twilio.project_id,
+                "number_registration.twilio.project_id (TWILIO_PROJECT_ID)",
+                &mut errors,
+            );
+            require(
+                &twilio.account_id,
+                "number_registration.twilio.account_id (TWILIO_ACCOUNT_ID)",
+                &mut errors,
+            );
+            require(
+                &twilio.auth_token,
+                "number_registration.twilio.auth_token (TWILIO_AUTH_TOKEN)",
+                &mut errors,
+            );
+        }
+
+        // Only the testing backend, which accepts every code, may store unkeyed hashes
+        if self.number_registration.backend != NumberRegistrationBackend::Testing {
+            require(&self.hashing.pepper, "hashing.pepper (HASH_PEPPER)", &mut errors);
+            require(
+                &self.hashing.pseudonym_key,
+                "hashing.pseudonym_key (PSEUDONYM_KEY)",
+                &mut errors,
+            );
+        }
+
+        match self.notification.backend {
+            NotificationBackend::OneSignal => {
+                let one_signal = &self.notification.one_signal;
+                require(
+                    &one_signal.id,
+                    "notification.one_signal.id (ONE_SIGNAL_ID)",
+                    &mut errors,
+                );
+                require(
+                    &one_signal.key,
+                    "notification.one_signal.key (ONE_SIGNAL_KEY)",
+                    &mut errors,
+                );
+            }
+            NotificationBackend::Firebase => {
+                require(
+                    &self.notification.firebase.fcm_token,
+                    "notification.firebase.fcm_token (FCM_TOKEN)",
+                    &mut errors,
+                );
+            }
+            NotificationBackend::Testing => {}
+        }
+
+        let outbox = &self.notification.outbox;
+        require_positive(
+            outbox.poll_interval as i64,
+            "notification.outbox.poll_interval (NOTIFICATION_OUTBOX_POLL_INTERVAL)",
+            &mut errors,
+        );
+        require_positive(
+            outbox.batch_size,
+            "notification.outbox.batch_size (NOTIFICATION_OUTBOX_BATCH_SIZE)",
+            &mut errors,
+        );
+        require_positive(
+            outbox.max_attempts as i64,
+            "notification.outbox.max_attempts (NOTIFICATION_OUTBOX_MAX_ATTEMPTS)",
+            &mut errors,
+        );
+        require_positive(
+            outbox.backoff,
+            "notification.outbox.backoff (NOTIFICATION_OUTBOX_BACKOFF)",
+            &mut errors,
+        );
+        if outbox.max_backoff < outbox.backoff {
+            errors.push(
+                "notification.outbox.max_backoff (NOTIFICATION_OUTBOX_MAX_BACKOFF) must not be smaller than the backoff"
+                    .to_string(),
+            );
+        }
+        require_positive(
+            outbox.lease,
+            "notification.outbox.lease (NOTIFICATION_OUTBOX_LEASE)",
+            &mut errors,
+        );
+        if let Some(retention) = outbox.retention {
+            require_positive(retention, "notification.outbox.retention", &mut errors);
+        }
+
+        for origin in self.cors.origins.iter() {
+            if !origin.starts_with("http://") && !origin.starts_with("https://") {
+                errors.push(format!("cors.origins contains the invalid origin '{}'", origin));
+            }
+        }
+
+        let min_version = Version::new(self.client.min_version.as_str());
+        let recommended_version = Version::new(self.client.recommended_version.as_str());
+
+        if min_version.is_err() {
+            errors.push(format!(
+                "client.min_version (MIN_CLIENT_VERSION) '{}' is not a version",
+                self.client.min_version
+            ));
+        }
+
+        if recommended_version.is_err() {
+            errors.push(format!(
+                "client.recommended_version (RECOMMENDED_CLIENT_VERSION) '{}' is not a version",
+                self.client.recommended_version
+            ));
+        }
+
+        if let (Ok(min_version), Ok(recommended_version)) = (min_version, recommended_version) {
+            if recommended_version < min_version {
+                errors.push(
+                    "client.recommended_version must not be older than client.min_version"
+                        .to_string(),
+                );
+            }
+        }
+
+        if self.limits.push_notification_contacts == 0 {
+            errors.push(
+                "limits.push_notification_contacts (LIMIT_PUSH_NOTIFICATION_CONTACTS) must be positive"
+                    .to_string(),
+            );
+        }
+
+        require_positive(
+            self.limits.rate_limits.window,
+            "limits.rate_limits.window (RATE_LIMIT_WINDOW)",
+            &mut errors,
+        );
+
+        let verification = &self.limits.verification;
+        require_positive(
+            verification.backoff,
+            "limits.verification.backoff (VERIFICATION_BACKOFF)",
+            &mut errors,
+        );
+        if verification.max_backoff < verification.backoff {
+            errors.push(
+                "limits.verification.max_backoff (VERIFICATION_MAX_BACKOFF) must not be smaller than the backoff"
+                    .to_string(),
+            );
+        }
+        require_positive(
+            verification.max_failed_checks as i64,
+            "limits.verification.max_failed_checks (VERIFICATION_MAX_FAILED_CHECKS)",
+            &mut errors,
+        );
+        for proxy in verification.trusted_proxies.iter() {
+            if proxy.parse::<std::net::IpAddr>().is_err() {
+                errors.push(format!(
+                    "limits.verification.trusted_proxies contains the invalid ip '{}'",
+                    proxy
+                ));
+            }
+        }
+
+        require_positive(
+            self.led_expiry.poll_interval as i64,
+            "led_expiry.poll_interval (LED_EXPIRY_POLL_INTERVAL)",
+            &mut errors,
+        );
+
+        let retention = &self.retention;
+        require_positive(
+            retention.poll_interval as i64,
+            "retention.poll_interval (RETENTION_POLL_INTERVAL)",
+            &mut errors,
+        );
+        require_positive(
+            retention.analytics,
+            "retention.analytics (RETENTION_ANALYTICS_DAYS)",
+            &mut errors,
+        );
+        require_positive(
+            retention.usage_statistics,
+            "retention.usage_statistics (RETENTION_USAGE_STATISTICS_DAYS)",
+            &mut errors,
+        );
+        // The analytics rate limits count the analytics of the window
+        if retention.analytics * 24 * 60 * 60 < self.limits.rate_limits.window {
+            errors.push(
+                "retention.analytics (RETENTION_ANALYTICS_DAYS) must cover limits.rate_limits.window"
+                    .to_string(),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            database_url = "postgres://localhost/gehma"
+            redis_url = "redis://localhost"
+
+            [session]
+            key = "secret"
+
+            [hashing]
+            pepper = "pepper"
+            pseudonym_key = "key"
+
+            [number_registration]
+            backend = "twilio"
+
+            [number_registration.twilio]
+            project_id = "project"
+            account_id = "account"
+            auth_token = "token"
+
+            [notification]
+            backend = "firebase"
+
+            [notification.firebase]
+            fcm_token = "fcm"
+
+            [limits.rate_limits]
+            led_updates = 5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(NumberRegistrationBackend::Twilio, config.number_registration.backend);
+        assert_eq!(NotificationBackend::Firebase, config.notification.backend);
+        assert_eq!(5, config.limits.rate_limits.led_updates);
+        assert_eq!(RateLimitConfig::default().xp, config.limits.rate_limits.xp);
+        assert_eq!(3000, config.server.port);
+        assert_eq!(Ok(()), config.validate());
+    }
+
+    #[test]
+    fn test_config_env_overrides() {
+        let mut config = Config::default();
+
+        config
+            .apply_env(&env(&[
+                ("DATABASE_URL", "postgres://localhost/gehma"),
+                ("REDIS_URL", "redis://localhost"),
+                ("SESSION_KEY", "secret"),
+                ("NUMBER_REGISTRATION_BACKEND", "testing"),
+                ("NOTIFICATION_BACKEND", "testing"),
+                ("CORS_ORIGINS", "https://a.xyz, https://b.xyz"),
+                ("LIMIT_PUSH_NOTIFICATION_CONTACTS", "64"),
+                ("NOTIFICATION_OUTBOX_MAX_ATTEMPTS", "3"),
+                ("LED_EXPIRY_POLL_INTERVAL", "30"),
+                ("RETENTION_ANALYTICS_DAYS", "30"),
+                ("HASH_PEPPER", "pepper"),
+                ("PSEUDONYM_KEY", "key"),
+            ]))
+            .unwrap();
+
+        assert_eq!(NotificationBackend::Testing, config.notification.backend);
+        assert_eq!(vec!["https://a.xyz", "https://b.xyz"], config.cors.origins);
+        assert_eq!(64, config.limits.push_notification_contacts);
+        assert_eq!(3, config.notification.outbox.max_attempts);
+        assert_eq!(30, config.led_expiry.poll_interval);
+        assert_eq!(30, config.retention.analytics);
+        assert_eq!("pepper", config.hashing.pepper);
+        assert_eq!("key", config.hashing.pseudonym_key);
+        assert_eq!(Ok(()), config.validate());
+    }
+
+    #[test]
+    fn test_config_invalid_env() {
+        let mut config = Config::default();
+
+        let err = config
+            .apply_env(&env(&[("PORT", "abc"), ("RATE_LIMIT_POLICY", "redis")]))
+            .unwrap_err();
+
+        assert_eq!(2, err.0.len());
+    }
+
+    #[test]
+    fn test_negotiate_client_version() {
+        let client = ClientConfig {
+            min_version: "0.6.0".to_string(),
+            recommended_version: "0.7.0".to_string(),
+        };
+
+        assert_eq!(Ok(VersionSupport::Supported), client.negotiate("0.7.0"));
+        assert_eq!(Ok(VersionSupport::Supported), client.negotiate("1.0.0"));
+        assert_eq!(Ok(VersionSupport::Deprecated), client.negotiate("0.6.0"));
+        // Patches are always supported
+        assert_eq!(Ok(VersionSupport::Deprecated), client.negotiate("0.6.3"));
+        assert_eq!(
+            Err(ServiceError::UpgradeRequired("0.6.0".to_string())),
+            client.negotiate("0.5.9")
+        );
+        assert!(client.negotiate("latest").is_err());
+    }
+
+    #[test]
+    fn test_config_validation_reports_everything() {
+        let mut config = Config::default();
+        config.cors.origins = vec!["gehma.xyz".to_string()];
+        config.client.recommended_version = "0.5".to_string();
+
+        let err = config.validate().unwrap_err();
+
+        assert!(err.0.iter().any(|w| w.contains("DATABASE_URL")));
+        assert!(err.0.iter().any(|w| w.contains("REDIS_URL")));
+        assert!(err.0.iter().any(|w| w.contains("SESSION_KEY")));
+        assert!(err.0.iter().any(|w| w.contains("TWILIO_PROJECT_ID")));
+        assert!(err.0.iter().any(|w| w.contains("HASH_PEPPER")));
+        assert!(err.0.iter().any(|w| w.contains("PSEUDONYM_KEY")));
+        assert!(err.0.iter().any(|w| w.contains("ONE_SIGNAL_ID")));
+        assert!(err.0.iter().any(|w| w.contains("gehma.xyz")));
+        assert!(err.0.iter().any(|w| w.contains("client.recommended_version")));
+    }
+}
@@ -0,0 +1,45 @@
+use actix_web::web;
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use core::errors::ServiceError;
+use core::models::dto::*;
+
+use crate::get_user_by_id;
+use crate::queries::*;
+use crate::services::session::SessionService;
+use log::info;
+
+/// Copy of everything, which is stored about the user `uid`
+pub(crate) fn export_account(
+    uid: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    account_dao: web::Data<Box<dyn PersistentAccountDao>>,
+    current_time: DateTime<Local>,
+) -> Result<AccountExportDto, ServiceError> {
+    info!("controllers/account/export_account");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    account_dao
+        .get_ref()
+        .export(&user?, current_time.naive_local())
+}
+
+/// Deletes the user `uid` with all its data and ends all its sessions
+pub(crate) fn delete_account(
+    uid: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    account_dao: web::Data<Box<dyn PersistentAccountDao>>,
+    session_service: web::Data<SessionService>,
+) -> Result<(), ServiceError> {
+    info!("controllers/account/delete_account");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    account_dao.get_ref().delete(&user?)?;
+
+    session_service.logout_all(&parsed)
+}
@@ -9,6 +9,7 @@ use crate::queries::*;
 use crate::routes::blacklist::PostData;
 use log::debug;
 
+use crate::controllers::user::client_hashes;
 use crate::get_user_by_id;
 
 pub(crate) fn get_entry(
@@ -21,15 +22,27 @@ pub(crate) fn get_entry(
     let user =
         get_user_by_id!(user_dao, &blocker);
 
-    user?;
+    let user = user?;
 
-    let bl = blacklist_dao
+    let bl: Vec<BlacklistDto> = blacklist_dao
         .get_ref()
         .get(blocker)?
         .into_iter()
         .map(|w| w.into())
         .collect();
 
+    let blocked: Vec<_> = bl.iter().map(|w| w.hash_blocked.clone()).collect();
+    let lookup = client_hashes(user_dao.get_ref().as_ref(), &blocked)?;
+
+    let bl = bl
+        .into_iter()
+        .map(|mut w| {
+            w.hash_blocker = w.hash_blocker.for_client(&user.tele_num);
+            w.hash_blocked = lookup.get(&w.hash_blocked).cloned().unwrap_or(w.hash_blocked);
+            w
+        })
+        .collect();
+
     Ok(bl)
 }
 
@@ -47,7 +60,7 @@ pub(crate) fn create_entry(
 
     let contact = user_dao
         .get_ref()
-        .get_by_hash_tele_num_unsafe(&HashedTeleNum(data.hash_blocked.clone()))?;
+        .get_by_hash_tele_num_unsafe(&HashedTeleNum::from_client(&data.hash_blocked))?;
 
     let blocked = PhoneNumber::my_from(&contact.tele_num, &contact.country_code)?;
 
@@ -60,7 +73,7 @@ pub(crate) fn create_entry(
 
 pub(crate) fn delete_entry(
     blocker: &str,
-    data: &PostData,
+    hash_blocked: &HashedTeleNum,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
     blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
 ) -> Result<(), ServiceError> {
@@ -69,8 +82,7 @@ pub(crate) fn delete_entry(
     let user =
         get_user_by_id!(user_dao, &blocker2);
 
-    blacklist_dao.get_ref().delete(
-        &user?.hash_tele_num,
-        &HashedTeleNum(data.hash_blocked.clone()),
-    )
+    blacklist_dao
+        .get_ref()
+        .delete(&user?.hash_tele_num, hash_blocked)
 }
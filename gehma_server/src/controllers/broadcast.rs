@@ -20,7 +20,7 @@ pub(crate) fn get_entries(
 
     let user_dao = user_dao.into_inner();
 
-    let contacts = contact_dao.get_contacts(&user, &user_dao)?;
+    let contacts = contact_dao.get_contacts(&user, None)?;
     let mut lookup = HashMap::new();
 
     for c in contacts {
@@ -34,7 +34,10 @@ pub(crate) fn get_entries(
             let contact = lookup.get(&w.originator_user_id);
 
             if let Some(c) = contact {
-                Some(w.my_from(c))
+                let mut element = w.my_from(c);
+                element.display_user = element.display_user.for_client(&user.tele_num);
+                element.originator_user = element.originator_user.with_client_hash();
+                Some(element)
             }
             else {
                 log::warn!("Filtering contact {:?}", w);
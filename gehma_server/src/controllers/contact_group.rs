@@ -0,0 +1,176 @@
+use actix_web::web;
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::*;
+
+use crate::controllers::user::{client_hashes, to_client_hashes};
+use crate::get_user_by_id;
+use crate::queries::*;
+use log::info;
+
+/// Members, which are users, are shown with the hashes of the client
+fn to_client(
+    mut group: ContactGroupDto,
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+) -> Result<ContactGroupDto, ServiceError> {
+    group.members = to_client_hashes(user_dao.get_ref().as_ref(), group.members)?;
+
+    Ok(group)
+}
+
+pub(crate) fn get_entries(
+    uid: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<Vec<ContactGroupDto>, ServiceError> {
+    info!("controllers/contact_group/get_entries");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    let groups: Vec<ContactGroupDto> = contact_group_dao
+        .get_ref()
+        .get_all(&user?.id)?
+        .into_iter()
+        .map(|(group, members)| group.into(members))
+        .collect();
+
+    let members: Vec<_> = groups.iter().flat_map(|w| w.members.clone()).collect();
+    let lookup = client_hashes(user_dao.get_ref().as_ref(), &members)?;
+
+    let groups = groups
+        .into_iter()
+        .map(|mut group| {
+            group.members = group
+                .members
+                .into_iter()
+                .map(|w| lookup.get(&w).cloned().unwrap_or(w))
+                .collect();
+            group
+        })
+        .collect();
+
+    Ok(groups)
+}
+
+pub(crate) fn create_entry(
+    uid: &str,
+    data: RequestContactGroupDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+    current_time: DateTime<Local>,
+) -> Result<ContactGroupDto, ServiceError> {
+    info!("controllers/contact_group/create_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let (name, members) = validate(&user.id, None, data, &contact_group_dao)?;
+
+    let now = current_time.naive_local();
+
+    let (group, members) = contact_group_dao.get_ref().create(
+        InsertContactGroupDao {
+            owner_id: user.id,
+            name,
+            created_at: now,
+            updated_at: now,
+        },
+        members,
+    )?;
+
+    to_client(group.into(members), &user_dao)
+}
+
+pub(crate) fn get_entry(
+    uid: &str,
+    group_id: i32,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<ContactGroupDto, ServiceError> {
+    info!("controllers/contact_group/get_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    let (group, members) = contact_group_dao.get_ref().get(&user?.id, group_id)?;
+
+    to_client(group.into(members), &user_dao)
+}
+
+pub(crate) fn update_entry(
+    uid: &str,
+    group_id: i32,
+    data: RequestContactGroupDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+    current_time: DateTime<Local>,
+) -> Result<ContactGroupDto, ServiceError> {
+    info!("controllers/contact_group/update_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let (name, members) = validate(&user.id, Some(group_id), data, &contact_group_dao)?;
+
+    let (group, members) = contact_group_dao.get_ref().update(
+        &user.id,
+        group_id,
+        name,
+        members,
+        current_time.naive_local(),
+    )?;
+
+    to_client(group.into(members), &user_dao)
+}
+
+pub(crate) fn delete_entry(
+    uid: &str,
+    group_id: i32,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<(), ServiceError> {
+    info!("controllers/contact_group/delete_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    contact_group_dao.get_ref().delete(&user?.id, group_id)
+}
+
+/// Trimmed name, which is unique among the other groups of `owner_id`, and the distinct members
+fn validate(
+    owner_id: &Uuid,
+    group_id: Option<i32>,
+    data: RequestContactGroupDto,
+    contact_group_dao: &web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<(String, Vec<HashedTeleNum>), ServiceError> {
+    let name = data.name.trim().to_string();
+
+    if name.is_empty() {
+        return Err(ServiceError::BadRequest("Name is empty".to_string()));
+    }
+
+    let existing = contact_group_dao.get_ref().get_all(owner_id)?;
+
+    if existing
+        .iter()
+        .any(|(group, _)| group.name == name && Some(group.id) != group_id)
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "Group {} already exists",
+            name
+        )));
+    }
+
+    let mut members = data.members;
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+    members.dedup();
+
+    Ok((name, members))
+}
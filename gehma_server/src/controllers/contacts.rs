@@ -1,61 +1,213 @@
 use actix_web::web;
 use core::errors::ServiceError;
 use core::models::dto::*;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::queries::*;
+use crate::ratelimits::{RateLimitAction, RateLimitWrapper};
 
 use crate::get_user_by_id;
 
 pub const MAX_ALLOWED_CONTACTS: usize = 10000;
 
+/// Adds the contacts to the contacts of the user
 pub(crate) fn create(
     uid: &str,
     _country_code: &str,
-    phone_numbers: &mut Vec<PayloadUserDto>,
+    phone_numbers: Vec<PayloadUserDto>,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
     blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
     contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
 ) -> Result<(), ServiceError> {
     let parsed = Uuid::parse_str(uid)?;
 
+    ratelimits.enforce(RateLimitAction::ContactUpload, uid, chrono::Local::now())?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    if phone_numbers.len() >= MAX_ALLOWED_CONTACTS {
+        return Err(ServiceError::BadRequest("Too many contacts.".to_string()));
+    }
+
+    let filtered_phone_numbers = filter_contacts(&parsed, phone_numbers, &blacklist_dao)?;
+
+    contact_dao.get_ref().create(
+        &user?,
+        &filtered_phone_numbers,
+        chrono::Local::now().naive_local(),
+    )?;
+
+    Ok(())
+}
+
+/// Replaces all contacts of the user
+pub(crate) fn replace(
+    uid: &str,
+    _country_code: &str,
+    phone_numbers: Vec<PayloadUserDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
+) -> Result<ContactSyncDto, ServiceError> {
+    let parsed = Uuid::parse_str(uid)?;
+
+    ratelimits.enforce(RateLimitAction::ContactUpload, uid, chrono::Local::now())?;
+
     let user = get_user_by_id!(user_dao, &parsed);
 
     if phone_numbers.len() >= MAX_ALLOWED_CONTACTS {
         return Err(ServiceError::BadRequest("Too many contacts.".to_string()));
     }
 
+    let filtered_phone_numbers = filter_contacts(&parsed, phone_numbers, &blacklist_dao)?;
+
+    let cursor = contact_dao.get_ref().replace(
+        &user?,
+        &filtered_phone_numbers,
+        chrono::Local::now().naive_local(),
+    )?;
+
+    Ok(ContactSyncDto { cursor })
+}
+
+/// Applies the changes of the contacts since the last upload
+pub(crate) fn sync(
+    uid: &str,
+    delta: PayloadContactsDeltaDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
+) -> Result<ContactSyncDto, ServiceError> {
+    let parsed = Uuid::parse_str(uid)?;
+
+    ratelimits.enforce(RateLimitAction::ContactUpload, uid, chrono::Local::now())?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    if delta.added.len() + delta.changed.len() + delta.removed.len() >= MAX_ALLOWED_CONTACTS {
+        return Err(ServiceError::BadRequest("Too many contacts.".to_string()));
+    }
+
+    let mut upserts = delta.added;
+    upserts.extend(delta.changed);
+
+    let upserts = filter_contacts(&parsed, upserts, &blacklist_dao)?;
+
+    let cursor = contact_dao.get_ref().sync(
+        &user?,
+        delta.cursor,
+        &upserts,
+        &delta.removed,
+        chrono::Local::now().naive_local(),
+    )?;
+
+    Ok(ContactSyncDto { cursor })
+}
+
+/// Users among the hashes, which the client holds. Every match is returned with the
+/// hash of the client, so the client can map it to its contact.
+pub(crate) fn discover(
+    uid: &str,
+    data: DiscoverContactsDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
+) -> Result<Vec<DiscoveredContactDto>, ServiceError> {
+    let parsed = Uuid::parse_str(uid)?;
+
+    ratelimits.enforce(RateLimitAction::ContactUpload, uid, chrono::Local::now())?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    if data.hashes.len() >= MAX_ALLOWED_CONTACTS {
+        return Err(ServiceError::BadRequest("Too many contacts.".to_string()));
+    }
+
+    let mut requested: HashMap<HashedTeleNum, String> = HashMap::new();
+
+    for hash in data.hashes {
+        requested.insert(HashedTeleNum::from_client(&hash), hash);
+    }
+
+    let hashes: Vec<_> = requested.keys().cloned().collect();
+
+    let discovered = contact_dao
+        .get_ref()
+        .discover(&user, &hashes)?
+        .into_iter()
+        .filter_map(|hash_tele_num| {
+            requested
+                .remove(&hash_tele_num)
+                .map(|hash| DiscoveredContactDto {
+                    hash,
+                    hash_tele_num,
+                })
+        })
+        .collect();
+
+    Ok(discovered)
+}
+
+/// Removes the blocked numbers. The last name of a number wins.
+fn filter_contacts(
+    uid: &Uuid,
+    phone_numbers: Vec<PayloadUserDto>,
+    blacklist_dao: &web::Data<Box<dyn PersistentBlacklistDao>>,
+) -> Result<Vec<PayloadUserDto>, ServiceError> {
     let blacklists: Vec<_> = blacklist_dao
         .get_ref()
-        .get(parsed)?
+        .get(*uid)?
         .into_iter()
         .map(|w| w.hash_blocked)
         .collect();
 
-    let filtered_phone_numbers: Vec<_> = phone_numbers
+    let mut filtered: Vec<PayloadUserDto> = Vec::with_capacity(phone_numbers.len());
+    let mut positions: HashMap<HashedTeleNum, usize> = HashMap::new();
+
+    for number in phone_numbers
         .into_iter()
         .filter(|w| !blacklists.contains(&w.hash_tele_num))
-        .collect();
-
-    let _ = contact_dao
-        .get_ref()
-        .create(&parsed, &user?, &filtered_phone_numbers)?;
+    {
+        match positions.get(&number.hash_tele_num) {
+            Some(&position) => filtered[position].name = number.name,
+            None => {
+                positions.insert(number.hash_tele_num.clone(), filtered.len());
+                filtered.push(number);
+            }
+        }
+    }
 
-    Ok(())
+    Ok(filtered)
 }
 
+/// Returns the ETag of the contacts and the contacts. They are not loaded, when
+/// `is_fresh` accepts the ETag. With `since` the removed contacts are returned too.
 pub(crate) fn get_contacts(
     uid: &str,
+    since: Option<chrono::NaiveDateTime>,
+    is_fresh: impl FnOnce(&str) -> bool,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
     blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
     contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
-) -> Result<Vec<ContactDto>, ServiceError> {
+) -> Result<(String, Option<ContactsDeltaDto>), ServiceError> {
     let parsed = Uuid::parse_str(uid)?;
 
     let user = get_user_by_id!(user_dao, &parsed);
 
     let user = user?;
 
+    let (count, last_change) = contact_dao.get_ref().get_contacts_version(&user, since)?;
+    let etag = contacts_etag(count, last_change);
+
+    if is_fresh(&etag) {
+        return Ok((etag, None));
+    }
+
     //TODO change this to a HashSet for performance
     let blacklists: Vec<_> = blacklist_dao
         .get_ref()
@@ -64,9 +216,7 @@ pub(crate) fn get_contacts(
         .map(|w| w.hash_blocked)
         .collect();
 
-    let mut contacts = contact_dao
-        .get_ref()
-        .get_contacts(&user, &user_dao.into_inner())?;
+    let mut contacts = contact_dao.get_ref().get_contacts(&user, since)?;
 
     contacts
         .iter_mut()
@@ -94,5 +244,22 @@ pub(crate) fn get_contacts(
         }
     }
 
-    Ok(contacts)
+    let contacts = contacts.into_iter().map(|w| w.with_client_hash()).collect();
+
+    let removed = match since {
+        Some(since) => contact_dao.get_ref().get_removed_contacts(&user, since)?,
+        None => Vec::new(),
+    };
+
+    Ok((etag, Some(ContactsDeltaDto { contacts, removed })))
+}
+
+/// Weak validator of the contacts. Every change of them changes the number of the
+/// contacts or their latest change.
+fn contacts_etag(count: i64, last_change: Option<chrono::NaiveDateTime>) -> String {
+    format!(
+        "W/\"{}-{}\"",
+        count,
+        last_change.map(|w| w.timestamp_nanos()).unwrap_or(0)
+    )
 }
@@ -0,0 +1,112 @@
+use actix_web::web;
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::*;
+
+use crate::get_user_by_id;
+use crate::queries::*;
+use log::{debug, info};
+
+/// Platforms of the app
+const PLATFORMS: &[&str] = &["android", "ios", "web"];
+
+/// Platform of the devices, which were registered by the legacy token endpoint
+pub(crate) const UNKNOWN_PLATFORM: &str = "unknown";
+
+pub(crate) fn get_entries(
+    uid: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+) -> Result<Vec<DeviceDto>, ServiceError> {
+    info!("controllers/device/get_entries");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    let devices = device_dao
+        .get_ref()
+        .get_all(&user?.id)?
+        .into_iter()
+        .map(|w| w.into())
+        .collect();
+
+    Ok(devices)
+}
+
+pub(crate) fn create_entry(
+    uid: &str,
+    data: RegisterDeviceDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+    current_time: DateTime<Local>,
+) -> Result<DeviceDto, ServiceError> {
+    info!("controllers/device/create_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let platform = data.platform.to_lowercase();
+
+    if !PLATFORMS.contains(&platform.as_str()) {
+        return Err(ServiceError::BadRequest(format!(
+            "Platform must be one of {}",
+            PLATFORMS.join(", ")
+        )));
+    }
+
+    register(
+        &user.id,
+        data.token,
+        platform,
+        data.app_version,
+        device_dao.get_ref().as_ref(),
+        current_time,
+    )
+}
+
+pub(crate) fn delete_entry(
+    uid: &str,
+    token: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+) -> Result<(), ServiceError> {
+    info!("controllers/device/delete_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    device_dao.get_ref().unregister(&user?.id, token)
+}
+
+/// Registers the token of `user_id` or refreshes `last_seen` of a known device
+pub(crate) fn register(
+    user_id: &Uuid,
+    token: String,
+    platform: String,
+    app_version: String,
+    device_dao: &dyn PersistentDeviceDao,
+    current_time: DateTime<Local>,
+) -> Result<DeviceDto, ServiceError> {
+    if token.trim().is_empty() {
+        return Err(ServiceError::BadRequest("Token is empty".to_string()));
+    }
+
+    debug!("Registering a {} device of {}", platform, user_id);
+
+    let now = current_time.naive_local();
+
+    let device = device_dao.register(InsertDeviceDao {
+        user_id: *user_id,
+        token,
+        platform,
+        app_version,
+        last_seen: now,
+        created_at: now,
+    })?;
+
+    Ok(device.into())
+}
@@ -0,0 +1,350 @@
+use actix_web::web;
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::queries::*;
+use crate::services::push_notifications::{Notification, NotificationKind, NotificationService};
+use log::{debug, info};
+
+use crate::get_user_by_id;
+
+pub(crate) fn get_entries(
+    uid: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+) -> Result<Vec<InvitationDto>, ServiceError> {
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let lookup = get_contact_lookup(&user, &contact_dao)?;
+
+    invitation_dao
+        .get_all(&user)?
+        .into_iter()
+        .map(|(inv, member)| into_dto(&user, inv, member, &lookup, &invitation_dao))
+        .collect()
+}
+
+pub(crate) fn get_entry(
+    uid: &str,
+    inv_id: i32,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+) -> Result<InvitationDto, ServiceError> {
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let lookup = get_contact_lookup(&user, &contact_dao)?;
+
+    let (inv, member) = invitation_dao.get(&user, inv_id)?;
+
+    into_dto(&user, inv, member, &lookup, &invitation_dao)
+}
+
+pub(crate) fn create(
+    uid: &str,
+    data: RequestInvitationCreateDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<InvitationDto, ServiceError> {
+    info!("controllers/invitation/create");
+
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let targets = get_targets(&user, &data.contacts, &data.groups, &contact_group_dao)?;
+
+    let invited = get_invitable_contacts(&user, &targets, &user_dao)?;
+
+    let members = to_contact_daos(&user, &invited);
+
+    let (inv, member) = invitation_dao.create_invitation(&user, &members, data)?;
+
+    send_push_notifications(&inv, invited, notification_service)?;
+
+    let lookup = get_contact_lookup(&user, &contact_dao)?;
+
+    into_dto(&user, inv, member, &lookup, &invitation_dao)
+}
+
+pub(crate) fn update_state(
+    uid: &str,
+    inv_id: i32,
+    data: UpdateInvitationStateDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<(), ServiceError> {
+    info!("controllers/invitation/update_state");
+
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let accept = data.accept;
+
+    invitation_dao.update_invitation(&user, inv_id, data)?;
+
+    if accept {
+        send_accepted_notification(
+            &user,
+            inv_id,
+            &user_dao,
+            &invitation_dao,
+            notification_service,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn add_members(
+    uid: &str,
+    inv_id: i32,
+    data: RequestInvitationAddMembersDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<InvitationDto, ServiceError> {
+    info!("controllers/invitation/add_members");
+
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    if data.contacts.is_empty() {
+        return Err(ServiceError::BadRequest("No contacts given".to_string()));
+    }
+
+    let invited = get_invitable_contacts(&user, &data.contacts, &user_dao)?;
+
+    let members = to_contact_daos(&user, &invited);
+
+    let (inv, member, added) = invitation_dao.add_members_to_invitation(&user, inv_id, &members)?;
+
+    // Members, which were already invited, are not notified again
+    let invited = invited
+        .into_iter()
+        .filter(|w| added.contains(&w.target_hash_tele_num))
+        .collect();
+
+    send_push_notifications(&inv, invited, notification_service)?;
+
+    let lookup = get_contact_lookup(&user, &contact_dao)?;
+
+    into_dto(&user, inv, member, &lookup, &invitation_dao)
+}
+
+/// The contacts and the members of the groups of `user`
+fn get_targets(
+    user: &UserDao,
+    contacts: &[HashedTeleNum],
+    groups: &[i32],
+    contact_group_dao: &web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<Vec<HashedTeleNum>, ServiceError> {
+    let mut targets = contacts.to_vec();
+
+    for group_id in groups {
+        let (_group, members) = contact_group_dao.get_ref().get(&user.id, *group_id)?;
+
+        targets.extend(members.into_iter().map(|w| w.hash_tele_num));
+    }
+
+    if targets.is_empty() {
+        return Err(ServiceError::BadRequest("No contacts given".to_string()));
+    }
+
+    targets.sort_by(|a, b| a.0.cmp(&b.0));
+    targets.dedup();
+
+    Ok(targets)
+}
+
+/// Only mutual contacts, which are not blacklisted, can be invited.
+fn get_invitable_contacts(
+    user: &UserDao,
+    hashes: &[HashedTeleNum],
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+) -> Result<Vec<ContactPushNotificationDao>, ServiceError> {
+    let contacts: Vec<_> = user_dao
+        .get_ref()
+        .get_contacts_for_push_notification(user)?
+        .into_iter()
+        .filter(|w| hashes.contains(&w.target_hash_tele_num))
+        .collect();
+
+    debug!("Invitable contacts {}", contacts.len());
+
+    Ok(contacts)
+}
+
+fn to_contact_daos(user: &UserDao, contacts: &[ContactPushNotificationDao]) -> Vec<ContactDao> {
+    let now = chrono::Local::now().naive_local();
+
+    contacts
+        .iter()
+        .map(|w| ContactDao {
+            from_id: user.id,
+            created_at: now,
+            name: w.name.clone(),
+            target_hash_tele_num: w.target_hash_tele_num.clone(),
+            updated_at: now,
+        })
+        .collect()
+}
+
+fn send_push_notifications(
+    inv: &InvitationDao,
+    contacts: Vec<ContactPushNotificationDao>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<(), ServiceError> {
+    let notifications: Vec<_> = contacts
+        .into_iter()
+        .flat_map(|c| {
+            let name = c.name.clone();
+            let notifications = Notification::for_contact(NotificationKind::InvitationReceived, c);
+
+            if notifications.is_empty() {
+                debug!("Filtering contact {} because no token", name);
+            }
+
+            notifications
+        })
+        .map(|w| w.with_text(inv.edit_text.clone()).with_invitation(inv.id))
+        .collect();
+
+    if notifications.is_empty() {
+        return Ok(());
+    }
+
+    notification_service.into_inner().push(notifications)?;
+
+    Ok(())
+}
+
+/// Notifies the originator, when `user` accepted the invitation `inv_id`.
+/// Only mutual contacts are notified.
+fn send_accepted_notification(
+    user: &UserDao,
+    inv_id: i32,
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+    invitation_dao: &web::Data<Box<dyn PersistentInvitation>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<(), ServiceError> {
+    let (inv, _) = invitation_dao.get(user, inv_id)?;
+
+    if inv.originator_user_id == user.id {
+        return Ok(());
+    }
+
+    let originator = user_dao.get_by_id(&inv.originator_user_id)?;
+
+    let notifications: Vec<_> = user_dao
+        .get_contacts_for_push_notification(user)?
+        .into_iter()
+        .find(|w| w.target_hash_tele_num == originator.hash_tele_num)
+        .map(|w| Notification::for_contact(NotificationKind::InvitationAccepted, w))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|w| w.with_text(inv.edit_text.clone()).with_invitation(inv.id))
+        .collect();
+
+    if notifications.is_empty() {
+        debug!("Originator of the invitation {} is not notified", inv_id);
+        return Ok(());
+    }
+
+    notification_service.into_inner().push(notifications)?;
+
+    Ok(())
+}
+
+fn get_contact_lookup(
+    user: &UserDao,
+    contact_dao: &web::Data<Box<dyn PersistentContactsDao>>,
+) -> Result<HashMap<Uuid, ContactDto>, ServiceError> {
+    let mut lookup = HashMap::new();
+
+    for c in contact_dao.get_contacts(user, None)? {
+        lookup.insert(c.user.id, c.with_client_hash());
+    }
+
+    Ok(lookup)
+}
+
+/// Members are only displayed with their details, when they are a contact of `user`.
+/// The numbers of the other members are not revealed.
+fn wrap_member(
+    member_id: &Uuid,
+    user: &UserDao,
+    lookup: &HashMap<Uuid, ContactDto>,
+) -> WrappedUserDto {
+    if member_id == &user.id {
+        return WrappedUserDto {
+            hash_tele_num: user.hash_tele_num.for_client(&user.tele_num),
+            name: String::new(),
+            user: None,
+        };
+    }
+
+    match lookup.get(member_id) {
+        Some(c) => WrappedUserDto {
+            hash_tele_num: c.user.hash_tele_num.clone(),
+            name: c.name.clone(),
+            user: Some(c.user.clone()),
+        },
+        None => WrappedUserDto {
+            hash_tele_num: HashedTeleNum(String::new()),
+            name: String::new(),
+            user: None,
+        },
+    }
+}
+
+fn into_dto(
+    user: &UserDao,
+    inv: InvitationDao,
+    member: InvitationMemberDao,
+    lookup: &HashMap<Uuid, ContactDto>,
+    invitation_dao: &web::Data<Box<dyn PersistentInvitation>>,
+) -> Result<InvitationDto, ServiceError> {
+    let members = invitation_dao
+        .get_members(inv.id)?
+        .into_iter()
+        .filter(|w| w.user_id != inv.originator_user_id)
+        .map(|w| wrap_member(&w.user_id, user, lookup))
+        .collect();
+
+    let originator = wrap_member(&inv.originator_user_id, user, lookup);
+
+    Ok(InvitationDto {
+        id: inv.id,
+        is_seen: member.is_seen,
+        state: member.state,
+        members,
+        originator,
+        original_text: inv.original_text,
+        edit_text: inv.edit_text,
+        original_time: inv.original_time,
+        edit_time: inv.edit_time,
+        created_at: inv.created_at,
+        updated_at: inv.updated_at,
+    })
+}
@@ -4,3 +4,8 @@ pub(crate) mod user;
 pub(crate) mod number_registration;
 pub(crate) mod profile_pictures;
 pub(crate) mod broadcast;
+pub(crate) mod invitation;
+pub(crate) mod device;
+pub(crate) mod notification_settings;
+pub(crate) mod contact_group;
+pub(crate) mod account;
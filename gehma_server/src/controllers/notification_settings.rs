@@ -0,0 +1,123 @@
+use actix_web::web;
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::*;
+
+use crate::controllers::user::to_client_hashes;
+use crate::get_user_by_id;
+use crate::queries::*;
+use log::info;
+
+pub(crate) fn get_entry(
+    uid: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+    current_time: DateTime<Local>,
+) -> Result<NotificationSettingsDto, ServiceError> {
+    info!("controllers/notification_settings/get_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let settings = settings_dao.get(&user.id)?.unwrap_or_else(|| {
+        NotificationSettingsDao::default_for(user.id, current_time.naive_local())
+    });
+
+    let muted = settings_dao.get_muted(&user.id)?;
+
+    to_client(settings.into(muted), &user_dao)
+}
+
+pub(crate) fn update_entry(
+    uid: &str,
+    data: UpdateNotificationSettingsDto,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+    current_time: DateTime<Local>,
+) -> Result<NotificationSettingsDto, ServiceError> {
+    info!("controllers/notification_settings/update_entry");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    if data.quiet_hours_start.is_some() != data.quiet_hours_end.is_some() {
+        return Err(ServiceError::BadRequest(
+            "Both or none of the quiet hours must be set".to_string(),
+        ));
+    }
+
+    if !settings_dao.is_valid_timezone(&data.timezone)? {
+        return Err(ServiceError::BadRequest(format!(
+            "Unknown timezone {}",
+            data.timezone
+        )));
+    }
+
+    let settings = settings_dao.update(NotificationSettingsDao {
+        user_id: user.id,
+        muted_until: data.muted_until,
+        quiet_hours_start: data.quiet_hours_start,
+        quiet_hours_end: data.quiet_hours_end,
+        timezone: data.timezone,
+        updated_at: current_time.naive_local(),
+    })?;
+
+    let muted = settings_dao.get_muted(&user.id)?;
+
+    to_client(settings.into(muted), &user_dao)
+}
+
+/// Muted contacts are shown with the hashes of the client
+fn to_client(
+    mut settings: NotificationSettingsDto,
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+) -> Result<NotificationSettingsDto, ServiceError> {
+    settings.muted_contacts = to_client_hashes(user_dao.get_ref().as_ref(), settings.muted_contacts)?;
+
+    Ok(settings)
+}
+
+pub(crate) fn mute_contact(
+    uid: &str,
+    hash_muted: HashedTeleNum,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+    current_time: DateTime<Local>,
+) -> Result<(), ServiceError> {
+    info!("controllers/notification_settings/mute_contact");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    if user.hash_tele_num == hash_muted {
+        return Err(ServiceError::BadRequest("Cannot mute yourself".to_string()));
+    }
+
+    let contact = user_dao.get_by_hash_tele_num_unsafe(&hash_muted)?;
+
+    settings_dao.mute(MutedContactDao {
+        user_id: user.id,
+        hash_muted: contact.hash_tele_num,
+        created_at: current_time.naive_local(),
+    })
+}
+
+pub(crate) fn unmute_contact(
+    uid: &str,
+    hash_muted: &HashedTeleNum,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+) -> Result<(), ServiceError> {
+    info!("controllers/notification_settings/unmute_contact");
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+
+    settings_dao.unmute(&user?.id, hash_muted)
+}
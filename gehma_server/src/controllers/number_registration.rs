@@ -1,23 +1,48 @@
 use actix_web::web;
 use core::errors::{InternalServerError, InvalidUserInput, ServiceError};
+use core::models::dao::UserDao;
 use core::models::dto::*;
 use core::models::PhoneNumber;
 
 use log::{debug, error, info, trace};
+use uuid::Uuid;
 
+use crate::get_user_by_id;
 use crate::queries::*;
-use crate::services::number_registration::NumberRegistrationService;
-
-pub const ACCESS_TOKEN_LENGTH: usize = 32;
+use crate::ratelimits::verification::*;
+use crate::ratelimits::{RateLimitAction, RateLimitWrapper};
+use crate::services::number_registration::{
+    NumberRegistrationService, NumberRegistrationServiceTrait,
+};
+use crate::services::push_notifications::{Notification, NotificationKind, NotificationService};
+use crate::services::session::SessionService;
 
 pub(crate) fn request(
     body: RequestCodeDto,
+    ip: Option<String>,
     number_registration_service: web::Data<NumberRegistrationService>,
+    ratelimits: web::Data<RateLimitWrapper>,
+    verification_dao: web::Data<Box<dyn PersistentVerificationDao>>,
+    verification_config: web::Data<VerificationLimitConfig>,
 ) -> Result<(), ServiceError> {
     trace!("controllers/auth/request_code");
 
     let parsed = PhoneNumber::my_from(&body.tele_num, &body.country_code)?;
 
+    ratelimits.enforce(
+        RateLimitAction::CodeRequest,
+        &parsed.to_string(),
+        chrono::Local::now(),
+    )?;
+
+    enforce_code_request(
+        verification_dao.get_ref().as_ref(),
+        &verification_config,
+        &parsed,
+        ip.as_deref(),
+        chrono::Local::now().naive_local(),
+    )?;
+
     number_registration_service.request_code(&parsed)?;
 
     info!("Code was requested");
@@ -29,51 +54,82 @@ pub(crate) fn check_code(
     body: RequestCheckCodeDto,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
     number_registration_service: web::Data<NumberRegistrationService>,
+    verification_dao: web::Data<Box<dyn PersistentVerificationDao>>,
+    verification_config: web::Data<VerificationLimitConfig>,
+    session_service: web::Data<SessionService>,
 ) -> Result<UserDto, ServiceError> {
     trace!("controllers/auth/check_code");
 
     let parsed = PhoneNumber::my_from(&body.tele_num, &body.country_code)?;
 
-    let res = number_registration_service.check_code(&parsed, &body.code)?;
+    let res = verify_code(
+        &parsed,
+        &body.code,
+        number_registration_service.get_ref().as_ref(),
+        verification_dao.get_ref().as_ref(),
+        &verification_config,
+    )?;
 
     // Create a new user when the code was correct
     if res {
         info!("Code is correct");
 
+        // The access token is only shown, when it is created. Other devices of
+        // the user keep using the current token.
+        let token = core::access_token::generate();
+        let hashed_token = core::access_token::hash(&token);
+
         // Check if a user already exists
-        match user_dao.get_ref().get_by_tele_num(&parsed) {
+        let (user, issued) = match user_dao.get_ref().get_by_tele_num(&parsed) {
             Ok(user) => {
-                debug!("User exists");
-                info!("user {:#?}", user);
-                let path = user_dao.get_profile_picture(&user).map_err(|err| {
-                    error!("Profile picture {:?}", err);
-                    err
-                })?;
-                return Ok(user.into(path));
+                if body.new_access_token || core::access_token::is_missing(&user.access_token) {
+                    debug!("User exists, rotating the access token");
+                    user_dao
+                        .get_ref()
+                        .update_access_token(&user.id, &hashed_token)?;
+                    // The sessions of the previous token end with it
+                    session_service.logout_all(&user.id)?;
+                    (user, true)
+                } else {
+                    debug!("User exists, keeping the access token");
+                    (user, false)
+                }
+            }
+            Err(ServiceError::ResourceDoesNotExist) => {
+                debug!("User does not exist. Inserting");
+                let user = user_dao
+                    .get_ref()
+                    .create(
+                        &parsed,
+                        &body.country_code,
+                        &body.client_version,
+                        &hashed_token,
+                    )
+                    .map_err(|e| {
+                        error!("{}", e);
+                        ServiceError::InternalServerError(InternalServerError::DatabaseError(
+                            e.to_string(),
+                        ))
+                    })?;
+                (user, true)
             }
-            Err(ServiceError::ResourceDoesNotExist) => debug!("User does not exist. Inserting"),
             Err(e) => {
                 error!("{:?}", e);
                 return Err(e);
             }
-        }
-
-        // If not then create one
-        debug!("Generate token");
-        let token = core::utils::generate_random_string(ACCESS_TOKEN_LENGTH);
+        };
 
-        debug!("Create new user");
-        let user = user_dao
-            .get_ref()
-            .create(&parsed, &body.country_code, &body.client_version, &token)
-            .map_err(|e| {
-                error!("{}", e);
-                ServiceError::InternalServerError(InternalServerError::DatabaseError(e.to_string()))
-            })?;
+        let path = user_dao.get_profile_picture(&user).map_err(|err| {
+            error!("Profile picture {:?}", err);
+            err
+        })?;
 
-        let path = user_dao.get_profile_picture(&user)?;
+        let mut dto: UserDto = user.into(path).with_client_hash();
+        if issued {
+            dto.access_token = Some(token);
+        }
 
-        Ok(user.into(path))
+        Ok(dto)
     } else {
         info!("Code was wrong");
         Err(ServiceError::InvalidUserInput(
@@ -81,3 +137,118 @@ pub(crate) fn check_code(
         ))
     }
 }
+
+/// Checks the `code` of `tele_num`. Wrong codes count towards the lockout.
+fn verify_code(
+    tele_num: &PhoneNumber,
+    code: &str,
+    number_registration_service: &dyn NumberRegistrationServiceTrait,
+    verification_dao: &dyn PersistentVerificationDao,
+    verification_config: &VerificationLimitConfig,
+) -> Result<bool, ServiceError> {
+    enforce_code_check(
+        verification_dao,
+        verification_config,
+        tele_num,
+        chrono::Local::now().naive_local(),
+    )?;
+
+    let res = number_registration_service.check_code(tele_num, code)?;
+
+    register_code_check(
+        verification_dao,
+        verification_config,
+        tele_num,
+        res,
+        chrono::Local::now().naive_local(),
+    )?;
+
+    Ok(res)
+}
+
+/// The user `uid` and its new number, which must not be registered yet
+pub(crate) fn ensure_tele_num_available(
+    uid: &str,
+    tele_num: &str,
+    country_code: &str,
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+) -> Result<(UserDao, PhoneNumber), ServiceError> {
+    trace!("controllers/auth/ensure_tele_num_available");
+
+    let parsed_id = Uuid::parse_str(uid)?;
+    let user = get_user_by_id!(user_dao, &parsed_id)?;
+
+    let parsed = PhoneNumber::my_from(tele_num, country_code)?;
+
+    if parsed.to_string() == user.tele_num {
+        return Err(ServiceError::BadRequest(
+            "The number is already the user's number".to_string(),
+        ));
+    }
+
+    match user_dao.get_ref().get_by_tele_num(&parsed) {
+        Ok(_) => Err(ServiceError::AlreadyExists),
+        Err(ServiceError::ResourceDoesNotExist) => Ok((user, parsed)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Moves the user `uid` to the new number, when the code, which was sent to it, is correct
+pub(crate) fn change_tele_num(
+    uid: &str,
+    body: ChangeTeleNumDto,
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+    account_dao: web::Data<Box<dyn PersistentAccountDao>>,
+    number_registration_service: web::Data<NumberRegistrationService>,
+    verification_dao: web::Data<Box<dyn PersistentVerificationDao>>,
+    verification_config: web::Data<VerificationLimitConfig>,
+) -> Result<UserDao, ServiceError> {
+    trace!("controllers/auth/change_tele_num");
+
+    let (user, parsed) =
+        ensure_tele_num_available(uid, &body.tele_num, &body.country_code, user_dao)?;
+
+    let res = verify_code(
+        &parsed,
+        &body.code,
+        number_registration_service.get_ref().as_ref(),
+        verification_dao.get_ref().as_ref(),
+        &verification_config,
+    )?;
+
+    if !res {
+        info!("Code was wrong");
+        return Err(ServiceError::InvalidUserInput(
+            InvalidUserInput::InvalidCode,
+        ));
+    }
+
+    info!("Number was changed");
+
+    account_dao
+        .get_ref()
+        .change_tele_num(&user, &parsed, &body.country_code)
+}
+
+/// Tells the contacts, which saved the old number, about the new one
+pub(crate) fn notify_tele_num_changed(
+    user: UserDao,
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<UserDto, ServiceError> {
+    trace!("controllers/auth/notify_tele_num_changed");
+
+    let notifications = user_dao
+        .get_ref()
+        .get_contacts_for_push_notification(&user)?
+        .into_iter()
+        .flat_map(|c| Notification::for_contact(NotificationKind::TeleNumChanged, c))
+        .map(|w| w.with_user(&user.id))
+        .collect();
+
+    notification_service.into_inner().push(notifications)?;
+
+    let path = user_dao.get_ref().get_profile_picture(&user)?;
+
+    Ok(user.into(path).with_client_hash())
+}
@@ -3,39 +3,77 @@ use chrono::{DateTime, Local};
 use core::errors::ServiceError;
 use core::models::dto::*;
 use core::models::PhoneNumber;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::queries::*;
-use crate::services::push_notifications::NotificationService;
+use crate::ratelimits::{RateLimitAction, RateLimitWrapper};
+use crate::services::push_notifications::{Notification, NotificationKind, NotificationService};
 use crate::services::session::*;
 use log::{debug, error, info, trace};
 
 //use crate::routes::user::{ResponseContact, UpdateTokenPayload, UpdateUser};
+use crate::controllers::device;
 use crate::get_user_by_id;
 use crate::routes::user::UpdateTokenPayload;
 
+/// Minutes, which the led can be switched on with an expiry
+const MAX_LED_EXPIRY: i64 = 24 * 60;
+
+/// Maps the stored hashes of registered users to the hashes of the clients. Other hashes
+/// are missing, because their numbers are unknown.
+pub(crate) fn client_hashes(
+    user_dao: &dyn PersistentUserDao,
+    hashes: &[HashedTeleNum],
+) -> Result<HashMap<HashedTeleNum, HashedTeleNum>, ServiceError> {
+    if !core::hashing::is_peppered() || hashes.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let lookup = user_dao
+        .get_tele_nums(hashes)?
+        .into_iter()
+        .map(|(hash, tele_num)| {
+            let client = hash.for_client(&tele_num);
+            (hash, client)
+        })
+        .collect();
+
+    Ok(lookup)
+}
+
+/// Replaces the stored hashes of registered users with the hashes of the clients
+pub(crate) fn to_client_hashes(
+    user_dao: &dyn PersistentUserDao,
+    hashes: Vec<HashedTeleNum>,
+) -> Result<Vec<HashedTeleNum>, ServiceError> {
+    let lookup = client_hashes(user_dao, &hashes)?;
+
+    Ok(hashes
+        .into_iter()
+        .map(|w| lookup.get(&w).cloned().unwrap_or(w))
+        .collect())
+}
+
 pub(crate) fn user_signin(
     request: HttpRequest,
     body: PostUserDto,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
     current_time: DateTime<Local>,
     notification_service: web::Data<NotificationService>,
     session_service: web::Data<SessionService>,
 ) -> Result<UserDto, ServiceError> {
     info!("fn user_signin");
 
-    let access_token = request.headers().get("Authorization").ok_or_else(|| {
-        error!("{}", "Missing access token in Authorization header");
-        ServiceError::BadRequest("Missing access token in Authorization header".to_string())
-    })?;
-
-    if !crate::ALLOWED_CLIENT_VERSIONS.contains(&body.client_version.as_str()) {
-        error!("Version mismatch. Server does not support client version");
-        return Err(ServiceError::BadRequest(format!(
-            "Version mismatch. The supported versions are {:?}",
-            crate::ALLOWED_CLIENT_VERSIONS
-        )));
-    }
+    let access_token = request
+        .headers()
+        .get("Authorization")
+        .and_then(|w| w.to_str().ok())
+        .ok_or_else(|| {
+            error!("{}", "Missing access token in Authorization header");
+            ServiceError::BadRequest("Missing access token in Authorization header".to_string())
+        })?;
 
     let country_code = &body.country_code;
     let tele = PhoneNumber::my_from(&body.tele_num, country_code)?;
@@ -43,26 +81,26 @@ pub(crate) fn user_signin(
     let user = user_dao.get_ref().get_by_tele_num(&tele)?;
 
     // Check if authorized
-    if &user.access_token != access_token {
-        eprintln!(
-            "Access token do not match ({:?} != {:?})",
-            user.access_token, access_token
-        );
+    if !core::access_token::verify(&user.access_token, access_token) {
+        info!("Access token of {} does not match", user.id);
         return Err(ServiceError::Unauthorized);
     }
 
-    // Update client version
+    // Update client version. This is not limited and gains no experience.
     if user.client_version != body.client_version {
-        update_user(
-            &user.id.to_string(),
+        apply_user_update(
+            &user.id,
             &UpdateUserDto {
                 description: user.description.clone(),
                 led: user.led,
                 client_version: body.client_version.clone(),
+                expires_in: None,
+                audience: user.led_audience,
             },
             &user_dao,
             current_time,
             notification_service,
+            true,
         )?;
     }
 
@@ -71,18 +109,100 @@ pub(crate) fn user_signin(
 
     user_dao.get_ref().create_usage_statistics_for_user(&user)?;
 
+    // Devices of signed in users keep receiving push notifications
+    device_dao
+        .get_ref()
+        .touch(&user.id, current_time.naive_local())?;
+
     // Set a new session token
-    let (session_token, _) = session_service.new_session(user.id);
+    let (session_token, claims) = session_service.new_session(user.id)?;
 
     let path = user_dao.get_profile_picture(&user)?;
 
-    let mut dto: UserDto = user.into(path);
+    let refresh_token = session_service.new_refresh_token(&claims)?;
+
+    let mut dto: UserDto = user.into(path).with_client_hash();
 
     dto.session_token = Some(session_token);
+    dto.refresh_token = refresh_token;
 
     Ok(dto)
 }
 
+/// Exchanges a refresh token for a new session
+pub(crate) fn refresh_session(
+    body: RequestRefreshSessionDto,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+    session_service: web::Data<SessionService>,
+    current_time: DateTime<Local>,
+) -> Result<SessionDto, ServiceError> {
+    info!("fn refresh_session");
+
+    let (session_token, claims, refresh_token) = session_service.refresh(body.refresh_token)?;
+
+    device_dao
+        .get_ref()
+        .touch(&claims.user_id()?, current_time.naive_local())?;
+
+    Ok(SessionDto {
+        session_token,
+        refresh_token,
+    })
+}
+
+/// Revokes the session, which was used for this request
+pub(crate) fn logout_session(
+    request: HttpRequest,
+    session_service: web::Data<SessionService>,
+) -> Result<(), ServiceError> {
+    info!("fn logout_session");
+
+    let token = request
+        .headers()
+        .get("Authorization")
+        .and_then(|w| w.to_str().ok())
+        .ok_or_else(|| ServiceError::Unauthorized)?;
+
+    session_service.logout(token.to_string())
+}
+
+/// Revokes all sessions of the user `uid`
+pub(crate) fn logout_all_sessions(
+    uid: &str,
+    session_service: web::Data<SessionService>,
+) -> Result<(), ServiceError> {
+    info!("fn logout_all_sessions");
+
+    let parsed = Uuid::parse_str(uid)?;
+
+    session_service.logout_all(&parsed)
+}
+
+/// Replaces the access token of the user `uid`. The new token is only returned now.
+/// All sessions of the previous token are revoked.
+pub(crate) fn rotate_access_token(
+    uid: &str,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    session_service: web::Data<SessionService>,
+) -> Result<AccessTokenDto, ServiceError> {
+    info!("fn rotate_access_token");
+
+    let parsed = Uuid::parse_str(uid)?;
+
+    let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
+
+    let access_token = core::access_token::generate();
+
+    user_dao
+        .get_ref()
+        .update_access_token(&user.id, &core::access_token::hash(&access_token))?;
+
+    session_service.logout_all(&user.id)?;
+
+    Ok(AccessTokenDto { access_token })
+}
+
 pub(crate) fn get_entry(
     uid: &str,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
@@ -94,24 +214,39 @@ pub(crate) fn get_entry(
 
     let path = user_dao.into_inner().get_profile_picture(&user)?;
 
-    let mut user: UserDto = user.into(path);
-
-    // Do not display access token on normal GET
-    // It will be only sent on `request_code`
-    user.access_token = None;
-
-    Ok(user)
+    Ok(user.into(path).with_client_hash())
 }
 
+/// Token of the released clients. It replaces the previous token as a device with an
+/// unknown platform.
 pub(crate) fn update_token_handler(
     uid: String,
     payload: UpdateTokenPayload,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+    current_time: DateTime<Local>,
 ) -> Result<(), ServiceError> {
     let parsed = Uuid::parse_str(&uid)?;
     let user = get_user_by_id!(user_dao, &parsed);
+    let user = user?;
 
-    user?;
+    match user.firebase_token {
+        Some(ref previous) if !previous.is_empty() && *previous != payload.token => {
+            device_dao.get_ref().remove_token(previous)?;
+        }
+        _ => {}
+    }
+
+    if !payload.token.is_empty() {
+        device::register(
+            &user.id,
+            payload.token.clone(),
+            device::UNKNOWN_PLATFORM.to_string(),
+            user.client_version.clone(),
+            device_dao.get_ref().as_ref(),
+            current_time,
+        )?;
+    }
 
     user_dao.get_ref().update_token(&parsed, payload.token)
 }
@@ -122,35 +257,78 @@ pub(crate) fn update_user(
     user_dao: &web::Data<Box<dyn PersistentUserDao>>,
     current_time: DateTime<Local>,
     notification_service: web::Data<NotificationService>,
+    ratelimits: &web::Data<RateLimitWrapper>,
 ) -> Result<UserDto, ::core::errors::ServiceError> {
     info!("controllers/user/update_user_without_auth");
     let parsed = Uuid::parse_str(uid)?;
-    let _user = get_user_by_id!(user_dao, &parsed);
+
+    if let Some(expires_in) = update_user.expires_in {
+        if expires_in <= 0 || expires_in > MAX_LED_EXPIRY {
+            return Err(ServiceError::BadRequest(format!(
+                "expires_in must be between 1 and {} minutes",
+                MAX_LED_EXPIRY
+            )));
+        }
+    }
+
+    ratelimits.enforce(RateLimitAction::LedUpdate, uid, current_time)?;
+
+    let xp_limit = ratelimits.is_reached(RateLimitAction::Xp, uid, current_time)?;
+
+    apply_user_update(
+        &parsed,
+        update_user,
+        user_dao,
+        current_time,
+        notification_service,
+        xp_limit,
+    )
+}
+
+fn apply_user_update(
+    parsed: &Uuid,
+    update_user: &UpdateUserDto,
+    user_dao: &web::Data<Box<dyn PersistentUserDao>>,
+    current_time: DateTime<Local>,
+    notification_service: web::Data<NotificationService>,
+    xp_limit: bool,
+) -> Result<UserDto, ServiceError> {
+    let _user = get_user_by_id!(user_dao, parsed);
 
     _user?;
 
     let (user, contacts) = user_dao
         .get_ref()
-        .update_user(&parsed, update_user, current_time)?;
+        .update_user(parsed, update_user, current_time, xp_limit)?;
 
     debug!("Contacts sending push_notifications {}", contacts.len());
 
-    let contacts = contacts
+    // A description is shown as broadcast
+    let kind = if user.description.is_empty() {
+        NotificationKind::LedOn
+    } else {
+        NotificationKind::NewBroadcast
+    };
+
+    let notifications = contacts
         .into_iter()
-        .filter_map(|c| {
-            if c.firebase_token.is_some() && c.firebase_token != Some("".to_string()) {
-                Some((c.name, c.firebase_token.unwrap()))
-            } else {
-                debug!("Filtering contact {} because no token", c.name);
-                None
+        .flat_map(|c| {
+            let name = c.name.clone();
+            let notifications = Notification::for_contact(kind, c);
+
+            if notifications.is_empty() {
+                debug!("Filtering contact {} because no token", name);
             }
+
+            notifications
         })
+        .map(|w| w.with_text(user.description.clone()).with_user(&user.id))
         .collect();
 
     if update_user.led && user.led {
-        info!("Contacts {:?}", contacts);
+        info!("Notifications {:?}", notifications);
         // Sending push notification
-        notification_service.into_inner().push(contacts)?;
+        notification_service.into_inner().push(notifications)?;
     }
 
     // Log the user update change
@@ -158,7 +336,7 @@ pub(crate) fn update_user(
 
     let path = user_dao.get_profile_picture(&user)?;
 
-    Ok(user.into(path))
+    Ok(user.into(path).with_client_hash())
 }
 
 pub(crate) fn change_profile_picture(
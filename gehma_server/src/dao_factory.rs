@@ -3,16 +3,23 @@ use crate::{Pool};
 use crate::queries::*;
 
 //pub struct DaoFactory(Pool, RedisPool);
-pub struct DaoFactory(Pool);
+pub struct DaoFactory(Pool, usize);
 
 impl DaoFactory {
     pub fn new(pool: Pool) -> Self {
-        Self(pool)
+        Self(pool, crate::LIMIT_PUSH_NOTIFICATION_CONTACTS)
+    }
+
+    /// Maximum number of contacts, which receive a push notification of an update
+    pub fn with_push_notification_limit(mut self, limit: usize) -> Self {
+        self.1 = limit;
+        self
     }
 
     pub fn get_user_dao(&self) -> Box<dyn PersistentUserDao> {
         Box::new(PgUserDao {
             pool: self.0.clone(),
+            push_notification_limit: self.1,
         })
     }
 
@@ -40,6 +47,48 @@ impl DaoFactory {
         })
     }
 
+    pub fn get_verification_dao(&self) -> Box<dyn PersistentVerificationDao> {
+        Box::new(PgVerificationDao {
+            pool: self.0.clone(),
+        })
+    }
+
+    pub fn get_outbox_dao(&self) -> Box<dyn PersistentOutboxDao> {
+        Box::new(PgOutboxDao {
+            pool: self.0.clone(),
+        })
+    }
+
+    pub fn get_device_dao(&self) -> Box<dyn PersistentDeviceDao> {
+        Box::new(PgDeviceDao {
+            pool: self.0.clone(),
+        })
+    }
+
+    pub fn get_notification_settings_dao(&self) -> Box<dyn PersistentNotificationSettingsDao> {
+        Box::new(PgNotificationSettingsDao {
+            pool: self.0.clone(),
+        })
+    }
+
+    pub fn get_contact_group_dao(&self) -> Box<dyn PersistentContactGroupDao> {
+        Box::new(PgContactGroupDao {
+            pool: self.0.clone(),
+        })
+    }
+
+    pub fn get_account_dao(&self) -> Box<dyn PersistentAccountDao> {
+        Box::new(PgAccountDao {
+            pool: self.0.clone(),
+        })
+    }
+
+    pub fn get_retention_dao(&self) -> Box<dyn PersistentRetentionDao> {
+        Box::new(PgRetentionDao {
+            pool: self.0.clone(),
+        })
+    }
+
     /*
     pub fn get_session_dao(&self) -> Box<dyn PersistentSessionDao> {
         Box::new(RedisSessionDao {
@@ -7,11 +7,12 @@ use actix_files::NamedFile;
 use actix_web::http::header;
 use actix_web::{middleware as actix_middleware, web, App, HttpResponse, HttpServer};
 use core::errors::{InternalServerError, ServiceError};
-use log::error;
+use log::{error, info, warn};
 use std::path::PathBuf;
 
 use crate::utils::*;
 
+pub(crate) mod config;
 pub(crate) mod controllers;
 pub(crate) mod queries;
 pub(crate) mod ratelimits; //move to services
@@ -19,6 +20,8 @@ pub(crate) mod routes;
 pub(crate) mod services;
 
 pub(crate) mod dao_factory;
+pub(crate) mod metrics;
+pub(crate) mod openapi;
 
 mod middleware;
 
@@ -32,12 +35,18 @@ use dao_factory::*;
 #[cfg(test)]
 mod tests;
 
-pub const ALLOWED_CLIENT_VERSIONS: &[&str] = &["0.6.0"];
+// Defaults of the configuration
+pub const MIN_CLIENT_VERSION: &str = "0.6.0";
 pub const LIMIT_PUSH_NOTIFICATION_CONTACTS: usize = 128;
 pub const ALLOWED_PROFILE_PICTURE_SIZE: usize = 10_000; //in Kilobytes
 
+use crate::config::Config;
 use crate::database::*;
+use crate::queries::RedisSessionDao;
 use crate::redis::*;
+use crate::services::push_notifications::outbox::OutboxNotificationService;
+use crate::services::push_notifications::NotificationService;
+use crate::services::session::SessionStore;
 
 #[actix_rt::main]
 pub(crate) async fn main() -> std::io::Result<()> {
@@ -45,46 +54,89 @@ pub(crate) async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "debug,actix_web=info,actix_server=info");
     env_logger::init();
 
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL expected");
-    //let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL expected");
+    let config = Config::load().unwrap_or_else(|err| {
+        error!("Invalid configuration:\n{}", err);
+        std::process::exit(1);
+    });
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = std::env::var("BINDING_ADDR").unwrap_or_else(|_| "localhost".to_string());
+    info!(
+        "Number registration {:?}, notifications {:?}",
+        config.number_registration.backend, config.notification.backend
+    );
 
-    let pool_pg = connect_pg(database_url);
-    //let pool_redis = connect_redis(redis_url);
+    // Only the testing backend runs without the keys, see `Config::validate`
+    if config.hashing.pepper.is_empty() {
+        warn!("No pepper is set, the hashes of the phone numbers are stored unkeyed");
+    }
+    core::hashing::set_pepper(Some(&config.hashing.pepper));
+
+    if config.hashing.pseudonym_key.is_empty() {
+        warn!("No pseudonym key is set, the statistics store the ids of the users");
+    }
+    core::pseudonym::set_key(Some(&config.hashing.pseudonym_key));
+
+    let pool_pg = connect_pg(config.server.database_url.clone());
+    let pool_redis = connect_redis(config.server.redis_url.clone());
+
+    // Shared between all workers and instances
+    let session_store: SessionStore = std::sync::Arc::new(RedisSessionDao {
+        redis_pool: pool_redis,
+    });
+    let ratelimits = get_ratelimits(pool_pg.clone(), &config.limits);
+
+    spawn_notification_worker(pool_pg.clone(), config.notification.clone());
+    spawn_led_expiry_worker(pool_pg.clone(), config.led_expiry.clone());
+    spawn_retention_worker(pool_pg.clone(), config.retention.clone());
+
+    let bind = format!("{}:{}", config.server.addr, config.server.port);
 
     let server = HttpServer::new(move || {
-        let dao_factory = DaoFactory::new(pool_pg.clone());
+        let dao_factory = DaoFactory::new(pool_pg.clone())
+            .with_push_notification_limit(config.limits.push_notification_contacts);
+
+        let cors = config
+            .cors
+            .origins
+            .iter()
+            .fold(Cors::new(), |cors, origin| cors.allowed_origin(origin));
 
         App::new()
             .data(pool_pg.clone())
-            //.data(pool_redis.clone())
-            //.data(get_auth())
-            .data(set_testing_auth())
-            .data(get_onesignal_notification_service())
-            .data(get_ratelimits())
-            .data(get_session_service())
+            .data(get_auth(&config.number_registration))
+            // Delivered by the notification worker
+            .data(Box::new(OutboxNotificationService::new(
+                dao_factory.get_outbox_dao(),
+            )) as NotificationService)
+            .data(ratelimits.clone())
+            .data(config.limits.verification.clone())
+            .data(config.client.clone())
+            .data(get_session_service_with_store(
+                &config.session,
+                session_store.clone(),
+            ))
             .data(dao_factory.get_user_dao())
             .data(dao_factory.get_contacts_dao())
             .data(dao_factory.get_blacklist_dao())
             .data(dao_factory.get_profile_pictures_dao())
             .data(dao_factory.get_invitation_dao())
+            .data(dao_factory.get_verification_dao())
+            .data(dao_factory.get_device_dao())
+            .data(dao_factory.get_notification_settings_dao())
+            .data(dao_factory.get_contact_group_dao())
+            .data(dao_factory.get_account_dao())
             .wrap(
-                Cors::new()
-                    .allowed_origin("http://localhost:3000")
-                    .allowed_origin("https://gehma.xyz")
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+                cors.allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
                     .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
                     .allowed_header(header::CONTENT_TYPE)
-                    .max_age(3600)
+                    .max_age(config.cors.max_age)
                     .finish(),
             )
             .wrap(actix_middleware::Logger::default())
             .data(web::JsonConfig::default().limit(4048 * 1024))
-            .wrap(actix_middleware::Compress::default())
             .wrap(middleware::auth::Authentication)
-            //.wrap(middleware::auth::Authentication)
+            .wrap(middleware::request_id::RequestIdentifier)
+            // Outermost, because error bodies are rewritten with the request id
+            .wrap(actix_middleware::Compress::default())
             .service(
                 web::scope("/static")
                     .service(web::resource("/{filename:.*}").route(web::get().to(load_file))),
@@ -97,53 +149,14 @@ pub(crate) async fn main() -> std::io::Result<()> {
                                 web::resource("/{filename:.*}").route(web::get().to(load_file)),
                             ),
                     )
-                    .service(web::resource("/signin").route(web::post().to(routes::user::signin)))
-                    .service(
-                        web::resource("/user/{uid}/token")
-                            .route(web::put().to(routes::user::update_token)),
-                    )
-                    .service(
-                        web::resource("/user/{uid}/blacklist")
-                            .route(web::get().to(routes::blacklist::get_all))
-                            .route(web::post().to(routes::blacklist::add))
-                            .route(web::put().to(routes::blacklist::delete)), //deletes
-                    )
-                    .service(
-                        web::resource("/user/{uid}/profile")
-                            .route(web::get().to(routes::profile_pictures::get_all))
-                            .route(web::post().to(routes::user::upload_profile_picture)),
-                    )
-                    .service(
-                        web::resource("/user/{uid}")
-                            .route(web::get().to(routes::user::get))
-                            .route(web::put().to(routes::user::update)),
-                    )
-                    .service(
-                        web::resource("/contacts/{uid}/{country_code}")
-                            .route(web::post().to(routes::contacts::create)),
-                    )
-                    .service(
-                        web::resource("/contacts/{uid}")
-                            .route(web::get().to(routes::contacts::get_contacts)),
-                    )
-                    .service(
-                        web::resource("/auth/request_code")
-                            .route(web::post().to(routes::number_registration::request_code)),
-                    )
-                    .service(
-                        web::resource("/auth/check")
-                            .route(web::post().to(routes::number_registration::check)),
-                    )
-                    .service(
-                        web::resource("/broadcasts/{uid}")
-                            .route(web::get().to(routes::broadcast::get_all)),
-                    )
+                    .service(web::scope("/v1").configure(routes::v1))
+                    .configure(routes::legacy)
                     .default_service(web::route().to(HttpResponse::NotFound)),
             )
     })
     .keep_alive(None);
 
-    let listener = server.bind(format!("{}:{}", addr, port));
+    let listener = server.bind(bind);
 
     listener.expect("Cannot bind").run().await
 }
@@ -0,0 +1,46 @@
+//! Counters of the process. They are logged by the background workers.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub struct Counter {
+    name: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the new total
+    pub fn add(&self, value: u64) -> u64 {
+        self.value.fetch_add(value, Ordering::Relaxed) + value
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Push tokens, which were cleared, because the provider rejected them
+pub static PRUNED_PUSH_TOKENS: Counter = Counter::new("pruned_push_tokens");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter() {
+        let counter = Counter::new("test");
+
+        assert_eq!(2, counter.add(2));
+        assert_eq!(5, counter.add(3));
+        assert_eq!(5, counter.get());
+    }
+}
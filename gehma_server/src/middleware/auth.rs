@@ -1,15 +1,6 @@
 use crate::services::session::SessionService;
-use chrono::prelude::*;
-use core::errors::ServiceError;
 use log::trace;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    /// time expires
-    exp: DateTime<Utc>,
-    /// time initialised
-    iat: DateTime<Utc>,
-}
+use uuid::Uuid;
 
 use actix_service::{Service, Transform};
 use actix_web::{
@@ -26,6 +17,42 @@ use std::{
     task::{Context, Poll},
 };
 
+/// Removes the version of `/api/v{n}/...`
+fn strip_version(path: &str) -> String {
+    let mut segments = path.splitn(4, '/');
+
+    match (segments.next(), segments.next(), segments.next(), segments.next()) {
+        (Some(""), Some("api"), Some(version), rest) if is_version(version) => {
+            format!("/api/{}", rest.unwrap_or(""))
+        }
+        _ => path.to_string(),
+    }
+}
+
+fn is_version(segment: &str) -> bool {
+    segment.len() > 1
+        && segment.starts_with('v')
+        && segment[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// Routes, which do not need a session
+fn is_public(path: &str) -> bool {
+    path.starts_with("/api/signin")
+        || path.starts_with("/api/auth")
+        || path.starts_with("/api/static")
+        || path == "/api/openapi.json"
+}
+
+/// Returns the `{uid}` segment of `/api/{resource}/{uid}/...`
+fn get_path_uid(path: &str) -> Option<&str> {
+    let mut segments = path.split('/').skip(1);
+
+    match (segments.next(), segments.next(), segments.next()) {
+        (Some("api"), Some(_), Some(uid)) if !uid.is_empty() => Some(uid),
+        _ => None,
+    }
+}
+
 pub struct Authentication;
 
 impl<S, B> Transform<S> for Authentication
@@ -71,10 +98,9 @@ where
 
         trace!("path {}", req.path());
 
-        if req.path().starts_with("/api/signin")
-            || req.path().starts_with("/api/auth")
-            || req.path().starts_with("/api/static")
-        {
+        let path = strip_version(req.path());
+
+        if is_public(&path) {
             debug!("Skipping authentication");
             let fut = self.service.call(req);
             return Box::pin(async move {
@@ -86,33 +112,100 @@ where
         let token = req
             .headers()
             .get("AUTHORIZATION")
-            .map(|value| value.to_str().ok())
-            .ok_or_else(|| {
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        let token = match token {
+            Some(token) => token,
+            None => {
                 warn!("No AUTHORIZATION header ({})", req.path());
-                ServiceError::Unauthorized
-            })
-            .unwrap()
-            .unwrap();
+                return Box::pin(async move {
+                    Ok(req.into_response(
+                        HttpResponse::Unauthorized()
+                            .json("Missing session token")
+                            .into_body(),
+                    ))
+                });
+            }
+        };
 
         trace!("Validate session token");
-        let authenticate_pass = session_service.validate(token.to_string()).unwrap();
+        let authenticated_id = session_service
+            .decode(token)
+            .and_then(|claims| claims.user_id());
 
-        if authenticate_pass {
-            debug!("Authentication ok");
-            let fut = self.service.call(req);
-            Box::pin(async move {
-                let res = fut.await?;
-                Ok(res)
-            })
-        } else {
-            info!("Authentication failed");
-            Box::pin(async move {
-                Ok(req.into_response(
-                    HttpResponse::Unauthorized()
-                        .json("Session expired")
-                        .into_body(),
-                ))
-            })
+        let authenticated_id = match authenticated_id {
+            Ok(id) => id,
+            Err(_) => {
+                info!("Authentication failed");
+                return Box::pin(async move {
+                    Ok(req.into_response(
+                        HttpResponse::Unauthorized()
+                            .json("Session expired")
+                            .into_body(),
+                    ))
+                });
+            }
+        };
+
+        // The session must belong to the user of the requested resource.
+        // A segment, which is not a uuid, is rejected as well.
+        if let Some(uid) = get_path_uid(&path) {
+            if Uuid::parse_str(uid).ok() != Some(authenticated_id) {
+                warn!(
+                    "Session of {} cannot access {}",
+                    authenticated_id,
+                    req.path()
+                );
+                return Box::pin(async move {
+                    Ok(req.into_response(
+                        HttpResponse::Unauthorized()
+                            .json("Unauthorized")
+                            .into_body(),
+                    ))
+                });
+            }
         }
+
+        debug!("Authentication ok");
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_path_uid() {
+        assert_eq!(Some("abc"), get_path_uid("/api/user/abc"));
+        assert_eq!(Some("abc"), get_path_uid("/api/user/abc/blacklist"));
+        assert_eq!(Some("abc"), get_path_uid("/api/contacts/abc/AT"));
+        assert_eq!(None, get_path_uid("/api/signin"));
+        assert_eq!(None, get_path_uid("/api/user/"));
+        assert_eq!(None, get_path_uid("/static/abc/def"));
+    }
+
+    #[test]
+    fn test_strip_version() {
+        assert_eq!("/api/user/abc", strip_version("/api/v1/user/abc"));
+        assert_eq!("/api/signin", strip_version("/api/v2/signin"));
+        assert_eq!("/api/", strip_version("/api/v1"));
+        assert_eq!("/api/user/abc", strip_version("/api/user/abc"));
+        assert_eq!("/api/vx/abc", strip_version("/api/vx/abc"));
+        assert_eq!("/static/v1/abc", strip_version("/static/v1/abc"));
+    }
+
+    #[test]
+    fn test_is_public() {
+        assert!(is_public(&strip_version("/api/v1/auth/check")));
+        assert!(is_public(&strip_version("/api/v1/openapi.json")));
+        assert!(is_public("/api/signin"));
+        assert!(!is_public(&strip_version("/api/v1/user/abc")));
     }
 }
@@ -1,6 +1,7 @@
 //mod read_request_body;
 //mod read_response_body;
 pub(crate) mod auth;
+pub(crate) mod request_id;
 
 //pub use read_request_body::Logging as RequestBodyLogging;
 //pub use read_response_body::Logging as ResponseBodyLogging;
@@ -0,0 +1,184 @@
+use core::errors::{ErrorResponse, ServiceError};
+use log::{debug, warn};
+use uuid::Uuid;
+
+use actix_service::{Service, Transform};
+use actix_web::{
+    dev::{Body, Payload, ResponseBody, ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use futures::{
+    future::{ok, Ready},
+    Future,
+};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longer ids of the client are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// Id of the request. It is taken from the `X-Request-Id` header or generated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+impl FromRequest for RequestId {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        match req.extensions().get::<RequestId>() {
+            Some(id) => ok(id.clone()),
+            None => ok(RequestId(Uuid::new_v4().to_string())),
+        }
+    }
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Envelope of errors, which were not raised as `ServiceError`
+fn envelope_from_status<B>(res: &ServiceResponse<B>, request_id: &str) -> ErrorResponse {
+    let status = res.status();
+
+    let code = match status.as_u16() {
+        400 => "bad_request",
+        401 => "unauthorized",
+        404 => "not_found",
+        405 => "method_not_allowed",
+        413 => "payload_too_large",
+        429 => "rate_limit",
+        500..=599 => "internal_error",
+        _ => "error",
+    };
+
+    let message = match res.response().error() {
+        Some(err) if !status.is_server_error() => err.to_string(),
+        _ => status
+            .canonical_reason()
+            .unwrap_or("Unknown error")
+            .to_string(),
+    };
+
+    ErrorResponse {
+        code: code.to_string(),
+        message,
+        details: None,
+        request_id: Some(request_id.to_string()),
+    }
+}
+
+/// Tags every request with an id. It is returned in the `X-Request-Id` header and
+/// in the body of error responses, so errors of the clients can be found in the logs.
+pub struct RequestIdentifier;
+
+impl<S, B> Transform<S> for RequestIdentifier
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdentifierMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdentifierMiddleware { service })
+    }
+}
+
+pub struct RequestIdentifierMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for RequestIdentifierMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| is_valid(value))
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        debug!("Request {} {}", request_id, req.path());
+
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+
+            let mut res = if res.status().is_client_error() || res.status().is_server_error() {
+                let envelope = match res.response().error() {
+                    Some(err) => match err.as_error::<ServiceError>() {
+                        Some(service_err) => service_err.to_response(Some(request_id.clone())),
+                        None => envelope_from_status(&res, &request_id),
+                    },
+                    None => envelope_from_status(&res, &request_id),
+                };
+
+                warn!("Request {} failed with {}", request_id, envelope.code);
+
+                let body = serde_json::to_string(&envelope).unwrap_or_default();
+
+                let mut res = res.map_body(|_, _| ResponseBody::Other(Body::from(body)));
+                res.headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                res
+            } else {
+                res
+            };
+
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid("4b1f6a2c-0d1e-4a5b-9c8d-7e6f5a4b3c2d"));
+        assert!(is_valid("client_42"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("a b"));
+        assert!(!is_valid("\n"));
+        assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
@@ -0,0 +1,466 @@
+//! OpenAPI document of `/api/v1`. The schemas of the DTOs are generated by `core`,
+//! the endpoints are listed here and must match `routes::v1`, which is checked by a test.
+use core::models::dto::openapi::{component_ref, ApiComponent, ApiSchema};
+use core::models::dto::*;
+use serde_json::{json, Map, Value};
+
+pub const API_VERSION: &str = "v1";
+
+struct Endpoint {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    request: Option<Value>,
+    response: Option<Value>,
+    /// Name, schema and whether the parameter is required
+    query: Vec<(&'static str, Value, bool)>,
+    /// Signin and the verification work without a session
+    public: bool,
+}
+
+impl Endpoint {
+    fn new(method: &'static str, path: &'static str, summary: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            summary,
+            request: None,
+            response: None,
+            query: Vec::new(),
+            public: false,
+        }
+    }
+
+    fn request(mut self, schema: Value) -> Self {
+        self.request = Some(schema);
+        self
+    }
+
+    fn response(mut self, schema: Value) -> Self {
+        self.response = Some(schema);
+        self
+    }
+
+    fn query(mut self, name: &'static str, schema: Value) -> Self {
+        self.query.push((name, schema, true));
+        self
+    }
+
+    fn optional_query(mut self, name: &'static str, schema: Value) -> Self {
+        self.query.push((name, schema, false));
+        self
+    }
+
+    fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        let query = self.query.iter().map(|(name, schema, required)| {
+            json!({ "name": name, "in": "query", "required": required, "schema": schema })
+        });
+
+        self.path
+            .split('/')
+            .filter(|w| w.starts_with('{') && w.ends_with('}'))
+            .map(|w| {
+                let name = &w[1..w.len() - 1];
+                let schema = match name {
+                    "uid" => json!({ "type": "string", "format": "uuid" }),
+                    "inv_id" | "group_id" => json!({ "type": "integer", "format": "int32" }),
+                    _ => json!({ "type": "string" }),
+                };
+
+                json!({ "name": name, "in": "path", "required": true, "schema": schema })
+            })
+            .chain(query)
+            .collect()
+    }
+
+    fn operation(&self) -> Value {
+        let mut operation = Map::new();
+
+        operation.insert("summary".to_string(), json!(self.summary));
+        operation.insert("parameters".to_string(), json!(self.parameters()));
+
+        if let Some(ref request) = self.request {
+            operation.insert(
+                "requestBody".to_string(),
+                json!({
+                    "required": true,
+                    "content": { "application/json": { "schema": request } }
+                }),
+            );
+        }
+
+        let success = match self.response {
+            Some(ref response) => json!({
+                "description": "Success",
+                "content": { "application/json": { "schema": response } }
+            }),
+            None => json!({ "description": "Success" }),
+        };
+
+        operation.insert(
+            "responses".to_string(),
+            json!({
+                "200": success,
+                "default": {
+                    "description": "Error",
+                    "content": { "application/json": { "schema": component_ref("ErrorResponse") } }
+                }
+            }),
+        );
+
+        if self.public {
+            operation.insert("security".to_string(), json!([]));
+        }
+
+        Value::Object(operation)
+    }
+}
+
+fn array(schema: Value) -> Value {
+    json!({ "type": "array", "items": schema })
+}
+
+fn endpoints() -> Vec<Endpoint> {
+    vec![
+        Endpoint::new("get", "/openapi.json", "This document").public(),
+        Endpoint::new("post", "/signin", "Sign in with the access token")
+            .request(PostUserDto::schema())
+            .response(UserDto::schema())
+            .public(),
+        Endpoint::new("post", "/auth/request_code", "Send a verification code by sms")
+            .request(RequestCodeDto::schema())
+            .public(),
+        Endpoint::new("post", "/auth/check", "Verify the code and create the user")
+            .request(RequestCheckCodeDto::schema())
+            .response(UserDto::schema())
+            .public(),
+        Endpoint::new("post", "/auth/refresh", "Rotate the refresh token")
+            .request(RequestRefreshSessionDto::schema())
+            .response(SessionDto::schema())
+            .public(),
+        Endpoint::new("delete", "/user/{uid}/session", "Log out the current session"),
+        Endpoint::new("delete", "/user/{uid}/sessions", "Log out all sessions"),
+        Endpoint::new("put", "/user/{uid}/token", "Set the push notification token of the released clients")
+            .request(component_ref("UpdateTokenPayload")),
+        Endpoint::new("post", "/user/{uid}/access_token", "Replace the access token and end all sessions")
+            .response(AccessTokenDto::schema()),
+        Endpoint::new(
+            "post",
+            "/user/{uid}/tele_num/request_code",
+            "Send a verification code to the new number",
+        )
+        .request(RequestCodeDto::schema()),
+        Endpoint::new("put", "/user/{uid}/tele_num", "Verify the code and change the number")
+            .request(ChangeTeleNumDto::schema())
+            .response(UserDto::schema()),
+        Endpoint::new("get", "/user/{uid}/devices", "Devices, which receive push notifications")
+            .response(array(DeviceDto::schema())),
+        Endpoint::new("post", "/user/{uid}/devices", "Register a device")
+            .request(RegisterDeviceDto::schema())
+            .response(DeviceDto::schema()),
+        Endpoint::new("delete", "/user/{uid}/devices/{token}", "Unregister a device"),
+        Endpoint::new(
+            "get",
+            "/user/{uid}/settings/notifications",
+            "Push notification preferences",
+        )
+        .response(NotificationSettingsDto::schema()),
+        Endpoint::new(
+            "put",
+            "/user/{uid}/settings/notifications",
+            "Mute everything until a time and set the quiet hours",
+        )
+        .request(UpdateNotificationSettingsDto::schema())
+        .response(NotificationSettingsDto::schema()),
+        Endpoint::new(
+            "post",
+            "/user/{uid}/settings/notifications/muted",
+            "Mute a contact",
+        )
+        .request(MuteContactDto::schema()),
+        Endpoint::new(
+            "delete",
+            "/user/{uid}/settings/notifications/muted/{hash_muted}",
+            "Unmute a contact",
+        ),
+        Endpoint::new("get", "/user/{uid}/blacklist", "Blocked users")
+            .response(array(BlacklistDto::schema())),
+        Endpoint::new("post", "/user/{uid}/blacklist", "Block a user")
+            .request(component_ref("BlacklistEntry")),
+        Endpoint::new(
+            "delete",
+            "/user/{uid}/blacklist/{hash_blocked}",
+            "Unblock a user",
+        ),
+        Endpoint::new("get", "/user/{uid}/profile", "Available profile pictures")
+            .response(array(ProfilePictureDto::schema())),
+        Endpoint::new("put", "/user/{uid}/profile", "Select a profile picture")
+            .request(UpdateProfilePictureDto::schema()),
+        Endpoint::new(
+            "get",
+            "/user/{uid}/export",
+            "Everything, which is stored about the user",
+        )
+        .response(AccountExportDto::schema()),
+        Endpoint::new("get", "/user/{uid}", "The user").response(UserDto::schema()),
+        Endpoint::new("put", "/user/{uid}", "Update the led, the description and its audience")
+            .request(UpdateUserDto::schema())
+            .response(UserDto::schema()),
+        Endpoint::new("delete", "/user/{uid}", "Delete the account and all its data"),
+        Endpoint::new("get", "/contacts/{uid}/groups", "Groups of the contacts")
+            .response(array(ContactGroupDto::schema())),
+        Endpoint::new("post", "/contacts/{uid}/groups", "Create a group of contacts")
+            .request(RequestContactGroupDto::schema())
+            .response(ContactGroupDto::schema()),
+        Endpoint::new("get", "/contacts/{uid}/groups/{group_id}", "A group of contacts")
+            .response(ContactGroupDto::schema()),
+        Endpoint::new(
+            "put",
+            "/contacts/{uid}/groups/{group_id}",
+            "Rename the group and replace its members",
+        )
+        .request(RequestContactGroupDto::schema())
+        .response(ContactGroupDto::schema()),
+        Endpoint::new("delete", "/contacts/{uid}/groups/{group_id}", "Delete a group"),
+        Endpoint::new(
+            "post",
+            "/contacts/{uid}/discover",
+            "Users among the hashes of the contacts",
+        )
+        .request(DiscoverContactsDto::schema())
+        .response(array(DiscoveredContactDto::schema())),
+        Endpoint::new("put", "/contacts/{uid}/{country_code}", "Replace all contacts")
+            .request(PayloadNumbersDto::schema())
+            .response(ContactSyncDto::schema()),
+        Endpoint::new(
+            "patch",
+            "/contacts/{uid}/{country_code}",
+            "Upload the changes of the contacts since the cursor",
+        )
+        .request(PayloadContactsDeltaDto::schema())
+        .response(ContactSyncDto::schema()),
+        Endpoint::new(
+            "get",
+            "/contacts/{uid}",
+            "Contacts, which use the app. `x-contacts-since` is the `since` of the next request. \
+             With `since` the changed and the removed contacts are returned",
+        )
+        .optional_query("since", chrono::NaiveDateTime::schema())
+        .response(json!({
+            "oneOf": [array(ContactDto::schema()), ContactsDeltaDto::schema()]
+        })),
+        Endpoint::new("get", "/broadcasts/{uid}", "Broadcasts of the contacts")
+            .query("mark_seen", bool::schema())
+            .response(array(BroadcastElementDto::schema())),
+        Endpoint::new(
+            "post",
+            "/invitations/{uid}/{inv_id}/members",
+            "Invite further contacts",
+        )
+        .request(RequestInvitationAddMembersDto::schema())
+        .response(InvitationDto::schema()),
+        Endpoint::new("get", "/invitations/{uid}/{inv_id}", "An invitation")
+            .response(InvitationDto::schema()),
+        Endpoint::new("put", "/invitations/{uid}/{inv_id}", "Accept or decline")
+            .request(UpdateInvitationStateDto::schema()),
+        Endpoint::new("get", "/invitations/{uid}", "All invitations")
+            .response(array(InvitationDto::schema())),
+        Endpoint::new("post", "/invitations/{uid}", "Create an invitation")
+            .request(RequestInvitationCreateDto::schema())
+            .response(InvitationDto::schema()),
+    ]
+}
+
+/// Bodies, which are not in `core::models::dto`
+fn server_components() -> Map<String, Value> {
+    let mut components = Map::new();
+
+    components.insert(
+        "ErrorResponse".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "code": { "type": "string" },
+                "message": { "type": "string" },
+                "details": { "type": "object" },
+                "request_id": { "type": "string", "nullable": true }
+            },
+            "required": ["code", "message"]
+        }),
+    );
+
+    components.insert(
+        "UpdateTokenPayload".to_string(),
+        json!({
+            "type": "object",
+            "properties": { "token": { "type": "string" } },
+            "required": ["token"]
+        }),
+    );
+
+    components.insert(
+        "BlacklistEntry".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "hash_blocked": { "type": "string" },
+                "country_code": { "type": "string" }
+            },
+            "required": ["hash_blocked", "country_code"]
+        }),
+    );
+
+    components
+}
+
+pub fn document() -> Value {
+    let mut paths = Map::new();
+
+    for endpoint in endpoints() {
+        let item = paths
+            .entry(endpoint.path.to_string())
+            .or_insert_with(|| json!({}));
+
+        item[endpoint.method] = endpoint.operation();
+    }
+
+    let mut schemas = api_components();
+    schemas.extend(server_components());
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "gehma",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": format!("/api/{}", API_VERSION) }],
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "session": { "type": "apiKey", "in": "header", "name": "Authorization" }
+            }
+        },
+        "security": [{ "session": [] }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{test, web, App};
+    use uuid::Uuid;
+
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get("$ref") {
+                    refs.push(reference.clone());
+                }
+                map.values().for_each(|w| collect_refs(w, refs));
+            }
+            Value::Array(values) => values.iter().for_each(|w| collect_refs(w, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_references_resolve() {
+        let document = document();
+        let mut refs = Vec::new();
+
+        collect_refs(&document, &mut refs);
+
+        assert!(!refs.is_empty());
+
+        for reference in refs {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            assert!(
+                document["components"]["schemas"].get(name).is_some(),
+                "{} is missing",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn test_every_dto_is_described() {
+        let document = document();
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+
+        for name in api_components().keys() {
+            assert!(schemas.contains_key(name));
+        }
+
+        assert!(schemas.contains_key(UserDto::NAME));
+        assert!(schemas.contains_key(InvitationDto::NAME));
+    }
+
+    #[test]
+    fn test_path_parameters() {
+        let document = document();
+        let parameters = &document["paths"]["/invitations/{uid}/{inv_id}"]["get"]["parameters"];
+
+        assert_eq!("uid", parameters[0]["name"]);
+        assert_eq!("integer", parameters[1]["schema"]["type"]);
+    }
+    /// Replaces the path parameters with valid values
+    fn concrete_path(path: &str) -> String {
+        path.split('/')
+            .map(|w| match w {
+                "{uid}" => Uuid::new_v4().to_string(),
+                "{inv_id}" | "{group_id}" => "1".to_string(),
+                "{country_code}" => "AT".to_string(),
+                w if w.starts_with('{') => "abc".to_string(),
+                w => w.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Every documented endpoint is routed by `routes::v1` and every other method
+    /// of a documented path is not. The handlers fail without app data, but they
+    /// neither answer 404 nor 405.
+    #[actix_rt::test]
+    async fn test_endpoints_match_routes() {
+        let mut app = test::init_service(
+            App::new().service(web::scope("/api/v1").configure(crate::routes::v1)),
+        )
+        .await;
+
+        let endpoints = endpoints();
+
+        for endpoint in endpoints.iter() {
+            for method in &["get", "post", "put", "patch", "delete"] {
+                let documented = endpoints
+                    .iter()
+                    .any(|w| w.path == endpoint.path && w.method == *method);
+
+                let req = test::TestRequest::default()
+                    .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+                    .uri(&format!(
+                        "/api/{}{}",
+                        API_VERSION,
+                        concrete_path(endpoint.path)
+                    ))
+                    .to_request();
+
+                let status = test::call_service(&mut app, req).await.status();
+                let routed =
+                    status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED;
+
+                assert_eq!(
+                    documented, routed,
+                    "{} {} answered {}",
+                    method, endpoint.path, status
+                );
+            }
+        }
+    }
+}
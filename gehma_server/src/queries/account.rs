@@ -0,0 +1,31 @@
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::*;
+use core::models::PhoneNumber;
+use mockall::*;
+
+type IResult<K> = Result<K, ServiceError>;
+
+#[automock]
+pub trait PersistentAccountDao {
+    /// Everything, which is stored about `user`
+    fn export(
+        &self,
+        user: &UserDao,
+        current_time: chrono::NaiveDateTime,
+    ) -> IResult<AccountExportDto>;
+
+    /// Removes all rows of `user` in one transaction. The usage statistics are anonymised.
+    /// Uploaded profile pictures are removed together with their files.
+    fn delete(&self, user: &UserDao) -> IResult<()>;
+
+    /// Moves `user` to the verified `tele_num` in one transaction. All hashes of the old
+    /// number are replaced, so the contacts, the blacklist and the votes are kept. Contacts,
+    /// which upload the old number, keep the user until the number is registered again.
+    fn change_tele_num(
+        &self,
+        user: &UserDao,
+        tele_num: &PhoneNumber,
+        country_code: &str,
+    ) -> IResult<UserDao>;
+}
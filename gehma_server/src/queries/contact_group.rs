@@ -0,0 +1,38 @@
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::HashedTeleNum;
+use mockall::*;
+use uuid::Uuid;
+
+type IResult<K> = Result<K, ServiceError>;
+
+#[automock]
+pub trait PersistentContactGroupDao {
+    /// Groups of `owner_id` with their members
+    fn get_all(
+        &self,
+        owner_id: &Uuid,
+    ) -> IResult<Vec<(ContactGroupDao, Vec<ContactGroupMemberDao>)>>;
+    /// Creates the group. Every member must be a contact of `owner_id`.
+    fn create(
+        &self,
+        group: InsertContactGroupDao,
+        members: Vec<HashedTeleNum>,
+    ) -> IResult<(ContactGroupDao, Vec<ContactGroupMemberDao>)>;
+    /// The group `group_id` of `owner_id` with its members
+    fn get(
+        &self,
+        owner_id: &Uuid,
+        group_id: i32,
+    ) -> IResult<(ContactGroupDao, Vec<ContactGroupMemberDao>)>;
+    /// Renames the group and replaces its members
+    fn update(
+        &self,
+        owner_id: &Uuid,
+        group_id: i32,
+        name: String,
+        members: Vec<HashedTeleNum>,
+        updated_at: chrono::NaiveDateTime,
+    ) -> IResult<(ContactGroupDao, Vec<ContactGroupMemberDao>)>;
+    fn delete(&self, owner_id: &Uuid, group_id: i32) -> IResult<()>;
+}
@@ -1,25 +1,64 @@
-use crate::queries::*;
 use core::errors::ServiceError;
 use core::models::dao::*;
 use core::models::dto::*;
 use mockall::*;
-use std::sync::Arc;
-use uuid::Uuid;
 
 type IResult<K> = Result<K, ServiceError>;
 
 #[automock]
 pub trait PersistentContactsDao {
-    fn create<'a>(
+    /// Adds `contacts` to the contacts of `user`. Known contacts are renamed.
+    fn create(
         &self,
-        id: &Uuid,
-        _user: &UserDao,
-        contacts: &'a Vec<&'a mut PayloadUserDto>,
+        user: &UserDao,
+        contacts: &[PayloadUserDto],
+        current_time: chrono::NaiveDateTime,
     ) -> IResult<()>;
 
+    /// Replaces the contacts of `user`. Numbers, which don't belong to a user yet, are
+    /// kept until they are registered. Returns the new sync cursor.
+    fn replace(
+        &self,
+        user: &UserDao,
+        contacts: &[PayloadUserDto],
+        current_time: chrono::NaiveDateTime,
+    ) -> IResult<i64>;
+
+    /// Applies the changes since `cursor`. Fails with `ServiceError::SyncConflict`,
+    /// when `cursor` is outdated. Returns the new sync cursor.
+    fn sync(
+        &self,
+        user: &UserDao,
+        cursor: i64,
+        upserts: &[PayloadUserDto],
+        removed: &[HashedTeleNum],
+        current_time: chrono::NaiveDateTime,
+    ) -> IResult<i64>;
+
+    /// Contacts of `user`, which use the app. With `since` only the new contacts and
+    /// the contacts, whose status or `updated_at` changed afterwards.
     fn get_contacts(
         &self,
         user: &UserDao,
-        user_dao: &Arc<Box<dyn PersistentUserDao>>,
+        since: Option<chrono::NaiveDateTime>,
     ) -> IResult<Vec<ContactDto>>;
+
+    /// Number of the contacts, which `get_contacts` and `get_removed_contacts` return,
+    /// and their latest change. It is read without loading the contacts.
+    fn get_contacts_version(
+        &self,
+        user: &UserDao,
+        since: Option<chrono::NaiveDateTime>,
+    ) -> IResult<(i64, Option<chrono::NaiveDateTime>)>;
+
+    /// Hashes of the contacts of `user`, which were removed after `since`. They are the
+    /// hashes of the client.
+    fn get_removed_contacts(
+        &self,
+        user: &UserDao,
+        since: chrono::NaiveDateTime,
+    ) -> IResult<Vec<HashedTeleNum>>;
+
+    /// Users among `hashes`, which did not block `user`
+    fn discover(&self, user: &UserDao, hashes: &[HashedTeleNum]) -> IResult<Vec<HashedTeleNum>>;
 }
@@ -0,0 +1,22 @@
+use core::errors::ServiceError;
+use core::models::dao::*;
+use mockall::*;
+use uuid::Uuid;
+
+type IResult<K> = Result<K, ServiceError>;
+
+/// Devices, which were not seen for this many days, don't receive push notifications
+pub const ACTIVE_DEVICE_DAYS: i64 = 90;
+
+#[automock]
+pub trait PersistentDeviceDao {
+    fn get_all(&self, user_id: &Uuid) -> IResult<Vec<DeviceDao>>;
+    /// Inserts the device or refreshes it. A token, which was registered by another
+    /// user, is rejected with `ServiceError::AlreadyExists`.
+    fn register(&self, device: InsertDeviceDao) -> IResult<DeviceDao>;
+    fn unregister(&self, user_id: &Uuid, token: &str) -> IResult<()>;
+    /// Removes the token of every user. Returns the number of removed devices.
+    fn remove_token(&self, token: &str) -> IResult<usize>;
+    /// Marks every device of the user as seen. Returns the number of touched devices.
+    fn touch(&self, user_id: &Uuid, last_seen: chrono::NaiveDateTime) -> IResult<usize>;
+}
@@ -0,0 +1,404 @@
+use diesel::{prelude::*, PgConnection};
+use uuid::Uuid;
+
+use core::errors::{InternalServerError, ServiceError};
+use core::models::dao::*;
+use core::models::dto::*;
+use core::models::PhoneNumber;
+
+use crate::Pool;
+
+use crate::queries::*;
+use log::info;
+
+use super::contacts::{bury_contacts_of, link_pending_contacts};
+
+type IResult<V> = Result<V, ServiceError>;
+
+/// Hashes of phone numbers without a foreign key to `users`, and the column, which
+/// makes a row unique together with the hash. The contacts, the blacklist, the votes
+/// and the broadcasts are updated by the foreign keys.
+const UNLINKED_HASHES: &[(&str, &str, &str)] = &[
+    ("muted_contacts", "hash_muted", "user_id"),
+    ("contact_group_members", "hash_tele_num", "group_id"),
+];
+
+/// Uploaded profile pictures are saved as `{uid}.{ending}` in this directory
+const PROFILE_PICTURE_DIR: &str = "static/profile_pictures";
+
+/// Endings of the uploaded profile pictures
+const PROFILE_PICTURE_ENDINGS: &[&str] = &["jpg", "png"];
+
+#[derive(Clone)]
+pub struct PgAccountDao {
+    pub pool: Pool,
+}
+
+impl PersistentAccountDao for PgAccountDao {
+    fn export(
+        &self,
+        user: &UserDao,
+        current_time: chrono::NaiveDateTime,
+    ) -> IResult<AccountExportDto> {
+        info!("queries/account/export");
+        use core::schema::analytics::dsl as analytics;
+        use core::schema::blacklist::dsl as blacklist;
+        use core::schema::broadcast::dsl as broadcast;
+        use core::schema::contact_group_members::dsl as members;
+        use core::schema::contact_groups::dsl as groups;
+        use core::schema::contacts::dsl as contacts;
+        use core::schema::devices::dsl as devices;
+        use core::schema::invitation::dsl as invitation;
+        use core::schema::invitation_members::dsl as invitation_members;
+        use core::schema::muted_contacts::dsl as muted;
+        use core::schema::notification_settings::dsl as settings;
+        use core::schema::pending_contacts::dsl as pending;
+        use core::schema::profile_pictures::dsl as pictures;
+        use core::schema::usage_statistics::dsl as usage_statistics;
+        use core::schema::usage_statistics_monthly::dsl as monthly;
+        use core::schema::votes::dsl as votes;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+        let pseudonym = user.pseudonym();
+
+        let path = pictures::profile_pictures
+            .filter(pictures::id.eq(user.profile_picture))
+            .select(pictures::path)
+            .first::<String>(conn)?;
+
+        let contact_groups = groups::contact_groups
+            .filter(groups::owner_id.eq(user.id))
+            .order(groups::id)
+            .load::<ContactGroupDao>(conn)?;
+
+        let group_members = ContactGroupMemberDao::belonging_to(&contact_groups)
+            .order(members::hash_tele_num)
+            .load::<ContactGroupMemberDao>(conn)?
+            .grouped_by(&contact_groups);
+
+        let notification_settings = settings::notification_settings
+            .filter(settings::user_id.eq(user.id))
+            .first::<NotificationSettingsDao>(conn)
+            .optional()?;
+
+        let notification_settings = match notification_settings {
+            Some(notification_settings) => Some(
+                notification_settings.into(
+                    muted::muted_contacts
+                        .filter(muted::user_id.eq(user.id))
+                        .load::<MutedContactDao>(conn)?,
+                ),
+            ),
+            None => None,
+        };
+
+        // Numbers, which don't belong to a user yet, are contacts too
+        let mut contacts: Vec<ExportedContactDto> = into_all(
+            contacts::contacts
+                .filter(contacts::from_id.eq(user.id))
+                .order(contacts::created_at)
+                .load::<ContactDao>(conn)?,
+        );
+
+        contacts.extend(into_all::<_, ExportedContactDto>(
+            pending::pending_contacts
+                .filter(pending::from_id.eq(user.id))
+                .order(pending::created_at)
+                .load::<PendingContactDao>(conn)?,
+        ));
+
+        let export = AccountExportDto {
+            user: user.clone().into(path),
+            contacts,
+            contact_groups: contact_groups
+                .into_iter()
+                .zip(group_members)
+                .map(|(group, members)| group.into(members))
+                .collect(),
+            blacklist: into_all(
+                blacklist::blacklist
+                    .filter(blacklist::hash_blocker.eq(&user.hash_tele_num))
+                    .load::<BlacklistDao>(conn)?,
+            ),
+            devices: into_all(
+                devices::devices
+                    .filter(devices::user_id.eq(user.id))
+                    .load::<DeviceDao>(conn)?,
+            ),
+            notification_settings,
+            broadcasts: into_all(
+                broadcast::broadcast
+                    .filter(broadcast::originator_user_id.eq(user.id))
+                    .order(broadcast::created_at)
+                    .load::<BroadcastElementDao>(conn)?,
+            ),
+            invitations: into_all(
+                invitation::invitation
+                    .filter(invitation::originator_user_id.eq(user.id))
+                    .order(invitation::created_at)
+                    .load::<InvitationDao>(conn)?,
+            ),
+            invitation_memberships: into_all(
+                invitation_members::invitation_members
+                    .filter(invitation_members::user_id.eq(user.id))
+                    .order(invitation_members::created_at)
+                    .load::<InvitationMemberDao>(conn)?,
+            ),
+            votes: into_all(
+                votes::votes
+                    .filter(votes::hash_tele_num.eq(&user.hash_tele_num))
+                    .load::<VoteDao>(conn)?,
+            ),
+            analytics: into_all(
+                analytics::analytics
+                    .filter(analytics::pseudonym.eq(&pseudonym))
+                    .order(analytics::created_at)
+                    .load::<AnalyticDao>(conn)?,
+            ),
+            usage_statistics: into_all(
+                usage_statistics::usage_statistics
+                    .filter(usage_statistics::pseudonym.eq(&pseudonym))
+                    .order(usage_statistics::created_at)
+                    .load::<UsageStatisticEntryDao>(conn)?,
+            ),
+            usage_statistics_monthly: into_all(
+                monthly::usage_statistics_monthly
+                    .filter(monthly::pseudonym.eq(&pseudonym))
+                    .order((monthly::yearly, monthly::monthly))
+                    .load::<UsageStatisticsMonthlyDao>(conn)?,
+            ),
+            exported_at: current_time,
+        };
+
+        Ok(export)
+    }
+
+    fn delete(&self, user: &UserDao) -> IResult<()> {
+        info!("queries/account/delete");
+        use core::schema::analytics::dsl as analytics;
+        use core::schema::blacklist::dsl as blacklist;
+        use core::schema::broadcast::dsl as broadcast;
+        use core::schema::contact_group_members::dsl as members;
+        use core::schema::contacts::dsl as contacts;
+        use core::schema::devices::dsl as devices;
+        use core::schema::invitation::dsl as invitation;
+        use core::schema::invitation_members::dsl as invitation_members;
+        use core::schema::muted_contacts::dsl as muted;
+        use core::schema::notification_outbox::dsl as outbox;
+        use core::schema::profile_pictures::dsl as pictures;
+        use core::schema::usage_statistics::dsl as usage_statistics;
+        use core::schema::usage_statistics_monthly::dsl as monthly;
+        use core::schema::users::dsl as users;
+        use core::schema::votes::dsl as votes;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            let hash = &user.hash_tele_num;
+
+            // Pending notifications to the devices of the user
+            let mut tokens = devices::devices
+                .filter(devices::user_id.eq(user.id))
+                .select(devices::token)
+                .load::<String>(conn)?;
+
+            tokens.extend(user.firebase_token.clone());
+
+            diesel::delete(outbox::notification_outbox.filter(outbox::token.eq_any(tokens)))
+                .execute(conn)?;
+
+            diesel::delete(
+                invitation::invitation.filter(invitation::originator_user_id.eq(user.id)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                invitation_members::invitation_members
+                    .filter(invitation_members::user_id.eq(user.id)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                broadcast::broadcast.filter(
+                    broadcast::originator_user_id
+                        .eq(user.id)
+                        .or(broadcast::display_user.eq(hash)),
+                ),
+            )
+            .execute(conn)?;
+
+            diesel::delete(votes::votes.filter(votes::hash_tele_num.eq(hash))).execute(conn)?;
+
+            diesel::delete(
+                blacklist::blacklist.filter(
+                    blacklist::hash_blocker
+                        .eq(hash)
+                        .or(blacklist::hash_blocked.eq(hash)),
+                ),
+            )
+            .execute(conn)?;
+
+            // The number disappears from the address books and the groups of the others
+            bury_contacts_of(conn, user, chrono::Local::now().naive_local())?;
+
+            diesel::delete(
+                contacts::contacts.filter(
+                    contacts::from_id
+                        .eq(user.id)
+                        .or(contacts::target_hash_tele_num.eq(hash)),
+                ),
+            )
+            .execute(conn)?;
+
+            diesel::delete(members::contact_group_members.filter(members::hash_tele_num.eq(hash)))
+                .execute(conn)?;
+
+            diesel::delete(muted::muted_contacts.filter(muted::hash_muted.eq(hash)))
+                .execute(conn)?;
+
+            let pseudonym = user.pseudonym();
+
+            diesel::delete(analytics::analytics.filter(analytics::pseudonym.eq(&pseudonym)))
+                .execute(conn)?;
+
+            // The monthly statistics keep counting the usage
+            let anonymous = anonymous_pseudonym();
+
+            diesel::update(
+                usage_statistics::usage_statistics
+                    .filter(usage_statistics::pseudonym.eq(&pseudonym)),
+            )
+            .set(usage_statistics::pseudonym.eq(&anonymous))
+            .execute(conn)?;
+
+            diesel::update(
+                monthly::usage_statistics_monthly.filter(monthly::pseudonym.eq(&pseudonym)),
+            )
+            .set(monthly::pseudonym.eq(&anonymous))
+            .execute(conn)?;
+
+            // Devices, settings, groups and the sync cursor are cascaded
+            let deleted =
+                diesel::delete(users::users.filter(users::id.eq(user.id))).execute(conn)?;
+
+            if deleted == 0 {
+                return Err(ServiceError::ResourceDoesNotExist);
+            }
+
+            // Uploaded pictures belong to their user only, the others are shared
+            diesel::delete(
+                pictures::profile_pictures.filter(pictures::path.like(format!("%{}.%", user.id))),
+            )
+            .execute(conn)?;
+
+            // Last, so a failure keeps the account
+            remove_profile_pictures(&user.id)
+        })
+    }
+
+    fn change_tele_num(
+        &self,
+        user: &UserDao,
+        tele_num: &PhoneNumber,
+        country_code: &str,
+    ) -> IResult<UserDao> {
+        info!("queries/account/change_tele_num");
+        use core::schema::tele_num_changes::dsl as changes;
+        use core::schema::users::dsl as users;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let new_tele_num = tele_num.to_string();
+        let new_hash = HashedTeleNum(core::hashing::hash_tele_num(&new_tele_num));
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            let changed = diesel::update(users::users.filter(users::id.eq(user.id)))
+                .set((
+                    users::tele_num.eq(&new_tele_num),
+                    users::country_code.eq(country_code),
+                    users::hash_tele_num.eq(&new_hash),
+                ))
+                .get_result::<UserDao>(conn)?;
+
+            for &reference in UNLINKED_HASHES {
+                rekey(conn, reference, &user.hash_tele_num, &new_hash)?;
+            }
+
+            let current_time = chrono::Local::now().naive_local();
+
+            link_pending_contacts(conn, &new_hash, current_time)?;
+
+            // Uploads of the old number by the contacts are kept for the user
+            diesel::insert_into(changes::tele_num_changes)
+                .values((
+                    changes::old_hash_tele_num.eq(&user.hash_tele_num),
+                    changes::hash_tele_num.eq(&new_hash),
+                    changes::changed_at.eq(current_time),
+                ))
+                .execute(conn)?;
+
+            Ok(changed)
+        })
+    }
+}
+
+/// Replaces `old` by `new` in `table.column`. Rows, which already hold both hashes,
+/// keep the one with `new`.
+fn rekey(
+    conn: &PgConnection,
+    (table, column, key): (&str, &str, &str),
+    old: &HashedTeleNum,
+    new: &HashedTeleNum,
+) -> IResult<usize> {
+    use diesel::sql_types::Text;
+
+    diesel::sql_query(format!(
+        "DELETE FROM {table} WHERE {column} = $1 AND {key} IN \
+         (SELECT {key} FROM {table} WHERE {column} = $2)",
+        table = table,
+        column = column,
+        key = key
+    ))
+    .bind::<Text, _>(old)
+    .bind::<Text, _>(new)
+    .execute(conn)?;
+
+    let updated = diesel::sql_query(format!(
+        "UPDATE {table} SET {column} = $2 WHERE {column} = $1",
+        table = table,
+        column = column
+    ))
+    .bind::<Text, _>(old)
+    .bind::<Text, _>(new)
+    .execute(conn)?;
+
+    Ok(updated)
+}
+
+/// Removes the uploaded profile pictures of `user_id`, which are publicly served
+fn remove_profile_pictures(user_id: &Uuid) -> IResult<()> {
+    for ending in PROFILE_PICTURE_ENDINGS {
+        let path = format!("{}/{}.{}", PROFILE_PICTURE_DIR, user_id, ending);
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => info!("Removed the profile picture {}", path),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(ServiceError::InternalServerError(
+                    InternalServerError::IOError(err.to_string()),
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn into_all<D: Into<T>, T>(list: Vec<D>) -> Vec<T> {
+    list.into_iter().map(|w| w.into()).collect()
+}
+
+/// Replaces the pseudonym of a deleted user
+fn anonymous_pseudonym() -> String {
+    format!("deleted:{}", Uuid::new_v4().simple())
+}
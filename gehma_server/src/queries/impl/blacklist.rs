@@ -8,8 +8,9 @@ use core::models::PhoneNumber;
 
 use crate::Pool;
 
+use super::contacts::touch_contacts_between;
 use crate::queries::*;
-use log::{error, info};
+use log::info;
 
 #[derive(Clone)]
 pub struct PgBlacklistDao {
@@ -27,16 +28,16 @@ impl PersistentBlacklistDao for PgBlacklistDao {
         let user = users
             .filter(id.eq(sblocker))
             .load::<UserDao>(conn)
-            .map_err(|_db_err| ServiceError::BadRequest("Invalid User".into()))?
+            .map_err(ServiceError::from)?
             .first()
             .cloned()
-            .ok_or_else(|| ServiceError::BadRequest("No user found".into()))?;
+            .ok_or(ServiceError::ResourceDoesNotExist)?;
 
         blacklist
             .filter(hash_blocker.eq(user.hash_tele_num))
             .load::<BlacklistDao>(conn)
             //.map(|w| w.into_iter().map(|k| k.into()).collect())
-            .map_err(|_db_err| ServiceError::BadRequest("Invalid User".into()))
+            .map_err(ServiceError::from)
     }
 
     fn create(
@@ -50,14 +51,20 @@ impl PersistentBlacklistDao for PgBlacklistDao {
         let conn: &PgConnection = &self.pool.get().unwrap();
         let new_inv: BlacklistDao = BlacklistDao::my_from(blocker, blocked);
 
-        diesel::insert_into(blacklist)
-            .values(&new_inv)
-            .get_result::<BlacklistDao>(conn)
-            //.map(|w| w.into())
-            .map_err(|_db_error| {
-                error!("{:?}", _db_error);
-                ServiceError::BadRequest("Cannot insert into blacklist".into())
-            })
+        conn.transaction::<_, ServiceError, _>(|| {
+            let created = diesel::insert_into(blacklist)
+                .values(&new_inv)
+                .get_result::<BlacklistDao>(conn)?;
+
+            touch_contacts_between(
+                conn,
+                &created.hash_blocker,
+                &created.hash_blocked,
+                chrono::Local::now().naive_local(),
+            )?;
+
+            Ok(created)
+        })
     }
 
     fn delete(&self, sblocker: &HashedTeleNum, sblocked: &HashedTeleNum) -> Result<(), ServiceError> {
@@ -70,10 +77,12 @@ impl PersistentBlacklistDao for PgBlacklistDao {
             .filter(hash_blocker.eq(sblocker))
             .filter(hash_blocked.eq(sblocked));
 
-        diesel::delete(target)
-            .execute(conn)
-            .map_err(|_db_error| ServiceError::BadRequest("Cannot delete blacklist".into()))?;
+        conn.transaction::<_, ServiceError, _>(|| {
+            diesel::delete(target).execute(conn)?;
+
+            touch_contacts_between(conn, sblocker, sblocked, chrono::Local::now().naive_local())?;
 
-        Ok(())
+            Ok(())
+        })
     }
 }
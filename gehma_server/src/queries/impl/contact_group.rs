@@ -0,0 +1,202 @@
+use diesel::{prelude::*, PgConnection};
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::HashedTeleNum;
+use uuid::Uuid;
+
+use crate::Pool;
+
+use super::contacts::touch_contacts_of;
+use crate::queries::*;
+use log::info;
+
+type IResult<V> = Result<V, ServiceError>;
+
+#[derive(Clone)]
+pub struct PgContactGroupDao {
+    pub pool: Pool,
+}
+
+/// Fails, if one of `members` is not a contact of `owner_id`
+pub(crate) fn check_members(
+    conn: &PgConnection,
+    owner_id: &Uuid,
+    members: &[HashedTeleNum],
+) -> IResult<()> {
+    use core::schema::contacts::dsl::{contacts, from_id, target_hash_tele_num};
+
+    let known: Vec<HashedTeleNum> = contacts
+        .filter(from_id.eq(owner_id))
+        .filter(target_hash_tele_num.eq_any(members))
+        .select(target_hash_tele_num)
+        .load(conn)?;
+
+    match members.iter().find(|w| !known.contains(w)) {
+        Some(unknown) => Err(ServiceError::BadRequest(format!(
+            "{} is not a contact",
+            unknown
+        ))),
+        None => Ok(()),
+    }
+}
+
+impl PersistentContactGroupDao for PgContactGroupDao {
+    fn get_all(
+        &self,
+        my_owner_id: &Uuid,
+    ) -> IResult<Vec<(ContactGroupDao, Vec<ContactGroupMemberDao>)>> {
+        info!("queries/contact_group/get_all");
+        use core::schema::contact_groups::dsl::{contact_groups, name, owner_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let groups = contact_groups
+            .filter(owner_id.eq(my_owner_id))
+            .order(name.asc())
+            .load::<ContactGroupDao>(conn)?;
+
+        let members = ContactGroupMemberDao::belonging_to(&groups)
+            .load::<ContactGroupMemberDao>(conn)?
+            .grouped_by(&groups);
+
+        Ok(groups.into_iter().zip(members).collect())
+    }
+
+    fn create(
+        &self,
+        group: InsertContactGroupDao,
+        members: Vec<HashedTeleNum>,
+    ) -> IResult<(ContactGroupDao, Vec<ContactGroupMemberDao>)> {
+        info!("queries/contact_group/create");
+        use core::schema::contact_group_members::dsl::contact_group_members;
+        use core::schema::contact_groups::dsl::contact_groups;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            check_members(conn, &group.owner_id, &members)?;
+
+            let created = diesel::insert_into(contact_groups)
+                .values(&group)
+                .get_result::<ContactGroupDao>(conn)?;
+
+            let members: Vec<_> = members
+                .into_iter()
+                .map(|w| ContactGroupMemberDao {
+                    group_id: created.id,
+                    hash_tele_num: w,
+                })
+                .collect();
+
+            diesel::insert_into(contact_group_members)
+                .values(&members)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            Ok((created, members))
+        })
+    }
+    fn get(
+        &self,
+        my_owner_id: &Uuid,
+        group_id: i32,
+    ) -> IResult<(ContactGroupDao, Vec<ContactGroupMemberDao>)> {
+        info!("queries/contact_group/get");
+        use core::schema::contact_groups::dsl::{contact_groups, id, owner_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let group = contact_groups
+            .filter(id.eq(group_id))
+            .filter(owner_id.eq(my_owner_id))
+            .first::<ContactGroupDao>(conn)
+            .optional()?
+            .ok_or(ServiceError::ResourceDoesNotExist)?;
+
+        let members = ContactGroupMemberDao::belonging_to(&group).load(conn)?;
+
+        Ok((group, members))
+    }
+
+    fn update(
+        &self,
+        my_owner_id: &Uuid,
+        group_id: i32,
+        my_name: String,
+        members: Vec<HashedTeleNum>,
+        my_updated_at: chrono::NaiveDateTime,
+    ) -> IResult<(ContactGroupDao, Vec<ContactGroupMemberDao>)> {
+        info!("queries/contact_group/update");
+        use core::schema::contact_group_members::dsl::contact_group_members;
+        use core::schema::contact_groups::dsl::{contact_groups, id, name, owner_id, updated_at};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            check_members(conn, my_owner_id, &members)?;
+
+            let updated = diesel::update(
+                contact_groups
+                    .filter(id.eq(group_id))
+                    .filter(owner_id.eq(my_owner_id)),
+            )
+            .set((name.eq(my_name), updated_at.eq(my_updated_at)))
+            .get_result::<ContactGroupDao>(conn)
+            .optional()?
+            .ok_or(ServiceError::ResourceDoesNotExist)?;
+
+            diesel::delete(ContactGroupMemberDao::belonging_to(&updated)).execute(conn)?;
+
+            let members: Vec<_> = members
+                .into_iter()
+                .map(|w| ContactGroupMemberDao {
+                    group_id: updated.id,
+                    hash_tele_num: w,
+                })
+                .collect();
+
+            diesel::insert_into(contact_group_members)
+                .values(&members)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            // The status of the owner might be visible to other contacts now
+            touch_contacts_of(conn, my_owner_id, my_updated_at)?;
+
+            Ok((updated, members))
+        })
+    }
+
+    fn delete(&self, my_owner_id: &Uuid, group_id: i32) -> IResult<()> {
+        info!("queries/contact_group/delete");
+        use core::schema::contact_groups::dsl::{contact_groups, id, owner_id};
+        use core::schema::users::dsl::{changed_at, led, led_audience, led_expires_at, users};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            // The audience falls back to everyone, so a status for the group is switched off
+            diesel::update(users.filter(led_audience.eq(group_id)))
+                .set((
+                    led.eq(false),
+                    led_expires_at.eq(None::<chrono::NaiveDateTime>),
+                    changed_at.eq(chrono::Local::now().naive_local()),
+                ))
+                .execute(conn)?;
+
+            // The members are deleted by the foreign key
+            let deleted = diesel::delete(
+                contact_groups
+                    .filter(id.eq(group_id))
+                    .filter(owner_id.eq(my_owner_id)),
+            )
+            .execute(conn)?;
+
+            match deleted {
+                0 => Err(ServiceError::ResourceDoesNotExist),
+                _ => Ok(()),
+            }
+        })
+    }
+}
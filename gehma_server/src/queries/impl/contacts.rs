@@ -9,7 +9,9 @@ use crate::Pool;
 
 use crate::queries::*;
 use log::info;
-use std::sync::Arc;
+
+/// Rows of one insert. Postgres allows 65535 parameters per statement.
+const CONTACTS_BATCH_SIZE: usize = 1000;
 
 #[derive(Clone)]
 pub struct PgContactsDao {
@@ -17,191 +19,157 @@ pub struct PgContactsDao {
 }
 
 impl PersistentContactsDao for PgContactsDao {
-    ///users which should be added, already filtered after blacklist contacts: Vec<ContactDto>,
-    fn create<'a>(
+    fn create(
         &self,
-        uid: &Uuid,
-        _user: &UserDao,
-        payload: &'a Vec<&'a mut PayloadUserDto>,
+        user: &UserDao,
+        payload: &[PayloadUserDto],
+        current_time: chrono::NaiveDateTime,
     ) -> Result<(), ServiceError> {
         info!("queries/contacts/create");
-        use core::schema::contacts::dsl::contacts;
 
         let conn: &PgConnection = &self.pool.get().unwrap();
 
-        if payload.is_empty() {
-            return Ok(());
-        }
+        conn.transaction::<_, ServiceError, _>(|| {
+            let payload = renumber_contacts(conn, payload)?;
 
-        let inserts: Vec<_> = payload
-            .into_iter()
-            .map(|w| ContactInsertDao {
-                from_id: uid.clone(),
-                target_hash_tele_num: w.hash_tele_num.clone(),
-                name: w.name.clone(),
-                created_at: chrono::Utc::now().naive_local(),
-            })
-            .collect();
+            upsert_contacts(conn, user, &payload, current_time)?;
 
-        for phone_contact in inserts {
-            let _ = diesel::insert_into(contacts)
-                .values(phone_contact)
-                .execute(conn); //IGNORE error, because there might be no user in `users`
-        }
+            // Clients with a cursor have to upload all contacts again
+            next_cursor(conn, user, current_time)?;
 
-        Ok(())
-
-        /*
-            let mut numbers: Vec<WrappedUserDto> = phone_numbers
-                .iter_mut()
-                .map(|w| WrappedUserDto {
-                    hash_tele_num: w.hash_tele_num.clone(),
-                    name: w.name.clone(),
-                    user: None,
-                })
-                .collect();
-
-            users
-                .filter(id.eq(uid)) // 1. Get user
-                .load::<UserDao>(conn)
-                .map_err(|_db_error| ServiceError::BadRequest("Invalid User".into()))
-                .and_then(|result| {
-                    if let Some(user) = result.first() {
-                        // 2. Get all blacklist
-                        blacklist
-                            .filter(
-                                hash_blocked
-                                    .eq(&user.hash_tele_num)
-                                    .or(hash_blocker.eq(&user.hash_tele_num)),
-                            )
-                            .load::<BlacklistDao>(conn)
-                            .map_err(|_db_error| {
-                                ServiceError::BadRequest("Cannot find blacklists".into())
-                            })
-                            .and_then(|lists| {
-                                let people_who_blacklisted_user: Vec<_> = lists
-                                    .into_iter()
-                                    .map(|w| {
-                                        if w.hash_blocker == user.hash_tele_num {
-                                            // 3. get the appropriate
-                                            w.hash_blocked //jener der blockiert soll sie auch nicht sehen
-                                        } else {
-                                            w.hash_blocker
-                                        }
-                                    })
-                                    .collect();
-
-                                users // 4. Get all contacts which were defined in the request array
-                                    .filter(
-                                        hash_tele_num.eq_any(
-                                            numbers
-                                                .iter()
-                                                .map(|w| w.hash_tele_num.clone())
-                                                .collect::<Vec<HashedTeleNum>>(),
-                                        ),
-                                    )
-                                    .order(changed_at.desc())
-                                    .load::<UserDao>(conn)
-                                    .map_err(|_db_error| {
-                                        ServiceError::BadRequest("Invalid User".into())
-                                    })
-                                    .and_then(|mut result| {
-                                        //i are contacts
-                                        for i in result.iter_mut() {
-                                            let mut res: Vec<_> = numbers
-                                                .iter_mut()
-                                                .filter(|w| w.hash_tele_num == i.hash_tele_num) // 5. downgrade user
-                                                .collect();
-
-                                            if let Some(mut res_user) = res.first_mut() {
-                                                res_user.user = Some(i.clone().into());
-                                            }
-                                        }
-
-                                        // 6. reset blacklisted
-
-                                        numbers
-                                            .iter_mut()
-                                            .filter(|w| {
-                                                people_who_blacklisted_user.contains(&w.hash_tele_num)
-                                            })
-                                            .for_each(|ref mut w| match &mut w.user {
-                                                Some(ref mut u) => {
-                                                    u.led = false; //Ignoring happens here
-                                                    u.description = String::new();
-
-                                                    //TODO to cross self blocked users cross here the
-                                                    //people
-                                                }
-                                                None => {}
-                                            });
-
-                                        Ok(numbers
-                                            .into_iter()
-                                            .filter(|w| w.user.is_some())
-                                            .collect::<Vec<WrappedUserDto>>())
-                                    })
-                                    .and_then(|numbers| {
-                                        use core::schema::contacts::dsl::{contacts, from_id};
-
-                                        let user_contacts: Vec<_> = numbers
-                                            .iter()
-                                            .map(|n| {
-                                                ContactDao::my_from(
-                                                    n.name.clone(),
-                                                    &user,
-                                                    n.user.as_ref().unwrap().tele_num.clone(),
-                                                )
-                                            })
-                                            .collect();
-
-                                        let target = contacts.filter(from_id.eq(user.id));
-
-                                        //We need to delete all numbers, because
-                                        //user shall not receive push notifications
-                                        //for contacts he deleted
-                                        let _ = diesel::delete(target).execute(conn).map_err(
-                                            |_db_err| {
-                                                eprintln!("{}", _db_err);
-                                                ServiceError::BadRequest("Could reset contacts".into())
-                                            },
-                                        )?;
-
-                                        let _ = diesel::insert_into(contacts)
-                                            .values(user_contacts)
-                                            .on_conflict_do_nothing()
-                                            .execute(conn)
-                                            .map_err(|_db_err| {
-                                                eprintln!("{}", _db_err);
-                                                ServiceError::BadRequest("Could set contacts".into())
-                                            })?;
-
-                                        Ok(numbers)
-                                    })
-                            })
-                    } else {
-                        Err(ServiceError::BadRequest("No user found".into()))
-                    }
-                })
-        */
+            Ok(())
+        })
+    }
+
+    fn replace(
+        &self,
+        user: &UserDao,
+        payload: &[PayloadUserDto],
+        current_time: chrono::NaiveDateTime,
+    ) -> Result<i64, ServiceError> {
+        info!("queries/contacts/replace");
+        use core::schema::contacts::dsl::{contacts, from_id, target_hash_tele_num};
+        use core::schema::pending_contacts::dsl as pending;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            let payload = renumber_contacts(conn, payload)?;
+            let hashes: Vec<_> = payload.iter().map(|w| w.hash_tele_num.clone()).collect();
+
+            // Contacts, which were deleted on the phone
+            let removed = contacts
+                .filter(from_id.eq(user.id))
+                .filter(target_hash_tele_num.ne_all(&hashes))
+                .select(target_hash_tele_num)
+                .load::<HashedTeleNum>(conn)?;
+
+            bury_contacts(conn, &user.id, &removed, current_time)?;
+
+            diesel::delete(
+                contacts
+                    .filter(from_id.eq(user.id))
+                    .filter(target_hash_tele_num.eq_any(&removed)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                pending::pending_contacts
+                    .filter(pending::from_id.eq(user.id))
+                    .filter(pending::target_hash_tele_num.ne_all(&hashes)),
+            )
+            .execute(conn)?;
+
+            upsert_contacts(conn, user, &payload, current_time)?;
+
+            next_cursor(conn, user, current_time)
+        })
+    }
+
+    fn sync(
+        &self,
+        user: &UserDao,
+        cursor: i64,
+        upserts: &[PayloadUserDto],
+        removed: &[HashedTeleNum],
+        current_time: chrono::NaiveDateTime,
+    ) -> Result<i64, ServiceError> {
+        info!("queries/contacts/sync");
+        use core::schema::contact_sync::dsl::{contact_sync, user_id};
+        use core::schema::contacts::dsl::{contacts, from_id, target_hash_tele_num};
+        use core::schema::pending_contacts::dsl as pending;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            // Concurrent syncs of the user wait for each other
+            let current = contact_sync
+                .filter(user_id.eq(user.id))
+                .for_update()
+                .first::<ContactSyncDao>(conn)
+                .optional()?
+                .map(|w| w.cursor)
+                .unwrap_or(0);
+
+            if current != cursor {
+                return Err(ServiceError::SyncConflict(current));
+            }
+
+            let upserts = renumber_contacts(conn, upserts)?;
+            let removed = renumber(conn, removed)?;
+
+            if !removed.is_empty() {
+                let buried = contacts
+                    .filter(from_id.eq(user.id))
+                    .filter(target_hash_tele_num.eq_any(&removed))
+                    .select(target_hash_tele_num)
+                    .load::<HashedTeleNum>(conn)?;
+
+                bury_contacts(conn, &user.id, &buried, current_time)?;
+
+                diesel::delete(
+                    contacts
+                        .filter(from_id.eq(user.id))
+                        .filter(target_hash_tele_num.eq_any(&buried)),
+                )
+                .execute(conn)?;
+
+                diesel::delete(
+                    pending::pending_contacts
+                        .filter(pending::from_id.eq(user.id))
+                        .filter(pending::target_hash_tele_num.eq_any(&removed)),
+                )
+                .execute(conn)?;
+            }
+
+            upsert_contacts(conn, user, &upserts, current_time)?;
+
+            next_cursor(conn, user, current_time)
+        })
     }
 
     fn get_contacts(
         &self,
         user: &UserDao,
-        user_dao: &Arc<Box<dyn PersistentUserDao>>,
+        since: Option<chrono::NaiveDateTime>,
     ) -> Result<Vec<ContactDto>, ::core::errors::ServiceError> {
         info!("queries/user/get_contacts");
 
         use core::schema::blacklist::dsl::{blacklist, hash_blocked, hash_blocker};
-        use core::schema::contacts::dsl::{contacts, from_id, name, target_hash_tele_num};
+        use core::schema::contact_group_members::dsl as members;
+        use core::schema::contacts::dsl::{
+            contacts, created_at as contact_created_at, from_id, name, target_hash_tele_num,
+            updated_at as contact_updated_at,
+        };
+        use core::schema::profile_pictures::dsl as pictures;
         use core::schema::users::dsl::*;
 
         let conn: &PgConnection = &self.pool.get().unwrap();
 
-        contacts
+        let mut query = contacts
             .filter(from_id.eq(user.id))
             .inner_join(users.on(hash_tele_num.eq(target_hash_tele_num)))
+            .inner_join(pictures::profile_pictures.on(pictures::id.eq(profile_picture)))
             .left_join(
                 blacklist.on(target_hash_tele_num
                     .eq(hash_blocked)
@@ -223,8 +191,24 @@ impl PersistentContactsDao for PgContactsDao {
                 client_version,
                 firebase_token.nullable(),
                 access_token,
+                led_expires_at,
+                led_audience,
+                pictures::path,
             ))
             .distinct()
+            .into_boxed();
+
+        // New contacts and contacts with a new status
+        if let Some(since) = since {
+            query = query.filter(
+                changed_at
+                    .gt(since)
+                    .or(contact_created_at.gt(since))
+                    .or(contact_updated_at.gt(since)),
+            );
+        }
+
+        query
             .load::<(
                 Uuid,   //id
                 String, //name
@@ -241,9 +225,20 @@ impl PersistentContactsDao for PgContactsDao {
                 String,                //client
                 Option<String>,        //firebase
                 String,                //access_token
+                Option<chrono::NaiveDateTime>, //led_expires_at
+                Option<i32>,                   //led_audience
+                String,                        //profile picture path
             )>(conn)
-            .map_err(|_db_error| ServiceError::BadRequest("Invalid User".into()))
+            .map_err(ServiceError::from)
             .and_then(|values| {
+                // Audiences of the contacts, which contain `user`
+                let audiences: Vec<i32> = values.iter().filter_map(|w| w.16).collect();
+                let visible: Vec<i32> = members::contact_group_members
+                    .filter(members::group_id.eq_any(&audiences))
+                    .filter(members::hash_tele_num.eq(&user.hash_tele_num))
+                    .select(members::group_id)
+                    .load(conn)?;
+
                 Ok(values
                     .into_iter()
                     .map(
@@ -263,8 +258,11 @@ impl PersistentContactsDao for PgContactsDao {
                             _client_version,
                             _firebase_token,
                             _access_token,
+                            _led_expires_at,
+                            _led_audience,
+                            _path,
                         )| {
-                            let user_d = UserDao {
+                            let mut user_d = UserDao {
                                 id: _id,
                                 tele_num: _tele_num,
                                 led: _led,
@@ -278,18 +276,471 @@ impl PersistentContactsDao for PgContactsDao {
                                 access_token: _access_token,
                                 hash_tele_num: _hash_tele_num,
                                 xp: _xp,
+                                led_expires_at: _led_expires_at,
+                                led_audience: _led_audience,
                             };
 
-                            let path = user_dao
-                                .get_profile_picture(&user_d)
-                                .expect("Fetching profile picture failed");
+                            if let Some(audience) = user_d.led_audience {
+                                if !visible.contains(&audience) {
+                                    user_d.led = false;
+                                    user_d.description = "".to_string();
+                                    user_d.led_expires_at = None;
+                                }
+                            }
 
-                            ContactDto::new(_name, _blocked.is_some(), user_d.into(path))
+                            // The audience is a private group of the contact
+                            user_d.led_audience = None;
+
+                            ContactDto::new(_name, _blocked.is_some(), user_d.into(_path))
                         },
                     )
                     .collect())
             })
     }
+
+    fn get_contacts_version(
+        &self,
+        user: &UserDao,
+        since: Option<chrono::NaiveDateTime>,
+    ) -> Result<(i64, Option<chrono::NaiveDateTime>), ServiceError> {
+        info!("queries/contacts/get_contacts_version");
+        use core::schema::contact_tombstones::dsl as tombstones;
+        use core::schema::contacts::dsl::{
+            contacts, created_at as contact_created_at, from_id, target_hash_tele_num,
+            updated_at as contact_updated_at,
+        };
+        use core::schema::users::dsl::{changed_at, hash_tele_num, users};
+        use diesel::dsl::{count_star, max};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let mut query = contacts
+            .filter(from_id.eq(user.id))
+            .inner_join(users.on(hash_tele_num.eq(target_hash_tele_num)))
+            .select((
+                count_star(),
+                max(changed_at),
+                max(contact_created_at),
+                max(contact_updated_at),
+            ))
+            .into_boxed();
+
+        // Same filter as `get_contacts`
+        if let Some(since) = since {
+            query = query.filter(
+                changed_at
+                    .gt(since)
+                    .or(contact_created_at.gt(since))
+                    .or(contact_updated_at.gt(since)),
+            );
+        }
+
+        let (count, status, created, updated) = query.first::<(
+            i64,
+            Option<chrono::NaiveDateTime>,
+            Option<chrono::NaiveDateTime>,
+            Option<chrono::NaiveDateTime>,
+        )>(conn)?;
+
+        // Removals change the version too
+        let mut tombstones = tombstones::contact_tombstones
+            .filter(tombstones::from_id.eq(user.id))
+            .select((count_star(), max(tombstones::removed_at)))
+            .into_boxed();
+
+        if let Some(since) = since {
+            tombstones = tombstones.filter(tombstones::removed_at.gt(since));
+        }
+
+        let (removed, removed_at) =
+            tombstones.first::<(i64, Option<chrono::NaiveDateTime>)>(conn)?;
+
+        Ok((
+            count + removed,
+            status.max(created).max(updated).max(removed_at),
+        ))
+    }
+
+    fn get_removed_contacts(
+        &self,
+        user: &UserDao,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<HashedTeleNum>, ServiceError> {
+        info!("queries/contacts/get_removed_contacts");
+        use core::schema::contact_tombstones::dsl::{
+            contact_tombstones, from_id, hash_tele_num, removed_at,
+        };
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        contact_tombstones
+            .filter(from_id.eq(user.id))
+            .filter(removed_at.gt(since))
+            .select(hash_tele_num)
+            .load::<HashedTeleNum>(conn)
+            .map_err(ServiceError::from)
+    }
+
+    fn discover(
+        &self,
+        user: &UserDao,
+        hashes: &[HashedTeleNum],
+    ) -> Result<Vec<HashedTeleNum>, ServiceError> {
+        info!("queries/contacts/discover");
+        use core::schema::blacklist::dsl::{
+            blacklist, hash_blocked, hash_blocker, id as blacklist_id,
+        };
+        use core::schema::users::dsl::{hash_tele_num, users};
+
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        users
+            .filter(hash_tele_num.eq_any(hashes))
+            .filter(hash_tele_num.ne(&user.hash_tele_num))
+            .left_join(
+                blacklist.on(hash_blocker
+                    .eq(hash_tele_num)
+                    .and(hash_blocked.eq(&user.hash_tele_num))),
+            )
+            .filter(blacklist_id.is_null())
+            .select(hash_tele_num)
+            .load::<HashedTeleNum>(conn)
+            .map_err(ServiceError::from)
+    }
+}
+
+/// Replaces the old numbers of users, which changed their number, by the current ones.
+/// The phones of their contacts still hold the old number.
+fn renumber(
+    conn: &PgConnection,
+    hashes: &[HashedTeleNum],
+) -> Result<Vec<HashedTeleNum>, ServiceError> {
+    let changes = tele_num_changes_of(conn, hashes)?;
+
+    Ok(hashes
+        .iter()
+        .map(|w| changes.get(w).cloned().unwrap_or_else(|| w.clone()))
+        .collect())
+}
+
+/// `renumber` for the uploaded contacts
+fn renumber_contacts(
+    conn: &PgConnection,
+    payload: &[PayloadUserDto],
+) -> Result<Vec<PayloadUserDto>, ServiceError> {
+    let hashes: Vec<_> = payload.iter().map(|w| w.hash_tele_num.clone()).collect();
+    let renumbered = renumber(conn, &hashes)?;
+
+    Ok(payload
+        .iter()
+        .zip(renumbered)
+        .map(|(w, hash_tele_num)| PayloadUserDto {
+            name: w.name.clone(),
+            hash_tele_num,
+        })
+        .collect())
+}
+
+/// Current hashes of the old hashes among `hashes`
+fn tele_num_changes_of(
+    conn: &PgConnection,
+    hashes: &[HashedTeleNum],
+) -> Result<std::collections::HashMap<HashedTeleNum, HashedTeleNum>, ServiceError> {
+    use core::schema::tele_num_changes::dsl::{hash_tele_num, old_hash_tele_num, tele_num_changes};
+
+    if hashes.is_empty() {
+        return Ok(Default::default());
+    }
+
+    let changes = tele_num_changes
+        .filter(old_hash_tele_num.eq_any(hashes))
+        .select((old_hash_tele_num, hash_tele_num))
+        .load::<(HashedTeleNum, HashedTeleNum)>(conn)?
+        .into_iter()
+        .collect();
+
+    Ok(changes)
+}
+
+/// Inserts the contacts in batches. Renamed contacts are updated. Numbers, which don't
+/// belong to a user, are pending, because of `fk_contacts_target_hash`.
+fn upsert_contacts(
+    conn: &PgConnection,
+    user: &UserDao,
+    payload: &[PayloadUserDto],
+    current_time: chrono::NaiveDateTime,
+) -> Result<(), ServiceError> {
+    use core::schema::contact_tombstones::dsl as tombstones;
+    use core::schema::contacts::dsl::{contacts, from_id, name, target_hash_tele_num, updated_at};
+    use core::schema::pending_contacts::dsl as pending;
+    use core::schema::users::dsl::{hash_tele_num, users};
+    use diesel::pg::upsert::excluded;
+    use std::collections::{HashMap, HashSet};
+
+    if payload.is_empty() {
+        return Ok(());
+    }
+
+    let hashes: Vec<_> = payload.iter().map(|w| w.hash_tele_num.clone()).collect();
+    let registered: HashSet<HashedTeleNum> = users
+        .filter(hash_tele_num.eq_any(&hashes))
+        .select(hash_tele_num)
+        .load::<HashedTeleNum>(conn)?
+        .into_iter()
+        .collect();
+
+    let known: HashMap<HashedTeleNum, String> = contacts
+        .filter(from_id.eq(user.id))
+        .select((target_hash_tele_num, name))
+        .load::<(HashedTeleNum, String)>(conn)?
+        .into_iter()
+        .collect();
+
+    let inserts: Vec<_> = payload
+        .iter()
+        .filter(|w| registered.contains(&w.hash_tele_num))
+        .filter(|w| !known.contains_key(&w.hash_tele_num))
+        .map(|w| ContactInsertDao {
+            from_id: user.id,
+            target_hash_tele_num: w.hash_tele_num.clone(),
+            name: w.name.clone(),
+            created_at: current_time,
+            updated_at: current_time,
+        })
+        .collect();
+
+    for batch in inserts.chunks(CONTACTS_BATCH_SIZE) {
+        diesel::insert_into(contacts)
+            .values(batch)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        // The contacts were added again
+        let added: Vec<_> = batch.iter().map(|w| &w.target_hash_tele_num).collect();
+
+        diesel::delete(
+            tombstones::contact_tombstones
+                .filter(tombstones::from_id.eq(user.id))
+                .filter(tombstones::target_hash_tele_num.eq_any(added)),
+        )
+        .execute(conn)?;
+    }
+
+    let pending: Vec<_> = payload
+        .iter()
+        .filter(|w| !registered.contains(&w.hash_tele_num))
+        .map(|w| PendingContactDao {
+            from_id: user.id,
+            target_hash_tele_num: w.hash_tele_num.clone(),
+            name: w.name.clone(),
+            created_at: current_time,
+        })
+        .collect();
+
+    for batch in pending.chunks(CONTACTS_BATCH_SIZE) {
+        diesel::insert_into(pending::pending_contacts)
+            .values(batch)
+            .on_conflict((pending::from_id, pending::target_hash_tele_num))
+            .do_update()
+            .set(pending::name.eq(excluded(pending::name)))
+            .execute(conn)?;
+    }
+
+    // Only the renamed contacts are fetched again with `since`
+    let renamed = payload.iter().filter(|w| {
+        known
+            .get(&w.hash_tele_num)
+            .map_or(false, |known_name| *known_name != w.name)
+    });
+
+    for contact in renamed {
+        diesel::update(
+            contacts
+                .filter(from_id.eq(user.id))
+                .filter(target_hash_tele_num.eq(&contact.hash_tele_num)),
+        )
+        .set((name.eq(&contact.name), updated_at.eq(current_time)))
+        .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Moves the pending contacts with the number `hash` to the contacts, when the number
+/// is registered. They are new for the next `get_contacts` with `since`.
+pub(crate) fn link_pending_contacts(
+    conn: &PgConnection,
+    hash: &HashedTeleNum,
+    current_time: chrono::NaiveDateTime,
+) -> Result<usize, ServiceError> {
+    use core::schema::pending_contacts::dsl::{pending_contacts, target_hash_tele_num};
+    use core::schema::tele_num_changes::dsl as changes;
+    use diesel::sql_types::{Text, Timestamp};
+
+    let linked = diesel::sql_query(
+        "INSERT INTO contacts (from_id, target_hash_tele_num, name, created_at, updated_at) \
+         SELECT p.from_id, p.target_hash_tele_num, p.name, $2, $2 FROM pending_contacts p \
+         WHERE p.target_hash_tele_num = $1 ON CONFLICT DO NOTHING",
+    )
+    .bind::<Text, _>(hash)
+    .bind::<Timestamp, _>(current_time)
+    .execute(conn)?;
+
+    diesel::sql_query(
+        "DELETE FROM contact_tombstones t USING pending_contacts p \
+         WHERE p.target_hash_tele_num = $1 AND t.from_id = p.from_id \
+            AND t.target_hash_tele_num = p.target_hash_tele_num",
+    )
+    .bind::<Text, _>(hash)
+    .execute(conn)?;
+
+    diesel::delete(pending_contacts.filter(target_hash_tele_num.eq(hash))).execute(conn)?;
+
+    // The number doesn't stand for the user, who left it, anymore
+    diesel::delete(changes::tele_num_changes.filter(changes::old_hash_tele_num.eq(hash)))
+        .execute(conn)?;
+
+    Ok(linked)
+}
+
+/// Records the removal of the contacts `removed` of the user `owner_id`, before they are
+/// deleted. The hashes of the clients are kept, because the numbers might be deleted too.
+pub(crate) fn bury_contacts(
+    conn: &PgConnection,
+    owner_id: &Uuid,
+    removed: &[HashedTeleNum],
+    current_time: chrono::NaiveDateTime,
+) -> Result<usize, ServiceError> {
+    use core::schema::contact_tombstones::dsl::{
+        contact_tombstones, from_id, hash_tele_num, removed_at, target_hash_tele_num,
+    };
+    use core::schema::users::dsl as users;
+    use diesel::pg::upsert::excluded;
+
+    if removed.is_empty() {
+        return Ok(0);
+    }
+
+    let tombstones: Vec<_> = users::users
+        .filter(users::hash_tele_num.eq_any(removed))
+        .select((users::hash_tele_num, users::tele_num))
+        .load::<(HashedTeleNum, String)>(conn)?
+        .into_iter()
+        .map(|(hash, tele_num)| ContactTombstoneDao {
+            from_id: *owner_id,
+            hash_tele_num: hash.for_client(&tele_num),
+            target_hash_tele_num: hash,
+            removed_at: current_time,
+        })
+        .collect();
+
+    let mut buried = 0;
+
+    for batch in tombstones.chunks(CONTACTS_BATCH_SIZE) {
+        buried += diesel::insert_into(contact_tombstones)
+            .values(batch)
+            .on_conflict((from_id, target_hash_tele_num))
+            .do_update()
+            .set((
+                hash_tele_num.eq(excluded(hash_tele_num)),
+                removed_at.eq(excluded(removed_at)),
+            ))
+            .execute(conn)?;
+    }
+
+    Ok(buried)
+}
+
+/// Records the removal of the contacts, which have the user `target`, before they are
+/// deleted
+pub(crate) fn bury_contacts_of(
+    conn: &PgConnection,
+    target: &UserDao,
+    current_time: chrono::NaiveDateTime,
+) -> Result<usize, ServiceError> {
+    use diesel::sql_types::{Text, Timestamp};
+
+    let buried = diesel::sql_query(
+        "INSERT INTO contact_tombstones (from_id, target_hash_tele_num, hash_tele_num, removed_at) \
+         SELECT from_id, target_hash_tele_num, $2, $3 FROM contacts \
+         WHERE target_hash_tele_num = $1 AND from_id <> $4 \
+         ON CONFLICT (from_id, target_hash_tele_num) \
+         DO UPDATE SET hash_tele_num = EXCLUDED.hash_tele_num, removed_at = EXCLUDED.removed_at",
+    )
+    .bind::<Text, _>(&target.hash_tele_num)
+    .bind::<Text, _>(target.hash_tele_num.for_client(&target.tele_num))
+    .bind::<Timestamp, _>(current_time)
+    .bind::<diesel::sql_types::Uuid, _>(target.id)
+    .execute(conn)?;
+
+    Ok(buried)
+}
+
+/// Marks the contacts between the users `a` and `b` in both directions as changed
+pub(crate) fn touch_contacts_between(
+    conn: &PgConnection,
+    a: &HashedTeleNum,
+    b: &HashedTeleNum,
+    current_time: chrono::NaiveDateTime,
+) -> Result<usize, ServiceError> {
+    use diesel::sql_types::{Text, Timestamp};
+
+    let touched = diesel::sql_query(
+        "UPDATE contacts c SET updated_at = $3 FROM users u \
+         WHERE c.from_id = u.id AND ((u.hash_tele_num = $1 AND c.target_hash_tele_num = $2) \
+            OR (u.hash_tele_num = $2 AND c.target_hash_tele_num = $1))",
+    )
+    .bind::<Text, _>(a)
+    .bind::<Text, _>(b)
+    .bind::<Timestamp, _>(current_time)
+    .execute(conn)?;
+
+    Ok(touched)
+}
+
+/// Marks the contacts, which have the user `owner_id`, as changed
+pub(crate) fn touch_contacts_of(
+    conn: &PgConnection,
+    owner_id: &Uuid,
+    current_time: chrono::NaiveDateTime,
+) -> Result<usize, ServiceError> {
+    use diesel::sql_types::Timestamp;
+
+    let touched = diesel::sql_query(
+        "UPDATE contacts c SET updated_at = $2 FROM users u \
+         WHERE u.id = $1 AND c.target_hash_tele_num = u.hash_tele_num",
+    )
+    .bind::<diesel::sql_types::Uuid, _>(*owner_id)
+    .bind::<Timestamp, _>(current_time)
+    .execute(conn)?;
+
+    Ok(touched)
+}
+
+/// Increases the sync cursor of `user`
+fn next_cursor(
+    conn: &PgConnection,
+    user: &UserDao,
+    current_time: chrono::NaiveDateTime,
+) -> Result<i64, ServiceError> {
+    use core::schema::contact_sync::dsl::{contact_sync, cursor, updated_at, user_id};
+
+    let sync = diesel::insert_into(contact_sync)
+        .values(&ContactSyncDao {
+            user_id: user.id,
+            cursor: 1,
+            updated_at: current_time,
+        })
+        .on_conflict(user_id)
+        .do_update()
+        .set((cursor.eq(cursor + 1), updated_at.eq(current_time)))
+        .get_result::<ContactSyncDao>(conn)?;
+
+    Ok(sync.cursor)
 }
 
 /*
@@ -353,7 +804,7 @@ pub(crate) fn get_query(
             hash_tele_num,
         ))
         .load::<User>(conn)
-        .map_err(|_db_error| ServiceError::BadRequest("Invalid User".into()))
+        .map_err(ServiceError::from)
         .and_then(|result| {
             if let Some(user) = result.first() {
                 //only the first row is required
@@ -369,7 +820,7 @@ pub(crate) fn get_query(
                     )
                     .order(changed_at.desc())
                     .load::<User>(conn)
-                    .map_err(|_db_error| ServiceError::BadRequest("Invalid User".into()))
+                    .map_err(ServiceError::from)
                     .and_then(|mut result| {
                         //i are contacts
                         for i in result.iter_mut() {
@@ -407,19 +858,13 @@ pub(crate) fn get_query(
                         //We need to delete all numbers, because
                         //user shall not receive push notifications
                         //for contacts he deleted
-                        let _ = diesel::delete(target).execute(conn).map_err(|_db_err| {
-                            eprintln!("{}", _db_err);
-                            ServiceError::BadRequest("Could reset contacts".into())
-                        })?;
+                        let _ = diesel::delete(target).execute(conn).map_err(ServiceError::from)?;
 
                         let _ = diesel::insert_into(contacts)
                             .values(user_contacts)
                             .on_conflict_do_nothing()
                             .execute(conn)
-                            .map_err(|_db_err| {
-                                eprintln!("{}", _db_err);
-                                ServiceError::BadRequest("Could set contacts".into())
-                            })?;
+                            .map_err(ServiceError::from)?;
 
                         Ok(numbers)
                     })
@@ -0,0 +1,117 @@
+use diesel::{prelude::*, PgConnection};
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+use uuid::Uuid;
+
+use crate::Pool;
+
+use crate::queries::*;
+use log::info;
+
+type IResult<V> = Result<V, ServiceError>;
+
+#[derive(Clone)]
+pub struct PgDeviceDao {
+    pub pool: Pool,
+}
+
+impl PersistentDeviceDao for PgDeviceDao {
+    fn get_all(&self, my_user_id: &Uuid) -> IResult<Vec<DeviceDao>> {
+        info!("queries/device/get_all");
+        use core::schema::devices::dsl::{devices, last_seen, user_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let list = devices
+            .filter(user_id.eq(my_user_id))
+            .order(last_seen.desc())
+            .load::<DeviceDao>(conn)?;
+
+        Ok(list)
+    }
+
+    fn register(&self, device: InsertDeviceDao) -> IResult<DeviceDao> {
+        info!("queries/device/register");
+        use core::schema::devices::dsl::{
+            app_version, devices, last_seen, platform, token, user_id,
+        };
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            let owner = devices
+                .filter(token.eq(&device.token))
+                .select(user_id)
+                .for_update()
+                .first::<Uuid>(conn)
+                .optional()?;
+
+            if owner.map_or(false, |owner| owner != device.user_id) {
+                return Err(ServiceError::AlreadyExists);
+            }
+
+            let registered = diesel::insert_into(devices)
+                .values(&device)
+                .on_conflict(token)
+                .do_update()
+                .set((
+                    platform.eq(&device.platform),
+                    app_version.eq(&device.app_version),
+                    last_seen.eq(device.last_seen),
+                ))
+                .get_result::<DeviceDao>(conn)?;
+
+            // A concurrent registration of another user won the insert
+            if registered.user_id != device.user_id {
+                return Err(ServiceError::AlreadyExists);
+            }
+
+            Ok(registered)
+        })
+    }
+
+    fn unregister(&self, my_user_id: &Uuid, my_token: &str) -> IResult<()> {
+        info!("queries/device/unregister");
+        use core::schema::devices::dsl::{devices, token, user_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let removed = diesel::delete(
+            devices
+                .filter(user_id.eq(my_user_id))
+                .filter(token.eq(my_token)),
+        )
+        .execute(conn)?;
+
+        if removed == 0 {
+            return Err(ServiceError::ResourceDoesNotExist);
+        }
+
+        Ok(())
+    }
+
+    fn remove_token(&self, my_token: &str) -> IResult<usize> {
+        info!("queries/device/remove_token");
+        use core::schema::devices::dsl::{devices, token};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let removed = diesel::delete(devices.filter(token.eq(my_token))).execute(conn)?;
+
+        Ok(removed)
+    }
+
+    fn touch(&self, my_user_id: &Uuid, my_last_seen: chrono::NaiveDateTime) -> IResult<usize> {
+        info!("queries/device/touch");
+        use core::schema::devices::dsl::{devices, last_seen, user_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let touched = diesel::update(devices.filter(user_id.eq(my_user_id)))
+            .set(last_seen.eq(my_last_seen))
+            .execute(conn)?;
+
+        Ok(touched)
+    }
+}
@@ -1,12 +1,10 @@
 use crate::queries::*;
 use crate::Pool;
-use chrono::{DateTime, Local};
 use core::errors::{InternalServerError, ServiceError};
 use core::models::dao::*;
 use core::models::dto::*;
-use core::models::PhoneNumber;
 use diesel::{prelude::*, PgConnection};
-use log::{debug, error, info, trace};
+use log::{error, info};
 use uuid::Uuid;
 
 type IResult<V> = Result<V, ServiceError>;
@@ -16,6 +14,60 @@ pub struct PgInvitationDao {
     pub pool: Pool,
 }
 
+impl PgInvitationDao {
+    /// Resolves the contacts to registered users and inserts them as members.
+    /// Contacts without an account are ignored. Returns the hashes of the users, which
+    /// were not members yet.
+    fn insert_members(
+        &self,
+        conn: &PgConnection,
+        my_inv_id: i32,
+        contacts: &[ContactDao],
+    ) -> IResult<Vec<HashedTeleNum>> {
+        use core::schema::invitation_members::dsl::{invitation_members, user_id};
+        use core::schema::users::dsl::{hash_tele_num, id, users};
+
+        if contacts.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let hashes: Vec<_> = contacts
+            .iter()
+            .map(|w| w.target_hash_tele_num.clone())
+            .collect();
+
+        let candidates = users
+            .filter(hash_tele_num.eq_any(hashes))
+            .select((id, hash_tele_num))
+            .load::<(Uuid, HashedTeleNum)>(conn)?;
+
+        let members: Vec<_> = candidates
+            .iter()
+            .map(|(member_id, _)| InvitationMemberDao {
+                inv_id: my_inv_id,
+                user_id: *member_id,
+                is_seen: false,
+                state: INVITATION_STATE_UNDECIDED,
+                created_at: chrono::Utc::now().naive_local(),
+                updated_at: chrono::Utc::now().naive_local(),
+            })
+            .collect();
+
+        let inserted = diesel::insert_into(invitation_members)
+            .values(&members)
+            .on_conflict_do_nothing()
+            .returning(user_id)
+            .get_results::<Uuid>(conn)
+            .map_err(ServiceError::from)?;
+
+        Ok(candidates
+            .into_iter()
+            .filter(|(member_id, _)| inserted.contains(member_id))
+            .map(|(_, hash)| hash)
+            .collect())
+    }
+}
+
 impl PersistentInvitation for PgInvitationDao {
     fn get_all(&self, user: &UserDao) -> IResult<Vec<(InvitationDao, InvitationMemberDao)>> {
         info!("fn get_all()");
@@ -35,54 +87,63 @@ impl PersistentInvitation for PgInvitationDao {
             let binv = invitation
                 .filter(id.eq(m.inv_id))
                 .load::<InvitationDao>(conn)
-                .map_err(|_db_err| {
-                    error!("Invitation {}", _db_err);
-                    ServiceError::BadRequest("Database fetch failed".to_string())
-                })?;
+                .map_err(ServiceError::from)?;
 
             let inv = binv
                 .first()
-                .ok_or_else(|| ServiceError::BadRequest("No invitation found".into()))?;
+                .ok_or(ServiceError::ResourceDoesNotExist)?;
 
             v.push((inv.clone(), m));
         }
 
-        //TODO sort
+        v.sort_by(|a, b| b.0.edit_time.cmp(&a.0.edit_time));
 
         Ok(v)
     }
 
-    fn get(&self, user: &UserDao, inv_id: i32) -> IResult<(InvitationDao, InvitationMemberDao)> {
+    fn get(&self, user: &UserDao, my_inv_id: i32) -> IResult<(InvitationDao, InvitationMemberDao)> {
         info!("fn get()");
 
-        use core::schema::invitation::dsl::*;
-        use core::schema::invitation_members::dsl::*;
+        use core::schema::invitation::dsl::{id, invitation};
+        use core::schema::invitation_members::dsl::{inv_id, invitation_members, user_id};
 
         let conn: &PgConnection = &self.pool.get().unwrap();
 
         let bmember_of = invitation_members
-            .filter(user_id.eq(user.id))
+            .filter(inv_id.eq(my_inv_id).and(user_id.eq(user.id)))
             .load::<InvitationMemberDao>(conn)?;
 
         let member_of = bmember_of
             .first()
-            .ok_or_else(|| ServiceError::BadRequest("No invitation (member) found".into()))?;
+            .ok_or_else(|| ServiceError::ResourceDoesNotExist)?;
 
         let binv = invitation
             .filter(id.eq(member_of.inv_id))
             .load::<InvitationDao>(conn)
-            .map_err(|_db_err| {
-                error!("Invitation {}", _db_err);
-                ServiceError::BadRequest("Database fetch failed".to_string())
-            })?;
+            .map_err(ServiceError::from)?;
 
         let inv = binv
             .first()
-            .ok_or_else(|| ServiceError::BadRequest("No invitation found".into()))?;
+            .ok_or_else(|| ServiceError::ResourceDoesNotExist)?;
 
         Ok((inv.clone(), member_of.clone()))
     }
 
+    fn get_members(&self, my_inv_id: i32) -> IResult<Vec<InvitationMemberDao>> {
+        info!("fn get_members()");
+
+        use core::schema::invitation_members::dsl::{created_at, inv_id, invitation_members};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let members = invitation_members
+            .filter(inv_id.eq(my_inv_id))
+            .order_by(created_at.asc())
+            .load::<InvitationMemberDao>(conn)?;
+
+        Ok(members)
+    }
+
     fn create_invitation(
         &self,
         user: &UserDao,
@@ -91,73 +152,72 @@ impl PersistentInvitation for PgInvitationDao {
     ) -> IResult<(InvitationDao, InvitationMemberDao)> {
         info!("fn create_invitation()");
 
-        use core::schema::invitation::dsl::*;
-        use core::schema::invitation_members::dsl::*;
+        use core::schema::invitation::dsl::invitation;
+        use core::schema::invitation_members::dsl::invitation_members;
 
         let conn: &PgConnection = &self.pool.get().unwrap();
 
-        let inv = InsertInvitationDao {
-            originator_user_id: user.id,
-            edit_text: data.text.clone(),
-            edit_time: data.time.clone(),
-            original_time: data.time,
-            original_text: data.text,
-            created_at: chrono::Utc::now().naive_local(),
-            updated_at: chrono::Utc::now().naive_local(),
-        };
-
-        let inserted_inv = diesel::insert_into(invitation)
-            .values(&inv)
-            .get_result::<InvitationDao>(conn)
-            .map_err(|_db_error| {
-                error!("{:?}", _db_error);
-                ServiceError::BadRequest("Cannot insert into invitation".into())
-            })?;
-
-        let mem = InvitationMemberDao {
-            inv_id: inserted_inv.id,
-            user_id: user.id.clone(),
-            is_seen: false,
-            state: 0,
-            created_at: chrono::Utc::now().naive_local(),
-            updated_at: chrono::Utc::now().naive_local(),
-        };
-
-        let inserted_mem = diesel::insert_into(invitation_members)
-            .values(&mem)
-            .get_result::<InvitationMemberDao>(conn)
-            .map_err(|_db_error| {
-                error!("{:?}", _db_error);
-                ServiceError::BadRequest("Cannot insert into invitation_members".into())
-            })?;
-
-        Ok((inserted_inv, inserted_mem))
+        conn.transaction::<_, ServiceError, _>(|| {
+            let inv = InsertInvitationDao {
+                originator_user_id: user.id,
+                edit_text: data.text.clone(),
+                edit_time: data.time.clone(),
+                original_time: data.time,
+                original_text: data.text,
+                created_at: chrono::Utc::now().naive_local(),
+                updated_at: chrono::Utc::now().naive_local(),
+            };
+
+            let inserted_inv = diesel::insert_into(invitation)
+                .values(&inv)
+                .get_result::<InvitationDao>(conn)
+                .map_err(ServiceError::from)?;
+
+            // The originator is a member, who has already accepted
+            let mem = InvitationMemberDao {
+                inv_id: inserted_inv.id,
+                user_id: user.id.clone(),
+                is_seen: true,
+                state: INVITATION_STATE_ACCEPTED,
+                created_at: chrono::Utc::now().naive_local(),
+                updated_at: chrono::Utc::now().naive_local(),
+            };
+
+            let inserted_mem = diesel::insert_into(invitation_members)
+                .values(&mem)
+                .get_result::<InvitationMemberDao>(conn)
+                .map_err(ServiceError::from)?;
+
+            self.insert_members(conn, inserted_inv.id, contacts)?;
+
+            Ok((inserted_inv, inserted_mem))
+        })
     }
 
     fn update_invitation(
         &self,
         user: &UserDao,
-        inv_id: i32,
+        my_inv_id: i32,
         data: UpdateInvitationStateDto,
     ) -> IResult<()> {
         info!("fn update_invitation()");
 
-        //use core::schema::invitation::dsl::*;
         use core::schema::invitation_members::dsl::*;
 
         let conn: &PgConnection = &self.pool.get().unwrap();
 
-        let target = invitation_members.filter(inv_id.eq(inv_id).and(user_id.eq(user.id)));
+        let target = invitation_members.filter(inv_id.eq(my_inv_id).and(user_id.eq(user.id)));
 
         let new_state = match data.accept {
-            true => 0,
-            false => 1,
+            true => INVITATION_STATE_ACCEPTED,
+            false => INVITATION_STATE_DECLINED,
         };
 
-        diesel::update(target)
+        let updated = diesel::update(target)
             .set((
                 updated_at.eq(chrono::Local::now().naive_local()),
                 state.eq(new_state),
+                is_seen.eq(true),
             ))
             .execute(conn)
             .map_err(|_db_error| {
@@ -167,6 +227,10 @@ impl PersistentInvitation for PgInvitationDao {
                 ))
             })?;
 
+        if updated == 0 {
+            return Err(ServiceError::ResourceDoesNotExist);
+        }
+
         Ok(())
     }
 
@@ -175,41 +239,20 @@ impl PersistentInvitation for PgInvitationDao {
         user: &UserDao,
         my_inv_id: i32,
         contacts: &[ContactDao],
-    ) -> IResult<(InvitationDao, InvitationMemberDao)> {
-        use core::schema::invitation::dsl::{invitation, id};
-        use core::schema::invitation_members::dsl::{invitation_members,inv_id, user_id};
+    ) -> IResult<(InvitationDao, InvitationMemberDao, Vec<HashedTeleNum>)> {
+        info!("fn add_members_to_invitation()");
 
-        let conn: &PgConnection = &self.pool.get().unwrap();
+        let (inv, member) = self.get(user, my_inv_id)?;
 
-        let binv = invitation
-            .filter(id.eq(my_inv_id))
-            .load::<InvitationDao>(conn)
-            .map_err(|_db_err| {
-                error!("Invitation {}", _db_err);
-                ServiceError::BadRequest("Database fetch failed".to_string())
-            })?;
+        if inv.originator_user_id != user.id {
+            error!("Only the originator can add members");
+            return Err(ServiceError::Unauthorized);
+        }
 
-        let inv = binv
-            .first()
-            .ok_or_else(|| ServiceError::BadRequest("No invitation found".into()))?;
-
-        let mem = InvitationMemberDao {
-            inv_id: inv.id,
-            user_id: user.id.clone(),
-            is_seen: false,
-            state: 0,
-            created_at: chrono::Utc::now().naive_local(),
-            updated_at: chrono::Utc::now().naive_local(),
-        };
+        let conn: &PgConnection = &self.pool.get().unwrap();
 
-        let inserted_mem = diesel::insert_into(invitation_members)
-            .values(&mem)
-            .get_result::<InvitationMemberDao>(conn)
-            .map_err(|_db_error| {
-                error!("{:?}", _db_error);
-                ServiceError::BadRequest("Cannot insert into invitation_members".into())
-            })?;
+        let added = self.insert_members(conn, inv.id, contacts)?;
 
-        Ok((inv.clone(), inserted_mem.clone()))
+        Ok((inv, member, added))
     }
 }
@@ -4,3 +4,10 @@ pub mod contacts;
 pub mod session;
 pub mod profile_picture;
 pub mod invitation;
+pub mod verification;
+pub mod outbox;
+pub mod device;
+pub mod notification_settings;
+pub mod contact_group;
+pub mod account;
+pub mod retention;
@@ -0,0 +1,117 @@
+use diesel::{prelude::*, sql_query, sql_types::Bool, sql_types::Text, PgConnection};
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::HashedTeleNum;
+use uuid::Uuid;
+
+use crate::Pool;
+
+use crate::queries::*;
+use log::info;
+
+type IResult<V> = Result<V, ServiceError>;
+
+#[derive(QueryableByName)]
+struct Exists {
+    #[sql_type = "Bool"]
+    exists: bool,
+}
+
+#[derive(Clone)]
+pub struct PgNotificationSettingsDao {
+    pub pool: Pool,
+}
+
+impl PersistentNotificationSettingsDao for PgNotificationSettingsDao {
+    fn get(&self, my_user_id: &Uuid) -> IResult<Option<NotificationSettingsDao>> {
+        info!("queries/notification_settings/get");
+        use core::schema::notification_settings::dsl::notification_settings;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let settings = notification_settings
+            .find(my_user_id)
+            .first::<NotificationSettingsDao>(conn)
+            .optional()?;
+
+        Ok(settings)
+    }
+
+    fn update(&self, settings: NotificationSettingsDao) -> IResult<NotificationSettingsDao> {
+        info!("queries/notification_settings/update");
+        use core::schema::notification_settings::dsl::{notification_settings, user_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let updated = diesel::insert_into(notification_settings)
+            .values(&settings)
+            .on_conflict(user_id)
+            .do_update()
+            .set(&settings)
+            .get_result::<NotificationSettingsDao>(conn)?;
+
+        Ok(updated)
+    }
+
+    fn is_valid_timezone(&self, timezone: &str) -> IResult<bool> {
+        info!("queries/notification_settings/is_valid_timezone");
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let result =
+            sql_query("SELECT EXISTS(SELECT 1 FROM pg_timezone_names WHERE name = $1) AS exists")
+                .bind::<Text, _>(timezone)
+                .get_result::<Exists>(conn)?;
+
+        Ok(result.exists)
+    }
+
+    fn get_muted(&self, my_user_id: &Uuid) -> IResult<Vec<MutedContactDao>> {
+        info!("queries/notification_settings/get_muted");
+        use core::schema::muted_contacts::dsl::{created_at, muted_contacts, user_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let list = muted_contacts
+            .filter(user_id.eq(my_user_id))
+            .order(created_at.asc())
+            .load::<MutedContactDao>(conn)?;
+
+        Ok(list)
+    }
+
+    fn mute(&self, muted: MutedContactDao) -> IResult<()> {
+        info!("queries/notification_settings/mute");
+        use core::schema::muted_contacts::dsl::muted_contacts;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        diesel::insert_into(muted_contacts)
+            .values(&muted)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn unmute(&self, my_user_id: &Uuid, my_hash_muted: &HashedTeleNum) -> IResult<()> {
+        info!("queries/notification_settings/unmute");
+        use core::schema::muted_contacts::dsl::{hash_muted, muted_contacts, user_id};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let removed = diesel::delete(
+            muted_contacts
+                .filter(user_id.eq(my_user_id))
+                .filter(hash_muted.eq(my_hash_muted)),
+        )
+        .execute(conn)?;
+
+        if removed == 0 {
+            return Err(ServiceError::ResourceDoesNotExist);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use diesel::{prelude::*, PgConnection};
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+
+use crate::Pool;
+
+use crate::queries::*;
+use log::info;
+
+type IResult<V> = Result<V, ServiceError>;
+
+#[derive(Clone)]
+pub struct PgOutboxDao {
+    pub pool: Pool,
+}
+
+impl PersistentOutboxDao for PgOutboxDao {
+    fn enqueue(&self, notifications: Vec<InsertNotificationOutboxDao>) -> IResult<()> {
+        info!("queries/outbox/enqueue");
+        use core::schema::notification_outbox::dsl::notification_outbox;
+
+        if notifications.is_empty() {
+            return Ok(());
+        }
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        diesel::insert_into(notification_outbox)
+            .values(&notifications)
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn claim_due(
+        &self,
+        now: chrono::NaiveDateTime,
+        lease_until: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> IResult<Vec<NotificationOutboxDao>> {
+        info!("queries/outbox/claim_due");
+        use core::schema::notification_outbox::dsl::{
+            attempts, id, next_attempt_at, notification_outbox, status,
+        };
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            let due = notification_outbox
+                .filter(status.eq(OUTBOX_PENDING))
+                .filter(next_attempt_at.le(now))
+                .order(next_attempt_at.asc())
+                .limit(limit)
+                .for_update()
+                .skip_locked()
+                .load::<NotificationOutboxDao>(conn)?;
+
+            let ids: Vec<i32> = due.iter().map(|w| w.id).collect();
+
+            let claimed = diesel::update(notification_outbox.filter(id.eq_any(ids)))
+                .set((
+                    next_attempt_at.eq(lease_until),
+                    attempts.eq(attempts + 1),
+                ))
+                .get_results::<NotificationOutboxDao>(conn)?;
+
+            Ok(claimed)
+        })
+    }
+
+    fn mark_delivered(&self, my_id: i32, now: chrono::NaiveDateTime) -> IResult<()> {
+        info!("queries/outbox/mark_delivered");
+        use core::schema::notification_outbox::dsl::{notification_outbox, status, updated_at};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        diesel::update(notification_outbox.find(my_id))
+            .set((status.eq(OUTBOX_DELIVERED), updated_at.eq(now)))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn mark_failed(
+        &self,
+        my_id: i32,
+        error: String,
+        retry_at: Option<chrono::NaiveDateTime>,
+        now: chrono::NaiveDateTime,
+    ) -> IResult<()> {
+        info!("queries/outbox/mark_failed");
+        use core::schema::notification_outbox::dsl::{
+            last_error, next_attempt_at, notification_outbox, status, updated_at,
+        };
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let (my_status, my_next_attempt_at) = match retry_at {
+            Some(retry_at) => (OUTBOX_PENDING, retry_at),
+            None => (OUTBOX_DEAD, now),
+        };
+
+        diesel::update(notification_outbox.find(my_id))
+            .set((
+                status.eq(my_status),
+                next_attempt_at.eq(my_next_attempt_at),
+                last_error.eq(Some(error)),
+                updated_at.eq(now),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn remove_delivered(&self, before: chrono::NaiveDateTime) -> IResult<usize> {
+        info!("queries/outbox/remove_delivered");
+        use core::schema::notification_outbox::dsl::{notification_outbox, status, updated_at};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let removed = diesel::delete(
+            notification_outbox
+                .filter(status.eq(OUTBOX_DELIVERED))
+                .filter(updated_at.lt(before)),
+        )
+        .execute(conn)?;
+
+        Ok(removed)
+    }
+}
@@ -0,0 +1,57 @@
+use chrono::NaiveDateTime;
+use diesel::{prelude::*, PgConnection};
+
+use core::errors::ServiceError;
+
+use crate::Pool;
+
+use crate::queries::*;
+use log::info;
+
+type IResult<V> = Result<V, ServiceError>;
+
+#[derive(Clone)]
+pub struct PgRetentionDao {
+    pub pool: Pool,
+}
+
+impl PersistentRetentionDao for PgRetentionDao {
+    fn purge_analytics(&self, before: NaiveDateTime) -> IResult<usize> {
+        info!("queries/retention/purge_analytics");
+        use core::schema::analytics::dsl::{analytics, created_at};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let deleted = diesel::delete(analytics.filter(created_at.lt(before))).execute(conn)?;
+
+        Ok(deleted)
+    }
+
+    fn purge_usage_statistics(&self, before: NaiveDateTime) -> IResult<usize> {
+        info!("queries/retention/purge_usage_statistics");
+        use core::schema::usage_statistics::dsl::{created_at, usage_statistics};
+        use diesel::sql_types::Timestamp;
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            // `stat_monthly` adds the monthly counts to the remaining usage statistics
+            diesel::sql_query(
+                "INSERT INTO usage_statistics_monthly (pseudonym, yearly, monthly, count) \
+                 SELECT pseudonym, date_part('year', created_at)::INT AS yearly, \
+                 date_part('month', created_at)::INT AS monthly, COUNT(*) \
+                 FROM usage_statistics WHERE created_at < $1 \
+                 GROUP BY pseudonym, yearly, monthly \
+                 ON CONFLICT (pseudonym, yearly, monthly) \
+                 DO UPDATE SET count = usage_statistics_monthly.count + EXCLUDED.count",
+            )
+            .bind::<Timestamp, _>(before)
+            .execute(conn)?;
+
+            let deleted =
+                diesel::delete(usage_statistics.filter(created_at.lt(before))).execute(conn)?;
+
+            Ok(deleted)
+        })
+    }
+}
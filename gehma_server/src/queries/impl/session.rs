@@ -1,8 +1,15 @@
 use crate::queries::*;
 use crate::RedisPool;
-use r2d2_redis::redis::{cmd};
+use r2d2_redis::r2d2::PooledConnection;
+use r2d2_redis::redis::{cmd, RedisError};
+use r2d2_redis::RedisConnectionManager;
 use uuid::Uuid;
-use core::errors::ServiceError;
+use core::errors::{InternalServerError, ServiceError};
+use log::error;
+#[cfg(test)]
+use std::collections::HashMap;
+#[cfg(test)]
+use std::sync::Mutex;
 
 #[derive(Clone)]
 pub struct RedisSessionDao {
@@ -13,6 +20,89 @@ pub fn get_default_expire() -> i64 {
     28800 //8 hours
 }
 
+fn get_session_key(id: &Uuid, session: &str) -> String {
+    format!("session:{}:{}", id, session)
+}
+
+fn get_refresh_token_key(id: &Uuid, family: &str) -> String {
+    format!("refresh:{}:{}", id, family)
+}
+
+/// Set of the session and refresh token keys of the user `id`. It lives as long as the
+/// longest of them, so the user can be logged out without scanning the keys.
+fn get_index_key(id: &Uuid) -> String {
+    format!("sessions:{}", id)
+}
+
+/// Sets `KEYS[1]` to `ARGV[1]` and adds it to the index `KEYS[2]`.
+/// Both expire after `ARGV[2]` seconds.
+const SET_SCRIPT: &str = r#"
+redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+redis.call('SADD', KEYS[2], KEYS[1])
+if redis.call('TTL', KEYS[2]) < tonumber(ARGV[2]) then
+    redis.call('EXPIRE', KEYS[2], ARGV[2])
+end
+return 1
+"#;
+
+/// Sets `KEYS[1]` to `ARGV[2]` if it is still `ARGV[1]` and keeps the index `KEYS[2]`
+/// at least as long
+const SWAP_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+    if redis.call('TTL', KEYS[2]) < tonumber(ARGV[3]) then
+        redis.call('EXPIRE', KEYS[2], ARGV[3])
+    end
+    return 1
+end
+return 0
+"#;
+
+/// Removes the keys in the index `KEYS[1]` and the index itself
+const CLEAR_SCRIPT: &str = r#"
+local keys = redis.call('SMEMBERS', KEYS[1])
+for i = 1, #keys, 1000 do
+    redis.call('DEL', unpack(keys, i, math.min(i + 999, #keys)))
+end
+redis.call('DEL', KEYS[1])
+return #keys
+"#;
+
+fn map_redis_error(err: RedisError) -> ServiceError {
+    error!("redis {}", err);
+    ServiceError::InternalServerError(InternalServerError::DatabaseError(err.to_string()))
+}
+
+impl RedisSessionDao {
+    fn connection(&self) -> Result<PooledConnection<RedisConnectionManager>, ServiceError> {
+        self.redis_pool.get().map_err(|err| {
+            error!("redis pool {}", err);
+            ServiceError::InternalServerError(InternalServerError::DatabaseError(err.to_string()))
+        })
+    }
+
+    /// Stores `value` under `key` and indexes it for the user `id`
+    fn set_indexed(
+        &self,
+        id: &Uuid,
+        key: String,
+        value: &str,
+        expire: Option<i64>,
+    ) -> Result<(), ServiceError> {
+        let mut connection = self.connection()?;
+
+        cmd("EVAL")
+            .arg(SET_SCRIPT)
+            .arg(2)
+            .arg(key)
+            .arg(get_index_key(id))
+            .arg(value)
+            .arg(expire.unwrap_or(get_default_expire()))
+            .query::<()>(&mut *connection)
+            .map_err(map_redis_error)
+    }
+}
+
 impl PersistentSessionDao for RedisSessionDao {
     fn set_new_session(
         &self,
@@ -20,27 +110,217 @@ impl PersistentSessionDao for RedisSessionDao {
         session: &str,
         expire: Option<i64>,
     ) -> Result<(), ServiceError> {
-        let mut connection = self.redis_pool.get().unwrap();
+        self.set_indexed(id, get_session_key(id, session), "1", expire)
+    }
 
-        cmd("SET")
-            .arg(id.to_string())
-            .arg(session)
-            .execute(&mut *connection);
+    fn is_session_valid(&self, id: &Uuid, session: &str) -> Result<bool, ServiceError> {
+        let mut connection = self.connection()?;
 
-        cmd("EXPIRE")
-            .arg(id.to_string())
-            .arg(expire.unwrap_or(get_default_expire()))
-            .execute(&mut *connection);
+        cmd("EXISTS")
+            .arg(get_session_key(id, session))
+            .query::<bool>(&mut *connection)
+            .map_err(map_redis_error)
+    }
+
+    fn remove_session(&self, id: &Uuid, session: &str) -> Result<(), ServiceError> {
+        let mut connection = self.connection()?;
+
+        cmd("DEL")
+            .arg(get_session_key(id, session))
+            .query::<()>(&mut *connection)
+            .map_err(map_redis_error)?;
 
         Ok(())
     }
 
     fn clear_session(&self, id: &Uuid) -> Result<(), ServiceError> {
-        let mut connection = self.redis_pool.get().unwrap();
+        let mut connection = self.connection()?;
+
+        cmd("EVAL")
+            .arg(CLEAR_SCRIPT)
+            .arg(1)
+            .arg(get_index_key(id))
+            .query::<()>(&mut *connection)
+            .map_err(map_redis_error)
+    }
+
+    fn set_refresh_token(
+        &self,
+        id: &Uuid,
+        family: &str,
+        token: &str,
+        expire: Option<i64>,
+    ) -> Result<(), ServiceError> {
+        self.set_indexed(id, get_refresh_token_key(id, family), token, expire)
+    }
+
+    fn get_refresh_token(&self, id: &Uuid, family: &str) -> Result<Option<String>, ServiceError> {
+        let mut connection = self.connection()?;
+
+        cmd("GET")
+            .arg(get_refresh_token_key(id, family))
+            .query::<Option<String>>(&mut *connection)
+            .map_err(map_redis_error)
+    }
+
+    fn swap_refresh_token(
+        &self,
+        id: &Uuid,
+        family: &str,
+        current: &str,
+        token: &str,
+        expire: Option<i64>,
+    ) -> Result<bool, ServiceError> {
+        let mut connection = self.connection()?;
+
+        cmd("EVAL")
+            .arg(SWAP_SCRIPT)
+            .arg(2)
+            .arg(get_refresh_token_key(id, family))
+            .arg(get_index_key(id))
+            .arg(current)
+            .arg(token)
+            .arg(expire.unwrap_or(get_default_expire()))
+            .query::<bool>(&mut *connection)
+            .map_err(map_redis_error)
+    }
+
+    fn remove_refresh_token(&self, id: &Uuid, family: &str) -> Result<(), ServiceError> {
+        let mut connection = self.connection()?;
 
         cmd("DEL")
-            .arg(id.to_string())
-            .execute(&mut *connection);
+            .arg(get_refresh_token_key(id, family))
+            .query::<()>(&mut *connection)
+            .map_err(map_redis_error)?;
+
+        Ok(())
+    }
+}
+
+/// Keeps the sessions in memory. They are lost on restart, so it is only used by the tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemorySessionDao {
+    /// Expiration timestamp of every session by user
+    sessions: Mutex<HashMap<Uuid, HashMap<String, i64>>>,
+    /// Current refresh token and its expiration timestamp of every login family by user
+    refresh_tokens: Mutex<HashMap<Uuid, HashMap<String, (String, i64)>>>,
+}
+
+#[cfg(test)]
+impl PersistentSessionDao for MemorySessionDao {
+    fn set_new_session(
+        &self,
+        id: &Uuid,
+        session: &str,
+        expire: Option<i64>,
+    ) -> Result<(), ServiceError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let user_sessions = sessions.entry(*id).or_insert_with(HashMap::new);
+
+        // Forget about expired sessions
+        user_sessions.retain(|_, exp| *exp > now);
+        user_sessions.insert(
+            session.to_string(),
+            now + expire.unwrap_or(get_default_expire()),
+        );
+
+        Ok(())
+    }
+
+    fn is_session_valid(&self, id: &Uuid, session: &str) -> Result<bool, ServiceError> {
+        let now = chrono::Utc::now().timestamp();
+        let sessions = self.sessions.lock().unwrap();
+
+        Ok(sessions
+            .get(id)
+            .and_then(|w| w.get(session))
+            .map(|exp| *exp > now)
+            .unwrap_or(false))
+    }
+
+    fn remove_session(&self, id: &Uuid, session: &str) -> Result<(), ServiceError> {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        if let Some(user_sessions) = sessions.get_mut(id) {
+            user_sessions.remove(session);
+        }
+
+        Ok(())
+    }
+
+    fn clear_session(&self, id: &Uuid) -> Result<(), ServiceError> {
+        self.sessions.lock().unwrap().remove(id);
+        self.refresh_tokens.lock().unwrap().remove(id);
+
+        Ok(())
+    }
+
+    fn set_refresh_token(
+        &self,
+        id: &Uuid,
+        family: &str,
+        token: &str,
+        expire: Option<i64>,
+    ) -> Result<(), ServiceError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut refresh_tokens = self.refresh_tokens.lock().unwrap();
+
+        let user_tokens = refresh_tokens.entry(*id).or_insert_with(HashMap::new);
+
+        user_tokens.retain(|_, (_, exp)| *exp > now);
+        user_tokens.insert(
+            family.to_string(),
+            (token.to_string(), now + expire.unwrap_or(get_default_expire())),
+        );
+
+        Ok(())
+    }
+
+    fn get_refresh_token(&self, id: &Uuid, family: &str) -> Result<Option<String>, ServiceError> {
+        let now = chrono::Utc::now().timestamp();
+        let refresh_tokens = self.refresh_tokens.lock().unwrap();
+
+        Ok(refresh_tokens
+            .get(id)
+            .and_then(|w| w.get(family))
+            .filter(|(_, exp)| *exp > now)
+            .map(|(token, _)| token.clone()))
+    }
+
+    fn swap_refresh_token(
+        &self,
+        id: &Uuid,
+        family: &str,
+        current: &str,
+        token: &str,
+        expire: Option<i64>,
+    ) -> Result<bool, ServiceError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut refresh_tokens = self.refresh_tokens.lock().unwrap();
+
+        let entry = refresh_tokens
+            .get_mut(id)
+            .and_then(|w| w.get_mut(family))
+            .filter(|(stored, exp)| *exp > now && stored == current);
+
+        match entry {
+            Some(entry) => {
+                *entry = (token.to_string(), now + expire.unwrap_or(get_default_expire()));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn remove_refresh_token(&self, id: &Uuid, family: &str) -> Result<(), ServiceError> {
+        let mut refresh_tokens = self.refresh_tokens.lock().unwrap();
+
+        if let Some(user_tokens) = refresh_tokens.get_mut(id) {
+            user_tokens.remove(family);
+        }
 
         Ok(())
     }
@@ -1,6 +1,8 @@
+use super::contacts::link_pending_contacts;
+use crate::queries::device::ACTIVE_DEVICE_DAYS;
 use crate::queries::*;
 use crate::Pool;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDateTime};
 use core::errors::{InternalServerError, ServiceError};
 use core::models::dao::*;
 use core::models::dto::*;
@@ -15,6 +17,7 @@ const INCREASE_XP: i32 = 100;
 #[derive(Clone)]
 pub struct PgUserDao {
     pub pool: Pool,
+    pub push_notification_limit: usize,
 }
 
 impl PersistentUserDao for PgUserDao {
@@ -32,10 +35,7 @@ impl PersistentUserDao for PgUserDao {
             .values(&ana)
             .get_result::<AnalyticDao>(conn)
             //.map(|w| w.into())
-            .map_err(|_db_error| {
-                error!("{}", _db_error);
-                ServiceError::BadRequest("Could not log change".into())
-            })
+            .map_err(ServiceError::from)
     }
 
     fn create(
@@ -52,17 +52,23 @@ impl PersistentUserDao for PgUserDao {
 
         let conn: &PgConnection = &self.pool.get().unwrap();
 
-        diesel::insert_into(users)
-            .values(&new_inv)
-            .get_result::<UserDao>(conn)
-            //.map(|w| w.into())
-            .map_err(|_db_error| {
-                error!("{}", _db_error);
-                error!("user {:#?}", new_inv);
-                ServiceError::InternalServerError(InternalServerError::DatabaseError(
-                    _db_error.to_string(),
-                ))
-            })
+        conn.transaction::<_, ServiceError, _>(|| {
+            let user = diesel::insert_into(users)
+                .values(&new_inv)
+                .get_result::<UserDao>(conn)
+                //.map(|w| w.into())
+                .map_err(|_db_error| {
+                    error!("{}", _db_error);
+                    error!("user {:#?}", new_inv);
+                    ServiceError::InternalServerError(InternalServerError::DatabaseError(
+                        _db_error.to_string(),
+                    ))
+                })?;
+
+            link_pending_contacts(conn, &user.hash_tele_num, user.created_at)?;
+
+            Ok(user)
+        })
     }
 
     fn create_usage_statistics_for_user(
@@ -79,38 +85,45 @@ impl PersistentUserDao for PgUserDao {
             .values(&ana)
             .get_result::<UsageStatisticEntryDao>(conn)
             //.map(|w| w.into())
-            .map_err(|_db_error| {
-                eprintln!("{}", _db_error);
-                ServiceError::BadRequest("Could not log change".into())
-            })
+            .map_err(ServiceError::from)
     }
 
     fn update_user(
         &self,
         myid: &Uuid,
         user: &UpdateUserDto,
-        _current_time: DateTime<Local>,
+        current_time: DateTime<Local>,
+        xp_limit: bool,
     ) -> Result<(UserDao, Vec<ContactPushNotificationDao>), ::core::errors::ServiceError> {
         info!("queries/user/update_user_query");
+        use core::schema::contact_groups::dsl::{contact_groups, id as group_id, owner_id};
         use core::schema::users::dsl::{
-            changed_at, client_version, description, id, led, users, xp,
+            changed_at, client_version, description, id, led, led_audience, led_expires_at, users,
+            xp,
         };
 
-        //TODO move to controller
-        /*
-        let xp_limit = self
-            .ratelimit_service
-            .inner
-            .lock()
-            .unwrap()
-            .check_rate_limit_xp(myid, &self.pool, current_time)?;
-        */
-        let xp_limit = false; //FIXME remove
-
         let conn: &PgConnection = &self.pool.get().unwrap();
 
+        if let Some(audience) = user.audience {
+            let owned: i64 = contact_groups
+                .filter(group_id.eq(audience))
+                .filter(owner_id.eq(myid))
+                .count()
+                .get_result(conn)?;
+
+            if owned == 0 {
+                return Err(ServiceError::BadRequest(
+                    "Audience is not a group of the user".to_string(),
+                ));
+            }
+        }
+
         let target = users.filter(id.eq(myid));
 
+        let expires_at = target
+            .first::<UserDao>(conn)?
+            .led_expiry(user, current_time.naive_local());
+
         let my_led = user.led;
 
         let inc_xp = match (my_led, xp_limit) {
@@ -122,27 +135,34 @@ impl PersistentUserDao for PgUserDao {
             .set((
                 description.eq(user.description.to_string()),
                 led.eq(my_led),
+                led_expires_at.eq(expires_at),
+                led_audience.eq(user.audience),
                 changed_at.eq(chrono::Local::now().naive_local()),
                 client_version.eq(user.client_version.clone()),
                 xp.eq(xp + inc_xp), // add experience for every event if `my_led` is true
             ))
             .execute(conn)
-            .map_err(|_db_error| ServiceError::BadRequest("Updating state failed".into()))?;
+            .map_err(ServiceError::from)?;
 
         users
             .filter(id.eq(myid))
             .load::<UserDao>(conn)
-            .map_err(|_db_error| ServiceError::BadRequest("Invalid User".into()))
+            .map_err(ServiceError::from)
             .and_then(|res_users| {
                 Ok(res_users
                     .first()
                     .cloned()
-                    .ok_or_else(|| ServiceError::BadRequest("No user found".into()))?)
+                    .ok_or(ServiceError::ResourceDoesNotExist)?)
                 //.map(|w| w.into())
             })
             .and_then(|user| {
                 if my_led {
-                    let contacts = get_users_for_sending_push_notification(&user, &self.pool)?;
+                    let contacts = get_users_for_sending_push_notification(
+                        &user,
+                        &self.pool,
+                        self.push_notification_limit,
+                        user.led_audience,
+                    )?;
 
                     Ok((user, contacts))
                 } else {
@@ -175,11 +195,11 @@ impl PersistentUserDao for PgUserDao {
         users
             .filter(id.eq(myid))
             .load::<UserDao>(conn)
-            .map_err(|_db_error| ServiceError::BadRequest("Invalid User".into()))
+            .map_err(ServiceError::from)
             .and_then(|w| {
                 w.first()
                     .cloned()
-                    .ok_or_else(|| ServiceError::BadRequest("No user found".into()))
+                    .ok_or(ServiceError::ResourceDoesNotExist)
                 //.map(|w| w.into())
             })
     }
@@ -218,15 +238,31 @@ impl PersistentUserDao for PgUserDao {
         users
             .filter(hash_tele_num.eq(user_hash_tele_num))
             .load::<UserDao>(conn)
-            .map_err(|_db_error| ServiceError::BadRequest("Invalid User".into()))
+            .map_err(ServiceError::from)
             .and_then(|w| {
                 w.first()
                     .cloned()
-                    .ok_or_else(|| ServiceError::BadRequest("No user found".into()))
+                    .ok_or(ServiceError::ResourceDoesNotExist)
                 //.map(|w| w.into())
             })
     }
 
+    fn get_tele_nums(
+        &self,
+        hashes: &[HashedTeleNum],
+    ) -> Result<Vec<(HashedTeleNum, String)>, ServiceError> {
+        info!("queries/user/get_tele_nums");
+        use core::schema::users::dsl::{hash_tele_num, tele_num, users};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        users
+            .filter(hash_tele_num.eq_any(hashes))
+            .select((hash_tele_num, tele_num))
+            .load::<(HashedTeleNum, String)>(conn)
+            .map_err(ServiceError::from)
+    }
+
     fn update_profile_picture(
         &self,
         user_id: Uuid,
@@ -269,14 +305,68 @@ impl PersistentUserDao for PgUserDao {
             //changed_at.eq(chrono::Local::now().naive_local()),
         )
         .execute(conn)
-        .map_err(|_db_error| {
-            error!("db_error {}", _db_error);
-            ServiceError::BadRequest("Updating state failed".into())
-        })?;
+        .map_err(ServiceError::from)?;
 
         Ok(())
     }
 
+    fn clear_token(&self, uid: &Uuid) -> Result<(), ServiceError> {
+        info!("queries/user/clear_token");
+        use core::schema::users::dsl::{firebase_token, id, users};
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        diesel::update(users.filter(id.eq(uid)))
+            .set(firebase_token.eq(None::<String>))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn update_access_token(
+        &self,
+        uid: &Uuid,
+        hashed_access_token: &str,
+    ) -> Result<(), ServiceError> {
+        info!("queries/user/update_access_token");
+        use core::schema::users::dsl::{access_token, id, users};
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let updated = diesel::update(users.filter(id.eq(uid)))
+            .set(access_token.eq(hashed_access_token))
+            .execute(conn)?;
+
+        if updated == 0 {
+            return Err(ServiceError::ResourceDoesNotExist);
+        }
+
+        Ok(())
+    }
+
+    fn get_by_token(&self, token: &str) -> Result<Vec<UserDao>, ServiceError> {
+        info!("queries/user/get_by_token");
+        use core::schema::users::dsl::{firebase_token, users};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let found = users
+            .filter(firebase_token.eq(token))
+            .load::<UserDao>(conn)?;
+
+        Ok(found)
+    }
+
+    fn get_contacts_for_push_notification(
+        &self,
+        user: &UserDao,
+    ) -> Result<Vec<ContactPushNotificationDao>, ServiceError> {
+        get_users_for_sending_push_notification(
+            user,
+            &self.pool,
+            self.push_notification_limit,
+            None,
+        )
+    }
+
     fn get_profile_picture(&self, user: &UserDao) -> Result<String, ServiceError> {
         let conn: &PgConnection = &self.pool.get().unwrap();
 
@@ -331,6 +421,32 @@ impl PersistentUserDao for PgUserDao {
         Ok(list)
     }
 
+    fn switch_off_expired(&self, now: NaiveDateTime) -> Result<Vec<UserDao>, ServiceError> {
+        info!("queries/user/switch_off_expired");
+        use core::schema::analytics::dsl::analytics;
+        use core::schema::users::dsl::{changed_at, led, led_expires_at, users};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        conn.transaction::<_, ServiceError, _>(|| {
+            let expired = diesel::update(users.filter(led.eq(true)).filter(led_expires_at.le(now)))
+                .set((
+                    led.eq(false),
+                    led_expires_at.eq(None::<NaiveDateTime>),
+                    changed_at.eq(now),
+                ))
+                .get_results::<UserDao>(conn)?;
+
+            let entries: Vec<_> = expired.iter().map(AnalyticDao::my_from).collect();
+
+            diesel::insert_into(analytics)
+                .values(&entries)
+                .execute(conn)?;
+
+            Ok(expired)
+        })
+    }
+
     /// Updates all unseen elements to seen.
     fn update_latest_broadcast(&self, user: &UserDao) -> Result<(), ::core::errors::ServiceError> {
         use core::schema::broadcast::dsl::*;
@@ -373,22 +489,47 @@ impl PersistentUserDao for PgUserDao {
 fn get_users_for_sending_push_notification(
     user: &UserDao, //sender
     pool: &Pool,
+    limit: usize,
+    audience: Option<i32>, // group of the sender, `None` is everyone
 ) -> Result<Vec<ContactPushNotificationDao>, ServiceError> {
     info!("queries/user/get_users_for_sending_push_notification");
 
     let conn: &PgConnection = &pool.get().unwrap();
 
+    let now = chrono::Local::now().naive_local();
+    let active_since = now - chrono::Duration::days(ACTIVE_DEVICE_DAYS);
+
+    // One row per contact with the tokens of all active devices. Contacts, which muted
+    // the sender, muted everything or have quiet hours, get no tokens, but are kept for
+    // the broadcast. Contacts outside of the `audience` get nothing.
     let my_contacts: Vec<ContactPushNotificationDao> = diesel::sql_query(
-        "SELECT from_id, name, firebase_token, target_hash_tele_num FROM contact_view WHERE from_id = $1",
+        "SELECT cv.from_id, cv.name, \
+            COALESCE(array_agg(d.token) FILTER (WHERE d.token IS NOT NULL), '{}')::text[] AS tokens, \
+            cv.target_hash_tele_num, cv.country_code \
+        FROM contact_view cv \
+        JOIN users u ON u.hash_tele_num = cv.target_hash_tele_num \
+        LEFT JOIN notification_settings s ON s.user_id = u.id \
+        LEFT JOIN devices d ON d.user_id = u.id AND d.last_seen > $2 \
+            AND NOT EXISTS(SELECT 1 FROM muted_contacts m WHERE m.user_id = u.id AND m.hash_muted = $3) \
+            AND (s.muted_until IS NULL OR s.muted_until <= $4) \
+            AND NOT in_quiet_hours(s.quiet_hours_start, s.quiet_hours_end, COALESCE(s.timezone, 'UTC')) \
+        WHERE cv.from_id = $1 \
+            AND ($5::int IS NULL OR EXISTS(SELECT 1 FROM contact_group_members g \
+                WHERE g.group_id = $5 AND g.hash_tele_num = cv.target_hash_tele_num)) \
+        GROUP BY cv.from_id, cv.name, cv.target_hash_tele_num, cv.country_code",
     )
     .bind::<diesel::sql_types::Uuid, _>(user.id)
+    .bind::<diesel::sql_types::Timestamp, _>(active_since)
+    .bind::<diesel::sql_types::Text, _>(&user.hash_tele_num.0)
+    .bind::<diesel::sql_types::Timestamp, _>(now)
+    .bind::<diesel::sql_types::Nullable<diesel::sql_types::Integer>, _>(audience)
     .load::<ContactPushNotificationDao>(conn)
     .map_err(|_db_error| {
         error!("{:?}", _db_error);
         ServiceError::InternalServerError(InternalServerError::DatabaseError(_db_error.to_string()))
     })?
     .into_iter()
-    .take(crate::LIMIT_PUSH_NOTIFICATION_CONTACTS)
+    .take(limit)
     .collect();
 
     /*
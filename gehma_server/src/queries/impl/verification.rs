@@ -0,0 +1,59 @@
+use diesel::{prelude::*, PgConnection};
+
+use core::errors::ServiceError;
+use core::models::dao::*;
+
+use crate::Pool;
+
+use crate::queries::*;
+use log::info;
+
+type IResult<V> = Result<V, ServiceError>;
+
+#[derive(Clone)]
+pub struct PgVerificationDao {
+    pub pool: Pool,
+}
+
+impl PersistentVerificationDao for PgVerificationDao {
+    fn get_limit(&self, my_limit_key: &str) -> IResult<Option<VerificationLimitDao>> {
+        info!("queries/verification/get_limit");
+        use core::schema::verification_limits::dsl::{limit_key, verification_limits};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        let limit = verification_limits
+            .filter(limit_key.eq(my_limit_key))
+            .first::<VerificationLimitDao>(conn)
+            .optional()?;
+
+        Ok(limit)
+    }
+
+    fn set_limit(&self, limit: &VerificationLimitDao) -> IResult<()> {
+        info!("queries/verification/set_limit");
+        use core::schema::verification_limits::dsl::{limit_key, verification_limits};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        diesel::insert_into(verification_limits)
+            .values(limit)
+            .on_conflict(limit_key)
+            .do_update()
+            .set(limit)
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    fn remove_limit(&self, my_limit_key: &str) -> IResult<()> {
+        info!("queries/verification/remove_limit");
+        use core::schema::verification_limits::dsl::{limit_key, verification_limits};
+
+        let conn: &PgConnection = &self.pool.get().unwrap();
+
+        diesel::delete(verification_limits.filter(limit_key.eq(my_limit_key))).execute(conn)?;
+
+        Ok(())
+    }
+}
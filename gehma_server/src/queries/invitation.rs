@@ -1,9 +1,6 @@
-use chrono::{DateTime, Local};
 use core::errors::ServiceError;
 use core::models::dto::*;
 use core::models::dao::*;
-use core::models::PhoneNumber;
-use uuid::Uuid;
 use mockall::*;
 
 type IResult<K> = Result<K, ServiceError>;
@@ -14,9 +11,13 @@ pub trait PersistentInvitation {
 
     fn get(&self, user: &UserDao, inv_id: i32) -> IResult<(InvitationDao, InvitationMemberDao)>;
 
+    /// Get all members (including the originator) of the invitation `inv_id`
+    fn get_members(&self, inv_id: i32) -> IResult<Vec<InvitationMemberDao>>;
+
     fn create_invitation(&self, user: &UserDao, contacts: &[ContactDao], data: RequestInvitationCreateDto) -> IResult<(InvitationDao, InvitationMemberDao)>;
 
     fn update_invitation(&self, user: &UserDao, inv_id: i32, data: UpdateInvitationStateDto) -> IResult<()>;
 
-    fn add_members_to_invitation(&self, user: &UserDao, inv_id: i32, contacts: &[ContactDao]) -> IResult<(InvitationDao, InvitationMemberDao)>;
+    /// Returns the hashes of the contacts, which were not members yet
+    fn add_members_to_invitation(&self, user: &UserDao, inv_id: i32, contacts: &[ContactDao]) -> IResult<(InvitationDao, InvitationMemberDao, Vec<HashedTeleNum>)>;
 }
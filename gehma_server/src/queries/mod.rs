@@ -5,6 +5,13 @@ pub mod r#impl;
 pub mod session;
 pub mod profile_picture;
 pub mod invitation;
+pub mod verification;
+pub mod outbox;
+pub mod device;
+pub mod notification_settings;
+pub mod contact_group;
+pub mod account;
+pub mod retention;
 
 pub use user::{MockPersistentUserDao, PersistentUserDao};
 pub use blacklist::{PersistentBlacklistDao, MockPersistentBlacklistDao};
@@ -12,11 +19,29 @@ pub use contacts::{PersistentContactsDao, MockPersistentContactsDao};
 pub use session::{PersistentSessionDao, MockPersistentSessionDao};
 pub use profile_picture::*;
 pub use invitation::*;
+pub use verification::{MockPersistentVerificationDao, PersistentVerificationDao};
+pub use outbox::{MockPersistentOutboxDao, PersistentOutboxDao};
+pub use device::{MockPersistentDeviceDao, PersistentDeviceDao};
+pub use notification_settings::{
+    MockPersistentNotificationSettingsDao, PersistentNotificationSettingsDao,
+};
+pub use contact_group::{MockPersistentContactGroupDao, PersistentContactGroupDao};
+pub use account::{MockPersistentAccountDao, PersistentAccountDao};
+pub use retention::{MockPersistentRetentionDao, PersistentRetentionDao};
 
 pub use r#impl::user::PgUserDao;
 pub use r#impl::blacklist::PgBlacklistDao;
 pub use r#impl::contacts::PgContactsDao;
+#[cfg(test)]
+pub use r#impl::session::MemorySessionDao;
 pub use r#impl::session::RedisSessionDao;
 pub use r#impl::profile_picture::*;
 pub use r#impl::invitation::PgInvitationDao;
+pub use r#impl::verification::PgVerificationDao;
+pub use r#impl::outbox::PgOutboxDao;
+pub use r#impl::device::PgDeviceDao;
+pub use r#impl::notification_settings::PgNotificationSettingsDao;
+pub use r#impl::contact_group::PgContactGroupDao;
+pub use r#impl::account::PgAccountDao;
+pub use r#impl::retention::PgRetentionDao;
 
@@ -0,0 +1,21 @@
+use core::errors::ServiceError;
+use core::models::dao::*;
+use core::models::dto::HashedTeleNum;
+use mockall::*;
+use uuid::Uuid;
+
+type IResult<K> = Result<K, ServiceError>;
+
+#[automock]
+pub trait PersistentNotificationSettingsDao {
+    /// `None`, when the user never changed the settings
+    fn get(&self, user_id: &Uuid) -> IResult<Option<NotificationSettingsDao>>;
+    /// Inserts or replaces the settings
+    fn update(&self, settings: NotificationSettingsDao) -> IResult<NotificationSettingsDao>;
+    /// Whether the database knows the IANA timezone
+    fn is_valid_timezone(&self, timezone: &str) -> IResult<bool>;
+
+    fn get_muted(&self, user_id: &Uuid) -> IResult<Vec<MutedContactDao>>;
+    fn mute(&self, muted: MutedContactDao) -> IResult<()>;
+    fn unmute(&self, user_id: &Uuid, hash_muted: &HashedTeleNum) -> IResult<()>;
+}
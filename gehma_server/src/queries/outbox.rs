@@ -0,0 +1,31 @@
+use core::errors::ServiceError;
+use core::models::dao::*;
+use mockall::*;
+
+type IResult<K> = Result<K, ServiceError>;
+
+/// Push notifications, which are delivered by the background worker
+#[automock]
+pub trait PersistentOutboxDao {
+    fn enqueue(&self, notifications: Vec<InsertNotificationOutboxDao>) -> IResult<()>;
+    /// Pending notifications, which are due at `now`. They are leased until
+    /// `lease_until`, so concurrent workers don't send them twice.
+    /// Every claim counts as an attempt, even if the worker crashes before the outcome is known.
+    fn claim_due(
+        &self,
+        now: chrono::NaiveDateTime,
+        lease_until: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> IResult<Vec<NotificationOutboxDao>>;
+    fn mark_delivered(&self, id: i32, now: chrono::NaiveDateTime) -> IResult<()>;
+    /// Retries at `next_attempt_at` or gives up, if it is `None`
+    fn mark_failed(
+        &self,
+        id: i32,
+        error: String,
+        next_attempt_at: Option<chrono::NaiveDateTime>,
+        now: chrono::NaiveDateTime,
+    ) -> IResult<()>;
+    /// Removes delivered notifications, which are older than `before`
+    fn remove_delivered(&self, before: chrono::NaiveDateTime) -> IResult<usize>;
+}
@@ -0,0 +1,16 @@
+use chrono::NaiveDateTime;
+use core::errors::ServiceError;
+use mockall::*;
+
+type IResult<K> = Result<K, ServiceError>;
+
+#[automock]
+pub trait PersistentRetentionDao {
+    /// Deletes the analytics, which were created before `before`.
+    /// Returns the number of deleted rows.
+    fn purge_analytics(&self, before: NaiveDateTime) -> IResult<usize>;
+
+    /// Adds the usage statistics, which were created before `before`, to the monthly counts
+    /// and deletes them. Returns the number of deleted rows.
+    fn purge_usage_statistics(&self, before: NaiveDateTime) -> IResult<usize>;
+}
@@ -5,6 +5,25 @@ use mockall::*;
 #[automock]
 pub trait PersistentSessionDao {
     fn set_new_session(&self, id: &Uuid, session: &str, expire: Option<i64>) -> Result<(), ServiceError>;
-    
+
+    /// Returns `true` if `session` of the user `id` exists and is not expired
+    fn is_session_valid(&self, id: &Uuid, session: &str) -> Result<bool, ServiceError>;
+
+    /// Removes only the `session` of the user `id`
+    fn remove_session(&self, id: &Uuid, session: &str) -> Result<(), ServiceError>;
+
+    /// Removes all sessions and refresh tokens of the user `id`
     fn clear_session(&self, id: &Uuid) -> Result<(), ServiceError>;
+
+    /// Sets the currently valid refresh token of the login `family`
+    fn set_refresh_token(&self, id: &Uuid, family: &str, token: &str, expire: Option<i64>) -> Result<(), ServiceError>;
+
+    /// Returns the currently valid refresh token of the login `family`
+    fn get_refresh_token(&self, id: &Uuid, family: &str) -> Result<Option<String>, ServiceError>;
+
+    /// Replaces the refresh token of the login `family` atomically, but only if it is still `current`.
+    /// Returns `false` if it was already replaced.
+    fn swap_refresh_token(&self, id: &Uuid, family: &str, current: &str, token: &str, expire: Option<i64>) -> Result<bool, ServiceError>;
+
+    fn remove_refresh_token(&self, id: &Uuid, family: &str) -> Result<(), ServiceError>;
 }
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDateTime};
 use core::errors::ServiceError;
 use core::models::dto::*;
 use core::models::dao::*;
@@ -24,6 +24,9 @@ pub trait PersistentUserDao {
 
     fn get_by_id(&self, id: &Uuid) -> IResult<UserDao>;
 
+    /// Numbers of the users with the stored `hashes`
+    fn get_tele_nums(&self, hashes: &[HashedTeleNum]) -> IResult<Vec<(HashedTeleNum, String)>>;
+
     fn create_analytics_for_user(&self, user: &UserDao) -> IResult<AnalyticDao>;
 
     fn create(
@@ -36,14 +39,29 @@ pub trait PersistentUserDao {
 
     fn create_usage_statistics_for_user(&self, user: &UserDao) -> IResult<UsageStatisticEntryDao>;
 
+    /// Updates the user. No experience is gained, when `xp_limit` was reached.
     fn update_user(
         &self,
         id: &Uuid,
         user: &UpdateUserDto,
         current_time: DateTime<Local>,
+        xp_limit: bool,
     ) -> IResult<(UserDao, Vec<ContactPushNotificationDao>)>;
+    /// Switches the leds off, which expired before `now`, and records it in the analytics.
+    /// Returns the switched off users.
+    fn switch_off_expired(&self, now: NaiveDateTime) -> IResult<Vec<UserDao>>;
     fn update_profile_picture(&self, id: Uuid, user: &UpdateProfilePictureDto) -> IResult<()>;
     fn update_token(&self, id: &Uuid, token: String) -> IResult<()>;
+    /// Removes the push notification token of the released clients
+    fn clear_token(&self, id: &Uuid) -> IResult<()>;
+    /// Replaces the access token. Only the hash is passed.
+    fn update_access_token(&self, id: &Uuid, hashed_access_token: &str) -> IResult<()>;
+    /// Users, which registered the push notification `token`
+    fn get_by_token(&self, token: &str) -> IResult<Vec<UserDao>>;
+
+    /// Get the contacts of `user`, which should receive a push notification from him.
+    /// Contacts, which don't want to be notified now, have no tokens.
+    fn get_contacts_for_push_notification(&self, user: &UserDao) -> IResult<Vec<ContactPushNotificationDao>>;
 
     /// Get the profile picture's path
     fn get_profile_picture(&self, user: &UserDao) -> IResult<String>;
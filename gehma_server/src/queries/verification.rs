@@ -0,0 +1,14 @@
+use core::errors::ServiceError;
+use core::models::dao::*;
+use mockall::*;
+
+type IResult<K> = Result<K, ServiceError>;
+
+/// Stores the limits of the sms verification, so they survive restarts
+#[automock]
+pub trait PersistentVerificationDao {
+    fn get_limit(&self, limit_key: &str) -> IResult<Option<VerificationLimitDao>>;
+    /// Inserts or replaces the limit
+    fn set_limit(&self, limit: &VerificationLimitDao) -> IResult<()>;
+    fn remove_limit(&self, limit_key: &str) -> IResult<()>;
+}
@@ -1,63 +1,161 @@
+pub mod verification;
+
 use crate::Pool;
 use chrono::prelude::*;
 use chrono::{Duration, Local};
-use core::errors::{ServiceError, InternalServerError};
+use core::errors::{InternalServerError, ServiceError};
 use core::models::dao::*;
 use diesel::{prelude::*, PgConnection};
 use log::{debug, error, info};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use uuid::Uuid;
 
 pub type MyDateTime = DateTime<Local>;
 
+/// Remove idle keys of the in-memory policy after this many checks
+const CLEANUP_INTERVAL: usize = 1024;
+
+/// Shared between all workers. The policies synchronise themselves, so a slow check
+/// does not block the checks of other requests.
 #[derive(Clone)]
 pub struct RateLimitWrapper {
-    pub inner: Arc<Mutex<Box<dyn RateLimitPolicy + 'static + Send>>>,
+    pub inner: Arc<dyn RateLimitPolicy + 'static + Send + Sync>,
 }
 
 impl RateLimitWrapper {
-    pub fn new(a: Box<dyn RateLimitPolicy + 'static + Send>) -> Self {
+    pub fn new(a: Box<dyn RateLimitPolicy + 'static + Send + Sync>) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(a)),
+            inner: Arc::from(a),
         }
     }
-}
 
-pub trait RateLimitPolicy {
-    /// Returns true if limit was reached
-    fn check_rate_limit_updates(
+    /// Returns `ServiceError::RateLimit` if the limit was reached
+    pub fn enforce(
         &self,
-        myid: &Uuid,
-        pool: &Pool,
+        action: RateLimitAction,
+        key: &str,
         current_time: MyDateTime,
-    ) -> Result<bool, ServiceError>;
+    ) -> Result<(), ServiceError> {
+        match self.inner.check(action, key, current_time)? {
+            Some(retry_after) => {
+                info!("Rate limit of {:?} reached for {}", action, key);
+                Err(ServiceError::RateLimit(retry_after))
+            }
+            None => Ok(()),
+        }
+    }
 
     /// Returns true if limit was reached
-    fn check_rate_limit_xp(
+    pub fn is_reached(
         &self,
-        myid: &Uuid,
-        pool: &Pool,
+        action: RateLimitAction,
+        key: &str,
         current_time: MyDateTime,
-    ) -> Result<bool, ServiceError>;
+    ) -> Result<bool, ServiceError> {
+        Ok(self.inner.check(action, key, current_time)?.is_some())
+    }
+}
+
+/// Everything, which is rate limited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitAction {
+    /// Changing the led or the description
+    LedUpdate,
+    /// Gaining experience with an update
+    Xp,
+    /// Requesting a verification code by sms
+    CodeRequest,
+    /// Uploading the contacts
+    ContactUpload,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Length of the window in seconds
+    pub window: i64,
+    pub led_updates: usize,
+    pub xp: usize,
+    pub code_requests: usize,
+    pub contact_uploads: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            window: 10 * 60,
+            led_updates: 3,
+            xp: 2,
+            code_requests: 5,
+            contact_uploads: 10,
+        }
+    }
 }
 
-pub struct DefaultRateLimitPolicy;
+impl RateLimitConfig {
+    /// Maximum number of requests of `action` in one window
+    pub fn limit(&self, action: RateLimitAction) -> usize {
+        match action {
+            RateLimitAction::LedUpdate => self.led_updates,
+            RateLimitAction::Xp => self.xp,
+            RateLimitAction::CodeRequest => self.code_requests,
+            RateLimitAction::ContactUpload => self.contact_uploads,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::seconds(self.window)
+    }
+}
+
+pub trait RateLimitPolicy {
+    /// Registers a request of `key` for `action`.
+    /// Returns the seconds until the next request is allowed if the limit was reached.
+    fn check(
+        &self,
+        action: RateLimitAction,
+        key: &str,
+        current_time: MyDateTime,
+    ) -> Result<Option<i64>, ServiceError>;
+}
+
+/// Seconds until `oldest` leaves the window
+fn retry_after(oldest: NaiveDateTime, window: Duration, current_time: NaiveDateTime) -> i64 {
+    (oldest + window - current_time).num_seconds().max(1)
+}
+
+/// Counts the updates of an user in the `analytics` table.
+/// Actions, which are not logged there, are handled by a `SlidingWindowRateLimitPolicy`.
+pub struct DefaultRateLimitPolicy {
+    pool: Pool,
+    config: RateLimitConfig,
+    fallback: SlidingWindowRateLimitPolicy,
+}
 
 impl DefaultRateLimitPolicy {
+    pub fn new(pool: Pool, config: RateLimitConfig) -> Self {
+        Self {
+            pool,
+            fallback: SlidingWindowRateLimitPolicy::new(config.clone()),
+            config,
+        }
+    }
+
     fn check_rate_limit(
         &self,
         myid: &Uuid,
-        pool: &Pool,
         current_time: MyDateTime,
         limit: usize,
-    ) -> Result<bool, ServiceError> {
+    ) -> Result<Option<i64>, ServiceError> {
         info!("ratelimits/mod/check_rate_limit");
 
-        use core::schema::analytics::dsl::{analytics, created_at, tele_num};
+        use core::schema::analytics::dsl::{analytics, created_at, pseudonym};
         use core::schema::users::dsl::{id, users};
 
-        let conn: &PgConnection = &pool.get().unwrap();
+        let conn: &PgConnection = &self.pool.get().unwrap();
 
         users
             .filter(id.eq(myid))
@@ -72,50 +170,113 @@ impl DefaultRateLimitPolicy {
             .and_then(|user| {
                 debug!("User found");
 
-                //TODO extract
-                let duration = Duration::minutes(10);
+                let duration = self.config.window();
 
                 let threshold = (current_time - duration).naive_local();
 
                 debug!("Threshold is {}", threshold);
 
-                let count = analytics
-                    .filter(tele_num.eq(user.tele_num).and(created_at.ge(threshold)))
+                let entries = analytics
+                    .filter(pseudonym.eq(user.pseudonym()).and(created_at.ge(threshold)))
+                    .order_by(created_at.asc())
                     .load::<AnalyticDao>(conn)
                     .map_err(|_db_error| {
                         error!("db error: {}", _db_error);
-                        ServiceError::InternalServerError(InternalServerError::DatabaseError(_db_error.to_string()))
-                    })?
-                    .len();
+                        ServiceError::InternalServerError(InternalServerError::DatabaseError(
+                            _db_error.to_string(),
+                        ))
+                    })?;
 
-                Ok(count > limit)
+                if entries.len() < limit {
+                    return Ok(None);
+                }
+
+                Ok(entries.first().map(|oldest| {
+                    retry_after(oldest.created_at, duration, current_time.naive_local())
+                }))
             })
     }
 }
 
 impl RateLimitPolicy for DefaultRateLimitPolicy {
-    fn check_rate_limit_updates(
+    fn check(
         &self,
-        myid: &Uuid,
-        pool: &Pool,
+        action: RateLimitAction,
+        key: &str,
         current_time: MyDateTime,
-    ) -> Result<bool, ServiceError> {
-        //TODO move into configuration
-        const LIMIT: usize = 3;
+    ) -> Result<Option<i64>, ServiceError> {
+        match action {
+            RateLimitAction::LedUpdate | RateLimitAction::Xp => {
+                let myid = Uuid::parse_str(key)?;
+                self.check_rate_limit(&myid, current_time, self.config.limit(action))
+            }
+            _ => self.fallback.check(action, key, current_time),
+        }
+    }
+}
+
+type Hits = HashMap<(RateLimitAction, String), VecDeque<MyDateTime>>;
+
+/// Keeps the timestamps of the requests in memory. They are lost on restart.
+pub struct SlidingWindowRateLimitPolicy {
+    config: RateLimitConfig,
+    hits: Mutex<Hits>,
+    checks: AtomicUsize,
+}
+
+impl SlidingWindowRateLimitPolicy {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            hits: Mutex::new(HashMap::new()),
+            checks: AtomicUsize::new(0),
+        }
+    }
 
-        self.check_rate_limit(myid, pool, current_time, LIMIT)
+    /// Forget the keys without requests in the current window
+    fn cleanup(hits: &mut Hits, threshold: MyDateTime) {
+        hits.retain(|_, w| w.back().map(|last| *last > threshold).unwrap_or(false));
     }
+}
 
-    fn check_rate_limit_xp(
+impl RateLimitPolicy for SlidingWindowRateLimitPolicy {
+    fn check(
         &self,
-        myid: &Uuid,
-        pool: &Pool,
+        action: RateLimitAction,
+        key: &str,
         current_time: MyDateTime,
-    ) -> Result<bool, ServiceError> {
-        //TODO move into configuration
-        const LIMIT: usize = 2;
+    ) -> Result<Option<i64>, ServiceError> {
+        let window = self.config.window();
+        let threshold = current_time - window;
+        let limit = self.config.limit(action);
+
+        let mut all_hits = self.hits.lock().unwrap();
+
+        if (self.checks.fetch_add(1, Ordering::Relaxed) + 1) % CLEANUP_INTERVAL == 0 {
+            Self::cleanup(&mut all_hits, threshold);
+        }
+
+        let hits = all_hits
+            .entry((action, key.to_string()))
+            .or_insert_with(VecDeque::new);
 
-        self.check_rate_limit(myid, pool, current_time, LIMIT)
+        while hits.front().map(|w| *w <= threshold).unwrap_or(false) {
+            hits.pop_front();
+        }
+
+        if hits.len() >= limit {
+            return Ok(hits.front().map(|oldest| {
+                retry_after(
+                    oldest.naive_local(),
+                    window,
+                    current_time.naive_local(),
+                )
+            }));
+        }
+
+        hits.push_back(current_time);
+
+        Ok(None)
     }
 }
 
@@ -123,22 +284,13 @@ impl RateLimitPolicy for DefaultRateLimitPolicy {
 pub struct TestingTrueRateLimitPolicy;
 
 impl RateLimitPolicy for TestingTrueRateLimitPolicy {
-    fn check_rate_limit_updates(
-        &self,
-        _myid: &Uuid,
-        _pool: &Pool,
-        _current_time: MyDateTime,
-    ) -> Result<bool, ServiceError> {
-        Ok(true)
-    }
-
-    fn check_rate_limit_xp(
+    fn check(
         &self,
-        _myid: &Uuid,
-        _pool: &Pool,
+        _action: RateLimitAction,
+        _key: &str,
         _current_time: MyDateTime,
-    ) -> Result<bool, ServiceError> {
-        Ok(true)
+    ) -> Result<Option<i64>, ServiceError> {
+        Ok(Some(1))
     }
 }
 
@@ -146,21 +298,108 @@ impl RateLimitPolicy for TestingTrueRateLimitPolicy {
 pub struct TestingFalseRateLimitPolicy;
 
 impl RateLimitPolicy for TestingFalseRateLimitPolicy {
-    fn check_rate_limit_updates(
+    fn check(
         &self,
-        _myid: &Uuid,
-        _pool: &Pool,
+        _action: RateLimitAction,
+        _key: &str,
         _current_time: MyDateTime,
-    ) -> Result<bool, ServiceError> {
-        Ok(false)
+    ) -> Result<Option<i64>, ServiceError> {
+        Ok(None)
     }
+}
 
-    fn check_rate_limit_xp(
-        &self,
-        _myid: &Uuid,
-        _pool: &Pool,
-        _current_time: MyDateTime,
-    ) -> Result<bool, ServiceError> {
-        Ok(false)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            window: 60,
+            led_updates: 2,
+            xp: 1,
+            code_requests: 1,
+            contact_uploads: 1,
+        }
+    }
+
+    #[test]
+    fn test_sliding_window_limit() {
+        let policy = SlidingWindowRateLimitPolicy::new(config());
+        let now = Local::now();
+
+        assert_eq!(None, policy.check(RateLimitAction::LedUpdate, "a", now).unwrap());
+        assert_eq!(
+            None,
+            policy
+                .check(RateLimitAction::LedUpdate, "a", now + Duration::seconds(10))
+                .unwrap()
+        );
+        assert_eq!(
+            Some(40),
+            policy
+                .check(RateLimitAction::LedUpdate, "a", now + Duration::seconds(20))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sliding_window_expires() {
+        let policy = SlidingWindowRateLimitPolicy::new(config());
+        let now = Local::now();
+
+        assert_eq!(None, policy.check(RateLimitAction::Xp, "a", now).unwrap());
+        assert!(policy.check(RateLimitAction::Xp, "a", now).unwrap().is_some());
+        assert_eq!(
+            None,
+            policy
+                .check(RateLimitAction::Xp, "a", now + Duration::seconds(61))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sliding_window_separate_keys() {
+        let policy = SlidingWindowRateLimitPolicy::new(config());
+        let now = Local::now();
+
+        assert_eq!(None, policy.check(RateLimitAction::CodeRequest, "a", now).unwrap());
+        assert_eq!(None, policy.check(RateLimitAction::CodeRequest, "b", now).unwrap());
+        assert_eq!(None, policy.check(RateLimitAction::ContactUpload, "a", now).unwrap());
+    }
+
+    #[test]
+    fn test_wrapper_enforce() {
+        let ratelimits = RateLimitWrapper::new(Box::new(SlidingWindowRateLimitPolicy::new(config())));
+        let now = Local::now();
+
+        assert_eq!(Ok(()), ratelimits.enforce(RateLimitAction::ContactUpload, "a", now));
+        assert_eq!(
+            Err(ServiceError::RateLimit(60)),
+            ratelimits.enforce(RateLimitAction::ContactUpload, "a", now)
+        );
+    }
+    #[test]
+    fn test_wrapper_shared_between_threads() {
+        let ratelimits = RateLimitWrapper::new(Box::new(SlidingWindowRateLimitPolicy::new(config())));
+        let now = Local::now();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let ratelimits = ratelimits.clone();
+                std::thread::spawn(move || {
+                    ratelimits
+                        .enforce(RateLimitAction::ContactUpload, "a", now)
+                        .is_ok()
+                })
+            })
+            .collect();
+
+        let allowed = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|allowed| *allowed)
+            .count();
+
+        assert_eq!(1, allowed);
     }
 }
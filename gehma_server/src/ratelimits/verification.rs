@@ -0,0 +1,321 @@
+//! Limits of the sms verification. Every code request costs money, that's why
+//! the requests are limited per phone number and per client ip with an exponential back-off.
+//! Guessing codes is prevented by a lockout after too many wrong codes.
+use actix_web::HttpRequest;
+use chrono::{Duration, NaiveDateTime};
+use core::errors::ServiceError;
+use core::models::dao::VerificationLimitDao;
+use core::models::PhoneNumber;
+use log::info;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::queries::PersistentVerificationDao;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VerificationLimitConfig {
+    /// First back-off in seconds. It doubles with every further request.
+    pub backoff: i64,
+    /// Maximum back-off in seconds
+    pub max_backoff: i64,
+    /// Requests of a phone number without back-off
+    pub free_requests_number: i32,
+    /// Requests of an ip without back-off
+    pub free_requests_ip: i32,
+    /// Wrong codes until the phone number is locked
+    pub max_failed_checks: i32,
+    /// Duration of the lockout in seconds
+    pub lockout: i64,
+    /// Counters are reset after this many seconds without requests
+    pub reset_after: i64,
+    /// Ips of the proxies, whose `X-Forwarded-For` is trusted
+    pub trusted_proxies: Vec<String>,
+}
+
+impl Default for VerificationLimitConfig {
+    fn default() -> Self {
+        Self {
+            backoff: 30,
+            max_backoff: 24 * 60 * 60,
+            free_requests_number: 1,
+            free_requests_ip: 10,
+            max_failed_checks: 5,
+            lockout: 60 * 60,
+            reset_after: 24 * 60 * 60,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+/// The ip of the client without the port. The forwarded ip is only used,
+/// when the request comes from a trusted proxy. Otherwise clients could pick any ip.
+pub fn client_ip(request: &HttpRequest, config: &VerificationLimitConfig) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+
+    let trusted = config
+        .trusted_proxies
+        .iter()
+        .any(|proxy| proxy.parse::<IpAddr>().ok() == Some(peer));
+
+    if !trusted {
+        return Some(peer.to_string());
+    }
+
+    let info = request.connection_info();
+    let addr = info.realip_remote_addr()?;
+
+    match addr.parse::<SocketAddr>() {
+        Ok(socket) => Some(socket.ip().to_string()),
+        Err(_) => Some(addr.to_string()),
+    }
+}
+
+fn number_key(tele_num: &PhoneNumber) -> String {
+    format!("number:{}", tele_num)
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+/// Seconds until `until` has passed
+fn blocked_for(until: Option<NaiveDateTime>, now: NaiveDateTime) -> Option<i64> {
+    until
+        .filter(|w| *w > now)
+        .map(|w| (w - now).num_seconds().max(1))
+}
+
+/// Starts over, when there was no request for `reset_after`
+fn current(
+    entry: Option<VerificationLimitDao>,
+    key: String,
+    config: &VerificationLimitConfig,
+    now: NaiveDateTime,
+) -> VerificationLimitDao {
+    match entry {
+        Some(w) if w.updated_at + Duration::seconds(config.reset_after) > now => w,
+        _ => VerificationLimitDao::my_from(key, now),
+    }
+}
+
+/// `backoff * 2^(exceeded - 1)`, but at most `max_backoff`
+fn backoff(exceeded: i32, config: &VerificationLimitConfig) -> i64 {
+    1i64.checked_shl((exceeded - 1) as u32)
+        .and_then(|w| config.backoff.checked_mul(w))
+        .filter(|w| *w > 0)
+        .unwrap_or(config.max_backoff)
+        .min(config.max_backoff)
+}
+
+/// Registers a code request.
+/// Returns the seconds until the next request is allowed if the request is blocked.
+fn register_request(
+    entry: VerificationLimitDao,
+    free_requests: i32,
+    config: &VerificationLimitConfig,
+    now: NaiveDateTime,
+) -> Result<VerificationLimitDao, i64> {
+    if let Some(seconds) = blocked_for(entry.locked_until, now) {
+        return Err(seconds);
+    }
+
+    if let Some(seconds) = blocked_for(entry.blocked_until, now) {
+        return Err(seconds);
+    }
+
+    let mut entry = entry;
+    entry.requests += 1;
+    entry.updated_at = now;
+
+    let exceeded = entry.requests - free_requests;
+    if exceeded > 0 {
+        entry.blocked_until = Some(now + Duration::seconds(backoff(exceeded, config)));
+    }
+
+    Ok(entry)
+}
+
+/// Registers a wrong code. The entry is locked after `max_failed_checks`.
+fn register_failed_check(
+    entry: VerificationLimitDao,
+    config: &VerificationLimitConfig,
+    now: NaiveDateTime,
+) -> VerificationLimitDao {
+    let mut entry = entry;
+    entry.failed_checks += 1;
+    entry.updated_at = now;
+
+    if entry.failed_checks >= config.max_failed_checks {
+        entry.failed_checks = 0;
+        entry.locked_until = Some(now + Duration::seconds(config.lockout));
+    }
+
+    entry
+}
+
+/// Checks the quotas of the phone number and the client ip before a code is sent.
+pub(crate) fn enforce_code_request(
+    dao: &dyn PersistentVerificationDao,
+    config: &VerificationLimitConfig,
+    tele_num: &PhoneNumber,
+    ip: Option<&str>,
+    now: NaiveDateTime,
+) -> Result<(), ServiceError> {
+    let mut keys = vec![(number_key(tele_num), config.free_requests_number)];
+
+    if let Some(ip) = ip {
+        keys.push((ip_key(ip), config.free_requests_ip));
+    }
+
+    let mut entries = Vec::with_capacity(keys.len());
+
+    // Nothing is saved, when any of them is blocked
+    for (key, free_requests) in keys {
+        let entry = current(dao.get_limit(&key)?, key, config, now);
+
+        match register_request(entry, free_requests, config, now) {
+            Ok(entry) => entries.push(entry),
+            Err(seconds) => {
+                info!("Code request of {} is blocked for {}s", tele_num, seconds);
+                return Err(ServiceError::RateLimit(seconds));
+            }
+        }
+    }
+
+    for entry in entries.iter() {
+        dao.set_limit(entry)?;
+    }
+
+    Ok(())
+}
+
+/// Fails if the phone number is locked because of too many wrong codes.
+pub(crate) fn enforce_code_check(
+    dao: &dyn PersistentVerificationDao,
+    config: &VerificationLimitConfig,
+    tele_num: &PhoneNumber,
+    now: NaiveDateTime,
+) -> Result<(), ServiceError> {
+    let key = number_key(tele_num);
+    let entry = current(dao.get_limit(&key)?, key, config, now);
+
+    match blocked_for(entry.locked_until, now) {
+        Some(seconds) => {
+            info!("Phone number {} is locked for {}s", tele_num, seconds);
+            Err(ServiceError::RateLimit(seconds))
+        }
+        None => Ok(()),
+    }
+}
+
+/// A correct code resets the limits of the phone number.
+pub(crate) fn register_code_check(
+    dao: &dyn PersistentVerificationDao,
+    config: &VerificationLimitConfig,
+    tele_num: &PhoneNumber,
+    correct: bool,
+    now: NaiveDateTime,
+) -> Result<(), ServiceError> {
+    let key = number_key(tele_num);
+
+    if correct {
+        return dao.remove_limit(&key);
+    }
+
+    let entry = current(dao.get_limit(&key)?, key, config, now);
+
+    dao.set_limit(&register_failed_check(entry, config, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn entry(now: NaiveDateTime) -> VerificationLimitDao {
+        VerificationLimitDao::my_from("number:+4366412345678", now)
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let config = VerificationLimitConfig::default();
+
+        assert_eq!(30, backoff(1, &config));
+        assert_eq!(60, backoff(2, &config));
+        assert_eq!(120, backoff(3, &config));
+        assert_eq!(config.max_backoff, backoff(100, &config));
+    }
+
+    #[test]
+    fn test_register_request_backoff() {
+        let config = VerificationLimitConfig::default();
+        let now = chrono::Local::now().naive_local();
+
+        let first = register_request(entry(now), 1, &config, now).unwrap();
+        assert_eq!(None, first.blocked_until);
+
+        let second = register_request(first, 1, &config, now).unwrap();
+        assert_eq!(Some(now + Duration::seconds(30)), second.blocked_until);
+
+        assert_eq!(Err(30), register_request(second.clone(), 1, &config, now));
+
+        let later = now + Duration::seconds(31);
+        let third = register_request(second, 1, &config, later).unwrap();
+        assert_eq!(Some(later + Duration::seconds(60)), third.blocked_until);
+    }
+
+    #[test]
+    fn test_lockout_after_failed_checks() {
+        let config = VerificationLimitConfig::default();
+        let now = chrono::Local::now().naive_local();
+
+        let mut e = entry(now);
+        for _ in 0..config.max_failed_checks - 1 {
+            e = register_failed_check(e, &config, now);
+            assert_eq!(None, e.locked_until);
+        }
+
+        e = register_failed_check(e, &config, now);
+        assert_eq!(Some(now + Duration::seconds(config.lockout)), e.locked_until);
+        assert_eq!(Err(config.lockout), register_request(e, 1, &config, now));
+    }
+
+    #[test]
+    fn test_reset_after_idle() {
+        let config = VerificationLimitConfig::default();
+        let now = chrono::Local::now().naive_local();
+
+        let mut e = entry(now);
+        e.requests = 10;
+
+        let later = now + Duration::seconds(config.reset_after + 1);
+        assert_eq!(0, current(Some(e), "x".to_string(), &config, later).requests);
+    }
+
+    #[test]
+    fn test_client_ip_ignores_forwarded_header() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .header("X-Forwarded-For", "1.2.3.4")
+            .to_http_request();
+
+        let config = VerificationLimitConfig::default();
+
+        assert_eq!(Some("10.0.0.1".to_string()), client_ip(&request, &config));
+    }
+
+    #[test]
+    fn test_client_ip_of_trusted_proxy() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .header("X-Forwarded-For", "1.2.3.4")
+            .to_http_request();
+
+        let config = VerificationLimitConfig {
+            trusted_proxies: vec!["10.0.0.1".to_string()],
+            ..VerificationLimitConfig::default()
+        };
+
+        assert_eq!(Some("1.2.3.4".to_string()), client_ip(&request, &config));
+    }
+}
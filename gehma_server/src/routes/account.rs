@@ -0,0 +1,48 @@
+use crate::controllers::account::{delete_account, export_account};
+use crate::queries::*;
+use crate::services::session::SessionService;
+use actix_web::{web, HttpResponse};
+use chrono::Local;
+use core::errors::ServiceError;
+use log::info;
+use web_contrib::utils::set_response_headers;
+
+/// `GET /user/{uid}/export`
+pub async fn export(
+    info: web::Path<String>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    account_dao: web::Data<Box<dyn PersistentAccountDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/account/export");
+
+    let export = export_account(&info.into_inner(), user_dao, account_dao, Local::now())?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .header(
+            "Content-Disposition",
+            "attachment; filename=\"gehma-export.json\"",
+        )
+        .json(export);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `DELETE /user/{uid}`
+pub async fn delete(
+    info: web::Path<String>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    account_dao: web::Data<Box<dyn PersistentAccountDao>>,
+    session_service: web::Data<SessionService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/account/delete");
+
+    delete_account(&info.into_inner(), user_dao, account_dao, session_service)?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").finish();
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
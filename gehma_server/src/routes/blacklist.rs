@@ -2,6 +2,7 @@ use crate::controllers::blacklist::{create_entry, delete_entry, get_entry};
 use crate::queries::*;
 use actix_web::{web, HttpResponse};
 use core::errors::ServiceError;
+use core::models::dto::HashedTeleNum;
 use log::info;
 use web_contrib::utils::set_response_headers;
 
@@ -62,7 +63,7 @@ pub async fn delete(
 
     delete_entry(
         &info.into_inner(),
-        &data.into_inner(),
+        &HashedTeleNum::from_client(&data.into_inner().hash_blocked),
         user_dao,
         blacklist_dao,
     )?;
@@ -72,3 +73,21 @@ pub async fn delete(
 
     Ok(res)
 }
+
+/// `DELETE /user/{uid}/blacklist/{hash_blocked}`
+pub async fn remove(
+    info: web::Path<(String, String)>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/blacklist/remove");
+
+    let (uid, hash_blocked) = info.into_inner();
+
+    delete_entry(&uid, &HashedTeleNum::from_client(&hash_blocked), user_dao, blacklist_dao)?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").finish();
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
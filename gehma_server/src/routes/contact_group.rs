@@ -0,0 +1,123 @@
+use crate::controllers::contact_group::{
+    create_entry, delete_entry, get_entries, get_entry, update_entry,
+};
+use crate::queries::*;
+use actix_web::{web, HttpResponse};
+use chrono::Local;
+use core::errors::ServiceError;
+use core::models::dto::RequestContactGroupDto;
+use log::info;
+use web_contrib::utils::set_response_headers;
+
+/// `GET /contacts/{uid}/groups`
+pub async fn get_all(
+    info: web::Path<String>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contact_group/get_all");
+
+    let groups = get_entries(&info.into_inner(), user_dao, contact_group_dao)?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(groups);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `POST /contacts/{uid}/groups`
+pub async fn add(
+    info: web::Path<String>,
+    data: web::Json<RequestContactGroupDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contact_group/add");
+
+    let group = create_entry(
+        &info.into_inner(),
+        data.into_inner(),
+        user_dao,
+        contact_group_dao,
+        Local::now(),
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(group);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `GET /contacts/{uid}/groups/{group_id}`
+pub async fn get(
+    info: web::Path<(String, i32)>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contact_group/get");
+
+    let (uid, group_id) = info.into_inner();
+
+    let group = get_entry(&uid, group_id, user_dao, contact_group_dao)?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(group);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `PUT /contacts/{uid}/groups/{group_id}`
+pub async fn update(
+    info: web::Path<(String, i32)>,
+    data: web::Json<RequestContactGroupDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contact_group/update");
+
+    let (uid, group_id) = info.into_inner();
+
+    let group = update_entry(
+        &uid,
+        group_id,
+        data.into_inner(),
+        user_dao,
+        contact_group_dao,
+        Local::now(),
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(group);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `DELETE /contacts/{uid}/groups/{group_id}`
+pub async fn remove(
+    info: web::Path<(String, i32)>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contact_group/remove");
+
+    let (uid, group_id) = info.into_inner();
+
+    delete_entry(&uid, group_id, user_dao, contact_group_dao)?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").finish();
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
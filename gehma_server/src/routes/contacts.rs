@@ -1,40 +1,98 @@
-use actix_web::{web, HttpResponse};
+use actix_web::http::header::{ETAG, IF_NONE_MATCH};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Local;
 
 use core::errors::ServiceError;
-use core::models::dto::PayloadNumbersDto;
+use core::models::dto::{DiscoverContactsDto, PayloadContactsDeltaDto, PayloadNumbersDto};
 
 use web_contrib::utils::set_response_headers;
 
-use crate::controllers::contacts::{create as ctrl_create, get_contacts as ctrl_get_contacts};
+use crate::controllers::contacts::{
+    create as ctrl_create, discover as ctrl_discover, get_contacts as ctrl_get_contacts,
+    replace as ctrl_replace, sync as ctrl_sync,
+};
 use crate::queries::*;
+use crate::ratelimits::RateLimitWrapper;
 
 use log::info;
 
+/// The `since` of the next request. It is the time of the server before the contacts
+/// were read, so no change is missed because of the clock of the client.
+pub const SINCE_HEADER: &str = "x-contacts-since";
+
+#[derive(Deserialize)]
+pub struct ContactsQuery {
+    /// Only contacts, which changed afterwards
+    since: Option<chrono::NaiveDateTime>,
+}
+
 pub async fn get_contacts(
+    request: HttpRequest,
     info: web::Path<String>,
+    query: web::Query<ContactsQuery>,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
     blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
     contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
 ) -> Result<HttpResponse, ServiceError> {
     info!("routes/user/get_contacts");
 
-    let users = ctrl_get_contacts(&info.into_inner(), user_dao, blacklist_dao, contact_dao)?;
+    let next_since = Local::now().naive_local();
+    let since = query.into_inner().since;
 
-    let mut res = HttpResponse::Ok()
-        .content_type("application/json")
-        .json(users);
+    let (etag, users) = ctrl_get_contacts(
+        &info.into_inner(),
+        since,
+        |etag| is_fresh(&request, etag),
+        user_dao,
+        blacklist_dao,
+        contact_dao,
+    )?;
+
+    let mut res = match users {
+        None => HttpResponse::NotModified().header(ETAG, etag).finish(),
+        Some(users) => {
+            let mut res = HttpResponse::Ok();
+
+            res.content_type("application/json")
+                .header(ETAG, etag)
+                .header(
+                    SINCE_HEADER,
+                    next_since.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+                );
+
+            // Without `since` the response is the list of all contacts
+            match since {
+                Some(_) => res.json(users),
+                None => res.json(users.contacts),
+            }
+        }
+    };
 
     set_response_headers(&mut res);
 
     Ok(res)
 }
 
+/// The client has the current version, if one of the tags of `If-None-Match` matches
+fn is_fresh(request: &HttpRequest, etag: &str) -> bool {
+    request
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|w| w.to_str().ok())
+        .map(|w| {
+            w.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        })
+        .unwrap_or(false)
+}
+
 pub async fn create(
     info: web::Path<(String, String)>,
-    mut payload: web::Json<PayloadNumbersDto>,
+    payload: web::Json<PayloadNumbersDto>,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
     blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
     contacts_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
 ) -> Result<HttpResponse, ServiceError> {
     info!("controllers/contact_exists/exists");
 
@@ -42,10 +100,11 @@ pub async fn create(
     let users = ctrl_create(
         &info.0,
         &info.1,
-        &mut payload.numbers,
+        payload.into_inner().numbers,
         user_dao,
         blacklist_dao,
         contacts_dao,
+        ratelimits,
     )?;
 
     let mut res = HttpResponse::Ok()
@@ -56,3 +115,93 @@ pub async fn create(
 
     Ok(res)
 }
+
+/// `PUT /contacts/{uid}/{country_code}`
+pub async fn replace(
+    info: web::Path<(String, String)>,
+    payload: web::Json<PayloadNumbersDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
+    contacts_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contacts/replace");
+
+    let (uid, country_code) = info.into_inner();
+
+    let cursor = ctrl_replace(
+        &uid,
+        &country_code,
+        payload.into_inner().numbers,
+        user_dao,
+        blacklist_dao,
+        contacts_dao,
+        ratelimits,
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(cursor);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `PATCH /contacts/{uid}/{country_code}`
+pub async fn sync(
+    info: web::Path<(String, String)>,
+    payload: web::Json<PayloadContactsDeltaDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    blacklist_dao: web::Data<Box<dyn PersistentBlacklistDao>>,
+    contacts_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contacts/sync");
+
+    let (uid, _country_code) = info.into_inner();
+
+    let cursor = ctrl_sync(
+        &uid,
+        payload.into_inner(),
+        user_dao,
+        blacklist_dao,
+        contacts_dao,
+        ratelimits,
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(cursor);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `POST /contacts/{uid}/discover`
+pub async fn discover(
+    info: web::Path<String>,
+    payload: web::Json<DiscoverContactsDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contacts_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    ratelimits: web::Data<RateLimitWrapper>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/contacts/discover");
+
+    let discovered = ctrl_discover(
+        &info.into_inner(),
+        payload.into_inner(),
+        user_dao,
+        contacts_dao,
+        ratelimits,
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(discovered);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
@@ -0,0 +1,71 @@
+use crate::controllers::device::{create_entry, delete_entry, get_entries};
+use crate::queries::*;
+use actix_web::{web, HttpResponse};
+use chrono::Local;
+use core::errors::ServiceError;
+use core::models::dto::RegisterDeviceDto;
+use log::info;
+use web_contrib::utils::set_response_headers;
+
+/// `GET /user/{uid}/devices`
+pub async fn get_all(
+    info: web::Path<String>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/device/get_all");
+
+    let devices = get_entries(&info.into_inner(), user_dao, device_dao)?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(devices);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `POST /user/{uid}/devices`
+pub async fn add(
+    info: web::Path<String>,
+    data: web::Json<RegisterDeviceDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/device/add");
+
+    let device = create_entry(
+        &info.into_inner(),
+        data.into_inner(),
+        user_dao,
+        device_dao,
+        Local::now(),
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(device);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `DELETE /user/{uid}/devices/{token}`
+pub async fn remove(
+    info: web::Path<(String, String)>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/device/remove");
+
+    let (uid, token) = info.into_inner();
+
+    delete_entry(&uid, &token, user_dao, device_dao)?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").finish();
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
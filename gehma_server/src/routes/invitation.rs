@@ -0,0 +1,138 @@
+use actix_web::{web, HttpResponse};
+use core::errors::ServiceError;
+use core::models::dto::*;
+
+use crate::controllers::invitation::*;
+use crate::queries::*;
+use crate::services::push_notifications::NotificationService;
+
+use web_contrib::utils::set_response_headers;
+
+use log::info;
+
+pub async fn get_all(
+    info: web::Path<String>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/invitation/get_all");
+
+    let invitations = get_entries(&info.into_inner(), user_dao, contact_dao, invitation_dao)?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(invitations);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+pub async fn get(
+    info: web::Path<(String, i32)>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/invitation/get");
+
+    let info = info.into_inner();
+
+    let invitation = get_entry(&info.0, info.1, user_dao, contact_dao, invitation_dao)?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(invitation);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+pub async fn add(
+    info: web::Path<String>,
+    data: web::Json<RequestInvitationCreateDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+    contact_group_dao: web::Data<Box<dyn PersistentContactGroupDao>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/invitation/add");
+
+    let invitation = create(
+        &info.into_inner(),
+        data.into_inner(),
+        user_dao,
+        contact_dao,
+        invitation_dao,
+        contact_group_dao,
+        notification_service,
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(invitation);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+pub async fn update(
+    info: web::Path<(String, i32)>,
+    data: web::Json<UpdateInvitationStateDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/invitation/update");
+
+    let info = info.into_inner();
+
+    update_state(
+        &info.0,
+        info.1,
+        data.into_inner(),
+        user_dao,
+        invitation_dao,
+        notification_service,
+    )?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").finish();
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+pub async fn add_members_to(
+    info: web::Path<(String, i32)>,
+    data: web::Json<RequestInvitationAddMembersDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    contact_dao: web::Data<Box<dyn PersistentContactsDao>>,
+    invitation_dao: web::Data<Box<dyn PersistentInvitation>>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/invitation/add_members_to");
+
+    let info = info.into_inner();
+
+    let invitation = add_members(
+        &info.0,
+        info.1,
+        data.into_inner(),
+        user_dao,
+        contact_dao,
+        invitation_dao,
+        notification_service,
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(invitation);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
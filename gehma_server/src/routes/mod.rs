@@ -4,3 +4,163 @@ pub mod contacts;
 pub mod number_registration;
 pub mod profile_pictures;
 pub mod broadcast;
+pub mod invitation;
+pub mod device;
+pub mod notification_settings;
+pub mod contact_group;
+pub mod account;
+pub mod openapi;
+
+use actix_web::web;
+
+/// Routes of `/api/v1`. Every change must be reflected in `crate::openapi`.
+pub(crate) fn v1(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/openapi.json").route(web::get().to(openapi::get)))
+        .service(web::resource("/signin").route(web::post().to(user::signin)))
+        .service(
+            web::resource("/auth/request_code")
+                .route(web::post().to(number_registration::request_code)),
+        )
+        .service(web::resource("/auth/check").route(web::post().to(number_registration::check)))
+        .service(web::resource("/auth/refresh").route(web::post().to(user::refresh)))
+        .service(web::resource("/user/{uid}/session").route(web::delete().to(user::logout)))
+        .service(web::resource("/user/{uid}/sessions").route(web::delete().to(user::logout_all)))
+        .service(web::resource("/user/{uid}/token").route(web::put().to(user::update_token)))
+        .service(
+            web::resource("/user/{uid}/access_token").route(web::post().to(user::rotate_token)),
+        )
+        .service(
+            web::resource("/user/{uid}/tele_num/request_code")
+                .route(web::post().to(number_registration::request_tele_num_change)),
+        )
+        .service(
+            web::resource("/user/{uid}/tele_num").route(web::put().to(number_registration::change)),
+        )
+        .service(
+            web::resource("/user/{uid}/devices")
+                .route(web::get().to(device::get_all))
+                .route(web::post().to(device::add)),
+        )
+        .service(
+            web::resource("/user/{uid}/devices/{token}").route(web::delete().to(device::remove)),
+        )
+        .service(
+            web::resource("/user/{uid}/settings/notifications")
+                .route(web::get().to(notification_settings::get))
+                .route(web::put().to(notification_settings::update)),
+        )
+        .service(
+            web::resource("/user/{uid}/settings/notifications/muted")
+                .route(web::post().to(notification_settings::mute)),
+        )
+        .service(
+            web::resource("/user/{uid}/settings/notifications/muted/{hash_muted}")
+                .route(web::delete().to(notification_settings::unmute)),
+        )
+        .service(
+            web::resource("/user/{uid}/blacklist")
+                .route(web::get().to(blacklist::get_all))
+                .route(web::post().to(blacklist::add)),
+        )
+        .service(
+            web::resource("/user/{uid}/blacklist/{hash_blocked}")
+                .route(web::delete().to(blacklist::remove)),
+        )
+        .service(
+            web::resource("/user/{uid}/profile")
+                .route(web::get().to(profile_pictures::get_all))
+                .route(web::put().to(user::upload_profile_picture)),
+        )
+        .service(web::resource("/user/{uid}/export").route(web::get().to(account::export)))
+        .service(
+            web::resource("/user/{uid}")
+                .route(web::get().to(user::get))
+                .route(web::put().to(user::update))
+                .route(web::delete().to(account::delete)),
+        )
+        .service(
+            web::resource("/contacts/{uid}/groups/{group_id}")
+                .route(web::get().to(contact_group::get))
+                .route(web::put().to(contact_group::update))
+                .route(web::delete().to(contact_group::remove)),
+        )
+        .service(
+            web::resource("/contacts/{uid}/groups")
+                .route(web::get().to(contact_group::get_all))
+                .route(web::post().to(contact_group::add)),
+        )
+        .service(
+            web::resource("/contacts/{uid}/discover").route(web::post().to(contacts::discover)),
+        )
+        .service(
+            web::resource("/contacts/{uid}/{country_code}")
+                .route(web::put().to(contacts::replace))
+                .route(web::patch().to(contacts::sync)),
+        )
+        .service(web::resource("/contacts/{uid}").route(web::get().to(contacts::get_contacts)))
+        .service(web::resource("/broadcasts/{uid}").route(web::get().to(broadcast::get_all)))
+        .service(
+            web::resource("/invitations/{uid}/{inv_id}/members")
+                .route(web::post().to(invitation::add_members_to)),
+        )
+        .service(
+            web::resource("/invitations/{uid}/{inv_id}")
+                .route(web::get().to(invitation::get))
+                .route(web::put().to(invitation::update)),
+        )
+        .service(
+            web::resource("/invitations/{uid}")
+                .route(web::get().to(invitation::get_all))
+                .route(web::post().to(invitation::add)),
+        );
+}
+
+/// Unversioned routes of `/api`. They are kept for the released clients.
+pub(crate) fn legacy(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/signin").route(web::post().to(user::signin)))
+        .service(web::resource("/user/{uid}/logout").route(web::post().to(user::logout)))
+        .service(web::resource("/user/{uid}/logout/all").route(web::post().to(user::logout_all)))
+        .service(web::resource("/user/{uid}/token").route(web::put().to(user::update_token)))
+        .service(
+            web::resource("/user/{uid}/blacklist")
+                .route(web::get().to(blacklist::get_all))
+                .route(web::post().to(blacklist::add))
+                .route(web::put().to(blacklist::delete)), //deletes
+        )
+        .service(
+            web::resource("/user/{uid}/profile")
+                .route(web::get().to(profile_pictures::get_all))
+                .route(web::post().to(user::upload_profile_picture)),
+        )
+        .service(
+            web::resource("/user/{uid}")
+                .route(web::get().to(user::get))
+                .route(web::put().to(user::update)),
+        )
+        .service(
+            web::resource("/contacts/{uid}/{country_code}")
+                .route(web::post().to(contacts::create)),
+        )
+        .service(web::resource("/contacts/{uid}").route(web::get().to(contacts::get_contacts)))
+        .service(
+            web::resource("/auth/request_code")
+                .route(web::post().to(number_registration::request_code)),
+        )
+        .service(web::resource("/auth/check").route(web::post().to(number_registration::check)))
+        .service(web::resource("/auth/refresh").route(web::post().to(user::refresh)))
+        .service(web::resource("/broadcasts/{uid}").route(web::get().to(broadcast::get_all)))
+        .service(
+            web::resource("/invitations/{uid}/{inv_id}/members")
+                .route(web::post().to(invitation::add_members_to)),
+        )
+        .service(
+            web::resource("/invitations/{uid}/{inv_id}")
+                .route(web::get().to(invitation::get))
+                .route(web::put().to(invitation::update)),
+        )
+        .service(
+            web::resource("/invitations/{uid}")
+                .route(web::get().to(invitation::get_all))
+                .route(web::post().to(invitation::add)),
+        );
+}
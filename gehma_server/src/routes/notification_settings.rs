@@ -0,0 +1,94 @@
+use crate::controllers::notification_settings::*;
+use crate::queries::*;
+use actix_web::{web, HttpResponse};
+use chrono::Local;
+use core::errors::ServiceError;
+use core::models::dto::{HashedTeleNum, MuteContactDto, UpdateNotificationSettingsDto};
+use log::info;
+use web_contrib::utils::set_response_headers;
+
+/// `GET /user/{uid}/settings/notifications`
+pub async fn get(
+    info: web::Path<String>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/notification_settings/get");
+
+    let settings = get_entry(&info.into_inner(), user_dao, settings_dao, Local::now())?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(settings);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `PUT /user/{uid}/settings/notifications`
+pub async fn update(
+    info: web::Path<String>,
+    data: web::Json<UpdateNotificationSettingsDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/notification_settings/update");
+
+    let settings = update_entry(
+        &info.into_inner(),
+        data.into_inner(),
+        user_dao,
+        settings_dao,
+        Local::now(),
+    )?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(settings);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `POST /user/{uid}/settings/notifications/muted`
+pub async fn mute(
+    info: web::Path<String>,
+    data: web::Json<MuteContactDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/notification_settings/mute");
+
+    mute_contact(
+        &info.into_inner(),
+        data.into_inner().hash_tele_num,
+        user_dao,
+        settings_dao,
+        Local::now(),
+    )?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").finish();
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `DELETE /user/{uid}/settings/notifications/muted/{hash_muted}`
+pub async fn unmute(
+    info: web::Path<(String, String)>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    settings_dao: web::Data<Box<dyn PersistentNotificationSettingsDao>>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/notification_settings/unmute");
+
+    let (uid, hash_muted) = info.into_inner();
+
+    unmute_contact(&uid, &HashedTeleNum::from_client(&hash_muted), user_dao, settings_dao)?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").finish();
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
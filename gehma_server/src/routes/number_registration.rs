@@ -1,8 +1,11 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 
 use log::{info};
 
-use crate::controllers::number_registration::{request, check_code};
+use crate::controllers::number_registration::{request as request_ctrl, check_code};
+use crate::controllers::number_registration::{
+    change_tele_num, ensure_tele_num_available, notify_tele_num_changed,
+};
 
 use web_contrib::utils::set_response_headers;
 
@@ -10,19 +13,34 @@ use core::errors::ServiceError;
 
 use core::models::dto::*;
 use crate::services::number_registration::NumberRegistrationService;
+use crate::services::push_notifications::NotificationService;
+use crate::services::session::SessionService;
+use crate::config::ClientConfig;
 use crate::queries::*;
+use crate::ratelimits::verification::{client_ip, VerificationLimitConfig};
+use crate::ratelimits::RateLimitWrapper;
 
 pub async fn request_code(
     _info: web::Path<()>,
+    request: HttpRequest,
     body: web::Json<RequestCodeDto>,
     //pool: web::Data<Pool>,
     number_registration_service: web::Data<NumberRegistrationService>,
+    ratelimits: web::Data<RateLimitWrapper>,
+    verification_dao: web::Data<Box<dyn PersistentVerificationDao>>,
+    verification_config: web::Data<VerificationLimitConfig>,
 ) -> Result<HttpResponse, ServiceError> {
     info!("routes/auth/request_code");
 
-    let _ = request(
+    let ip = client_ip(&request, &verification_config);
+
+    let _ = request_ctrl(
             body.into_inner(),
+            ip,
             number_registration_service,
+            ratelimits,
+            verification_dao,
+            verification_config,
         )?;
 
     let mut res = HttpResponse::Ok()
@@ -34,18 +52,28 @@ pub async fn request_code(
     Ok(res)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn check(
     _info: web::Path<()>,
     body: web::Json<RequestCheckCodeDto>,
     number_registration_service: web::Data<NumberRegistrationService>,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    verification_dao: web::Data<Box<dyn PersistentVerificationDao>>,
+    verification_config: web::Data<VerificationLimitConfig>,
+    client_config: web::Data<ClientConfig>,
+    session_service: web::Data<SessionService>,
 ) -> Result<HttpResponse, ServiceError> {
     info!("routes/auth/check");
 
+    let support = client_config.negotiate(&body.client_version)?;
+
     let res = check_code(
             body.into_inner(),
             user_dao,
             number_registration_service,
+            verification_dao,
+            verification_config,
+            session_service,
         )?;
 
     let mut res = HttpResponse::Ok()
@@ -53,5 +81,78 @@ pub async fn check(
                 .json(res);
 
     set_response_headers(&mut res);
+    client_config.set_response_headers(support, &mut res);
+    Ok(res)
+}
+
+/// `POST /user/{uid}/tele_num/request_code`
+#[allow(clippy::too_many_arguments)]
+pub async fn request_tele_num_change(
+    info: web::Path<String>,
+    request: HttpRequest,
+    body: web::Json<RequestCodeDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    number_registration_service: web::Data<NumberRegistrationService>,
+    ratelimits: web::Data<RateLimitWrapper>,
+    verification_dao: web::Data<Box<dyn PersistentVerificationDao>>,
+    verification_config: web::Data<VerificationLimitConfig>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/auth/request_tele_num_change");
+
+    ensure_tele_num_available(
+        &info.into_inner(),
+        &body.tele_num,
+        &body.country_code,
+        &user_dao,
+    )?;
+
+    request_ctrl(
+        body.into_inner(),
+        client_ip(&request, &verification_config),
+        number_registration_service,
+        ratelimits,
+        verification_dao,
+        verification_config,
+    )?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").json(());
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `PUT /user/{uid}/tele_num`
+#[allow(clippy::too_many_arguments)]
+pub async fn change(
+    info: web::Path<String>,
+    body: web::Json<ChangeTeleNumDto>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    account_dao: web::Data<Box<dyn PersistentAccountDao>>,
+    number_registration_service: web::Data<NumberRegistrationService>,
+    verification_dao: web::Data<Box<dyn PersistentVerificationDao>>,
+    verification_config: web::Data<VerificationLimitConfig>,
+    notification_service: web::Data<NotificationService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/auth/change");
+
+    let user = change_tele_num(
+        &info.into_inner(),
+        body.into_inner(),
+        &user_dao,
+        account_dao,
+        number_registration_service,
+        verification_dao,
+        verification_config,
+    )?;
+
+    let user = notify_tele_num_changed(user, &user_dao, notification_service)?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(user);
+
+    set_response_headers(&mut res);
+
     Ok(res)
 }
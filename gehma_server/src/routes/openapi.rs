@@ -0,0 +1,16 @@
+use actix_web::HttpResponse;
+use core::errors::ServiceError;
+use log::info;
+use web_contrib::utils::set_response_headers;
+
+pub async fn get() -> Result<HttpResponse, ServiceError> {
+    info!("routes/openapi/get");
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(crate::openapi::document());
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
@@ -8,24 +8,31 @@ use log::{info, trace};
 use web_contrib::utils::set_response_headers;
 
 use crate::controllers::user::*;
+use crate::config::ClientConfig;
 use crate::queries::*;
+use crate::ratelimits::RateLimitWrapper;
 use chrono::Local;
 
 pub async fn signin(
     request: HttpRequest,
     body: web::Json<PostUserDto>,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
     notification_service: web::Data<NotificationService>,
     session_service: web::Data<SessionService>,
+    client_config: web::Data<ClientConfig>,
 ) -> Result<HttpResponse, ServiceError> {
     info!("routes/user/signin");
 
     let current_time = Local::now();
 
+    let support = client_config.negotiate(&body.client_version)?;
+
     let user = user_signin(
         request,
         body.into_inner(),
         user_dao,
+        device_dao,
         current_time,
         notification_service,
         session_service,
@@ -36,6 +43,7 @@ pub async fn signin(
         .json(user);
 
     set_response_headers(&mut res);
+    client_config.set_response_headers(support, &mut res);
 
     Ok(res)
 }
@@ -117,22 +125,99 @@ pub async fn update(
     data: web::Json<UpdateUserDto>,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
     notification_service: web::Data<NotificationService>,
+    ratelimits: web::Data<RateLimitWrapper>,
+    client_config: web::Data<ClientConfig>,
 ) -> Result<HttpResponse, ServiceError> {
     info!("routes/user/update");
 
     let current_time = Local::now();
 
+    let support = client_config.negotiate(&data.client_version)?;
+
     let user = update_user(
         &info.into_inner(),
         &data.into_inner(),
         &user_dao,
         current_time,
         notification_service,
+        &ratelimits,
     )?;
 
-    Ok(HttpResponse::Ok()
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(&user);
+
+    client_config.set_response_headers(support, &mut res);
+
+    Ok(res)
+}
+
+pub async fn refresh(
+    body: web::Json<RequestRefreshSessionDto>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
+    session_service: web::Data<SessionService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/user/refresh");
+
+    let session = refresh_session(body.into_inner(), device_dao, session_service, Local::now())?;
+
+    let mut res = HttpResponse::Ok()
+        .content_type("application/json")
+        .json(session);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+pub async fn logout(
+    request: HttpRequest,
+    _info: web::Path<String>,
+    session_service: web::Data<SessionService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/user/logout");
+
+    logout_session(request, session_service)?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").json(());
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+pub async fn logout_all(
+    info: web::Path<String>,
+    session_service: web::Data<SessionService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/user/logout_all");
+
+    logout_all_sessions(&info.into_inner(), session_service)?;
+
+    let mut res = HttpResponse::Ok().content_type("application/json").json(());
+
+    set_response_headers(&mut res);
+
+    Ok(res)
+}
+
+/// `POST /user/{uid}/access_token`
+pub async fn rotate_token(
+    info: web::Path<String>,
+    user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    session_service: web::Data<SessionService>,
+) -> Result<HttpResponse, ServiceError> {
+    info!("routes/user/rotate_token");
+
+    let token = rotate_access_token(&info.into_inner(), user_dao, session_service)?;
+
+    let mut res = HttpResponse::Ok()
         .content_type("application/json")
-        .json(&user))
+        .json(token);
+
+    set_response_headers(&mut res);
+
+    Ok(res)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -144,10 +229,17 @@ pub async fn update_token(
     _info: web::Path<String>,
     body: web::Json<UpdateTokenPayload>,
     user_dao: web::Data<Box<dyn PersistentUserDao>>,
+    device_dao: web::Data<Box<dyn PersistentDeviceDao>>,
 ) -> Result<HttpResponse, ServiceError> {
     info!("routes/push_notification/update_token");
 
-    let _ = update_token_handler(_info.into_inner(), body.into_inner(), user_dao)?;
+    update_token_handler(
+        _info.into_inner(),
+        body.into_inner(),
+        user_dao,
+        device_dao,
+        Local::now(),
+    )?;
 
     let mut res = HttpResponse::Ok().content_type("application/json").json(());
 
@@ -0,0 +1,75 @@
+//! Leds with an expiry are switched off by a background worker, so contacts don't see
+//! stale "available" states.
+use crate::queries::PersistentUserDao;
+use chrono::NaiveDateTime;
+use core::errors::ServiceError;
+use log::{error, info};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LedExpiryConfig {
+    /// Seconds between two polls of the worker
+    pub poll_interval: u64,
+}
+
+impl Default for LedExpiryConfig {
+    fn default() -> Self {
+        Self { poll_interval: 60 }
+    }
+}
+
+/// Switches the expired leds off. Returns the number of switched off users.
+pub fn switch_off_expired(
+    users: &dyn PersistentUserDao,
+    now: NaiveDateTime,
+) -> Result<usize, ServiceError> {
+    let expired = users.switch_off_expired(now)?;
+
+    for user in expired.iter() {
+        info!("Led of {} expired", user.id);
+    }
+
+    Ok(expired.len())
+}
+
+/// Polls the expired leds forever. It is started in its own thread.
+pub fn run_worker(users: Box<dyn PersistentUserDao>, config: LedExpiryConfig) {
+    info!("Led expiry worker started");
+
+    loop {
+        let now = chrono::Local::now().naive_local();
+
+        if let Err(err) = switch_off_expired(users.as_ref(), now) {
+            error!("Led expiry failed {}", err);
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(config.poll_interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::MockPersistentUserDao;
+    use core::models::dao::UserDao;
+    use mockall::predicate::*;
+
+    #[test]
+    fn test_switch_off_expired() {
+        let now = chrono::Local::now().naive_local();
+
+        let mut users = MockPersistentUserDao::new();
+        users
+            .expect_switch_off_expired()
+            .with(eq(now))
+            .times(1)
+            .returning(|_| {
+                Ok(vec![
+                    UserDao::my_from("+4366412345678", "AT", "0.6.0", ""),
+                    UserDao::my_from("+4365012345678", "AT", "0.6.0", ""),
+                ])
+            });
+
+        assert_eq!(2, switch_off_expired(&users, now).unwrap());
+    }
+}
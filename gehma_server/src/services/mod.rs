@@ -1,3 +1,5 @@
+pub(crate) mod led_expiry;
 pub(crate) mod number_registration;
 pub(crate) mod push_notifications;
+pub(crate) mod retention;
 pub(crate) mod session;
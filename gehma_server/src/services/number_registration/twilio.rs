@@ -7,7 +7,8 @@ use serde::{Deserialize, Serialize};
 
 use reqwest::Client;
 
-#[derive(Clone)]
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct TwilioConfiguration {
     pub project_id: String,
     pub account_id: String,
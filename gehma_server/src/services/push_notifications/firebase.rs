@@ -1,4 +1,3 @@
-use super::Token as FirebaseToken;
 use crate::services::push_notifications::*;
 use core::errors::{InternalServerError, ServiceError};
 
@@ -9,7 +8,8 @@ use serde_json::json;
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use reqwest::Client;
 
-#[derive(Clone)]
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct FirebaseConfiguration {
     pub fcm_token: String,
 }
@@ -35,38 +35,82 @@ struct FirebaseResponse {
     success: usize,
     failure: usize,
     canonical_ids: usize,
+    /// In the order of `registration_ids`
+    #[serde(default)]
+    results: Vec<FirebaseResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FirebaseResult {
+    error: Option<String>,
+}
+
+/// Errors of tokens, which will never be valid again
+const INVALID_TOKEN_ERRORS: &[&str] = &["NotRegistered", "InvalidRegistration"];
+
+/// Tokens of `registration_ids`, which are rejected by firebase
+fn invalid_tokens(response: &FirebaseResponse, registration_ids: &[String]) -> Vec<String> {
+    response
+        .results
+        .iter()
+        .zip(registration_ids)
+        .filter(|(result, _)| match result.error {
+            Some(ref error) => INVALID_TOKEN_ERRORS.contains(&error.as_str()),
+            None => false,
+        })
+        .map(|(_, token)| token.clone())
+        .collect()
 }
 
-type Name = String;
 impl NotificationServiceTrait for FirebaseNotificationService {
-    fn push(&self, values: Vec<(Name, FirebaseToken)>) -> Result<(), ServiceError> {
+    fn push(&self, notifications: Vec<Notification>) -> Result<PushReport, ServiceError> {
         let client = Client::new();
         //let size : usize = values.len();
 
         let api_token = self.config.fcm_token.clone();
 
-        for (name, token) in values {
-            info!("Send to {}", token);
+        let mut report = PushReport::default();
+
+        for notification in notifications {
+            info!("Send {} to {}", notification.kind, notification.token);
             let response = client
                 .post("https://fcm.googleapis.com/fcm/send")
                 .header(CONTENT_TYPE, "application/json")
                 .header(AUTHORIZATION, format!("key={}", api_token))
                 .json(&json!({
                     "notification": {
-                        "title": format!("{} ist motiviert", name),
-                        "body": "",
-                        "icon": "ic_stat_name_nougat"
+                        "title": notification.title(),
+                        "body": notification.body(),
+                        "icon": notification::ICON,
+                        "tag": notification.collapse_key(),
                     },
+                    "data": notification.payload(),
+                    "collapse_key": notification.collapse_key(),
+                    "time_to_live": notification::TTL,
                     "priority": "high",
-                    "registration_ids": [token]
+                    "registration_ids": [notification.token]
                 }))
                 .send()
+                .and_then(|res| res.error_for_status())
+                .and_then(|mut res| res.json::<FirebaseResponse>())
                 .map_err(|err| {
                     error!("error {:?}", err);
                     ServiceError::InternalServerError(InternalServerError::NotificationError)
                 });
 
             debug!("response {:?}", response);
+
+            // Failed deliveries are retried by the outbox
+            let response = response?;
+
+            debug!(
+                "Success {}, failure {}, canonical ids {}",
+                response.success, response.failure, response.canonical_ids
+            );
+
+            report
+                .invalid_tokens
+                .extend(invalid_tokens(&response, &[notification.token]));
         }
 
         /*
@@ -115,6 +159,33 @@ impl NotificationServiceTrait for FirebaseNotificationService {
         tokio::run(work);
         */
 
-        Ok(())
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_tokens() {
+        let response: FirebaseResponse = serde_json::from_str(
+            r#"{
+                "multicast_id": 1715198469273987789,
+                "success": 1,
+                "failure": 2,
+                "canonical_ids": 0,
+                "results": [
+                    { "error": "NotRegistered" },
+                    { "message_id": "1" },
+                    { "error": "Unavailable" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let tokens = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        assert_eq!(vec!["a".to_string()], invalid_tokens(&response, &tokens));
     }
 }
@@ -1,17 +1,25 @@
 use core::errors::ServiceError;
 
 pub mod firebase;
+pub mod notification;
 pub mod one_signal;
+pub mod outbox;
 pub mod testing;
 
-use mockall::*;
+pub use notification::{Notification, NotificationKind};
 
-type Name = String;
-type Token = String;
+use mockall::*;
 
 pub type NotificationService = Box<dyn NotificationServiceTrait>;
 
+/// Outcome of a push, which was accepted by the provider
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PushReport {
+    /// Tokens, which the provider reported as unregistered or invalid
+    pub invalid_tokens: Vec<String>,
+}
+
 #[automock]
 pub trait NotificationServiceTrait {
-    fn push(&self, contacts: Vec<(Name, Token)>) -> Result<(), ServiceError>;
+    fn push(&self, notifications: Vec<Notification>) -> Result<PushReport, ServiceError>;
 }
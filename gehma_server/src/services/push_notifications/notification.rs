@@ -0,0 +1,321 @@
+//! Notifications are rendered from templates, so every backend sends the same
+//! title, body and deep-link data. The language is chosen by the recipient's country.
+use core::models::dao::ContactPushNotificationDao;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Seconds until undelivered notifications are discarded by the provider
+pub const TTL: u32 = 2 * 24 * 60 * 60;
+
+/// Android icon of the notifications
+pub const ICON: &str = "ic_stat_name_nougat";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    LedOn,
+    NewBroadcast,
+    InvitationReceived,
+    InvitationAccepted,
+    /// A contact has a new phone number
+    TeleNumChanged,
+}
+
+impl NotificationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::LedOn => "led_on",
+            NotificationKind::NewBroadcast => "new_broadcast",
+            NotificationKind::InvitationReceived => "invitation_received",
+            NotificationKind::InvitationAccepted => "invitation_accepted",
+            NotificationKind::TeleNumChanged => "tele_num_changed",
+        }
+    }
+}
+
+impl fmt::Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for NotificationKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "led_on" => Ok(NotificationKind::LedOn),
+            "new_broadcast" => Ok(NotificationKind::NewBroadcast),
+            "invitation_received" => Ok(NotificationKind::InvitationReceived),
+            "invitation_accepted" => Ok(NotificationKind::InvitationAccepted),
+            "tele_num_changed" => Ok(NotificationKind::TeleNumChanged),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    English,
+    German,
+}
+
+impl Language {
+    /// English is the fallback for every country without a translation
+    pub fn from_country_code(country_code: &str) -> Self {
+        match country_code.to_uppercase().as_str() {
+            "AT" | "DE" | "CH" | "LI" => Language::German,
+            _ => Language::English,
+        }
+    }
+
+    /// ISO 639-1 code
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
+}
+
+/// Keys of `Notification::data`
+pub const DATA_USER_ID: &str = "user_id";
+pub const DATA_INVITATION_ID: &str = "invitation_id";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub token: String,
+    /// Name of the originator in the contacts of the recipient
+    pub name: String,
+    /// Country of the recipient
+    pub country_code: String,
+    /// Text of the broadcast or the invitation
+    pub text: String,
+    /// Ids, which are needed by the client to open the right screen
+    pub data: BTreeMap<String, String>,
+}
+
+impl Notification {
+    pub fn new(
+        kind: NotificationKind,
+        token: impl Into<String>,
+        name: impl Into<String>,
+        country_code: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind,
+            token: token.into(),
+            name: name.into(),
+            country_code: country_code.into(),
+            text: String::new(),
+            data: BTreeMap::new(),
+        }
+    }
+
+    /// One notification for every device of `contact`. Empty tokens are skipped.
+    pub fn for_contact(kind: NotificationKind, contact: ContactPushNotificationDao) -> Vec<Self> {
+        let name = contact.name;
+        let country_code = contact.country_code;
+
+        contact
+            .tokens
+            .into_iter()
+            .filter(|token| !token.is_empty())
+            .map(|token| Self::new(kind, token, name.clone(), country_code.clone()))
+            .collect()
+    }
+
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    pub fn with_user(mut self, user_id: &Uuid) -> Self {
+        self.data
+            .insert(DATA_USER_ID.to_string(), user_id.to_string());
+        self
+    }
+
+    pub fn with_invitation(mut self, inv_id: i32) -> Self {
+        self.data
+            .insert(DATA_INVITATION_ID.to_string(), inv_id.to_string());
+        self
+    }
+
+    pub fn language(&self) -> Language {
+        Language::from_country_code(&self.country_code)
+    }
+
+    pub fn title(&self) -> String {
+        let name = &self.name;
+
+        match (self.kind, self.language()) {
+            (NotificationKind::LedOn, Language::English) => format!("{} is motivated", name),
+            (NotificationKind::LedOn, Language::German) => format!("{} ist motiviert", name),
+            (NotificationKind::NewBroadcast, Language::English) => {
+                format!("New broadcast from {}", name)
+            }
+            (NotificationKind::NewBroadcast, Language::German) => {
+                format!("Neuer Broadcast von {}", name)
+            }
+            (NotificationKind::InvitationReceived, Language::English) => {
+                format!("{} invited you", name)
+            }
+            (NotificationKind::InvitationReceived, Language::German) => {
+                format!("{} hat dich eingeladen", name)
+            }
+            (NotificationKind::InvitationAccepted, Language::English) => {
+                format!("{} accepted your invitation", name)
+            }
+            (NotificationKind::InvitationAccepted, Language::German) => {
+                format!("{} hat deine Einladung angenommen", name)
+            }
+            (NotificationKind::TeleNumChanged, Language::English) => {
+                format!("{} has a new number", name)
+            }
+            (NotificationKind::TeleNumChanged, Language::German) => {
+                format!("{} hat eine neue Nummer", name)
+            }
+        }
+    }
+
+    /// The text, if there is one
+    pub fn body(&self) -> String {
+        if !self.text.is_empty() {
+            return self.text.clone();
+        }
+
+        let body = match (self.kind, self.language()) {
+            (NotificationKind::LedOn, Language::English)
+            | (NotificationKind::NewBroadcast, Language::English) => "Your friends are motivated",
+            (NotificationKind::LedOn, Language::German)
+            | (NotificationKind::NewBroadcast, Language::German) => "Deine Freunde sind motiviert",
+            (NotificationKind::InvitationReceived, Language::English) => "Are you in?",
+            (NotificationKind::InvitationReceived, Language::German) => "Bist du dabei?",
+            (NotificationKind::InvitationAccepted, Language::English) => "Have fun!",
+            (NotificationKind::InvitationAccepted, Language::German) => "Viel Spaß!",
+            (NotificationKind::TeleNumChanged, Language::English) => "Update your contacts",
+            (NotificationKind::TeleNumChanged, Language::German) => "Aktualisiere deine Kontakte",
+        };
+
+        body.to_string()
+    }
+
+    /// Screen of the app, which is opened by the notification
+    pub fn deep_link(&self) -> String {
+        match self.kind {
+            NotificationKind::LedOn | NotificationKind::TeleNumChanged => {
+                "gehma://contacts".to_string()
+            }
+            NotificationKind::NewBroadcast => "gehma://broadcasts".to_string(),
+            NotificationKind::InvitationReceived | NotificationKind::InvitationAccepted => {
+                match self.data.get(DATA_INVITATION_ID) {
+                    Some(inv_id) => format!("gehma://invitations/{}", inv_id),
+                    None => "gehma://invitations".to_string(),
+                }
+            }
+        }
+    }
+
+    /// Newer notifications of the same originator or invitation replace older ones
+    pub fn collapse_key(&self) -> String {
+        let subject = self
+            .data
+            .get(DATA_INVITATION_ID)
+            .or_else(|| self.data.get(DATA_USER_ID));
+
+        match subject {
+            Some(subject) => format!("{}:{}", self.kind, subject),
+            None => self.kind.to_string(),
+        }
+    }
+
+    /// Data of the notification, which is passed to the app
+    pub fn payload(&self) -> BTreeMap<String, String> {
+        let mut payload = self.data.clone();
+
+        payload.insert("kind".to_string(), self.kind.to_string());
+        payload.insert("link".to_string(), self.deep_link());
+
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_localised_by_country() {
+        let german = Notification::new(NotificationKind::LedOn, "token", "Alice", "AT");
+        let english = Notification::new(NotificationKind::LedOn, "token", "Alice", "GB");
+
+        assert_eq!("Alice ist motiviert", german.title());
+        assert_eq!("Alice is motivated", english.title());
+        assert_eq!("Deine Freunde sind motiviert", german.body());
+        assert_eq!(Language::English, Language::from_country_code(""));
+    }
+
+    #[test]
+    fn test_text_is_the_body() {
+        let notification =
+            Notification::new(NotificationKind::InvitationReceived, "token", "Alice", "DE")
+                .with_text("Football");
+
+        assert_eq!("Alice hat dich eingeladen", notification.title());
+        assert_eq!("Football", notification.body());
+    }
+
+    #[test]
+    fn test_payload_and_collapse_key() {
+        let notification =
+            Notification::new(NotificationKind::InvitationAccepted, "token", "Alice", "AT")
+                .with_invitation(5);
+
+        let payload = notification.payload();
+
+        assert_eq!("invitation_accepted:5", notification.collapse_key());
+        assert_eq!(
+            Some(&"invitation_accepted".to_string()),
+            payload.get("kind")
+        );
+        assert_eq!(
+            Some(&"gehma://invitations/5".to_string()),
+            payload.get("link")
+        );
+        assert_eq!(Some(&"5".to_string()), payload.get(DATA_INVITATION_ID));
+    }
+
+    #[test]
+    fn test_for_contact_fans_out_to_devices() {
+        let contact = ContactPushNotificationDao {
+            from_id: Uuid::new_v4(),
+            name: "Alice".to_string(),
+            tokens: vec!["phone".to_string(), "".to_string(), "tablet".to_string()],
+            target_hash_tele_num: core::models::dto::HashedTeleNum("hash".to_string()),
+            country_code: "AT".to_string(),
+        };
+
+        let notifications = Notification::for_contact(NotificationKind::LedOn, contact);
+
+        assert_eq!(2, notifications.len());
+        assert_eq!("phone", notifications[0].token);
+        assert_eq!("tablet", notifications[1].token);
+        assert_eq!("AT", notifications[1].country_code);
+    }
+
+    #[test]
+    fn test_kind_roundtrip() {
+        for kind in &[
+            NotificationKind::LedOn,
+            NotificationKind::NewBroadcast,
+            NotificationKind::InvitationReceived,
+            NotificationKind::InvitationAccepted,
+            NotificationKind::TeleNumChanged,
+        ] {
+            assert_eq!(Ok(*kind), kind.as_str().parse());
+        }
+    }
+}
@@ -1,4 +1,3 @@
-use super::Token;
 use crate::services::push_notifications::*;
 use core::errors::{InternalServerError, ServiceError};
 
@@ -9,7 +8,8 @@ use serde_json::json;
 use reqwest::header::{CONTENT_TYPE};
 use reqwest::Client;
 
-#[derive(Clone)]
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct OneSignalConfiguration {
     pub id: String,
     pub key: String,
@@ -24,58 +24,89 @@ pub struct OneSignalService {
  * {
     "id": "xxx",
     "recipients": 1,
-    "external_id": null
+    "external_id": null,
+    "errors": { "invalid_player_ids": ["xxx"] }
 }*/
 #[derive(Debug, Deserialize)]
 struct OneSignalResponse {
+    #[serde(default)]
     id: String,
+    #[serde(default)]
     recipients: u32,
+    /// A list of messages or an object with the rejected player ids
+    errors: Option<serde_json::Value>,
+}
+
+impl OneSignalResponse {
+    fn invalid_player_ids(&self) -> Vec<String> {
+        self.errors
+            .as_ref()
+            .and_then(|errors| errors.get("invalid_player_ids"))
+            .and_then(|ids| ids.as_array())
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|w| w.as_str())
+                    .map(|w| w.to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
 
-type Name = String;
 impl NotificationServiceTrait for OneSignalService {
-    fn push(&self, values: Vec<(Name, Token)>) -> Result<(), ServiceError> {
+    fn push(&self, notifications: Vec<Notification>) -> Result<PushReport, ServiceError> {
         let client = Client::new();
-        //let size : usize = values.len();
 
         let id = self.config.id.clone();
         let _api_token = self.config.key.clone();
 
-        if values.len() > 2000 {
-            //Onesignal rate limit
-            return Err(ServiceError::BadRequest(
-                "Too many contacts. Ratelimit reached".to_string(),
-            ));
-        }
+        let mut report = PushReport::default();
+
+        // Every recipient gets its own request, because the texts are localised
+        for notification in notifications.into_iter().filter(|w| !w.token.is_empty()) {
+            let language = notification.language().code();
+
+            let _response = client
+                .post("https://onesignal.com/api/v1/notifications")
+                .header(CONTENT_TYPE, "application/json")
+                //.header(AUTHORIZATION, api_token.clone())
+                .json(&json!({
+                    "app_id": id,
+                    // `en` is required by OneSignal
+                    "headings": {
+                        "en": notification.title(),
+                        language: notification.title(),
+                    },
+                    "contents": {
+                        "en": notification.body(),
+                        language: notification.body(),
+                    },
+                    "data": notification.payload(),
+                    "collapse_id": notification.collapse_key(),
+                    "ttl": notification::TTL,
+                    "include_player_ids": [notification.token]
+                }))
+                .send()
+                .and_then(|res| res.error_for_status())
+                .and_then(|mut res| res.json::<OneSignalResponse>())
+                .map_err(|err| {
+                    error!("error {:#?}", err);
+                    ServiceError::InternalServerError(InternalServerError::NotificationError)
+                });
 
-        let tokens: Vec<_> = values
-            .into_iter()
-            .filter(|w| w.1 != "".to_string())
-            .map(|w| w.1)
-            .collect();
-
-        log::debug!("Tokens {:?}", tokens.len());
-
-        let _response = client
-            .post("https://onesignal.com/api/v1/notifications")
-            .header(CONTENT_TYPE, "application/json")
-            //.header(AUTHORIZATION, api_token.clone())
-            .json(&json!({
-                "app_id": id,
-                "contents": {
-                    "en": format!("Your friends are motivated"),
-                    "de": format!("Deine Freunde sind motiviert"),
-                },
-                "ttl": 172800, //two days
-                "include_player_ids": tokens
-            }))
-            .send()
-            .map_err(|err| {
-                error!("error {:#?}", err);
-                ServiceError::InternalServerError(InternalServerError::NotificationError)
-            });
+            log::info!("response {:#?}", _response);
+
+            // Failed deliveries are retried by the outbox
+            let response = _response?;
 
-        log::info!("response {:#?}", _response);
+            log::debug!(
+                "Notification {} has {} recipients",
+                response.id,
+                response.recipients
+            );
+
+            report.invalid_tokens.extend(response.invalid_player_ids());
+        }
 
         /*
         let work = tokio::prelude::stream::iter_ok(values)
@@ -123,6 +154,28 @@ impl NotificationServiceTrait for OneSignalService {
         tokio::run(work);
         */
 
-        Ok(())
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_player_ids() {
+        let response: OneSignalResponse = serde_json::from_str(
+            r#"{ "id": "1", "recipients": 1, "errors": { "invalid_player_ids": ["a", "b"] } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(vec!["a", "b"], response.invalid_player_ids());
+
+        let response: OneSignalResponse = serde_json::from_str(
+            r#"{ "id": "", "recipients": 0, "errors": ["All included players are not subscribed"] }"#,
+        )
+        .unwrap();
+
+        assert!(response.invalid_player_ids().is_empty());
     }
 }
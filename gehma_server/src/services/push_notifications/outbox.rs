@@ -0,0 +1,690 @@
+//! Push notifications are written to the `notification_outbox` table and delivered
+//! by a background worker, so a slow or failing backend does not block requests.
+//! Failed deliveries are retried with exponential back-off until `max_attempts`.
+use super::*;
+use crate::metrics::PRUNED_PUSH_TOKENS;
+use crate::queries::{PersistentDeviceDao, PersistentOutboxDao, PersistentUserDao};
+use chrono::{Duration, NaiveDateTime};
+use core::models::dao::*;
+use log::{debug, error, info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutboxConfig {
+    /// Seconds between two polls of the worker
+    pub poll_interval: u64,
+    /// Notifications per poll
+    pub batch_size: i64,
+    /// Attempts until a notification is dead
+    pub max_attempts: i32,
+    /// First back-off in seconds. It doubles with every failed attempt.
+    pub backoff: i64,
+    /// Maximum back-off in seconds
+    pub max_backoff: i64,
+    /// Seconds a claimed notification is hidden from other workers
+    pub lease: i64,
+    /// Delivered notifications are removed after this many seconds
+    pub retention: Option<i64>,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: 5,
+            batch_size: 100,
+            max_attempts: 8,
+            backoff: 30,
+            max_backoff: 60 * 60,
+            lease: 60,
+            retention: Some(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Enqueues the notifications instead of sending them
+pub struct OutboxNotificationService {
+    dao: Box<dyn PersistentOutboxDao>,
+}
+
+impl OutboxNotificationService {
+    pub fn new(dao: Box<dyn PersistentOutboxDao>) -> Self {
+        Self { dao }
+    }
+}
+
+fn to_outbox(notification: Notification, now: NaiveDateTime) -> InsertNotificationOutboxDao {
+    InsertNotificationOutboxDao {
+        name: notification.name,
+        token: notification.token,
+        status: OUTBOX_PENDING.to_string(),
+        attempts: 0,
+        next_attempt_at: now,
+        created_at: now,
+        updated_at: now,
+        kind: notification.kind.to_string(),
+        country_code: notification.country_code,
+        text: notification.text,
+        data: serde_json::to_string(&notification.data).unwrap_or_else(|_| "{}".to_string()),
+    }
+}
+
+/// `None`, if the row cannot be sent anymore
+fn from_outbox(row: &NotificationOutboxDao) -> Option<Notification> {
+    let kind = row.kind.parse().ok()?;
+    let data = serde_json::from_str(&row.data).ok()?;
+
+    Some(Notification {
+        kind,
+        token: row.token.clone(),
+        name: row.name.clone(),
+        country_code: row.country_code.clone(),
+        text: row.text.clone(),
+        data,
+    })
+}
+
+impl NotificationServiceTrait for OutboxNotificationService {
+    fn push(&self, notifications: Vec<Notification>) -> Result<PushReport, ServiceError> {
+        let now = chrono::Utc::now().naive_utc();
+
+        let notifications: Vec<_> = notifications
+            .into_iter()
+            .filter(|w| !w.token.is_empty())
+            .map(|w| to_outbox(w, now))
+            .collect();
+
+        debug!("Enqueue {} notifications", notifications.len());
+
+        self.dao.enqueue(notifications)?;
+
+        // The tokens are checked by the worker
+        Ok(PushReport::default())
+    }
+}
+
+/// Result of one poll of the worker
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DeliveryReport {
+    pub delivered: usize,
+    pub retried: usize,
+    pub dead: usize,
+    /// Tokens, which were rejected by the provider and cleared
+    pub pruned: usize,
+}
+
+/// `backoff * 2^(attempts - 1)`, but at most `max_backoff`
+fn backoff(attempts: i32, config: &OutboxConfig) -> i64 {
+    1i64.checked_shl((attempts - 1).max(0) as u32)
+        .and_then(|w| config.backoff.checked_mul(w))
+        .filter(|w| *w > 0)
+        .unwrap_or(config.max_backoff)
+        .min(config.max_backoff)
+}
+
+/// Removes the rejected token from the devices and clears it of the users.
+/// Returns `true` if it was stored anywhere.
+fn prune_token(
+    users: &dyn PersistentUserDao,
+    devices: &dyn PersistentDeviceDao,
+    token: &str,
+) -> Result<bool, ServiceError> {
+    let mut cleared = devices.remove_token(token)? > 0;
+
+    for user in users.get_by_token(token)? {
+        info!("Clear the rejected push token of {}", user.id);
+        users.clear_token(&user.id)?;
+        cleared = true;
+    }
+
+    Ok(cleared)
+}
+
+/// Prunes every rejected token. A failure is only logged, so the outcome of the
+/// notifications is still recorded. Returns the number of pruned tokens.
+fn prune_tokens(
+    users: &dyn PersistentUserDao,
+    devices: &dyn PersistentDeviceDao,
+    tokens: &[String],
+) -> usize {
+    let mut pruned = 0;
+
+    for token in tokens {
+        match prune_token(users, devices, token) {
+            Ok(true) => pruned += 1,
+            Ok(false) => {}
+            Err(err) => error!("Cannot prune the push token {}: {}", token, err),
+        }
+    }
+
+    PRUNED_PUSH_TOKENS.add(pruned as u64);
+
+    pruned
+}
+
+/// Sends the due notifications and records the outcome of each. Every notification is
+/// sent with its own call of `sender`, so a failure only retries the failed one.
+/// Tokens, which are rejected by the provider, are cleared.
+pub fn deliver_due(
+    dao: &dyn PersistentOutboxDao,
+    users: &dyn PersistentUserDao,
+    devices: &dyn PersistentDeviceDao,
+    sender: &dyn NotificationServiceTrait,
+    config: &OutboxConfig,
+    now: NaiveDateTime,
+) -> Result<DeliveryReport, ServiceError> {
+    let lease_until = now + Duration::seconds(config.lease);
+    let due = dao.claim_due(now, lease_until, config.batch_size)?;
+
+    let mut report = DeliveryReport::default();
+
+    for notification in due {
+        // The lease of the last attempt expired, because a worker crashed
+        if notification.attempts > config.max_attempts {
+            warn!(
+                "Notification {} is dead after {} attempts",
+                notification.id, config.max_attempts
+            );
+            dao.mark_failed(notification.id, "Lease expired".to_string(), None, now)?;
+            report.dead += 1;
+            continue;
+        }
+
+        let payload = match from_outbox(&notification) {
+            Some(payload) => payload,
+            None => {
+                warn!("Notification {} is malformed", notification.id);
+                dao.mark_failed(notification.id, "Malformed".to_string(), None, now)?;
+                report.dead += 1;
+                continue;
+            }
+        };
+
+        match sender.push(vec![payload]) {
+            Ok(push) => {
+                report.pruned += prune_tokens(users, devices, &push.invalid_tokens);
+
+                if push.invalid_tokens.contains(&notification.token) {
+                    dao.mark_failed(notification.id, "Invalid token".to_string(), None, now)?;
+                    report.dead += 1;
+                } else {
+                    dao.mark_delivered(notification.id, now)?;
+                    report.delivered += 1;
+                }
+            }
+            Err(err) => {
+                // The attempt was already counted by the claim
+                let attempts = notification.attempts;
+
+                if attempts >= config.max_attempts {
+                    warn!(
+                        "Notification {} is dead after {} attempts: {}",
+                        notification.id, attempts, err
+                    );
+                    dao.mark_failed(notification.id, err.to_string(), None, now)?;
+                    report.dead += 1;
+                } else {
+                    let retry_at = now + Duration::seconds(backoff(attempts, config));
+                    dao.mark_failed(notification.id, err.to_string(), Some(retry_at), now)?;
+                    report.retried += 1;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Polls the outbox forever. It is started in its own thread.
+pub fn run_worker(
+    dao: Box<dyn PersistentOutboxDao>,
+    users: Box<dyn PersistentUserDao>,
+    devices: Box<dyn PersistentDeviceDao>,
+    sender: NotificationService,
+    config: OutboxConfig,
+) {
+    info!("Notification worker started");
+
+    loop {
+        let now = chrono::Utc::now().naive_utc();
+
+        match deliver_due(
+            dao.as_ref(),
+            users.as_ref(),
+            devices.as_ref(),
+            sender.as_ref(),
+            &config,
+            now,
+        ) {
+            Ok(report) if report.pruned > 0 => info!(
+                "Outbox {:?}, {} {}",
+                report,
+                PRUNED_PUSH_TOKENS.name(),
+                PRUNED_PUSH_TOKENS.get()
+            ),
+            Ok(report) if report != DeliveryReport::default() => info!("Outbox {:?}", report),
+            Ok(_) => {}
+            Err(err) => error!("Outbox delivery failed {}", err),
+        }
+
+        if let Some(retention) = config.retention {
+            if let Err(err) = dao.remove_delivered(now - Duration::seconds(retention)) {
+                error!("Outbox cleanup failed {}", err);
+            }
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(config.poll_interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::{MockPersistentDeviceDao, MockPersistentOutboxDao, MockPersistentUserDao};
+    use core::errors::InternalServerError;
+    use mockall::predicate::*;
+
+    fn pending(id: i32, attempts: i32, now: NaiveDateTime) -> NotificationOutboxDao {
+        NotificationOutboxDao {
+            id,
+            name: "Alice".to_string(),
+            token: format!("token{}", id),
+            status: OUTBOX_PENDING.to_string(),
+            attempts,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+            kind: NotificationKind::LedOn.to_string(),
+            country_code: "AT".to_string(),
+            text: String::new(),
+            data: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let config = OutboxConfig::default();
+
+        assert_eq!(30, backoff(1, &config));
+        assert_eq!(60, backoff(2, &config));
+        assert_eq!(120, backoff(3, &config));
+        assert_eq!(config.max_backoff, backoff(100, &config));
+    }
+
+    #[test]
+    fn test_enqueue_skips_empty_tokens() {
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_enqueue()
+            .withf(|w: &Vec<InsertNotificationOutboxDao>| {
+                w.len() == 1
+                    && w[0].token == "token"
+                    && w[0].status == OUTBOX_PENDING
+                    && w[0].kind == "invitation_received"
+                    && w[0].data == r#"{"invitation_id":"5"}"#
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let service = OutboxNotificationService::new(Box::new(dao));
+
+        service
+            .push(vec![
+                Notification::new(NotificationKind::InvitationReceived, "token", "Alice", "AT")
+                    .with_invitation(5),
+                Notification::new(NotificationKind::InvitationReceived, "", "Bob", "AT"),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_outbox_roundtrip() {
+        let now = chrono::Utc::now().naive_utc();
+        let notification =
+            Notification::new(NotificationKind::NewBroadcast, "token", "Alice", "DE")
+                .with_text("Running")
+                .with_user(&uuid::Uuid::new_v4());
+
+        let row = to_outbox(notification.clone(), now);
+        let row = NotificationOutboxDao {
+            id: 1,
+            name: row.name,
+            token: row.token,
+            status: row.status,
+            attempts: row.attempts,
+            next_attempt_at: row.next_attempt_at,
+            last_error: None,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            kind: row.kind,
+            country_code: row.country_code,
+            text: row.text,
+            data: row.data,
+        };
+
+        assert_eq!(Some(notification), from_outbox(&row));
+        assert_eq!(
+            None,
+            from_outbox(&NotificationOutboxDao {
+                kind: "unknown".to_string(),
+                ..row
+            })
+        );
+    }
+
+    #[test]
+    fn test_deliver_due() {
+        let config = OutboxConfig::default();
+        let now = chrono::Utc::now().naive_utc();
+
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_claim_due()
+            .with(
+                eq(now),
+                eq(now + Duration::seconds(config.lease)),
+                eq(config.batch_size),
+            )
+            .times(1)
+            .returning(move |_, _, _| Ok(vec![pending(1, 1, now), pending(2, 1, now)]));
+        dao.expect_mark_delivered()
+            .withf(move |id, at| (*id == 1 || *id == 2) && *at == now)
+            .times(2)
+            .returning(|_, _| Ok(()));
+
+        // Each is sent on its own
+        let mut sender = MockNotificationServiceTrait::new();
+        sender
+            .expect_push()
+            .withf(|notifications: &Vec<Notification>| notifications.len() == 1)
+            .times(2)
+            .returning(|_| Ok(PushReport::default()));
+
+        let report = deliver_due(
+            &dao,
+            &MockPersistentUserDao::new(),
+            &MockPersistentDeviceDao::new(),
+            &sender,
+            &config,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(
+            DeliveryReport {
+                delivered: 2,
+                retried: 0,
+                dead: 0,
+                pruned: 0,
+            },
+            report
+        );
+    }
+
+    #[test]
+    fn test_deliver_due_retries() {
+        let config = OutboxConfig::default();
+        let now = chrono::Utc::now().naive_utc();
+
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_claim_due()
+            .times(1)
+            .returning(move |_, _, _| Ok(vec![pending(1, 1, now), pending(2, 2, now)]));
+        dao.expect_mark_failed()
+            .withf(move |id, _, retry_at, _| {
+                *id == 1 && *retry_at == Some(now + Duration::seconds(30))
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        dao.expect_mark_failed()
+            .withf(move |id, _, retry_at, _| {
+                *id == 2 && *retry_at == Some(now + Duration::seconds(60))
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut sender = MockNotificationServiceTrait::new();
+        sender.expect_push().times(2).returning(|_| {
+            Err(ServiceError::InternalServerError(
+                InternalServerError::NotificationError,
+            ))
+        });
+
+        let report = deliver_due(
+            &dao,
+            &MockPersistentUserDao::new(),
+            &MockPersistentDeviceDao::new(),
+            &sender,
+            &config,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(2, report.retried);
+    }
+
+    #[test]
+    fn test_deliver_due_retries_only_the_failed() {
+        let config = OutboxConfig::default();
+        let now = chrono::Utc::now().naive_utc();
+
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_claim_due().times(1).returning(move |_, _, _| {
+            Ok(vec![
+                pending(1, 1, now),
+                pending(2, 1, now),
+                pending(3, 1, now),
+            ])
+        });
+        dao.expect_mark_delivered()
+            .withf(move |id, at| (*id == 1 || *id == 3) && *at == now)
+            .times(2)
+            .returning(|_, _| Ok(()));
+        dao.expect_mark_failed()
+            .withf(move |id, _, retry_at, _| {
+                *id == 2 && *retry_at == Some(now + Duration::seconds(30))
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut sender = MockNotificationServiceTrait::new();
+        sender.expect_push().times(3).returning(|notifications| {
+            match notifications[0].token.as_str() {
+                "token2" => Err(ServiceError::InternalServerError(
+                    InternalServerError::NotificationError,
+                )),
+                _ => Ok(PushReport::default()),
+            }
+        });
+
+        let report = deliver_due(
+            &dao,
+            &MockPersistentUserDao::new(),
+            &MockPersistentDeviceDao::new(),
+            &sender,
+            &config,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(
+            DeliveryReport {
+                delivered: 2,
+                retried: 1,
+                dead: 0,
+                pruned: 0,
+            },
+            report
+        );
+    }
+
+    #[test]
+    fn test_deliver_due_expired_lease() {
+        let config = OutboxConfig::default();
+        let now = chrono::Utc::now().naive_utc();
+        let attempts = config.max_attempts + 1;
+
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_claim_due()
+            .times(1)
+            .returning(move |_, _, _| Ok(vec![pending(1, attempts, now)]));
+        dao.expect_mark_failed()
+            .withf(|id, _, retry_at, _| *id == 1 && retry_at.is_none())
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        // It is not sent again
+        let mut sender = MockNotificationServiceTrait::new();
+        sender.expect_push().times(0);
+
+        let report = deliver_due(
+            &dao,
+            &MockPersistentUserDao::new(),
+            &MockPersistentDeviceDao::new(),
+            &sender,
+            &config,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(1, report.dead);
+    }
+
+    #[test]
+    fn test_deliver_due_dead_letter() {
+        let config = OutboxConfig::default();
+        let now = chrono::Utc::now().naive_utc();
+        let attempts = config.max_attempts;
+
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_claim_due()
+            .times(1)
+            .returning(move |_, _, _| Ok(vec![pending(1, attempts, now)]));
+        dao.expect_mark_failed()
+            .withf(|id, _, retry_at, _| *id == 1 && retry_at.is_none())
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut sender = MockNotificationServiceTrait::new();
+        sender.expect_push().times(1).returning(|_| {
+            Err(ServiceError::InternalServerError(
+                InternalServerError::NotificationError,
+            ))
+        });
+
+        let report = deliver_due(
+            &dao,
+            &MockPersistentUserDao::new(),
+            &MockPersistentDeviceDao::new(),
+            &sender,
+            &config,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(1, report.dead);
+    }
+
+    #[test]
+    fn test_deliver_due_prunes_invalid_tokens() {
+        let config = OutboxConfig::default();
+        let now = chrono::Utc::now().naive_utc();
+        let user_id = uuid::Uuid::new_v4();
+
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_claim_due()
+            .times(1)
+            .returning(move |_, _, _| Ok(vec![pending(1, 1, now)]));
+        dao.expect_mark_failed()
+            .withf(|id, _, retry_at, _| *id == 1 && retry_at.is_none())
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut users = MockPersistentUserDao::new();
+        users
+            .expect_get_by_token()
+            .withf(|token: &str| token == "token1")
+            .times(1)
+            .returning(move |_| {
+                Ok(vec![UserDao {
+                    id: user_id,
+                    ..UserDao::my_from("+4366412345678", "AT", "0.6.0", "")
+                }])
+            });
+        users
+            .expect_clear_token()
+            .withf(move |id| *id == user_id)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let mut devices = MockPersistentDeviceDao::new();
+        devices
+            .expect_remove_token()
+            .withf(|token: &str| token == "token1")
+            .times(1)
+            .returning(|_| Ok(2));
+
+        let mut sender = MockNotificationServiceTrait::new();
+        sender.expect_push().times(1).returning(|notifications| {
+            Ok(PushReport {
+                invalid_tokens: vec![notifications[0].token.clone()],
+            })
+        });
+
+        let before = PRUNED_PUSH_TOKENS.get();
+        let report = deliver_due(&dao, &users, &devices, &sender, &config, now).unwrap();
+
+        assert_eq!(1, report.pruned);
+        assert_eq!(1, report.dead);
+        assert!(PRUNED_PUSH_TOKENS.get() > before);
+    }
+
+    #[test]
+    fn test_deliver_due_prune_failure() {
+        let config = OutboxConfig::default();
+        let now = chrono::Utc::now().naive_utc();
+
+        let mut dao = MockPersistentOutboxDao::new();
+        dao.expect_claim_due()
+            .times(1)
+            .returning(move |_, _, _| Ok(vec![pending(1, 1, now), pending(2, 1, now)]));
+        dao.expect_mark_failed()
+            .withf(|id, _, retry_at, _| *id == 1 && retry_at.is_none())
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        // The other notification is still recorded
+        dao.expect_mark_delivered()
+            .with(eq(2), eq(now))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let mut devices = MockPersistentDeviceDao::new();
+        devices.expect_remove_token().times(1).returning(|_| {
+            Err(ServiceError::InternalServerError(
+                InternalServerError::DatabaseError("down".to_string()),
+            ))
+        });
+
+        let mut sender = MockNotificationServiceTrait::new();
+        sender.expect_push().times(2).returning(|notifications| {
+            match notifications[0].token.as_str() {
+                "token1" => Ok(PushReport {
+                    invalid_tokens: vec!["token1".to_string()],
+                }),
+                _ => Ok(PushReport::default()),
+            }
+        });
+
+        let report = deliver_due(
+            &dao,
+            &MockPersistentUserDao::new(),
+            &devices,
+            &sender,
+            &config,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(0, report.pruned);
+        assert_eq!(1, report.dead);
+        assert_eq!(1, report.delivered);
+    }
+}
@@ -4,7 +4,7 @@ use core::errors::ServiceError;
 pub struct TestingNotificationService;
 
 impl NotificationServiceTrait for TestingNotificationService {
-    fn push(&self, _: Vec<(String, Token)>) -> Result<(), ServiceError> {
-        Ok(())
+    fn push(&self, _: Vec<Notification>) -> Result<PushReport, ServiceError> {
+        Ok(PushReport::default())
     }
 }
@@ -0,0 +1,89 @@
+//! Analytics and usage statistics are only kept for a limited time. Older usage
+//! statistics are rolled up into monthly counts, older analytics are deleted.
+use crate::queries::PersistentRetentionDao;
+use chrono::{Duration, NaiveDateTime};
+use core::errors::ServiceError;
+use log::{error, info};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Seconds between two runs of the worker
+    pub poll_interval: u64,
+    /// Days, for which the analytics are kept
+    pub analytics: i64,
+    /// Days, for which the raw usage statistics are kept
+    pub usage_statistics: i64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: 24 * 60 * 60,
+            analytics: 90,
+            usage_statistics: 90,
+        }
+    }
+}
+
+/// Purges the expired rows. Returns the number of purged analytics and usage statistics.
+pub fn purge(
+    dao: &dyn PersistentRetentionDao,
+    config: &RetentionConfig,
+    now: NaiveDateTime,
+) -> Result<(usize, usize), ServiceError> {
+    let analytics = dao.purge_analytics(now - Duration::days(config.analytics))?;
+    let usage_statistics =
+        dao.purge_usage_statistics(now - Duration::days(config.usage_statistics))?;
+
+    info!(
+        "Purged {} analytics and {} usage statistics",
+        analytics, usage_statistics
+    );
+
+    Ok((analytics, usage_statistics))
+}
+
+/// Purges the expired rows forever. It is started in its own thread.
+pub fn run_worker(dao: Box<dyn PersistentRetentionDao>, config: RetentionConfig) {
+    info!("Retention worker started");
+
+    loop {
+        let now = chrono::Local::now().naive_local();
+
+        if let Err(err) = purge(dao.as_ref(), &config, now) {
+            error!("Retention failed {}", err);
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(config.poll_interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::MockPersistentRetentionDao;
+    use mockall::predicate::*;
+
+    #[test]
+    fn test_purge() {
+        let now = chrono::Local::now().naive_local();
+        let config = RetentionConfig {
+            poll_interval: 60,
+            analytics: 30,
+            usage_statistics: 90,
+        };
+
+        let mut dao = MockPersistentRetentionDao::new();
+        dao.expect_purge_analytics()
+            .with(eq(now - Duration::days(30)))
+            .times(1)
+            .returning(|_| Ok(3));
+        dao.expect_purge_usage_statistics()
+            .with(eq(now - Duration::days(90)))
+            .times(1)
+            .returning(|_| Ok(5));
+
+        assert_eq!((3, 5), purge(&dao, &config, now).unwrap());
+    }
+}
@@ -2,14 +2,26 @@ use chrono::prelude::*;
 use core::errors::ServiceError;
 use jsonwebtoken::{decode, encode, Header, Validation};
 use mockall::*;
+use std::sync::Arc;
 use time::Duration;
 use uuid::Uuid;
-use log::{info, error};
+use log::{debug, info, warn};
+use ring::constant_time::verify_slices_are_equal;
 
-const SESSION_DURATION: i64 = 8;
+use crate::queries::PersistentSessionDao;
+
+/// Default lifetime of the session token in seconds
+pub const SESSION_DURATION: i64 = 60 * 60;
+/// Default lifetime of the refresh token in seconds
+pub const REFRESH_TOKEN_DURATION: i64 = 30 * 24 * 60 * 60;
+
+const REFRESH_TOKEN_LENGTH: usize = 48;
 
 pub type SessionService = Box<dyn SessionKeyVerification>;
 
+/// Keeps track of the active sessions, so they can be revoked
+pub type SessionStore = Arc<dyn PersistentSessionDao + Send + Sync>;
+
 /// `Claims` is encoded the session token
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -19,65 +31,279 @@ pub struct Claims {
     exp: i64,
     /// Issue date
     iat: i64,
+    /// Session id
+    jti: String,
+    /// Login family, which is shared with the refresh tokens of the login
+    #[serde(default)]
+    fam: String,
 }
 
 pub struct SessionServicePriv {
     secret: String,
+    store: Option<SessionStore>,
+    /// Lifetime of the session token in seconds
+    session_duration: i64,
+    /// Lifetime of the refresh token in seconds
+    refresh_token_duration: i64,
 }
 
 impl SessionServicePriv {
     pub fn new(secret: String) -> Self {
-        Self { secret }
+        Self {
+            secret,
+            store: None,
+            session_duration: SESSION_DURATION,
+            refresh_token_duration: REFRESH_TOKEN_DURATION,
+        }
+    }
+
+    /// Overwrites the default lifetimes (in seconds)
+    pub fn with_durations(mut self, session_duration: i64, refresh_token_duration: i64) -> Self {
+        self.session_duration = session_duration;
+        self.refresh_token_duration = refresh_token_duration;
+        self
+    }
+
+    /// Sessions are additionally checked against `store`,
+    /// so they can be revoked before they expire
+    pub fn with_store(mut self, store: SessionStore) -> Self {
+        self.store = Some(store);
+        self
     }
 }
 
 #[automock]
 pub trait SessionKeyVerification {
     /// Creates new session key
-    fn new_session(&self, id: Uuid) -> (String, Claims);
+    fn new_session(&self, id: Uuid) -> Result<(String, Claims), ServiceError>;
 
     /// Returns `true` if `token` is valid
     fn validate(&self, token: String) -> Result<bool, ServiceError>;
+
+    /// Returns the claims of `token` if it is valid
+    fn decode(&self, token: String) -> Result<Claims, ServiceError>;
+
+    /// Revokes the session of `token` and the refresh tokens of its login
+    fn logout(&self, token: String) -> Result<(), ServiceError>;
+
+    /// Revokes all sessions of the user `id`
+    fn logout_all(&self, id: &Uuid) -> Result<(), ServiceError>;
+
+    /// Creates a refresh token for the login of the session `claims`.
+    /// Returns `None` if refreshing is not supported.
+    fn new_refresh_token(&self, claims: &Claims) -> Result<Option<String>, ServiceError>;
+
+    /// Exchanges the `refresh_token` for a new session token and a new refresh token.
+    /// Using a refresh token twice revokes all sessions of the user.
+    fn refresh(&self, refresh_token: String) -> Result<(String, Claims, String), ServiceError>;
+}
+
+/// The refresh token consists of `{user}.{family}.{secret}`.
+/// All rotated tokens of one login share the same family.
+struct RefreshToken {
+    id: Uuid,
+    family: String,
+    secret: String,
+}
+
+impl RefreshToken {
+    fn new(id: Uuid, family: String) -> Self {
+        Self {
+            id,
+            family,
+            secret: core::utils::generate_random_string(REFRESH_TOKEN_LENGTH),
+        }
+    }
+
+    fn parse(raw: &str) -> Result<Self, ServiceError> {
+        let splitted: Vec<_> = raw.split('.').collect();
+
+        match splitted.as_slice() {
+            [id, family, secret] => Ok(Self {
+                id: Uuid::parse_str(id).map_err(|_| ServiceError::Unauthorized)?,
+                family: family.to_string(),
+                secret: secret.to_string(),
+            }),
+            _ => Err(ServiceError::Unauthorized),
+        }
+    }
+
+    fn as_token(&self) -> String {
+        format!("{}.{}.{}", self.id.simple(), self.family, self.secret)
+    }
 }
 
 impl Claims {
-    pub fn new(id: Uuid) -> Self {
+    /// Claims of a new login
+    pub fn new(id: Uuid, duration: i64) -> Self {
+        Self::with_family(id, duration, Uuid::new_v4().simple().to_string())
+    }
+
+    /// Claims of a refreshed session of the login `family`
+    pub fn with_family(id: Uuid, duration: i64, family: String) -> Self {
         Self {
             sub: id.simple().to_string(),
-            exp: Utc::now().checked_add_signed(Duration::hours(SESSION_DURATION)).unwrap().timestamp(),
+            exp: Utc::now().checked_add_signed(Duration::seconds(duration)).unwrap().timestamp(),
             iat: Utc::now().timestamp(),
+            jti: Uuid::new_v4().simple().to_string(),
+            fam: family,
         }
     }
+
+    /// The id of the user, who owns the session
+    pub fn user_id(&self) -> Result<Uuid, ServiceError> {
+        Ok(Uuid::parse_str(&self.sub)?)
+    }
+}
+
+impl SessionServicePriv {
+    fn store_session(&self, claim: Claims) -> Result<(String, Claims), ServiceError> {
+        if let Some(store) = &self.store {
+            store.set_new_session(&claim.user_id()?, &claim.jti, Some(claim.exp - claim.iat))?;
+        }
+
+        Ok((encode(&Header::default(), &claim, self.secret.as_ref()).unwrap(), claim))
+    }
+
+    /// Revokes the refresh tokens and all sessions of the user
+    fn revoke(&self, store: &SessionStore, token: &RefreshToken) -> Result<(), ServiceError> {
+        store.remove_refresh_token(&token.id, &token.family)?;
+        store.clear_session(&token.id)
+    }
 }
 
 impl SessionKeyVerification for SessionServicePriv {
-    fn new_session(&self, id: Uuid) -> (String, Claims) {
-        let claim = Claims::new(id);
-        (encode(&Header::default(), &claim, self.secret.as_ref()).unwrap(), claim)
+    fn new_session(&self, id: Uuid) -> Result<(String, Claims), ServiceError> {
+        self.store_session(Claims::new(id, self.session_duration))
     }
 
     fn validate(&self, token: String) -> Result<bool, ServiceError> {
+        match self.decode(token) {
+            Ok(_) => Ok(true),
+            Err(ServiceError::Unauthorized) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn decode(&self, token: String) -> Result<Claims, ServiceError> {
         let token = decode::<Claims>(&token, self.secret.as_ref(), &Validation::default());
 
-        match token {
-            Ok(_) => Ok(true),
+        let claims = match token {
+            Ok(data) => data.claims,
             Err(err) => {
-                error!("session {:?}", err);
-                info!("Session invalid");
-                Ok(false)
+                debug!("Session invalid {:?}", err);
+                return Err(ServiceError::Unauthorized);
             },
+        };
+
+        if let Some(store) = &self.store {
+            if !store.is_session_valid(&claims.user_id()?, &claims.jti)? {
+                info!("Session was revoked");
+                return Err(ServiceError::Unauthorized);
+            }
+        }
+
+        Ok(claims)
+    }
+
+    fn logout(&self, token: String) -> Result<(), ServiceError> {
+        let claims = self.decode(token)?;
+
+        if let Some(store) = &self.store {
+            let id = claims.user_id()?;
+
+            store.remove_session(&id, &claims.jti)?;
+            // The device cannot refresh the session anymore
+            store.remove_refresh_token(&id, &claims.fam)?;
+        }
+
+        Ok(())
+    }
+
+    fn logout_all(&self, id: &Uuid) -> Result<(), ServiceError> {
+        if let Some(store) = &self.store {
+            store.clear_session(id)?;
+        }
+
+        Ok(())
+    }
+
+    fn new_refresh_token(&self, claims: &Claims) -> Result<Option<String>, ServiceError> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(None),
+        };
+
+        let token = RefreshToken::new(claims.user_id()?, claims.fam.clone());
+
+        store.set_refresh_token(
+            &token.id,
+            &token.family,
+            &token.secret,
+            Some(self.refresh_token_duration),
+        )?;
+
+        Ok(Some(token.as_token()))
+    }
+
+    fn refresh(&self, refresh_token: String) -> Result<(String, Claims, String), ServiceError> {
+        let store = self.store.as_ref().ok_or_else(|| {
+            ServiceError::BadRequest("Refresh tokens are not supported".to_string())
+        })?;
+
+        let token = RefreshToken::parse(&refresh_token)?;
+
+        let current = store
+            .get_refresh_token(&token.id, &token.family)?
+            .ok_or_else(|| {
+                info!("Refresh token expired or revoked");
+                ServiceError::Unauthorized
+            })?;
+
+        if verify_slices_are_equal(current.as_bytes(), token.secret.as_bytes()).is_err() {
+            // The token was already rotated, so it has been used before.
+            // Someone else might have stolen it.
+            warn!("Refresh token reuse detected for {}", token.id);
+            self.revoke(store, &token)?;
+            return Err(ServiceError::Unauthorized);
         }
+
+        let rotated = RefreshToken::new(token.id, token.family.clone());
+
+        let swapped = store.swap_refresh_token(
+            &rotated.id,
+            &rotated.family,
+            &token.secret,
+            &rotated.secret,
+            Some(self.refresh_token_duration),
+        )?;
+
+        if !swapped {
+            // A concurrent request used the same token
+            warn!("Refresh token reuse detected for {}", token.id);
+            self.revoke(store, &token)?;
+            return Err(ServiceError::Unauthorized);
+        }
+
+        let (session_token, claims) = self.store_session(Claims::with_family(
+            rotated.id,
+            self.session_duration,
+            rotated.family.clone(),
+        ))?;
+
+        Ok((session_token, claims, rotated.as_token()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::queries::MemorySessionDao;
 
     #[test]
     fn test_session_key_correct() {
         let service = SessionServicePriv::new("my secret".to_string());
-        let (token, _) = service.new_session(Uuid::new_v4());
+        let (token, _) = service.new_session(Uuid::new_v4()).unwrap();
 
         let c = service.validate(token);
         assert!(c.is_ok());
@@ -87,7 +313,7 @@ mod tests {
     #[test]
     fn test_session_key_expired() {
         let service = SessionServicePriv::new("my secret".to_string());
-        let (token, mut claim) = service.new_session(Uuid::new_v4());
+        let (token, mut claim) = service.new_session(Uuid::new_v4()).unwrap();
 
         claim.exp = 0;
         let updated = encode(&Header::default(), &claim, "my secret".as_ref()).unwrap();
@@ -97,14 +323,159 @@ mod tests {
         assert_eq!(Ok(false), c);
     }
 
+    #[test]
+    fn test_session_key_decode_user_id() {
+        let service = SessionServicePriv::new("my secret".to_string());
+        let id = Uuid::new_v4();
+        let (token, _) = service.new_session(id).unwrap();
+
+        let claims = service.decode(token).unwrap();
+        assert_eq!(Ok(id), claims.user_id());
+    }
+
     #[test]
     fn test_session_key_different_secrets() {
         let service = SessionServicePriv::new("my secret".to_string());
-        let (mut token, mut _claim) = service.new_session(Uuid::new_v4());
+        let (mut token, mut _claim) = service.new_session(Uuid::new_v4()).unwrap();
         
         let service2 = SessionServicePriv::new("other secret".to_string());
 
         let c = service2.validate(token);
         assert_eq!(Ok(false), c);
     }
+
+    #[test]
+    fn test_session_logout() {
+        let store: SessionStore = Arc::new(MemorySessionDao::default());
+        let service = SessionServicePriv::new("my secret".to_string()).with_store(store);
+        let (token, _) = service.new_session(Uuid::new_v4()).unwrap();
+
+        assert_eq!(Ok(true), service.validate(token.clone()));
+
+        service.logout(token.clone()).unwrap();
+
+        assert_eq!(Ok(false), service.validate(token));
+    }
+
+    #[test]
+    fn test_session_logout_all() {
+        let store: SessionStore = Arc::new(MemorySessionDao::default());
+        let service = SessionServicePriv::new("my secret".to_string()).with_store(store);
+        let id = Uuid::new_v4();
+        let (token, _) = service.new_session(id).unwrap();
+        let (token2, _) = service.new_session(id).unwrap();
+        let (other_token, _) = service.new_session(Uuid::new_v4()).unwrap();
+
+        service.logout_all(&id).unwrap();
+
+        assert_eq!(Ok(false), service.validate(token));
+        assert_eq!(Ok(false), service.validate(token2));
+        assert_eq!(Ok(true), service.validate(other_token));
+    }
+
+    #[test]
+    fn test_session_unknown_to_store() {
+        let store: SessionStore = Arc::new(MemorySessionDao::default());
+        let stateless = SessionServicePriv::new("my secret".to_string());
+        let service = SessionServicePriv::new("my secret".to_string()).with_store(store);
+        let (token, _) = stateless.new_session(Uuid::new_v4()).unwrap();
+
+        assert_eq!(Ok(false), service.validate(token));
+    }
+
+    #[test]
+    fn test_session_key_duration() {
+        let service = SessionServicePriv::new("my secret".to_string()).with_durations(60, 120);
+        let (_, claim) = service.new_session(Uuid::new_v4()).unwrap();
+
+        assert_eq!(60, claim.exp - claim.iat);
+    }
+
+    #[test]
+    fn test_refresh_token_rotation() {
+        let store: SessionStore = Arc::new(MemorySessionDao::default());
+        let service = SessionServicePriv::new("my secret".to_string()).with_store(store);
+        let id = Uuid::new_v4();
+        let (_, claims) = service.new_session(id).unwrap();
+
+        let refresh_token = service.new_refresh_token(&claims).unwrap().unwrap();
+
+        let (token, claims, rotated) = service.refresh(refresh_token.clone()).unwrap();
+
+        assert_eq!(Ok(id), claims.user_id());
+        assert_eq!(Ok(true), service.validate(token));
+        assert_ne!(refresh_token, rotated);
+
+        let (token, _, _) = service.refresh(rotated).unwrap();
+        assert_eq!(Ok(true), service.validate(token));
+    }
+
+    #[test]
+    fn test_refresh_token_reuse() {
+        let store: SessionStore = Arc::new(MemorySessionDao::default());
+        let service = SessionServicePriv::new("my secret".to_string()).with_store(store);
+        let id = Uuid::new_v4();
+        let (_, claims) = service.new_session(id).unwrap();
+
+        let refresh_token = service.new_refresh_token(&claims).unwrap().unwrap();
+
+        let (token, _, rotated) = service.refresh(refresh_token.clone()).unwrap();
+
+        // The old token is used a second time
+        assert_eq!(
+            Err(ServiceError::Unauthorized),
+            service.refresh(refresh_token).map(|_| ())
+        );
+
+        // Everything of the family is revoked
+        assert_eq!(Ok(false), service.validate(token));
+        assert_eq!(
+            Err(ServiceError::Unauthorized),
+            service.refresh(rotated).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_refresh_token_without_store() {
+        let service = SessionServicePriv::new("my secret".to_string());
+
+        let (_, claims) = service.new_session(Uuid::new_v4()).unwrap();
+
+        assert_eq!(Ok(None), service.new_refresh_token(&claims));
+    }
+
+    #[test]
+    fn test_logout_revokes_refresh_token() {
+        let store: SessionStore = Arc::new(MemorySessionDao::default());
+        let service = SessionServicePriv::new("my secret".to_string()).with_store(store);
+        let id = Uuid::new_v4();
+
+        let (_, claims) = service.new_session(id).unwrap();
+        let refresh_token = service.new_refresh_token(&claims).unwrap().unwrap();
+
+        // Another login of the same user
+        let (other_token, other_claims) = service.new_session(id).unwrap();
+        let other_refresh_token = service.new_refresh_token(&other_claims).unwrap().unwrap();
+
+        // The refreshed session belongs to the same login
+        let (token, _, _) = service.refresh(refresh_token).unwrap();
+        service.logout(token.clone()).unwrap();
+
+        assert_eq!(Ok(false), service.validate(token));
+        assert_eq!(Ok(true), service.validate(other_token));
+        assert!(service.refresh(other_refresh_token).is_ok());
+    }
+
+    #[test]
+    fn test_refresh_token_swap() {
+        let store = MemorySessionDao::default();
+        let id = Uuid::new_v4();
+
+        store.set_refresh_token(&id, "family", "first", None).unwrap();
+
+        assert_eq!(Ok(true), store.swap_refresh_token(&id, "family", "first", "second", None));
+        // The second request with the same token loses
+        assert_eq!(Ok(false), store.swap_refresh_token(&id, "family", "first", "third", None));
+        assert_eq!(Ok(Some("second".to_string())), store.get_refresh_token(&id, "family"));
+    }
 }
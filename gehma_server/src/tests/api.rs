@@ -3,11 +3,18 @@ use super::*;
 use actix_web::{test, web, App};
 use serde_json::json;
 
+use crate::config::{ClientConfig, UPGRADE_RECOMMENDED_HEADER};
+use crate::middleware::request_id::{RequestIdentifier, REQUEST_ID_HEADER};
 use crate::queries::*;
+use core::errors::ErrorResponse;
+use crate::services::push_notifications::{
+    MockNotificationServiceTrait, NotificationKind, PushReport,
+};
 use core::models::dao::*;
 use uuid::Uuid;
 
 use lazy_static::lazy_static;
+use mockall::predicate::{always, eq};
 
 lazy_static! {
     static ref USER: UserDao = UserDao {
@@ -21,9 +28,11 @@ lazy_static! {
         profile_picture: 1,
         hash_tele_num: hash("+4366412345678".to_string()),
         xp: 0,
-        client_version: super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
+        client_version: super::MIN_CLIENT_VERSION.to_string(),
         access_token: "".to_string(),
         firebase_token: None,
+        led_expires_at: None,
+        led_audience: None,
     };
 }
 
@@ -34,6 +43,10 @@ macro_rules! init_server {
                 .data(set_testing_auth() as Box<dyn NumberRegistrationServiceTrait>)
                 .data(set_testing_notification_service() as NotificationService)
                 .data(set_ratelimits())
+                .data(set_verification_dao())
+                .data(set_device_dao())
+                .data(VerificationLimitConfig::default())
+                .data(ClientConfig::default())
                 .data(get_session_service())
                 .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
                 .data(Box::new($blacklist_dao) as Box<dyn PersistentBlacklistDao>)
@@ -106,8 +119,13 @@ async fn test_create_user() {
         .expect_get_profile_picture()
         .returning(|_| Ok("path".to_string()));
 
+    let stored_token = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+    let stored = stored_token.clone();
+
     user_dao_mock.expect_create().returning(
-        |tele_num, country_code, client_version, _access_token| {
+        move |tele_num, country_code, client_version, access_token| {
+            *stored.lock().unwrap() = access_token.to_string();
+
             Ok(UserDao {
                 id: Uuid::new_v4(),
                 tele_num: tele_num.to_string(),
@@ -122,6 +140,8 @@ async fn test_create_user() {
                 client_version: client_version.to_string(),
                 access_token: "".to_string(),
                 firebase_token: None,
+                led_expires_at: None,
+                led_audience: None,
             })
         },
     );
@@ -135,7 +155,7 @@ async fn test_create_user() {
         .set_json(&json! ({
             "tele_num": tele_num,
             "country_code": "AT",
-            "client_version": super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
+            "client_version": super::MIN_CLIENT_VERSION.to_string(),
         }))
         .to_request();
 
@@ -152,7 +172,7 @@ async fn test_create_user() {
         .set_json(&json!({
             "tele_num": tele_num,
             "country_code": "AT",
-            "client_version": super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
+            "client_version": super::MIN_CLIENT_VERSION.to_string(),
             "code": "123"
         }))
         .to_request();
@@ -169,6 +189,437 @@ async fn test_create_user() {
 
     let user: UserDto = test::read_response_json(&mut app, req).await;
     assert_eq!(user.tele_num, tele_num.to_string());
+
+    // Only the hash of the returned token is stored
+    let access_token = user.access_token.unwrap();
+    let stored_token = stored_token.lock().unwrap();
+
+    assert_ne!(access_token, *stored_token);
+    assert!(core::access_token::verify(&stored_token, &access_token));
+}
+
+#[actix_rt::test]
+async fn test_check_code_rotates_access_token() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contact_exists_dao_mock = MockPersistentContactsDao::new();
+
+    user_dao_mock
+        .expect_get_by_tele_num()
+        .returning(|_| Ok(USER.clone()));
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    // `USER` has no access token yet
+    user_dao_mock
+        .expect_update_access_token()
+        .times(1)
+        .returning(|id, hashed_access_token| {
+            assert_eq!(USER.id, *id);
+            assert!(hashed_access_token.starts_with("1$"));
+            Ok(())
+        });
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contact_exists_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/check")
+        .set_json(&json!({
+            "tele_num": "+4366412345678",
+            "country_code": "AT",
+            "client_version": super::MIN_CLIENT_VERSION.to_string(),
+            "code": "123"
+        }))
+        .to_request();
+
+    let user: UserDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(
+        Some(core::access_token::ACCESS_TOKEN_LENGTH),
+        user.access_token.map(|w| w.len())
+    );
+}
+
+#[actix_rt::test]
+async fn test_check_code_keeps_access_token() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+
+    user_dao_mock.expect_get_by_tele_num().returning(|_| {
+        let mut user = USER.clone();
+        user.access_token = core::access_token::hash("secret");
+        Ok(user)
+    });
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    // Other devices of the user keep their token
+    user_dao_mock.expect_update_access_token().times(0);
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_testing_auth() as Box<dyn NumberRegistrationServiceTrait>)
+            .data(set_verification_dao())
+            .data(VerificationLimitConfig::default())
+            .data(ClientConfig::default())
+            .data(get_session_service())
+            .data(Box::new(user_dao_mock) as Box<dyn PersistentUserDao>)
+            .service(web::scope("/api/v1").configure(crate::routes::v1)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/check")
+        .set_json(&json!({
+            "tele_num": "+4366412345678",
+            "country_code": "AT",
+            "client_version": super::MIN_CLIENT_VERSION.to_string(),
+            "code": "123"
+        }))
+        .to_request();
+
+    let user: UserDto = test::read_response_json(&mut app, req).await;
+
+    assert!(user.access_token.is_none());
+}
+
+#[actix_rt::test]
+async fn test_check_code_new_access_token() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+
+    user_dao_mock.expect_get_by_tele_num().returning(|_| {
+        let mut user = USER.clone();
+        user.access_token = core::access_token::hash("secret");
+        Ok(user)
+    });
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    user_dao_mock
+        .expect_update_access_token()
+        .with(eq(USER.id), always())
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let store: SessionStore = std::sync::Arc::new(MemorySessionDao::default());
+    let session_service = set_session_service_with_store(store.clone());
+    let (session_token, _) = session_service.new_session(USER.id).unwrap();
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_testing_auth() as Box<dyn NumberRegistrationServiceTrait>)
+            .data(set_verification_dao())
+            .data(VerificationLimitConfig::default())
+            .data(ClientConfig::default())
+            .data(set_session_service_with_store(store.clone()))
+            .data(Box::new(user_dao_mock) as Box<dyn PersistentUserDao>)
+            .service(web::scope("/api/v1").configure(crate::routes::v1)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/check")
+        .set_json(&json!({
+            "tele_num": "+4366412345678",
+            "country_code": "AT",
+            "client_version": super::MIN_CLIENT_VERSION.to_string(),
+            "code": "123",
+            "new_access_token": true
+        }))
+        .to_request();
+
+    let user: UserDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(
+        Some(core::access_token::ACCESS_TOKEN_LENGTH),
+        user.access_token.map(|w| w.len())
+    );
+    // The sessions of the previous token are revoked
+    assert!(!session_service.validate(session_token).unwrap());
+}
+
+#[actix_rt::test]
+async fn test_signin_verifies_access_token() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+
+    user_dao_mock.expect_get_by_tele_num().returning(|_| {
+        let mut user = USER.clone();
+        user.access_token = core::access_token::hash("secret");
+        Ok(user)
+    });
+
+    user_dao_mock
+        .expect_create_usage_statistics_for_user()
+        .times(1)
+        .returning(|user| {
+            Ok(UsageStatisticEntryDao {
+                id: 1,
+                pseudonym: user.pseudonym(),
+                created_at: chrono::Local::now().naive_local(),
+            })
+        });
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    // Only the successful signin marks the devices as seen
+    let mut device_dao_mock = MockPersistentDeviceDao::new();
+    device_dao_mock
+        .expect_touch()
+        .with(eq(USER.id), always())
+        .times(1)
+        .returning(|_, _| Ok(1));
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_testing_notification_service() as NotificationService)
+            .data(get_session_service())
+            .data(ClientConfig::default())
+            .data(Box::new(user_dao_mock) as Box<dyn PersistentUserDao>)
+            .data(Box::new(device_dao_mock) as Box<dyn PersistentDeviceDao>)
+            .route("/api/signin", web::post().to(crate::routes::user::signin)),
+    )
+    .await;
+
+    let body = PostUserDto {
+        tele_num: "+4366412345678".to_string(),
+        country_code: "AT".to_string(),
+        client_version: super::MIN_CLIENT_VERSION.to_string(),
+    };
+
+    let req = test::TestRequest::post()
+        .uri("/api/signin")
+        .header("Authorization", "wrong")
+        .set_json(&body)
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(401, resp.status().as_u16());
+
+    let req = test::TestRequest::post()
+        .uri("/api/signin")
+        .header("Authorization", "secret")
+        .set_json(&body)
+        .to_request();
+
+    let user: UserDto = test::read_response_json(&mut app, req).await;
+
+    assert!(user.session_token.is_some());
+    assert!(user.access_token.is_none());
+}
+
+#[actix_rt::test]
+async fn test_v1_rotate_access_token() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_update_access_token()
+        .times(1)
+        .returning(|_id, _hashed_access_token| Ok(()));
+
+    let store: SessionStore = std::sync::Arc::new(MemorySessionDao::default());
+    let session_service = set_session_service_with_store(store.clone());
+    let (session_token, _) = session_service.new_session(USER.id).unwrap();
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_session_service_with_store(store.clone()))
+            .data(Box::new(user_dao_mock) as Box<dyn PersistentUserDao>)
+            .data(Box::new(blacklist_dao_mock) as Box<dyn PersistentBlacklistDao>)
+            .service(web::scope("/api/v1").configure(crate::routes::v1)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/user/{}/access_token", USER.id))
+        .to_request();
+
+    let token: AccessTokenDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(core::access_token::ACCESS_TOKEN_LENGTH, token.access_token.len());
+    // The sessions of the previous token are revoked
+    assert!(!session_service.validate(session_token).unwrap());
+}
+
+#[actix_rt::test]
+async fn test_request_code_rate_limit() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contact_exists_dao_mock = MockPersistentContactsDao::new();
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contact_exists_dao_mock).await;
+
+    let limit = crate::ratelimits::RateLimitConfig::default().code_requests;
+
+    for i in 0..=limit {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/request_code")
+            .set_json(&json! ({
+                "tele_num": "+4366412345678",
+                "country_code": "AT",
+                "client_version": super::MIN_CLIENT_VERSION.to_string(),
+            }))
+            .to_request();
+
+        let resp = test::call_service(&mut app, req).await;
+
+        if i < limit {
+            assert!(resp.status().is_success());
+        } else {
+            assert_eq!(resp.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+            assert!(resp
+                .headers()
+                .contains_key(actix_web::http::header::RETRY_AFTER));
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn test_check_code_locked() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let mut verification_dao_mock = MockPersistentVerificationDao::new();
+
+    verification_dao_mock.expect_get_limit().returning(|key| {
+        let now = chrono::Local::now().naive_local();
+        let mut limit = VerificationLimitDao::my_from(key, now);
+        limit.locked_until = Some(now + chrono::Duration::minutes(5));
+        Ok(Some(limit))
+    });
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_testing_auth() as Box<dyn NumberRegistrationServiceTrait>)
+            .data(Box::new(user_dao_mock) as Box<dyn PersistentUserDao>)
+            .data(Box::new(verification_dao_mock) as Box<dyn PersistentVerificationDao>)
+            .data(VerificationLimitConfig::default())
+            .data(ClientConfig::default())
+            .data(get_session_service())
+            .route(
+                "/api/auth/check",
+                web::post().to(crate::routes::number_registration::check),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/check")
+        .set_json(&json!({
+            "tele_num": "+4366412345678",
+            "country_code": "AT",
+            "client_version": super::MIN_CLIENT_VERSION.to_string(),
+            "code": "123"
+        }))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(resp.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp
+        .headers()
+        .contains_key(actix_web::http::header::RETRY_AFTER));
+}
+
+#[actix_rt::test]
+async fn test_check_code_upgrade_required() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/check")
+        .set_json(&json!({
+            "tele_num": "+4366412345678",
+            "country_code": "AT",
+            "client_version": "0.1.0",
+            "code": "123"
+        }))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(resp.status(), actix_web::http::StatusCode::UPGRADE_REQUIRED);
+
+    let body: ErrorResponse = test::read_body_json(resp).await;
+    assert_eq!(body.code, "upgrade_required");
+    assert_eq!(
+        body.details,
+        Some(json!({ "min_version": super::MIN_CLIENT_VERSION }))
+    );
+}
+
+#[actix_rt::test]
+async fn test_update_user_upgrade_recommended() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_create_analytics_for_user()
+        .returning(|user| {
+            Ok(AnalyticDao {
+                id: 1,
+                pseudonym: user.pseudonym(),
+                led: user.led,
+                description: user.description.clone(),
+                created_at: chrono::Utc::now().naive_local(),
+            })
+        });
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    user_dao_mock
+        .expect_update_user()
+        .returning(|_id, user, current_time, _xp_limit| {
+            Ok((USER.clone().apply_update(user, current_time.naive_local()), vec![]))
+        });
+
+    let client_config = ClientConfig {
+        min_version: super::MIN_CLIENT_VERSION.to_string(),
+        recommended_version: "99.0.0".to_string(),
+    };
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_testing_notification_service() as NotificationService)
+            .data(set_ratelimits())
+            .data(client_config)
+            .data(get_session_service())
+            .data(Box::new(user_dao_mock) as Box<dyn PersistentUserDao>)
+            .route("/api/user/{uid}", web::put().to(crate::routes::user::update)),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/user/{}", USER.id))
+        .set_json(&json!({
+            "description": "",
+            "led": true,
+            "client_version": super::MIN_CLIENT_VERSION,
+        }))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    assert_eq!(
+        resp.headers().get(UPGRADE_RECOMMENDED_HEADER).unwrap(),
+        "99.0.0"
+    );
 }
 
 #[actix_rt::test]
@@ -259,7 +710,7 @@ async fn test_update_user() {
         .returning(|user| {
             Ok(AnalyticDao {
                 id: 1,
-                tele_num: user.tele_num.clone(),
+                pseudonym: user.pseudonym(),
                 led: user.led,
                 description: user.description.clone(),
                 created_at: chrono::Utc::now().naive_local(),
@@ -273,7 +724,7 @@ async fn test_update_user() {
     user_dao_mock
         .expect_update_user()
         .times(1)
-        .returning(|_id, user, current_time| {
+        .returning(|_id, user, current_time, _xp_limit| {
             let u = USER.clone();
             Ok((u.apply_update(user, current_time.naive_local()), vec![]))
         });
@@ -285,7 +736,9 @@ async fn test_update_user() {
         .set_json(&core::models::dto::UpdateUserDto {
             description: "test".to_string(),
             led: true,
-            client_version: super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
+            client_version: super::MIN_CLIENT_VERSION.to_string(),
+            expires_in: None,
+            audience: None,
         })
         .to_request();
 
@@ -306,7 +759,7 @@ async fn test_update_user() {
 }
 
 #[actix_rt::test]
-async fn test_update_token_user() {
+async fn test_update_user_with_expiry() {
     let mut user_dao_mock = MockPersistentUserDao::new();
     let blacklist_dao_mock = MockPersistentBlacklistDao::new();
     let contacts_dao_mock = MockPersistentContactsDao::new();
@@ -314,21 +767,182 @@ async fn test_update_token_user() {
     setup_login_account!(user_dao_mock);
 
     user_dao_mock
-        .expect_update_token()
+        .expect_create_analytics_for_user()
         .times(1)
-        .returning(|_id, _token| Ok(()));
+        .returning(|user| {
+            Ok(AnalyticDao {
+                id: 1,
+                pseudonym: user.pseudonym(),
+                led: user.led,
+                description: user.description.clone(),
+                created_at: chrono::Utc::now().naive_local(),
+            })
+        });
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    user_dao_mock
+        .expect_update_user()
+        .times(1)
+        .returning(|_id, user, current_time, _xp_limit| {
+            Ok((
+                USER.clone().apply_update(user, current_time.naive_local()),
+                vec![],
+            ))
+        });
 
     let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
 
+    let before = chrono::Local::now().naive_local();
+
     let req = test::TestRequest::put()
-        .uri(&format!("/api/user/{}/token", Uuid::new_v4(),))
-        .set_json(&crate::routes::user::UpdateTokenPayload {
-            token: "test".to_string(),
-        })
+        .uri(&format!("/api/user/{}", USER.id))
+        .set_json(&json!({
+            "description": "",
+            "led": true,
+            "client_version": super::MIN_CLIENT_VERSION,
+            "expires_in": 120,
+        }))
         .to_request();
 
-    let resp = test::call_service(&mut app, req).await;
-    assert!(resp.status().is_success());
+    let user: UserDto = test::read_response_json(&mut app, req).await;
+
+    let expires_at = user.led_expires_at.expect("No expiry");
+
+    assert!(user.led);
+    assert!(expires_at >= before + chrono::Duration::minutes(120));
+    assert!(expires_at <= chrono::Local::now().naive_local() + chrono::Duration::minutes(120));
+}
+
+#[actix_rt::test]
+async fn test_update_user_with_audience() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_create_analytics_for_user()
+        .returning(|user| {
+            Ok(AnalyticDao {
+                id: 1,
+                pseudonym: user.pseudonym(),
+                led: user.led,
+                description: user.description.clone(),
+                created_at: chrono::Utc::now().naive_local(),
+            })
+        });
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    user_dao_mock
+        .expect_update_user()
+        .times(1)
+        .returning(|_id, user, current_time, _xp_limit| {
+            assert_eq!(Some(3), user.audience);
+            Ok((
+                USER.clone().apply_update(user, current_time.naive_local()),
+                vec![],
+            ))
+        });
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/user/{}", USER.id))
+        .set_json(&json!({
+            "description": "Football",
+            "led": true,
+            "client_version": super::MIN_CLIENT_VERSION,
+            "audience": 3,
+        }))
+        .to_request();
+
+    let user: UserDto = test::read_response_json(&mut app, req).await;
+
+    assert!(user.led);
+    assert_eq!(Some(3), user.led_audience);
+}
+
+#[actix_rt::test]
+async fn test_update_user_invalid_expiry() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/user/{}", USER.id))
+        .set_json(&json!({
+            "description": "",
+            "led": true,
+            "client_version": super::MIN_CLIENT_VERSION,
+            "expires_in": 0,
+        }))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(400, resp.status().as_u16());
+}
+
+#[test]
+fn test_led_expiry_is_kept() {
+    let now = chrono::Local::now().naive_local();
+    let expires_at = now + chrono::Duration::minutes(30);
+
+    let user = UserDao {
+        led: true,
+        led_expires_at: Some(expires_at),
+        ..USER.clone()
+    };
+
+    let update = |led, expires_in| core::models::dto::UpdateUserDto {
+        description: "".to_string(),
+        led,
+        client_version: super::MIN_CLIENT_VERSION.to_string(),
+        expires_in,
+        audience: None,
+    };
+
+    assert_eq!(Some(expires_at), user.led_expiry(&update(true, None), now));
+    assert_eq!(None, user.led_expiry(&update(false, None), now));
+    assert_eq!(
+        Some(now + chrono::Duration::minutes(5)),
+        user.led_expiry(&update(true, Some(5)), now)
+    );
+    assert_eq!(None, USER.led_expiry(&update(true, None), now));
+}
+
+#[actix_rt::test]
+async fn test_update_token_user() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_update_token()
+        .times(1)
+        .returning(|_id, _token| Ok(()));
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/user/{}/token", Uuid::new_v4(),))
+        .set_json(&crate::routes::user::UpdateTokenPayload {
+            token: "test".to_string(),
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
 }
 
 #[actix_rt::test]
@@ -448,251 +1062,2223 @@ async fn test_remove_blacklist() {
     assert!(resp.status().is_success());
 }
 
+macro_rules! init_server_versioned {
+    ($user_dao:ident, $blacklist_dao:ident) => {
+        test::init_service(
+            App::new()
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($blacklist_dao) as Box<dyn PersistentBlacklistDao>)
+                .service(
+                    web::scope("/api")
+                        .service(web::scope("/v1").configure(crate::routes::v1))
+                        .configure(crate::routes::legacy),
+                ),
+        )
+    };
+}
+
+fn expect_blacklist_delete(blacklist_dao_mock: &mut MockPersistentBlacklistDao) {
+    blacklist_dao_mock
+        .expect_delete()
+        .times(1)
+        .returning(|blocker, blocked| {
+            assert_eq!(*blocker, hash("+4366412345678".to_string()));
+            assert_eq!(*blocked, hash("+4365012345678".to_string()));
+
+            Ok(())
+        });
+}
+
 #[actix_rt::test]
-async fn test_contacts() {
+async fn test_v1_remove_blacklist() {
     let mut user_dao_mock = MockPersistentUserDao::new();
     let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
-    let mut contacts_dao_mock = MockPersistentContactsDao::new();
 
-    user_dao_mock
-        .expect_get_by_id()
-        .times(2)
-        .returning(|_id| Ok(USER.clone()));
+    setup_login_account!(user_dao_mock);
+    expect_blacklist_delete(&mut blacklist_dao_mock);
 
-    contacts_dao_mock
-        .expect_create()
-        .times(1)
-        .returning(|_uid, _user, _phone_number| Ok(()));
+    let mut app = init_server_versioned!(user_dao_mock, blacklist_dao_mock).await;
 
-    blacklist_dao_mock
-        .expect_get()
-        .times(3)
-        .returning(|_| Ok(vec![]));
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/api/v1/user/{}/blacklist/{}",
+            USER.id,
+            hash("+4365012345678")
+        ))
+        .to_request();
 
-    contacts_dao_mock
-        .expect_get_contacts()
-        .times(1)
-        .returning(|_user, _| {
-            let mut u = USER.clone();
-            u.led = true;
-            u.description = "test".to_string();
-            Ok(vec![ContactDto {
-                blocked: false,
-                name: "Test".to_string(),
-                user: u.into("path".to_string()),
-            }])
-        });
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+}
 
-    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+#[actix_rt::test]
+async fn test_legacy_alias_remove_blacklist() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
 
-    let req = test::TestRequest::post()
-        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
-        .set_json(&PayloadNumbersDto {
-            numbers: vec![PayloadUserDto {
-                name: "Test".to_string(),
-                hash_tele_num: hash("+4366412345678"),
-            }],
+    setup_login_account!(user_dao_mock);
+    expect_blacklist_delete(&mut blacklist_dao_mock);
+
+    let mut app = init_server_versioned!(user_dao_mock, blacklist_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/user/{}/blacklist", USER.id))
+        .set_json(&crate::routes::blacklist::PostData {
+            hash_blocked: hash("+4365012345678").to_string(),
+            country_code: "AT".to_string(),
         })
         .to_request();
 
     let resp = test::call_service(&mut app, req).await;
     assert!(resp.status().is_success());
+}
 
-    let req = test::TestRequest::get()
-        .uri(&format!("/api/contacts/{}", Uuid::new_v4(),))
-        .to_request();
-
-    //let resp = test::call_service(&mut app, req).await;
-    //assert!(resp.status().is_success());
-
-    let users: Vec<ContactDto> = test::read_response_json(&mut app, req).await;
-
-    assert!(users.len() > 0);
-    assert_eq!(true, users.get(0).unwrap().user.led);
-    assert_eq!("test", &users.get(0).unwrap().user.description);
+macro_rules! init_server_devices {
+    ($user_dao:ident, $device_dao:ident) => {
+        test::init_service(
+            App::new()
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($device_dao) as Box<dyn PersistentDeviceDao>)
+                .service(web::scope("/api/v1").configure(crate::routes::v1)),
+        )
+    };
 }
 
 #[actix_rt::test]
-async fn test_contacts_with_blacklist_1() {
+async fn test_v1_register_device() {
     let mut user_dao_mock = MockPersistentUserDao::new();
-    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
-    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut device_dao_mock = MockPersistentDeviceDao::new();
 
-    user_dao_mock
-        .expect_get_by_id()
-        .times(2)
-        .returning(|_id| Ok(USER.clone()));
+    setup_login_account!(user_dao_mock);
 
-    contacts_dao_mock
-        .expect_create()
+    device_dao_mock
+        .expect_register()
         .times(1)
-        .returning(|_uid, _user, _phone_number| Ok(()));
-
-    blacklist_dao_mock.expect_get().times(2).returning(|_| {
-        Ok(vec![BlacklistDao {
-            id: Uuid::new_v4(),
-            created_at: chrono::Utc::now().naive_local(),
-            hash_blocker: hash("+4366412345678".to_string()),
-            hash_blocked: hash("+4365012345678".to_string()),
-        }])
-    });
+        .returning(|device| {
+            assert_eq!(USER.id, device.user_id);
+            assert_eq!("ios", device.platform);
 
-    contacts_dao_mock
-        .expect_get_contacts()
-        .times(1)
-        .returning(|_user, _| {
-            Ok(vec![ContactDto {
-                blocked: false,
-                name: "Test".to_string(),
-                user: UserDto {
-                    id: Uuid::new_v4(),
-                    tele_num: "+4365012345678".to_string(),
-                    led: true,
-                    country_code: "AT".to_string(),
-                    description: "doing something".to_string(),
-                    changed_at: chrono::Utc::now().naive_local(),
-                    profile_picture: "".to_string(),
-                    hash_tele_num: hash("+4365012345678".to_string()),
-                    xp: 0,
-                    client_version: super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
-                    access_token: None,
-                    firebase_token: None,
-                    session_token: None,
-                },
-            }])
+            Ok(DeviceDao {
+                id: 1,
+                user_id: device.user_id,
+                token: device.token,
+                platform: device.platform,
+                app_version: device.app_version,
+                last_seen: device.last_seen,
+                created_at: device.created_at,
+            })
         });
 
-    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+    let mut app = init_server_devices!(user_dao_mock, device_dao_mock).await;
 
     let req = test::TestRequest::post()
-        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
-        .set_json(&PayloadNumbersDto {
-            numbers: vec![PayloadUserDto {
-                name: "Test".to_string(),
-                hash_tele_num: hash("+4366412345678"),
-            }],
+        .uri(&format!("/api/v1/user/{}/devices", USER.id))
+        .set_json(&RegisterDeviceDto {
+            token: "tablet".to_string(),
+            platform: "iOS".to_string(),
+            app_version: "0.6.0".to_string(),
         })
         .to_request();
 
-    let resp = test::call_service(&mut app, req).await;
-    assert!(resp.status().is_success());
+    let device: DeviceDto = test::read_response_json(&mut app, req).await;
 
-    let req = test::TestRequest::get()
-        .uri(&format!("/api/contacts/{}", Uuid::new_v4(),))
-        .to_request();
+    assert_eq!("tablet", device.token);
+    assert_eq!("ios", device.platform);
+}
 
-    let users: Vec<ContactDto> = test::read_response_json(&mut app, req).await;
+#[actix_rt::test]
+async fn test_v1_register_device_unknown_platform() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let device_dao_mock = MockPersistentDeviceDao::new();
 
-    assert_eq!(1, users.len());
-    assert_eq!(false, users.get(0).unwrap().user.led);
-    assert_eq!("", &users.get(0).unwrap().user.description);
+    setup_login_account!(user_dao_mock);
 
-    //let resp = test::call_service(&mut app, req).await;
-    //assert!(resp.status().is_success());
+    let mut app = init_server_devices!(user_dao_mock, device_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/user/{}/devices", USER.id))
+        .set_json(&RegisterDeviceDto {
+            token: "tablet".to_string(),
+            platform: "symbian".to_string(),
+            app_version: "0.6.0".to_string(),
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(400, resp.status().as_u16());
 }
 
 #[actix_rt::test]
-async fn test_contacts_with_blacklist_2() {
+async fn test_v1_unregister_device() {
     let mut user_dao_mock = MockPersistentUserDao::new();
-    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
-    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut device_dao_mock = MockPersistentDeviceDao::new();
 
-    user_dao_mock
-        .expect_get_by_id()
-        .times(2)
-        .returning(|_id| Ok(USER.clone()));
+    setup_login_account!(user_dao_mock);
 
-    contacts_dao_mock
+    device_dao_mock
+        .expect_unregister()
+        .withf(|user_id, token: &str| *user_id == USER.id && token == "tablet")
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let mut app = init_server_devices!(user_dao_mock, device_dao_mock).await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/user/{}/devices/tablet", USER.id))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+}
+
+macro_rules! init_server_account {
+    ($user_dao:ident, $account_dao:ident) => {
+        test::init_service(
+            App::new()
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($account_dao) as Box<dyn PersistentAccountDao>)
+                .service(web::scope("/api/v1").configure(crate::routes::v1)),
+        )
+    };
+}
+
+#[actix_rt::test]
+async fn test_v1_delete_account() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut account_dao_mock = MockPersistentAccountDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    account_dao_mock
+        .expect_delete()
+        .withf(|user| user.id == USER.id)
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let mut app = init_server_account!(user_dao_mock, account_dao_mock).await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/user/{}", USER.id))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_v1_delete_unknown_account() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let account_dao_mock = MockPersistentAccountDao::new();
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(1)
+        .returning(|_| Err(ServiceError::ResourceDoesNotExist));
+
+    let mut app = init_server_account!(user_dao_mock, account_dao_mock).await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/user/{}", USER.id))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(!resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_v1_export_account() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut account_dao_mock = MockPersistentAccountDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    account_dao_mock
+        .expect_export()
+        .withf(|user, _| user.id == USER.id)
+        .times(1)
+        .returning(|user, current_time| {
+            Ok(AccountExportDto {
+                user: user.clone().into("path".to_string()),
+                contacts: vec![ExportedContactDto {
+                    name: "Bob".to_string(),
+                    hash_tele_num: hash("+4366412345679".to_string()),
+                    created_at: current_time,
+                }],
+                contact_groups: Vec::new(),
+                blacklist: Vec::new(),
+                devices: Vec::new(),
+                notification_settings: None,
+                broadcasts: Vec::new(),
+                invitations: Vec::new(),
+                invitation_memberships: Vec::new(),
+                votes: Vec::new(),
+                analytics: Vec::new(),
+                usage_statistics: Vec::new(),
+                usage_statistics_monthly: Vec::new(),
+                exported_at: current_time,
+            })
+        });
+
+    let mut app = init_server_account!(user_dao_mock, account_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/user/{}/export", USER.id))
+        .to_request();
+
+    let export: AccountExportDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(USER.id, export.user.id);
+    assert_eq!(None, export.user.access_token);
+    assert_eq!("Bob", export.contacts[0].name);
+}
+
+macro_rules! init_server_tele_num {
+    ($user_dao:ident, $account_dao:ident, $auth:expr, $notification_service:expr) => {
+        test::init_service(
+            App::new()
+                .data($auth as Box<dyn NumberRegistrationServiceTrait>)
+                .data($notification_service)
+                .data(set_ratelimits())
+                .data(set_verification_dao())
+                .data(VerificationLimitConfig::default())
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($account_dao) as Box<dyn PersistentAccountDao>)
+                .service(web::scope("/api/v1").configure(crate::routes::v1)),
+        )
+    };
+}
+
+fn change_tele_num_request(code: &str) -> test::TestRequest {
+    test::TestRequest::put()
+        .uri(&format!("/api/v1/user/{}/tele_num", USER.id))
+        .set_json(&ChangeTeleNumDto {
+            tele_num: "+4366412345679".to_string(),
+            code: code.to_string(),
+            country_code: "AT".to_string(),
+        })
+}
+
+#[actix_rt::test]
+async fn test_v1_change_tele_num() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut account_dao_mock = MockPersistentAccountDao::new();
+    let mut notification_service_mock = MockNotificationServiceTrait::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_by_tele_num()
+        .times(1)
+        .returning(|_| Err(ServiceError::ResourceDoesNotExist));
+
+    account_dao_mock
+        .expect_change_tele_num()
+        .withf(|user, tele_num, country_code| {
+            user.id == USER.id && tele_num.to_string() == "+4366412345679" && country_code == "AT"
+        })
+        .times(1)
+        .returning(|user, tele_num, _| {
+            let mut user = user.clone();
+            user.tele_num = tele_num.to_string();
+            user.hash_tele_num = hash(tele_num.to_string());
+            Ok(user)
+        });
+
+    user_dao_mock
+        .expect_get_contacts_for_push_notification()
+        .times(1)
+        .returning(|user| {
+            assert_eq!(hash("+4366412345679"), user.hash_tele_num);
+
+            Ok(vec![ContactPushNotificationDao {
+                from_id: Uuid::new_v4(),
+                name: "Alice".to_string(),
+                tokens: vec!["token".to_string()],
+                target_hash_tele_num: user.hash_tele_num.clone(),
+                country_code: "GB".to_string(),
+            }])
+        });
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    notification_service_mock
+        .expect_push()
+        .times(1)
+        .returning(|notifications| {
+            assert_eq!(1, notifications.len());
+            assert_eq!(NotificationKind::TeleNumChanged, notifications[0].kind);
+            assert_eq!("Alice has a new number", notifications[0].title());
+            Ok(PushReport::default())
+        });
+
+    let mut app = init_server_tele_num!(
+        user_dao_mock,
+        account_dao_mock,
+        set_testing_auth(),
+        Box::new(notification_service_mock) as NotificationService
+    )
+    .await;
+
+    let req = change_tele_num_request("123").to_request();
+    let user: UserDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(USER.id, user.id);
+    assert_eq!("+4366412345679", user.tele_num);
+}
+
+#[actix_rt::test]
+async fn test_v1_change_tele_num_already_registered() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let account_dao_mock = MockPersistentAccountDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_by_tele_num()
+        .times(1)
+        .returning(|_| Ok(USER.clone()));
+
+    let mut app = init_server_tele_num!(
+        user_dao_mock,
+        account_dao_mock,
+        set_testing_auth(),
+        set_testing_notification_service()
+    )
+    .await;
+
+    let req = change_tele_num_request("123").to_request();
+    let resp = test::call_service(&mut app, req).await;
+    let body: ErrorResponse = test::read_body_json(resp).await;
+
+    assert_eq!("already_exists", body.code);
+}
+
+#[actix_rt::test]
+async fn test_v1_change_tele_num_wrong_code() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let account_dao_mock = MockPersistentAccountDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_by_tele_num()
+        .times(1)
+        .returning(|_| Err(ServiceError::ResourceDoesNotExist));
+
+    let auth = Box::new(TestingAuthentificatorAlwaysFalse {
+        config: TestingAuthConfiguration {
+            id: "test".to_string(),
+            auth_token: "test".to_string(),
+        },
+    });
+
+    let mut app = init_server_tele_num!(
+        user_dao_mock,
+        account_dao_mock,
+        auth,
+        set_testing_notification_service()
+    )
+    .await;
+
+    let req = change_tele_num_request("000").to_request();
+    let resp = test::call_service(&mut app, req).await;
+    let body: ErrorResponse = test::read_body_json(resp).await;
+
+    assert_eq!("invalid_code", body.code);
+}
+
+macro_rules! init_server_settings {
+    ($user_dao:ident, $settings_dao:ident) => {
+        test::init_service(
+            App::new()
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($settings_dao) as Box<dyn PersistentNotificationSettingsDao>)
+                .service(web::scope("/api/v1").configure(crate::routes::v1)),
+        )
+    };
+}
+
+#[actix_rt::test]
+async fn test_v1_get_default_notification_settings() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut settings_dao_mock = MockPersistentNotificationSettingsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    settings_dao_mock.expect_get().times(1).returning(|_| Ok(None));
+    settings_dao_mock
+        .expect_get_muted()
+        .times(1)
+        .returning(|user_id| {
+            Ok(vec![MutedContactDao {
+                user_id: *user_id,
+                hash_muted: hash("+4365012345678"),
+                created_at: chrono::Utc::now().naive_local(),
+            }])
+        });
+
+    let mut app = init_server_settings!(user_dao_mock, settings_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/user/{}/settings/notifications", USER.id))
+        .to_request();
+
+    let settings: NotificationSettingsDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!("UTC", settings.timezone);
+    assert_eq!(None, settings.quiet_hours_start);
+    assert_eq!(vec![hash("+4365012345678")], settings.muted_contacts);
+}
+
+#[actix_rt::test]
+async fn test_v1_update_notification_settings() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut settings_dao_mock = MockPersistentNotificationSettingsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    settings_dao_mock
+        .expect_is_valid_timezone()
+        .withf(|timezone: &str| timezone == "Europe/Vienna")
+        .times(1)
+        .returning(|_| Ok(true));
+    settings_dao_mock
+        .expect_update()
+        .times(1)
+        .returning(|settings| {
+            assert_eq!(USER.id, settings.user_id);
+            Ok(settings)
+        });
+    settings_dao_mock
+        .expect_get_muted()
+        .times(1)
+        .returning(|_| Ok(vec![]));
+
+    let mut app = init_server_settings!(user_dao_mock, settings_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/user/{}/settings/notifications", USER.id))
+        .set_json(&json!({
+            "muted_until": null,
+            "quiet_hours_start": "22:00:00",
+            "quiet_hours_end": "07:00:00",
+            "timezone": "Europe/Vienna",
+        }))
+        .to_request();
+
+    let settings: NotificationSettingsDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!("Europe/Vienna", settings.timezone);
+    assert_eq!(
+        Some(chrono::NaiveTime::from_hms(22, 0, 0)),
+        settings.quiet_hours_start
+    );
+}
+
+#[actix_rt::test]
+async fn test_v1_update_notification_settings_half_quiet_hours() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let settings_dao_mock = MockPersistentNotificationSettingsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    let mut app = init_server_settings!(user_dao_mock, settings_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/user/{}/settings/notifications", USER.id))
+        .set_json(&json!({
+            "muted_until": null,
+            "quiet_hours_start": "22:00:00",
+            "quiet_hours_end": null,
+            "timezone": "UTC",
+        }))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(400, resp.status().as_u16());
+}
+
+#[actix_rt::test]
+async fn test_v1_mute_contact() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut settings_dao_mock = MockPersistentNotificationSettingsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_by_hash_tele_num_unsafe()
+        .times(1)
+        .returning(|_| Ok(UserDao::my_from("+4365012345678", "AT", "0.6.0", "")));
+
+    settings_dao_mock
+        .expect_mute()
+        .times(1)
+        .returning(|muted| {
+            assert_eq!(USER.id, muted.user_id);
+            assert_eq!(hash("+4365012345678"), muted.hash_muted);
+            Ok(())
+        });
+
+    let mut app = init_server_settings!(user_dao_mock, settings_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/user/{}/settings/notifications/muted", USER.id))
+        .set_json(&MuteContactDto {
+            hash_tele_num: hash("+4365012345678"),
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+}
+
+macro_rules! init_server_groups {
+    ($user_dao:ident, $contact_group_dao:ident) => {
+        test::init_service(
+            App::new()
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($contact_group_dao) as Box<dyn PersistentContactGroupDao>)
+                .service(web::scope("/api/v1").configure(crate::routes::v1)),
+        )
+    };
+}
+
+fn contact_group(id: i32, name: &str) -> ContactGroupDao {
+    ContactGroupDao {
+        id,
+        owner_id: USER.id,
+        name: name.to_string(),
+        created_at: chrono::Utc::now().naive_local(),
+        updated_at: chrono::Utc::now().naive_local(),
+    }
+}
+
+#[actix_rt::test]
+async fn test_v1_get_contact_groups() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contact_group_dao_mock = MockPersistentContactGroupDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    contact_group_dao_mock
+        .expect_get_all()
+        .times(1)
+        .returning(|_| {
+            Ok(vec![(
+                contact_group(1, "Football"),
+                vec![ContactGroupMemberDao {
+                    group_id: 1,
+                    hash_tele_num: hash("+4365012345678"),
+                }],
+            )])
+        });
+
+    let mut app = init_server_groups!(user_dao_mock, contact_group_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/contacts/{}/groups", USER.id))
+        .to_request();
+
+    let groups: Vec<ContactGroupDto> = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(1, groups.len());
+    assert_eq!("Football", groups[0].name);
+    assert_eq!(vec![hash("+4365012345678")], groups[0].members);
+}
+
+#[actix_rt::test]
+async fn test_v1_create_contact_group() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contact_group_dao_mock = MockPersistentContactGroupDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    contact_group_dao_mock
+        .expect_get_all()
+        .times(1)
+        .returning(|_| Ok(vec![(contact_group(1, "Family"), vec![])]));
+    contact_group_dao_mock
+        .expect_create()
+        .times(1)
+        .returning(|group, members| {
+            assert_eq!(USER.id, group.owner_id);
+            assert_eq!(1, members.len());
+
+            let members = members
+                .into_iter()
+                .map(|w| ContactGroupMemberDao {
+                    group_id: 2,
+                    hash_tele_num: w,
+                })
+                .collect();
+
+            Ok((contact_group(2, &group.name), members))
+        });
+
+    let mut app = init_server_groups!(user_dao_mock, contact_group_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/contacts/{}/groups", USER.id))
+        .set_json(&RequestContactGroupDto {
+            name: " Football ".to_string(),
+            members: vec![hash("+4365012345678"), hash("+4365012345678")],
+        })
+        .to_request();
+
+    let group: ContactGroupDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(2, group.id);
+    assert_eq!("Football", group.name);
+    assert_eq!(vec![hash("+4365012345678")], group.members);
+}
+
+#[actix_rt::test]
+async fn test_v1_create_contact_group_duplicate_name() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contact_group_dao_mock = MockPersistentContactGroupDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    contact_group_dao_mock
+        .expect_get_all()
+        .times(1)
+        .returning(|_| Ok(vec![(contact_group(1, "Football"), vec![])]));
+
+    let mut app = init_server_groups!(user_dao_mock, contact_group_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/contacts/{}/groups", USER.id))
+        .set_json(&RequestContactGroupDto {
+            name: "Football".to_string(),
+            members: vec![],
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(400, resp.status().as_u16());
+}
+
+#[actix_rt::test]
+async fn test_v1_rename_contact_group() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contact_group_dao_mock = MockPersistentContactGroupDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    // Keeping the name of the group itself is no conflict
+    contact_group_dao_mock
+        .expect_get_all()
+        .times(1)
+        .returning(|_| Ok(vec![(contact_group(1, "Football"), vec![])]));
+    contact_group_dao_mock
+        .expect_update()
+        .times(1)
+        .returning(|_owner_id, group_id, name, members, _updated_at| {
+            assert_eq!(1, group_id);
+            assert!(members.is_empty());
+            Ok((contact_group(group_id, &name), vec![]))
+        });
+
+    let mut app = init_server_groups!(user_dao_mock, contact_group_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/contacts/{}/groups/1", USER.id))
+        .set_json(&RequestContactGroupDto {
+            name: "Football".to_string(),
+            members: vec![],
+        })
+        .to_request();
+
+    let group: ContactGroupDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!("Football", group.name);
+}
+
+#[actix_rt::test]
+async fn test_v1_delete_contact_group() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contact_group_dao_mock = MockPersistentContactGroupDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    contact_group_dao_mock
+        .expect_delete()
+        .times(1)
+        .returning(|_owner_id, group_id| match group_id {
+            1 => Ok(()),
+            _ => Err(ServiceError::ResourceDoesNotExist),
+        });
+
+    let mut app = init_server_groups!(user_dao_mock, contact_group_dao_mock).await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/contacts/{}/groups/1", USER.id))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+}
+
+macro_rules! init_server_contacts {
+    ($user_dao:ident, $blacklist_dao:ident, $contacts_dao:ident) => {
+        test::init_service(
+            App::new()
+                .data(set_ratelimits())
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($blacklist_dao) as Box<dyn PersistentBlacklistDao>)
+                .data(Box::new($contacts_dao) as Box<dyn PersistentContactsDao>)
+                .service(web::scope("/api/v1").configure(crate::routes::v1)),
+        )
+    };
+}
+
+#[actix_rt::test]
+async fn test_v1_sync_contacts() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    blacklist_dao_mock.expect_get().times(1).returning(|_| {
+        Ok(vec![BlacklistDao {
+            id: Uuid::new_v4(),
+            created_at: chrono::Utc::now().naive_local(),
+            hash_blocker: hash("+4366412345678"),
+            hash_blocked: hash("+4369912345678"),
+        }])
+    });
+
+    contacts_dao_mock
+        .expect_sync()
+        .times(1)
+        .returning(|_user, cursor, upserts, removed, _current_time| {
+            assert_eq!(3, cursor);
+            // The blocked number is skipped and the renamed contact is only sent once
+            assert_eq!(2, upserts.len());
+            assert_eq!("Renamed", upserts[0].name);
+            assert_eq!(vec![hash("+4367612345678")], removed.to_vec());
+            Ok(cursor + 1)
+        });
+
+    let mut app = init_server_contacts!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/v1/contacts/{}/AT", USER.id))
+        .set_json(&PayloadContactsDeltaDto {
+            cursor: 3,
+            added: vec![
+                PayloadUserDto {
+                    name: "Test".to_string(),
+                    hash_tele_num: hash("+4365012345678"),
+                },
+                PayloadUserDto {
+                    name: "Blocked".to_string(),
+                    hash_tele_num: hash("+4369912345678"),
+                },
+                PayloadUserDto {
+                    name: "New".to_string(),
+                    hash_tele_num: hash("+4368112345678"),
+                },
+            ],
+            changed: vec![PayloadUserDto {
+                name: "Renamed".to_string(),
+                hash_tele_num: hash("+4365012345678"),
+            }],
+            removed: vec![hash("+4367612345678")],
+        })
+        .to_request();
+
+    let sync: ContactSyncDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(ContactSyncDto { cursor: 4 }, sync);
+}
+
+#[actix_rt::test]
+async fn test_v1_sync_contacts_outdated_cursor() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    blacklist_dao_mock.expect_get().returning(|_| Ok(vec![]));
+
+    contacts_dao_mock
+        .expect_sync()
+        .times(1)
+        .returning(|_user, _cursor, _upserts, _removed, _current_time| {
+            Err(ServiceError::SyncConflict(7))
+        });
+
+    let mut app = init_server_contacts!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/v1/contacts/{}/AT", USER.id))
+        .set_json(&json!({ "cursor": 2, "removed": [hash("+4365012345678")] }))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(409, resp.status().as_u16());
+
+    let body: ErrorResponse = test::read_body_json(resp).await;
+
+    assert_eq!("sync_conflict", body.code);
+    assert_eq!(Some(json!({ "cursor": 7 })), body.details);
+}
+
+#[actix_rt::test]
+async fn test_v1_upload_contacts_returns_cursor() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    blacklist_dao_mock.expect_get().returning(|_| Ok(vec![]));
+
+    contacts_dao_mock
+        .expect_replace()
+        .times(1)
+        .returning(|_user, contacts, _current_time| {
+            assert_eq!(1, contacts.len());
+            Ok(1)
+        });
+
+    let mut app = init_server_contacts!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/contacts/{}/AT", USER.id))
+        .set_json(&PayloadNumbersDto {
+            numbers: vec![PayloadUserDto {
+                name: "Test".to_string(),
+                hash_tele_num: hash("+4365012345678"),
+            }],
+        })
+        .to_request();
+
+    let sync: ContactSyncDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(1, sync.cursor);
+}
+
+#[actix_rt::test]
+async fn test_upload_contacts_appends() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(1)
+        .returning(|_id| Ok(USER.clone()));
+
+    blacklist_dao_mock.expect_get().returning(|_| Ok(vec![]));
+
+    // The legacy upload doesn't replace the contacts
+    contacts_dao_mock
+        .expect_create()
+        .times(1)
+        .returning(|_user, _contacts, _current_time| Ok(()));
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT"))
+        .set_json(&PayloadNumbersDto {
+            numbers: vec![PayloadUserDto {
+                name: "Test".to_string(),
+                hash_tele_num: hash("+4365012345678"),
+            }],
+        })
+        .to_request();
+
+    let resp: Option<ContactSyncDto> = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(None, resp);
+}
+
+#[actix_rt::test]
+async fn test_v1_discover_contacts() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    contacts_dao_mock
+        .expect_discover()
+        .times(1)
+        .returning(|_user, hashes| {
+            // Duplicates are only looked up once
+            assert_eq!(2, hashes.len());
+            Ok(vec![hash("+4365012345678")])
+        });
+
+    let mut app = init_server_contacts!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let requested = hash("+4365012345678").0;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/v1/contacts/{}/discover", USER.id))
+        .set_json(&DiscoverContactsDto {
+            hashes: vec![
+                requested.clone(),
+                hash("+4369912345678").0,
+                requested.clone(),
+            ],
+        })
+        .to_request();
+
+    let discovered: Vec<DiscoveredContactDto> = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(
+        vec![DiscoveredContactDto {
+            hash: requested,
+            hash_tele_num: hash("+4365012345678"),
+        }],
+        discovered
+    );
+}
+
+#[actix_rt::test]
+async fn test_v1_rejects_legacy_verbs() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+
+    let mut app = init_server_versioned!(user_dao_mock, blacklist_dao_mock).await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/v1/user/{}/blacklist", USER.id))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_client_error());
+}
+
+#[actix_rt::test]
+async fn test_v1_openapi() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+
+    let mut app = init_server_versioned!(user_dao_mock, blacklist_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/openapi.json")
+        .to_request();
+
+    let document: serde_json::Value = test::read_response_json(&mut app, req).await;
+
+    assert_eq!("3.0.3", document["openapi"]);
+    assert!(document["paths"]["/user/{uid}/blacklist/{hash_blocked}"]["delete"].is_object());
+    assert!(document["components"]["schemas"]["UserDto"].is_object());
+}
+
+#[actix_rt::test]
+async fn test_v1_get_contacts_since() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    blacklist_dao_mock.expect_get().returning(|_| Ok(vec![]));
+
+    contacts_dao_mock
+        .expect_get_contacts_version()
+        .times(1)
+        .returning(|_user, _since| Ok((1, None)));
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, since| {
+            assert_eq!(
+                Some(chrono::NaiveDate::from_ymd(2020, 7, 30).and_hms(12, 0, 0)),
+                since
+            );
+            Ok(vec![])
+        });
+
+    contacts_dao_mock
+        .expect_get_removed_contacts()
+        .times(1)
+        .returning(|_user, _since| Ok(vec![hash("+4365012345678")]));
+
+    let mut app = init_server_contacts!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/v1/contacts/{}?since=2020-07-30T12:00:00",
+            USER.id
+        ))
+        .to_request();
+
+    let delta: ContactsDeltaDto = test::read_response_json(&mut app, req).await;
+
+    assert!(delta.contacts.is_empty());
+    assert_eq!(vec![hash("+4365012345678")], delta.removed);
+}
+
+#[actix_rt::test]
+async fn test_v1_get_contacts_not_modified() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(2)
+        .returning(|_id| Ok(USER.clone()));
+
+    blacklist_dao_mock.expect_get().returning(|_| Ok(vec![]));
+
+    contacts_dao_mock
+        .expect_get_contacts_version()
+        .times(2)
+        .returning(|_user, _since| {
+            Ok((2, Some(chrono::NaiveDate::from_ymd(2020, 7, 30).and_hms(12, 0, 0))))
+        });
+
+    // The second request is answered without loading the contacts
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, since| {
+            assert_eq!(None, since);
+            Ok(vec![])
+        });
+
+    let mut app = init_server_contacts!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/contacts/{}", USER.id))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(200, resp.status().as_u16());
+    assert!(resp
+        .headers()
+        .contains_key(crate::routes::contacts::SINCE_HEADER));
+
+    let etag = resp
+        .headers()
+        .get("ETag")
+        .expect("No ETag")
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/contacts/{}", USER.id))
+        .header("If-None-Match", etag)
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(304, resp.status().as_u16());
+}
+
+#[actix_rt::test]
+async fn test_contacts() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(2)
+        .returning(|_id| Ok(USER.clone()));
+
+    contacts_dao_mock
+        .expect_create()
+        .times(1)
+        .returning(|_user, _contacts, _current_time| Ok(()));
+
+    blacklist_dao_mock
+        .expect_get()
+        .times(3)
+        .returning(|_| Ok(vec![]));
+
+    contacts_dao_mock
+        .expect_get_contacts_version()
+        .times(1)
+        .returning(|_user, _since| Ok((1, None)));
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, _| {
+            let mut u = USER.clone();
+            u.led = true;
+            u.description = "test".to_string();
+            Ok(vec![ContactDto {
+                blocked: false,
+                name: "Test".to_string(),
+                user: u.into("path".to_string()),
+            }])
+        });
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
+        .set_json(&PayloadNumbersDto {
+            numbers: vec![PayloadUserDto {
+                name: "Test".to_string(),
+                hash_tele_num: hash("+4366412345678"),
+            }],
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/contacts/{}", Uuid::new_v4(),))
+        .to_request();
+
+    //let resp = test::call_service(&mut app, req).await;
+    //assert!(resp.status().is_success());
+
+    let users: Vec<ContactDto> = test::read_response_json(&mut app, req).await;
+
+    assert!(users.len() > 0);
+    assert_eq!(true, users.get(0).unwrap().user.led);
+    assert_eq!("test", &users.get(0).unwrap().user.description);
+}
+
+#[actix_rt::test]
+async fn test_contacts_with_blacklist_1() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(2)
+        .returning(|_id| Ok(USER.clone()));
+
+    contacts_dao_mock
+        .expect_create()
+        .times(1)
+        .returning(|_user, _contacts, _current_time| Ok(()));
+
+    blacklist_dao_mock.expect_get().times(2).returning(|_| {
+        Ok(vec![BlacklistDao {
+            id: Uuid::new_v4(),
+            created_at: chrono::Utc::now().naive_local(),
+            hash_blocker: hash("+4366412345678".to_string()),
+            hash_blocked: hash("+4365012345678".to_string()),
+        }])
+    });
+
+    contacts_dao_mock
+        .expect_get_contacts_version()
+        .times(1)
+        .returning(|_user, _since| Ok((1, None)));
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, _| {
+            Ok(vec![ContactDto {
+                blocked: false,
+                name: "Test".to_string(),
+                user: UserDto {
+                    id: Uuid::new_v4(),
+                    tele_num: "+4365012345678".to_string(),
+                    led: true,
+                    country_code: "AT".to_string(),
+                    description: "doing something".to_string(),
+                    changed_at: chrono::Utc::now().naive_local(),
+                    profile_picture: "".to_string(),
+                    hash_tele_num: hash("+4365012345678".to_string()),
+                    xp: 0,
+                    client_version: super::MIN_CLIENT_VERSION.to_string(),
+                    access_token: None,
+                    firebase_token: None,
+                    session_token: None,
+                    refresh_token: None,
+                    led_expires_at: None,
+                    led_audience: None,
+                },
+            }])
+        });
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
+        .set_json(&PayloadNumbersDto {
+            numbers: vec![PayloadUserDto {
+                name: "Test".to_string(),
+                hash_tele_num: hash("+4366412345678"),
+            }],
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/contacts/{}", Uuid::new_v4(),))
+        .to_request();
+
+    let users: Vec<ContactDto> = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(1, users.len());
+    assert_eq!(false, users.get(0).unwrap().user.led);
+    assert_eq!("", &users.get(0).unwrap().user.description);
+
+    //let resp = test::call_service(&mut app, req).await;
+    //assert!(resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_contacts_with_blacklist_2() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(2)
+        .returning(|_id| Ok(USER.clone()));
+
+    contacts_dao_mock
         .expect_create()
         .times(1)
-        .returning(|_uid, _user, _phone_number| Ok(()));
+        .returning(|_user, _contacts, _current_time| Ok(()));
+
+    blacklist_dao_mock.expect_get().times(3).returning(|_| {
+        Ok(vec![BlacklistDao {
+            id: Uuid::new_v4(),
+            created_at: chrono::Utc::now().naive_local(),
+            hash_blocked: hash("+4366412345678".to_string()), //reversed
+            hash_blocker: hash("+4365012345678".to_string()),
+        }])
+    });
+
+    contacts_dao_mock
+        .expect_get_contacts_version()
+        .times(1)
+        .returning(|_user, _since| Ok((1, None)));
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, _| {
+            Ok(vec![ContactDto {
+                blocked: false,
+                name: "Test".to_string(),
+                user: UserDto {
+                    id: Uuid::new_v4(),
+                    tele_num: "+4365012345678".to_string(),
+                    led: true,
+                    country_code: "AT".to_string(),
+                    description: "doing something".to_string(),
+                    changed_at: chrono::Utc::now().naive_local(),
+                    profile_picture: "".to_string(),
+                    hash_tele_num: hash("+4365012345678".to_string()),
+                    xp: 0,
+                    client_version: super::MIN_CLIENT_VERSION.to_string(),
+                    access_token: None,
+                    firebase_token: None,
+                    session_token: None,
+                    refresh_token: None,
+                    led_expires_at: None,
+                    led_audience: None,
+                },
+            }])
+        });
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
+        .set_json(&PayloadNumbersDto {
+            numbers: vec![PayloadUserDto {
+                name: "Test".to_string(),
+                hash_tele_num: hash("+4366412345678"),
+            }],
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/contacts/{}", Uuid::new_v4(),))
+        .to_request();
+
+    let users: Vec<ContactDto> = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(1, users.len());
+    assert_eq!(false, users.get(0).unwrap().user.led);
+    assert_eq!("", &users.get(0).unwrap().user.description);
+
+    //let resp = test::call_service(&mut app, req).await;
+    //assert!(resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_contacts_max() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+
+    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+
+    let len = 100000;
+    let mut numbers = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        numbers.push(PayloadUserDto {
+            name: "Test".to_string(),
+            hash_tele_num: hash("+4366412345678"),
+        });
+    }
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
+        .set_json(&PayloadNumbersDto { numbers })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_client_error());
+}
+
+macro_rules! init_server_invitation {
+    ($user_dao:ident, $contacts_dao:ident, $invitation_dao:ident, $notification_service:expr) => {
+        test::init_service(
+            App::new()
+                .data($notification_service)
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($contacts_dao) as Box<dyn PersistentContactsDao>)
+                .data(Box::new($invitation_dao) as Box<dyn PersistentInvitation>)
+                .data(set_contact_group_dao())
+                .route(
+                    "/api/invitations/{uid}",
+                    web::get().to(crate::routes::invitation::get_all),
+                )
+                .route(
+                    "/api/invitations/{uid}",
+                    web::post().to(crate::routes::invitation::add),
+                )
+                .route(
+                    "/api/invitations/{uid}/{inv_id}",
+                    web::get().to(crate::routes::invitation::get),
+                )
+                .route(
+                    "/api/invitations/{uid}/{inv_id}",
+                    web::put().to(crate::routes::invitation::update),
+                )
+                .route(
+                    "/api/invitations/{uid}/{inv_id}/members",
+                    web::post().to(crate::routes::invitation::add_members_to),
+                ),
+        )
+    };
+}
+
+fn invitation(id: i32, originator: Uuid) -> (InvitationDao, InvitationMemberDao) {
+    (
+        InvitationDao {
+            id,
+            originator_user_id: originator,
+            edit_text: "Football".to_string(),
+            edit_time: chrono::Utc::now().naive_local(),
+            original_text: "Football".to_string(),
+            original_time: chrono::Utc::now().naive_local(),
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
+        },
+        InvitationMemberDao {
+            inv_id: id,
+            user_id: originator,
+            is_seen: true,
+            state: INVITATION_STATE_ACCEPTED,
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
+        },
+    )
+}
+
+#[actix_rt::test]
+async fn test_create_invitation() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut invitation_dao_mock = MockPersistentInvitation::new();
+    let mut notification_service_mock = MockNotificationServiceTrait::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_contacts_for_push_notification()
+        .times(1)
+        .returning(|user| {
+            Ok(vec![
+                ContactPushNotificationDao {
+                    from_id: user.id,
+                    name: "Invited".to_string(),
+                    tokens: vec!["token".to_string()],
+                    target_hash_tele_num: hash("+4365012345678"),
+                    country_code: "AT".to_string(),
+                },
+                ContactPushNotificationDao {
+                    from_id: user.id,
+                    name: "Not invited".to_string(),
+                    tokens: vec!["token2".to_string()],
+                    target_hash_tele_num: hash("+4366912345678"),
+                    country_code: "AT".to_string(),
+                },
+            ])
+        });
+
+    invitation_dao_mock
+        .expect_create_invitation()
+        .times(1)
+        .returning(|user, contacts, _data| {
+            assert_eq!(1, contacts.len());
+            assert_eq!(hash("+4365012345678"), contacts[0].target_hash_tele_num);
+            Ok(invitation(1, user.id))
+        });
+
+    invitation_dao_mock
+        .expect_get_members()
+        .times(1)
+        .returning(|_| Ok(vec![invitation(1, USER.id).1]));
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, _| Ok(vec![]));
+
+    notification_service_mock
+        .expect_push()
+        .times(1)
+        .returning(|notifications| {
+            assert_eq!(1, notifications.len());
+            assert_eq!("token", notifications[0].token);
+            assert_eq!(NotificationKind::InvitationReceived, notifications[0].kind);
+            assert_eq!("Invited hat dich eingeladen", notifications[0].title());
+            assert_eq!("Football", notifications[0].body());
+            assert_eq!("gehma://invitations/1", notifications[0].deep_link());
+            Ok(PushReport::default())
+        });
+
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        Box::new(notification_service_mock) as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/invitations/{}", USER.id))
+        .set_json(&RequestInvitationCreateDto {
+            text: "Football".to_string(),
+            time: chrono::Utc::now().naive_local(),
+            contacts: vec![hash("+4365012345678")],
+            groups: vec![],
+        })
+        .to_request();
+
+    let inv: InvitationDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(1, inv.id);
+    assert_eq!("Football".to_string(), inv.original_text);
+    assert_eq!(USER.hash_tele_num, inv.originator.hash_tele_num);
+    assert_eq!(0, inv.members.len());
+}
+
+#[actix_rt::test]
+async fn test_create_invitation_with_group() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut invitation_dao_mock = MockPersistentInvitation::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_contacts_for_push_notification()
+        .times(1)
+        .returning(|user| {
+            Ok(vec![ContactPushNotificationDao {
+                from_id: user.id,
+                name: "Invited".to_string(),
+                tokens: vec![],
+                target_hash_tele_num: hash("+4365012345678"),
+                country_code: "AT".to_string(),
+            }])
+        });
+
+    invitation_dao_mock
+        .expect_create_invitation()
+        .times(1)
+        .returning(|user, contacts, _data| {
+            assert_eq!(1, contacts.len());
+            assert_eq!(hash("+4365012345678"), contacts[0].target_hash_tele_num);
+            Ok(invitation(1, user.id))
+        });
+
+    invitation_dao_mock
+        .expect_get_members()
+        .returning(|_| Ok(vec![invitation(1, USER.id).1]));
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .returning(|_user, _| Ok(vec![]));
+
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        set_testing_notification_service() as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/invitations/{}", USER.id))
+        .set_json(&json!({
+            "text": "Football",
+            "time": chrono::Utc::now().naive_local(),
+            "contacts": [],
+            "groups": [1],
+        }))
+        .to_request();
+
+    let inv: InvitationDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(1, inv.id);
+}
+
+#[actix_rt::test]
+async fn test_create_invitation_with_unknown_group() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+    let invitation_dao_mock = MockPersistentInvitation::new();
+
+    setup_login_account!(user_dao_mock);
+
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        set_testing_notification_service() as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/invitations/{}", USER.id))
+        .set_json(&RequestInvitationCreateDto {
+            text: "Football".to_string(),
+            time: chrono::Utc::now().naive_local(),
+            contacts: vec![],
+            groups: vec![2],
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(404, resp.status());
+}
+
+#[actix_rt::test]
+async fn test_create_invitation_without_contacts() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+    let invitation_dao_mock = MockPersistentInvitation::new();
+
+    setup_login_account!(user_dao_mock);
+
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        set_testing_notification_service() as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/invitations/{}", USER.id))
+        .set_json(&RequestInvitationCreateDto {
+            text: "Football".to_string(),
+            time: chrono::Utc::now().naive_local(),
+            contacts: vec![],
+            groups: vec![],
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(400, resp.status());
+}
+
+#[actix_rt::test]
+async fn test_add_members_notifies_new_members() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut invitation_dao_mock = MockPersistentInvitation::new();
+    let mut notification_service_mock = MockNotificationServiceTrait::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_contacts_for_push_notification()
+        .times(1)
+        .returning(|user| {
+            Ok(vec![
+                ContactPushNotificationDao {
+                    from_id: user.id,
+                    name: "Member".to_string(),
+                    tokens: vec!["token".to_string()],
+                    target_hash_tele_num: hash("+4365012345678"),
+                    country_code: "AT".to_string(),
+                },
+                ContactPushNotificationDao {
+                    from_id: user.id,
+                    name: "Invited".to_string(),
+                    tokens: vec!["token2".to_string()],
+                    target_hash_tele_num: hash("+4366912345678"),
+                    country_code: "AT".to_string(),
+                },
+            ])
+        });
+
+    // The first contact is already a member
+    invitation_dao_mock
+        .expect_add_members_to_invitation()
+        .times(1)
+        .returning(|user, inv_id, contacts| {
+            assert_eq!(2, contacts.len());
+            let (inv, member) = invitation(inv_id, user.id);
+            Ok((inv, member, vec![hash("+4366912345678")]))
+        });
+
+    invitation_dao_mock
+        .expect_get_members()
+        .times(1)
+        .returning(|_| Ok(vec![invitation(1, USER.id).1]));
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, _| Ok(vec![]));
+
+    notification_service_mock
+        .expect_push()
+        .times(1)
+        .returning(|notifications| {
+            assert_eq!(1, notifications.len());
+            assert_eq!("token2", notifications[0].token);
+            Ok(PushReport::default())
+        });
+
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        Box::new(notification_service_mock) as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/invitations/{}/1/members", USER.id))
+        .set_json(&RequestInvitationAddMembersDto {
+            contacts: vec![hash("+4365012345678"), hash("+4366912345678")],
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_update_invitation_state() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut invitation_dao_mock = MockPersistentInvitation::new();
+    let mut notification_service_mock = MockNotificationServiceTrait::new();
+
+    let originator = UserDao {
+        id: Uuid::new_v4(),
+        hash_tele_num: hash("+4365012345678"),
+        ..USER.clone()
+    };
+    let originator_id = originator.id;
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(2)
+        .returning(move |id| {
+            if id == &originator.id {
+                Ok(originator.clone())
+            } else {
+                Ok(USER.clone())
+            }
+        });
+
+    user_dao_mock
+        .expect_get_contacts_for_push_notification()
+        .times(1)
+        .returning(|user| {
+            Ok(vec![ContactPushNotificationDao {
+                from_id: user.id,
+                name: "Me".to_string(),
+                tokens: vec!["token".to_string()],
+                target_hash_tele_num: hash("+4365012345678"),
+                country_code: "GB".to_string(),
+            }])
+        });
 
-    blacklist_dao_mock.expect_get().times(3).returning(|_| {
-        Ok(vec![BlacklistDao {
-            id: Uuid::new_v4(),
-            created_at: chrono::Utc::now().naive_local(),
-            hash_blocked: hash("+4366412345678".to_string()), //reversed
-            hash_blocker: hash("+4365012345678".to_string()),
-        }])
-    });
+    invitation_dao_mock
+        .expect_update_invitation()
+        .times(1)
+        .returning(|_user, inv_id, data| {
+            assert_eq!(5, inv_id);
+            assert!(data.accept);
+            Ok(())
+        });
+
+    invitation_dao_mock
+        .expect_get()
+        .times(1)
+        .returning(move |_user, inv_id| Ok(invitation(inv_id, originator_id)));
+
+    notification_service_mock
+        .expect_push()
+        .times(1)
+        .returning(|notifications| {
+            assert_eq!(1, notifications.len());
+            assert_eq!(NotificationKind::InvitationAccepted, notifications[0].kind);
+            assert_eq!("Me accepted your invitation", notifications[0].title());
+            assert_eq!("invitation_accepted:5", notifications[0].collapse_key());
+            Ok(PushReport::default())
+        });
+
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        Box::new(notification_service_mock) as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/invitations/{}/{}", USER.id, 5))
+        .set_json(&UpdateInvitationStateDto { accept: true })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_get_invitation_not_member() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut invitation_dao_mock = MockPersistentInvitation::new();
+
+    setup_login_account!(user_dao_mock);
 
     contacts_dao_mock
         .expect_get_contacts()
         .times(1)
-        .returning(|_user, _| {
-            Ok(vec![ContactDto {
-                blocked: false,
-                name: "Test".to_string(),
-                user: UserDto {
-                    id: Uuid::new_v4(),
-                    tele_num: "+4365012345678".to_string(),
-                    led: true,
-                    country_code: "AT".to_string(),
-                    description: "doing something".to_string(),
-                    changed_at: chrono::Utc::now().naive_local(),
-                    profile_picture: "".to_string(),
-                    hash_tele_num: hash("+4365012345678".to_string()),
-                    xp: 0,
-                    client_version: super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
-                    access_token: None,
-                    firebase_token: None,
-                    session_token: None,
-                },
-            }])
+        .returning(|_user, _| Ok(vec![]));
+
+    invitation_dao_mock
+        .expect_get()
+        .times(1)
+        .returning(|_user, _inv_id| Err(ServiceError::ResourceDoesNotExist));
+
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        set_testing_notification_service() as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/invitations/{}/{}", USER.id, 5))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(404, resp.status());
+}
+
+#[actix_rt::test]
+async fn test_get_invitation_hides_strangers() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let mut contacts_dao_mock = MockPersistentContactsDao::new();
+    let mut invitation_dao_mock = MockPersistentInvitation::new();
+
+    setup_login_account!(user_dao_mock);
+
+    contacts_dao_mock
+        .expect_get_contacts()
+        .times(1)
+        .returning(|_user, _| Ok(vec![]));
+
+    // The originator is not a contact of `USER`
+    let originator = Uuid::new_v4();
+
+    invitation_dao_mock
+        .expect_get()
+        .times(1)
+        .returning(move |_user, inv_id| {
+            let (inv, _) = invitation(inv_id, originator);
+            Ok((inv, invitation(inv_id, USER.id).1))
         });
 
-    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+    invitation_dao_mock
+        .expect_get_members()
+        .times(1)
+        .returning(move |inv_id| {
+            Ok(vec![
+                invitation(inv_id, originator).1,
+                invitation(inv_id, USER.id).1,
+            ])
+        });
 
-    let req = test::TestRequest::post()
-        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
-        .set_json(&PayloadNumbersDto {
-            numbers: vec![PayloadUserDto {
-                name: "Test".to_string(),
-                hash_tele_num: hash("+4366412345678"),
-            }],
-        })
+    let mut app = init_server_invitation!(
+        user_dao_mock,
+        contacts_dao_mock,
+        invitation_dao_mock,
+        set_testing_notification_service() as NotificationService
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/invitations/{}/{}", USER.id, 5))
+        .to_request();
+
+    let inv: InvitationDto = test::read_response_json(&mut app, req).await;
+
+    assert_eq!(HashedTeleNum(String::new()), inv.originator.hash_tele_num);
+    assert!(inv.originator.user.is_none());
+    assert_eq!(1, inv.members.len());
+    assert_eq!(USER.hash_tele_num, inv.members[0].hash_tele_num);
+}
+
+macro_rules! init_server_authentication {
+    ($user_dao:ident, $blacklist_dao:ident) => {
+        test::init_service(
+            App::new()
+                .data(get_session_service())
+                .data(Box::new($user_dao) as Box<dyn PersistentUserDao>)
+                .data(Box::new($blacklist_dao) as Box<dyn PersistentBlacklistDao>)
+                .wrap(middleware::auth::Authentication)
+                .route("/api/user/{uid}", web::get().to(crate::routes::user::get))
+                .route(
+                    "/api/user/{uid}/blacklist",
+                    web::get().to(crate::routes::blacklist::get_all),
+                ),
+        )
+    };
+}
+
+#[actix_rt::test]
+async fn test_auth_own_user() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+
+    setup_login_account!(user_dao_mock);
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    let (session_token, _) = get_session_service().new_session(USER.id).unwrap();
+
+    let mut app = init_server_authentication!(user_dao_mock, blacklist_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/{}", USER.id))
+        .header("AUTHORIZATION", session_token)
         .to_request();
 
     let resp = test::call_service(&mut app, req).await;
     assert!(resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_auth_cross_user_access() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+
+    let (session_token, _) = get_session_service().new_session(Uuid::new_v4()).unwrap();
+
+    let mut app = init_server_authentication!(user_dao_mock, blacklist_dao_mock).await;
 
     let req = test::TestRequest::get()
-        .uri(&format!("/api/contacts/{}", Uuid::new_v4(),))
+        .uri(&format!("/api/user/{}", USER.id))
+        .header("AUTHORIZATION", session_token.clone())
         .to_request();
 
-    let users: Vec<ContactDto> = test::read_response_json(&mut app, req).await;
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(401, resp.status());
 
-    assert_eq!(1, users.len());
-    assert_eq!(false, users.get(0).unwrap().user.led);
-    assert_eq!("", &users.get(0).unwrap().user.description);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/{}/blacklist", USER.id))
+        .header("AUTHORIZATION", session_token)
+        .to_request();
 
-    //let resp = test::call_service(&mut app, req).await;
-    //assert!(resp.status().is_success());
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(401, resp.status());
 }
 
 #[actix_rt::test]
-async fn test_contacts_max() {
+async fn test_auth_invalid_uid() {
     let user_dao_mock = MockPersistentUserDao::new();
     let blacklist_dao_mock = MockPersistentBlacklistDao::new();
-    let contacts_dao_mock = MockPersistentContactsDao::new();
 
-    let mut app = init_server!(user_dao_mock, blacklist_dao_mock, contacts_dao_mock).await;
+    let (session_token, _) = get_session_service().new_session(USER.id).unwrap();
 
-    let len = 100000;
-    let mut numbers = Vec::with_capacity(len);
+    let mut app = init_server_authentication!(user_dao_mock, blacklist_dao_mock).await;
 
-    for _ in 0..len {
-        numbers.push(PayloadUserDto {
-            name: "Test".to_string(),
-            hash_tele_num: hash("+4366412345678"),
-        });
-    }
+    let req = test::TestRequest::get()
+        .uri("/api/user/not-a-uuid/blacklist")
+        .header("AUTHORIZATION", session_token)
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(401, resp.status());
+}
+
+#[actix_rt::test]
+async fn test_auth_missing_session_token() {
+    let user_dao_mock = MockPersistentUserDao::new();
+    let blacklist_dao_mock = MockPersistentBlacklistDao::new();
+
+    let mut app = init_server_authentication!(user_dao_mock, blacklist_dao_mock).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/{}", USER.id))
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(401, resp.status());
+}
+
+#[actix_rt::test]
+async fn test_auth_logout() {
+    let mut user_dao_mock = MockPersistentUserDao::new();
+
+    user_dao_mock
+        .expect_get_by_id()
+        .times(1)
+        .returning(|_id| Ok(USER.clone()));
+
+    user_dao_mock
+        .expect_get_profile_picture()
+        .returning(|_| Ok("path".to_string()));
+
+    let store: SessionStore = std::sync::Arc::new(MemorySessionDao::default());
+
+    let (session_token, _) = set_session_service_with_store(store.clone())
+        .new_session(USER.id)
+        .unwrap();
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_session_service_with_store(store.clone()))
+            .data(Box::new(user_dao_mock) as Box<dyn PersistentUserDao>)
+            .wrap(middleware::auth::Authentication)
+            .route("/api/user/{uid}", web::get().to(crate::routes::user::get))
+            .route(
+                "/api/user/{uid}/logout",
+                web::post().to(crate::routes::user::logout),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/{}", USER.id))
+        .header("AUTHORIZATION", session_token.clone())
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
 
     let req = test::TestRequest::post()
-        .uri(&format!("/api/contacts/{}/{}", Uuid::new_v4(), "AT",))
-        .set_json(&PayloadNumbersDto { numbers })
+        .uri(&format!("/api/user/{}/logout", USER.id))
+        .header("AUTHORIZATION", session_token.clone())
         .to_request();
 
     let resp = test::call_service(&mut app, req).await;
-    assert!(resp.status().is_client_error());
+    assert!(resp.status().is_success());
+
+    // The session was revoked
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/{}", USER.id))
+        .header("AUTHORIZATION", session_token)
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(401, resp.status());
+}
+
+#[actix_rt::test]
+async fn test_v1_refresh_session() {
+    let store: SessionStore = std::sync::Arc::new(MemorySessionDao::default());
+    let session_service = set_session_service_with_store(store.clone());
+
+    let (_, claims) = session_service.new_session(USER.id).unwrap();
+    let refresh_token = session_service.new_refresh_token(&claims).unwrap().unwrap();
+
+    let mut device_dao_mock = MockPersistentDeviceDao::new();
+    device_dao_mock
+        .expect_touch()
+        .with(eq(USER.id), always())
+        .times(1)
+        .returning(|_, _| Ok(1));
+
+    let mut app = test::init_service(
+        App::new()
+            .data(set_session_service_with_store(store.clone()))
+            .data(Box::new(device_dao_mock) as Box<dyn PersistentDeviceDao>)
+            .route(
+                "/api/v1/auth/refresh",
+                web::post().to(crate::routes::user::refresh),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/refresh")
+        .set_json(&RequestRefreshSessionDto {
+            refresh_token: refresh_token.clone(),
+        })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let session: SessionDto = test::read_body_json(resp).await;
+
+    assert_ne!(refresh_token, session.refresh_token);
+    assert_eq!(Ok(true), session_service.validate(session.session_token.clone()));
+
+    // The rotated token cannot be used again
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/refresh")
+        .set_json(&RequestRefreshSessionDto { refresh_token })
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(401, resp.status());
+
+    // Everything of the user was revoked
+    assert_eq!(Ok(false), session_service.validate(session.session_token));
+}
+
+async fn failing_route(info: web::Path<String>) -> Result<HttpResponse, ServiceError> {
+    Err(match info.as_str() {
+        "not_found" => ServiceError::ResourceDoesNotExist,
+        "rate_limit" => ServiceError::RateLimit(42),
+        "invalid_phone_number" => ServiceError::InvalidUserInput(
+            core::errors::InvalidUserInput::InvalidPhoneNumber("123".to_string()),
+        ),
+        "already_exists" => ServiceError::AlreadyExists,
+        "bad_request" => ServiceError::BadRequest("Invalid UUID".to_string()),
+        _ => ServiceError::InternalServerError(
+            core::errors::InternalServerError::DatabaseError("secret".to_string()),
+        ),
+    })
+}
+
+macro_rules! init_server_errors {
+    () => {
+        test::init_service(
+            App::new().wrap(RequestIdentifier).service(
+                web::scope("/api")
+                    .route("/errors/{kind}", web::get().to(failing_route))
+                    .default_service(web::route().to(HttpResponse::NotFound)),
+            ),
+        )
+    };
+}
+
+/// The mobile client depends on the codes and statuses of the error envelope
+#[actix_rt::test]
+async fn test_error_envelope_contract() {
+    let mut app = init_server_errors!().await;
+
+    let cases = vec![
+        ("not_found", 404, None),
+        ("rate_limit", 429, Some(json!({ "retry_after": 42 }))),
+        ("invalid_phone_number", 400, Some(json!({ "value": "123" }))),
+        ("already_exists", 409, None),
+        ("bad_request", 400, None),
+        ("internal_error", 500, None),
+    ];
+
+    for (code, status, details) in cases {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/errors/{}", code))
+            .header(REQUEST_ID_HEADER, "test-request")
+            .to_request();
+
+        let resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(status, resp.status().as_u16(), "{}", code);
+        assert_eq!(
+            "test-request",
+            resp.headers().get(REQUEST_ID_HEADER).unwrap()
+        );
+
+        let body: ErrorResponse = test::read_body_json(resp).await;
+
+        assert_eq!(code, body.code);
+        assert_eq!(details, body.details, "{}", code);
+        assert_eq!(Some("test-request".to_string()), body.request_id);
+        assert!(!body.message.contains("secret"));
+    }
+}
+
+#[actix_rt::test]
+async fn test_error_envelope_retry_after() {
+    let mut app = init_server_errors!().await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/errors/rate_limit")
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+        "42",
+        resp.headers()
+            .get(actix_web::http::header::RETRY_AFTER)
+            .unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_error_envelope_unknown_route() {
+    let mut app = init_server_errors!().await;
+
+    let req = test::TestRequest::get().uri("/api/unknown").to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(404, resp.status());
+    // Generated, when the client sends no id
+    assert!(resp.headers().contains_key(REQUEST_ID_HEADER));
+
+    let body: ErrorResponse = test::read_body_json(resp).await;
+
+    assert_eq!("not_found", body.code);
+    assert!(body.request_id.is_some());
 }
@@ -4,7 +4,7 @@ use actix_web::{test, web, App};
 
 use crate::queries::*;
 use crate::services::push_notifications::{
-    MockNotificationServiceTrait, NotificationService, NotificationServiceTrait,
+    MockNotificationServiceTrait, NotificationService, NotificationServiceTrait, PushReport,
 };
 
 use crate::services::number_registration::NumberRegistrationServiceTrait;
@@ -91,11 +91,17 @@ macro_rules! private_init_server_integration_test {
                 .data(set_testing_auth() as Box<dyn NumberRegistrationServiceTrait>)
                 .data($notification_service)
                 .data(set_ratelimits())
+                .data(VerificationLimitConfig::default())
+                .data(crate::config::ClientConfig::default())
+                .data(get_dao_factory($pool).get_verification_dao())
+                .data(get_dao_factory($pool).get_device_dao())
                 .data(get_dao_factory($pool).get_user_dao())
                 .data(get_dao_factory($pool).get_blacklist_dao())
                 .data(get_dao_factory($pool).get_contacts_dao())
                 .data(get_dao_factory($pool).get_profile_pictures_dao())
                 .data(get_dao_factory($pool).get_invitation_dao())
+                .data(get_dao_factory($pool).get_contact_group_dao())
+                .data(get_dao_factory($pool).get_account_dao())
                 .data(get_session_service())
                 .wrap(middleware::auth::Authentication)
                 .route("/api/signin", web::post().to(crate::routes::user::signin))
@@ -199,7 +205,7 @@ macro_rules! signin {
             .set_json(&core::models::dto::PostUserDto {
                 tele_num: $query_user.tele_num.clone(),
                 country_code: $query_user.country_code.clone(),
-                client_version: super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
+                client_version: super::MIN_CLIENT_VERSION.to_string(),
             })
             .to_request();
 
@@ -216,7 +222,9 @@ macro_rules! gehma {
             .set_json(&core::models::dto::UpdateUserDto {
                 description: $descr.to_string(),
                 led: true,
-                client_version: super::ALLOWED_CLIENT_VERSIONS[0].to_string(),
+                client_version: super::MIN_CLIENT_VERSION.to_string(),
+                expires_in: None,
+                audience: None,
             })
             .to_request();
 
@@ -259,6 +267,10 @@ fn cleanup(pool: &Pool) {
         .execute(&pool.get().unwrap())
         .unwrap();
 
+    sql_query("DELETE FROM usage_statistics_monthly;")
+        .execute(&pool.get().unwrap())
+        .unwrap();
+
     sql_query("DELETE FROM analytics;")
         .execute(&pool.get().unwrap())
         .unwrap();
@@ -274,7 +286,7 @@ async fn create_user() -> UserDto {
 
     let tele_num = "+4366412345678";
     let country_code = "AT";
-    let client_version = super::ALLOWED_CLIENT_VERSIONS[0].to_string();
+    let client_version = super::MIN_CLIENT_VERSION.to_string();
     let code = "123";
 
     let req = test::TestRequest::post()
@@ -320,7 +332,7 @@ async fn create_user2() -> UserDto {
 
     let tele_num = "+4365012345678";
     let country_code = "AT";
-    let client_version = super::ALLOWED_CLIENT_VERSIONS[0].to_string();
+    let client_version = super::MIN_CLIENT_VERSION.to_string();
     let code = "123";
 
     let req = test::TestRequest::post()
@@ -364,7 +376,7 @@ async fn create_user3() -> UserDto {
 
     let tele_num = "+4366912345678";
     let country_code = "AT";
-    let client_version = super::ALLOWED_CLIENT_VERSIONS[0].to_string();
+    let client_version = super::MIN_CLIENT_VERSION.to_string();
     let code = "123";
 
     let req = test::TestRequest::post()
@@ -488,7 +500,7 @@ async fn test_create_user() {
 
     let tele_num = "+4366412345678";
     let country_code = "AT";
-    let client_version = super::ALLOWED_CLIENT_VERSIONS[0].to_string();
+    let client_version = super::MIN_CLIENT_VERSION.to_string();
     let code = "123";
 
     let req = test::TestRequest::post()
@@ -645,11 +657,11 @@ async fn test_push_notifications() {
 
     let mut m = MockNotificationServiceTrait::new();
 
-    m.expect_push().times(1).returning(|contacts| {
-        assert_eq!(contacts.len(), 1);
-        assert_eq!("Second".to_string(), contacts.get(0).unwrap().0);
-        assert_eq!("token2".to_string(), contacts.get(0).unwrap().1);
-        Ok(())
+    m.expect_push().times(1).returning(|notifications| {
+        assert_eq!(notifications.len(), 1);
+        assert_eq!("Second".to_string(), notifications.get(0).unwrap().name);
+        assert_eq!("token2".to_string(), notifications.get(0).unwrap().token);
+        Ok(PushReport::default())
     });
 
     let mut app = private_init_server_integration_test!(
@@ -708,9 +720,9 @@ async fn test_push_notifications_one_friendship() {
 
     let mut m = MockNotificationServiceTrait::new();
 
-    m.expect_push().times(1).returning(|contacts| {
-        assert_eq!(contacts.len(), 0);
-        Ok(())
+    m.expect_push().times(1).returning(|notifications| {
+        assert_eq!(notifications.len(), 0);
+        Ok(PushReport::default())
     });
 
     let mut app = private_init_server_integration_test!(
@@ -763,11 +775,11 @@ async fn test_push_notifications_blacklist1() {
 
     let mut m = MockNotificationServiceTrait::new();
 
-    m.expect_push().times(1).returning(|contacts| {
-        assert_eq!(contacts.len(), 1);
-        assert_eq!("Third2".to_string(), contacts.get(0).unwrap().0);
-        assert_eq!("token3".to_string(), contacts.get(0).unwrap().1);
-        Ok(())
+    m.expect_push().times(1).returning(|notifications| {
+        assert_eq!(notifications.len(), 1);
+        assert_eq!("Third2".to_string(), notifications.get(0).unwrap().name);
+        assert_eq!("token3".to_string(), notifications.get(0).unwrap().token);
+        Ok(PushReport::default())
     });
 
     let mut app = private_init_server_integration_test!(
@@ -842,11 +854,11 @@ async fn test_push_notifications_blacklist2() {
 
     let mut m = MockNotificationServiceTrait::new();
 
-    m.expect_push().times(1).returning(|contacts| {
-        assert_eq!(contacts.len(), 1);
-        assert_eq!("Third2".to_string(), contacts.get(0).unwrap().0);
-        assert_eq!("token3".to_string(), contacts.get(0).unwrap().1);
-        Ok(())
+    m.expect_push().times(1).returning(|notifications| {
+        assert_eq!(notifications.len(), 1);
+        assert_eq!("Third2".to_string(), notifications.get(0).unwrap().name);
+        assert_eq!("token3".to_string(), notifications.get(0).unwrap().token);
+        Ok(PushReport::default())
     });
 
     let mut app = private_init_server_integration_test!(
@@ -1024,13 +1036,13 @@ async fn test_see_if_blocked_perspective_creator() {
 
     cleanup(&pool);
 
-    assert_eq!(contacts.len(), 1);
-    assert_eq!(contacts.get(0).unwrap().name, "test contact".to_string());
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications.get(0).unwrap().name, "test contact".to_string());
     assert_eq!(
-        contacts.get(0).unwrap().user.hash_tele_num,
+        notifications.get(0).unwrap().user.hash_tele_num,
         hash("+4365012345678")
     );
-    assert!(contacts.get(0).unwrap().blocked);
+    assert!(notifications.get(0).unwrap().blocked);
 }
 
 /// This test checks if a user (A) blocks an user (B),
@@ -1077,13 +1089,13 @@ async fn test_see_if_blocked_perspective_blocked() {
 
     cleanup(&pool);
 
-    assert_eq!(contacts.len(), 1);
-    assert_eq!(contacts.get(0).unwrap().name, "test contact".to_string());
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications.get(0).unwrap().name, "test contact".to_string());
     assert_eq!(
-        contacts.get(0).unwrap().user.hash_tele_num,
+        notifications.get(0).unwrap().user.hash_tele_num,
         hash("+4366412345678")
     );
-    assert!(contacts.get(0).unwrap().blocked);
+    assert!(notifications.get(0).unwrap().blocked);
 }
 
 #[actix_rt::test]
@@ -1262,3 +1274,672 @@ async fn test_get_broadcasts_not_cleared() {
 
     assert_eq!(broadcasts.len(), 1);
 }
+
+#[actix_rt::test]
+async fn test_delete_account() {
+    use core::schema::blacklist::dsl::{blacklist, hash_blocked};
+    use core::schema::broadcast::dsl::{broadcast, display_user};
+    use core::schema::contacts::dsl::{contacts, from_id, target_hash_tele_num};
+    use core::schema::profile_pictures::dsl as pictures;
+    use core::schema::usage_statistics::dsl::{pseudonym, usage_statistics};
+    use diesel::prelude::*;
+
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let cmp_user2 = create_user2().await;
+
+    let mut app = init_server_integration_test!(&pool).await;
+
+    let user_signin = signin!(app, cmp_user);
+    let user_signin2 = signin!(app, cmp_user2);
+
+    let session_token = user_signin.session_token.unwrap();
+    let session_token2 = user_signin2.session_token.unwrap();
+
+    make_friend!(
+        app,
+        cmp_user,
+        "test contact",
+        "+4365012345678",
+        session_token.clone()
+    );
+    make_friend!(
+        app,
+        cmp_user2,
+        "test contact",
+        "+4366412345678",
+        session_token2.clone()
+    );
+
+    ignore_contact!(app, cmp_user, "+4365012345678", session_token.clone());
+
+    gehma!(
+        app,
+        cmp_user2,
+        "updated description",
+        session_token2.clone()
+    );
+
+    let conn: &PgConnection = &pool.get().unwrap();
+    let dao_factory = get_dao_factory(&pool);
+
+    // An uploaded profile picture
+    let picture_path = format!("static/profile_pictures/{}.png", cmp_user2.id);
+    std::fs::create_dir_all("static/profile_pictures").unwrap();
+    std::fs::write(&picture_path, b"png").unwrap();
+
+    let picture_id = diesel::insert_into(pictures::profile_pictures)
+        .values(pictures::path.eq(format!("{}.png", cmp_user2.id)))
+        .returning(pictures::id)
+        .get_result::<i32>(conn)
+        .unwrap();
+
+    dao_factory
+        .get_user_dao()
+        .update_profile_picture(
+            cmp_user2.id,
+            &core::models::dto::UpdateProfilePictureDto {
+                profile_id: picture_id,
+            },
+        )
+        .unwrap();
+
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user2.id).unwrap();
+
+    dao_factory.get_account_dao().delete(&user).unwrap();
+
+    let hash_deleted = hash("+4365012345678");
+
+    let deleted = dao_factory.get_user_dao().get_by_id(&cmp_user2.id).is_err();
+    let remaining_contacts = contacts
+        .filter(
+            from_id
+                .eq(cmp_user2.id)
+                .or(target_hash_tele_num.eq(&hash_deleted)),
+        )
+        .count()
+        .get_result::<i64>(conn)
+        .unwrap();
+    let remaining_blacklist = blacklist
+        .filter(hash_blocked.eq(&hash_deleted))
+        .count()
+        .get_result::<i64>(conn)
+        .unwrap();
+    let remaining_broadcasts = broadcast
+        .filter(display_user.eq(&hash_deleted))
+        .count()
+        .get_result::<i64>(conn)
+        .unwrap();
+    let remaining_statistics = usage_statistics
+        .filter(pseudonym.eq(user.pseudonym()))
+        .count()
+        .get_result::<i64>(conn)
+        .unwrap();
+    let remaining_pictures = pictures::profile_pictures
+        .filter(pictures::id.eq(picture_id))
+        .count()
+        .get_result::<i64>(conn)
+        .unwrap();
+    let picture_exists = std::path::Path::new(&picture_path).exists();
+    let other_user = dao_factory.get_user_dao().get_by_id(&cmp_user.id).is_ok();
+
+    cleanup(&pool);
+
+    assert!(deleted);
+    assert_eq!(0, remaining_contacts);
+    assert_eq!(0, remaining_blacklist);
+    assert_eq!(0, remaining_broadcasts);
+    assert_eq!(0, remaining_statistics);
+    assert_eq!(0, remaining_pictures);
+    assert!(!picture_exists);
+    assert!(other_user);
+}
+
+#[actix_rt::test]
+async fn test_change_tele_num() {
+    use core::models::PhoneNumber;
+    use core::schema::blacklist::dsl::{blacklist, hash_blocked};
+    use core::schema::contacts::dsl::{contacts, target_hash_tele_num};
+    use diesel::prelude::*;
+
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let cmp_user2 = create_user2().await;
+
+    let mut app = init_server_integration_test!(&pool).await;
+
+    let user_signin = signin!(app, cmp_user);
+    let session_token = user_signin.session_token.unwrap();
+
+    make_friend!(
+        app,
+        cmp_user,
+        "test contact",
+        "+4365012345678",
+        session_token.clone()
+    );
+
+    ignore_contact!(app, cmp_user, "+4365012345678", session_token.clone());
+
+    let dao_factory = get_dao_factory(&pool);
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user2.id).unwrap();
+    let tele_num = PhoneNumber::my_from("+4367612345678", "AT").unwrap();
+
+    let changed = dao_factory
+        .get_account_dao()
+        .change_tele_num(&user, &tele_num, "AT")
+        .unwrap();
+
+    let conn: &PgConnection = &pool.get().unwrap();
+    let hash_old = hash("+4365012345678");
+    let hash_new = hash("+4367612345678");
+
+    let count_contacts = |hash_tele_num: &HashedTeleNum| {
+        contacts
+            .filter(target_hash_tele_num.eq(hash_tele_num))
+            .count()
+            .get_result::<i64>(conn)
+            .unwrap()
+    };
+    let count_blacklist = |hash_tele_num: &HashedTeleNum| {
+        blacklist
+            .filter(hash_blocked.eq(hash_tele_num))
+            .count()
+            .get_result::<i64>(conn)
+            .unwrap()
+    };
+
+    let contacts_old = count_contacts(&hash_old);
+    let contacts_new = count_contacts(&hash_new);
+    let blacklist_old = count_blacklist(&hash_old);
+    let blacklist_new = count_blacklist(&hash_new);
+
+    cleanup(&pool);
+
+    assert_eq!(cmp_user2.id, changed.id);
+    assert_eq!("+4367612345678", changed.tele_num);
+    assert_eq!(hash_new, changed.hash_tele_num);
+    assert_eq!(0, contacts_old);
+    assert_eq!(1, contacts_new);
+    assert_eq!(0, blacklist_old);
+    assert_eq!(1, blacklist_new);
+}
+
+#[actix_rt::test]
+async fn test_change_tele_num_then_full_contacts_upload() {
+    use core::models::PhoneNumber;
+
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let cmp_user2 = create_user2().await;
+
+    let dao_factory = get_dao_factory(&pool);
+    let contacts_dao = dao_factory.get_contacts_dao();
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user.id).unwrap();
+    let user2 = dao_factory.get_user_dao().get_by_id(&cmp_user2.id).unwrap();
+    let now = chrono::Local::now().naive_local();
+
+    // The phone of the contact holds the old number
+    let upload = || {
+        vec![PayloadUserDto {
+            name: "Bob".to_string(),
+            hash_tele_num: hash("+4365012345678"),
+        }]
+    };
+
+    contacts_dao.replace(&user, &upload(), now).unwrap();
+
+    let tele_num = PhoneNumber::my_from("+4367612345678", "AT").unwrap();
+    dao_factory
+        .get_account_dao()
+        .change_tele_num(&user2, &tele_num, "AT")
+        .unwrap();
+
+    contacts_dao.replace(&user, &upload(), now).unwrap();
+    let kept = contacts_dao.get_contacts(&user, None).unwrap();
+    let removed = contacts_dao
+        .get_removed_contacts(&user, now - chrono::Duration::minutes(1))
+        .unwrap();
+
+    cleanup(&pool);
+
+    assert_eq!(1, kept.len());
+    assert_eq!(cmp_user2.id, kept[0].user.id);
+    assert_eq!(hash("+4367612345678"), kept[0].user.hash_tele_num);
+    assert!(removed.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_register_device_of_other_user() {
+    use core::errors::ServiceError;
+
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let cmp_user2 = create_user2().await;
+
+    let dao_factory = get_dao_factory(&pool);
+    let device_dao = dao_factory.get_device_dao();
+    let now = chrono::Local::now().naive_local();
+
+    let device = |user_id: uuid::Uuid| InsertDeviceDao {
+        user_id,
+        token: "shared token".to_string(),
+        platform: "android".to_string(),
+        app_version: super::MIN_CLIENT_VERSION.to_string(),
+        last_seen: now,
+        created_at: now,
+    };
+
+    let registered = device_dao.register(device(cmp_user.id)).unwrap();
+    let taken_over = device_dao.register(device(cmp_user2.id));
+    let devices = device_dao.get_all(&cmp_user.id).unwrap();
+    let devices2 = device_dao.get_all(&cmp_user2.id).unwrap();
+
+    cleanup(&pool);
+
+    assert_eq!(cmp_user.id, registered.user_id);
+    match taken_over {
+        Err(ServiceError::AlreadyExists) => {}
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    assert_eq!(1, devices.len());
+    assert_eq!(0, devices2.len());
+}
+
+#[actix_rt::test]
+async fn test_sync_contacts_keeps_unknown_numbers_pending() {
+    use core::schema::contacts::dsl::{contacts, from_id, target_hash_tele_num};
+    use core::schema::pending_contacts::dsl as pending;
+    use diesel::prelude::*;
+
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let _cmp_user2 = create_user2().await;
+
+    let dao_factory = get_dao_factory(&pool);
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user.id).unwrap();
+    let now = chrono::Local::now().naive_local();
+
+    let upserts = vec![
+        PayloadUserDto {
+            name: "User".to_string(),
+            hash_tele_num: hash("+4365012345678"),
+        },
+        PayloadUserDto {
+            name: "Stranger".to_string(),
+            hash_tele_num: hash("+4369912345678"),
+        },
+    ];
+
+    let cursor = dao_factory
+        .get_contacts_dao()
+        .sync(&user, 0, &upserts, &[], now)
+        .unwrap();
+
+    let conn: &PgConnection = &pool.get().unwrap();
+    let stored = contacts
+        .filter(from_id.eq(cmp_user.id))
+        .select(target_hash_tele_num)
+        .load::<HashedTeleNum>(conn)
+        .unwrap();
+    let pending = pending::pending_contacts
+        .filter(pending::from_id.eq(cmp_user.id))
+        .select(pending::target_hash_tele_num)
+        .load::<HashedTeleNum>(conn)
+        .unwrap();
+
+    cleanup(&pool);
+
+    assert_eq!(1, cursor);
+    assert_eq!(vec![hash("+4365012345678")], stored);
+    assert_eq!(vec![hash("+4369912345678")], pending);
+}
+
+#[actix_rt::test]
+async fn test_contact_registers_after_the_first_sync() {
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+
+    let dao_factory = get_dao_factory(&pool);
+    let contacts_dao = dao_factory.get_contacts_dao();
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user.id).unwrap();
+    let synced_at = chrono::Local::now().naive_local();
+
+    contacts_dao
+        .sync(
+            &user,
+            0,
+            &[PayloadUserDto {
+                name: "Bob".to_string(),
+                hash_tele_num: hash("+4365012345678"),
+            }],
+            &[],
+            synced_at,
+        )
+        .unwrap();
+    let before = contacts_dao.get_contacts(&user, None).unwrap();
+
+    let cmp_user2 = create_user2().await;
+
+    let after = contacts_dao.get_contacts(&user, Some(synced_at)).unwrap();
+
+    cleanup(&pool);
+
+    assert!(before.is_empty());
+    assert_eq!(1, after.len());
+    assert_eq!("Bob", after[0].name);
+    assert_eq!(cmp_user2.id, after[0].user.id);
+}
+
+#[actix_rt::test]
+async fn test_get_contacts_since_rename() {
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let _cmp_user2 = create_user2().await;
+
+    let dao_factory = get_dao_factory(&pool);
+    let contacts_dao = dao_factory.get_contacts_dao();
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user.id).unwrap();
+
+    let contact = |name: &str| PayloadUserDto {
+        name: name.to_string(),
+        hash_tele_num: hash("+4365012345678"),
+    };
+
+    // After the last change of the status of the contact. Postgres keeps microseconds.
+    let added_at = chrono::Local::now().date().naive_local().and_hms(12, 0, 0)
+        + chrono::Duration::days(1);
+    let renamed_at = added_at + chrono::Duration::minutes(1);
+    let uploaded_at = renamed_at + chrono::Duration::minutes(1);
+
+    contacts_dao
+        .sync(&user, 0, &[contact("Bob")], &[], added_at)
+        .unwrap();
+    let added = contacts_dao.get_contacts_version(&user, None).unwrap();
+    let unchanged = contacts_dao.get_contacts(&user, Some(added_at)).unwrap();
+
+    contacts_dao
+        .sync(&user, 1, &[contact("Robert")], &[], renamed_at)
+        .unwrap();
+    let renamed = contacts_dao.get_contacts_version(&user, None).unwrap();
+    let changed = contacts_dao.get_contacts(&user, Some(added_at)).unwrap();
+
+    // The same name again
+    contacts_dao
+        .sync(&user, 2, &[contact("Robert")], &[], uploaded_at)
+        .unwrap();
+    let uploaded = contacts_dao.get_contacts_version(&user, None).unwrap();
+
+    cleanup(&pool);
+
+    assert_eq!((1, Some(added_at)), added);
+    assert!(unchanged.is_empty());
+    assert_eq!((1, Some(renamed_at)), renamed);
+    assert_eq!(1, changed.len());
+    assert_eq!("Robert", changed[0].name);
+    assert_eq!(renamed, uploaded);
+}
+
+#[actix_rt::test]
+async fn test_get_contacts_since_removal() {
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let cmp_user2 = create_user2().await;
+
+    let dao_factory = get_dao_factory(&pool);
+    let contacts_dao = dao_factory.get_contacts_dao();
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user.id).unwrap();
+    let user2 = dao_factory.get_user_dao().get_by_id(&cmp_user2.id).unwrap();
+
+    let contact = || PayloadUserDto {
+        name: "Bob".to_string(),
+        hash_tele_num: user2.hash_tele_num.clone(),
+    };
+
+    let added_at = chrono::Local::now().date().naive_local().and_hms(12, 0, 0)
+        + chrono::Duration::days(1);
+    let removed_at = added_at + chrono::Duration::minutes(1);
+    let readded_at = removed_at + chrono::Duration::minutes(1);
+
+    contacts_dao
+        .sync(&user, 0, &[contact()], &[], added_at)
+        .unwrap();
+    contacts_dao
+        .sync(&user, 1, &[], &[user2.hash_tele_num.clone()], removed_at)
+        .unwrap();
+    let removed = contacts_dao.get_removed_contacts(&user, added_at).unwrap();
+    let version = contacts_dao
+        .get_contacts_version(&user, Some(added_at))
+        .unwrap();
+
+    contacts_dao
+        .sync(&user, 2, &[contact()], &[], readded_at)
+        .unwrap();
+    let readded = contacts_dao.get_removed_contacts(&user, added_at).unwrap();
+
+    // The account of the contact is deleted
+    let deleted_at = chrono::Local::now().naive_local();
+    dao_factory.get_account_dao().delete(&user2).unwrap();
+    let deleted = contacts_dao
+        .get_removed_contacts(&user, deleted_at)
+        .unwrap();
+
+    cleanup(&pool);
+
+    assert_eq!(vec![user2.hash_tele_num.clone()], removed);
+    assert_eq!((1, Some(removed_at)), version);
+    assert!(readded.is_empty());
+    // The hash of the client
+    assert_eq!(vec![cmp_user2.hash_tele_num], deleted);
+}
+
+#[actix_rt::test]
+async fn test_purge_usage_statistics_keeps_monthly_totals() {
+    use chrono::NaiveDate;
+    use core::schema::usage_statistics::dsl::usage_statistics;
+    use core::schema::usage_statistics_monthly::dsl::usage_statistics_monthly;
+    use diesel::prelude::*;
+    use diesel::sql_query;
+    use diesel::sql_types::{BigInt, Integer, Text};
+    use diesel::QueryableByName;
+
+    #[derive(Debug, PartialEq, QueryableByName)]
+    struct MonthlyTotal {
+        #[sql_type = "Text"]
+        pseudonym: String,
+        #[sql_type = "Integer"]
+        yearly: i32,
+        #[sql_type = "Integer"]
+        monthly: i32,
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+
+    let dao_factory = get_dao_factory(&pool);
+    let user = dao_factory.get_user_dao().get_by_id(&cmp_user.id).unwrap();
+    let conn: &PgConnection = &pool.get().unwrap();
+
+    let at = |month, day| NaiveDate::from_ymd(2020, month, day).and_hms(12, 0, 0);
+    let entries: Vec<_> = vec![at(1, 10), at(1, 20), at(2, 5), at(4, 1)]
+        .into_iter()
+        .map(|created_at| InsertUsageStatisticEntryDao {
+            pseudonym: user.pseudonym(),
+            created_at,
+        })
+        .collect();
+
+    diesel::insert_into(usage_statistics)
+        .values(&entries)
+        .execute(conn)
+        .unwrap();
+
+    // January was already rolled up once
+    diesel::insert_into(usage_statistics_monthly)
+        .values(&UsageStatisticsMonthlyDao {
+            pseudonym: user.pseudonym(),
+            yearly: 2020,
+            monthly: 1,
+            count: 3,
+        })
+        .execute(conn)
+        .unwrap();
+
+    let totals = || {
+        sql_query(
+            "SELECT pseudonym, yearly, monthly, count FROM stat_monthly ORDER BY yearly, monthly",
+        )
+        .load::<MonthlyTotal>(conn)
+        .unwrap()
+    };
+
+    let before = totals();
+
+    let purged = dao_factory
+        .get_retention_dao()
+        .purge_usage_statistics(at(3, 1))
+        .unwrap();
+
+    let after = totals();
+    let remaining = usage_statistics.count().get_result::<i64>(conn).unwrap();
+
+    cleanup(&pool);
+
+    assert_eq!(3, purged);
+    assert_eq!(1, remaining);
+    assert_eq!(before, after);
+    assert_eq!(
+        vec![5, 1, 1],
+        after.iter().map(|w| w.count).collect::<Vec<_>>()
+    );
+}
+
+/// Removes the pepper, even when the test fails
+struct Pepper;
+
+impl Pepper {
+    fn set(pepper: &str) -> Self {
+        core::hashing::set_pepper(Some(pepper));
+        Pepper
+    }
+}
+
+impl Drop for Pepper {
+    fn drop(&mut self) {
+        core::hashing::set_pepper(None);
+    }
+}
+
+#[actix_rt::test]
+async fn test_peppered_hashes_round_trip() {
+    use core::schema::contacts::dsl::{contacts, from_id, target_hash_tele_num};
+    use diesel::prelude::*;
+
+    let _pepper = Pepper::set("pepper");
+
+    let pool = get_pool();
+
+    cleanup(&pool);
+
+    init_data!(&pool);
+
+    let cmp_user = create_user().await;
+    let cmp_user2 = create_user2().await;
+
+    let mut app = init_server_integration_test!(&pool).await;
+
+    let user_signin = signin!(app, cmp_user);
+    let session_token = user_signin.session_token.unwrap();
+
+    make_friend!(app, cmp_user, "User", "+4365012345678", session_token.clone());
+    ignore_contact!(app, cmp_user, "+4365012345678", session_token.clone());
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/contacts/{}", cmp_user.id))
+        .header("AUTHORIZATION", session_token.clone())
+        .to_request();
+
+    let listed: Vec<ContactDto> = test::read_response_json(&mut app, req).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/user/{}/blacklist", cmp_user.id))
+        .header("AUTHORIZATION", session_token)
+        .to_request();
+
+    let blacklists: Vec<BlacklistDto> = test::read_response_json(&mut app, req).await;
+
+    let user2 = get_dao_factory(&pool)
+        .get_user_dao()
+        .get_by_id(&cmp_user2.id)
+        .unwrap();
+
+    let conn: &PgConnection = &pool.get().unwrap();
+    let stored = contacts
+        .filter(from_id.eq(cmp_user.id))
+        .select(target_hash_tele_num)
+        .load::<HashedTeleNum>(conn)
+        .unwrap();
+
+    cleanup(&pool);
+
+    // Only the peppered hash is stored, but the client gets its own hash back
+    assert_eq!(vec![user2.hash_tele_num.clone()], stored);
+    assert_ne!(hash("+4365012345678"), user2.hash_tele_num);
+    assert_eq!(hash("+4365012345678"), cmp_user2.hash_tele_num);
+    assert_eq!(hash("+4366412345678"), user_signin.hash_tele_num);
+
+    assert_eq!(1, listed.len());
+    assert_eq!(cmp_user2.id, listed[0].user.id);
+    assert_eq!(hash("+4365012345678"), listed[0].user.hash_tele_num);
+
+    assert_eq!(1, blacklists.len());
+    assert_eq!(hash("+4366412345678"), blacklists[0].hash_blocker);
+    assert_eq!(hash("+4365012345678"), blacklists[0].hash_blocked);
+}
@@ -1,6 +1,13 @@
 pub use super::*;
 
-use crate::ratelimits::{DefaultRateLimitPolicy, RateLimitWrapper};
+use crate::config::SessionConfig;
+use crate::services::session::{SessionService, SessionStore};
+use crate::queries::{
+    MockPersistentContactGroupDao, MockPersistentDeviceDao, MockPersistentVerificationDao,
+    PersistentContactGroupDao, PersistentDeviceDao, PersistentVerificationDao,
+};
+use crate::ratelimits::verification::VerificationLimitConfig;
+use crate::ratelimits::{RateLimitConfig, RateLimitWrapper, SlidingWindowRateLimitPolicy};
 use crate::services::number_registration::{
     NumberRegistrationService, NumberRegistrationServiceTrait,
 };
@@ -29,7 +36,75 @@ pub(crate) fn set_testing_notification_service() -> NotificationService {
 }
 
 pub(crate) fn set_ratelimits() -> RateLimitWrapper {
-    RateLimitWrapper::new(Box::new(DefaultRateLimitPolicy))
+    RateLimitWrapper::new(Box::new(SlidingWindowRateLimitPolicy::new(
+        RateLimitConfig::default(),
+    )))
+}
+
+pub(crate) fn set_session_service_with_store(store: SessionStore) -> SessionService {
+    let config = SessionConfig {
+        key: std::env::var("SESSION_KEY").expect("No SESSION_KEY configured"),
+        ..SessionConfig::default()
+    };
+
+    get_session_service_with_store(&config, store)
+}
+
+/// Verification limits, which never block
+pub(crate) fn set_verification_dao() -> Box<dyn PersistentVerificationDao> {
+    let mut verification_dao = MockPersistentVerificationDao::new();
+
+    verification_dao.expect_get_limit().returning(|_| Ok(None));
+    verification_dao.expect_set_limit().returning(|_| Ok(()));
+    verification_dao.expect_remove_limit().returning(|_| Ok(()));
+
+    Box::new(verification_dao)
+}
+
+pub(crate) fn set_device_dao() -> Box<dyn PersistentDeviceDao> {
+    let mut device_dao = MockPersistentDeviceDao::new();
+
+    device_dao.expect_register().returning(|device| {
+        Ok(core::models::dao::DeviceDao {
+            id: 1,
+            user_id: device.user_id,
+            token: device.token,
+            platform: device.platform,
+            app_version: device.app_version,
+            last_seen: device.last_seen,
+            created_at: device.created_at,
+        })
+    });
+    device_dao.expect_remove_token().returning(|_| Ok(0));
+    device_dao.expect_touch().returning(|_, _| Ok(1));
+
+    Box::new(device_dao)
+}
+
+/// Group `1` of every user contains `+4365012345678`
+pub(crate) fn set_contact_group_dao() -> Box<dyn PersistentContactGroupDao> {
+    let mut contact_group_dao = MockPersistentContactGroupDao::new();
+
+    contact_group_dao
+        .expect_get()
+        .returning(|owner_id, group_id| match group_id {
+            1 => Ok((
+                core::models::dao::ContactGroupDao {
+                    id: 1,
+                    owner_id: *owner_id,
+                    name: "Football".to_string(),
+                    created_at: chrono::Utc::now().naive_local(),
+                    updated_at: chrono::Utc::now().naive_local(),
+                },
+                vec![core::models::dao::ContactGroupMemberDao {
+                    group_id: 1,
+                    hash_tele_num: hash("+4365012345678"),
+                }],
+            )),
+            _ => Err(core::errors::ServiceError::ResourceDoesNotExist),
+        });
+
+    Box::new(contact_group_dao)
 }
 
 fn hash(value: impl Into<String>) -> HashedTeleNum {
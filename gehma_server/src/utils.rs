@@ -1,27 +1,27 @@
+use crate::services::led_expiry::{self, LedExpiryConfig};
 use crate::services::number_registration::testing::*;
 use crate::services::number_registration::twilio::*;
 use crate::services::number_registration::NumberRegistrationService;
 use crate::services::push_notifications::firebase::*;
 use crate::services::push_notifications::one_signal::*;
+use crate::services::push_notifications::outbox::run_worker;
 use crate::services::push_notifications::testing::*;
 use crate::services::push_notifications::NotificationService;
+use crate::services::retention::{self, RetentionConfig};
 use crate::services::session::*;
 
+use crate::config::*;
 use crate::ratelimits::*;
-
-#[allow(dead_code)]
-pub(crate) fn get_auth() -> NumberRegistrationService {
-    let project_id = std::env::var("TWILIO_PROJECT_ID").expect("no PROJECT_ID");
-    let auth_token = std::env::var("TWILIO_AUTH_TOKEN").expect("no AUTH_TOKEN");
-    let sid = std::env::var("TWILIO_ACCOUNT_ID").expect("no ACCOUNT_ID");
-
-    let config = TwilioConfiguration {
-        project_id,
-        account_id: sid,
-        auth_token,
-    };
-
-    Box::new(TwilioAuthenticator { config })
+use crate::Pool;
+
+/// Selects the configured backend
+pub(crate) fn get_auth(config: &NumberRegistrationConfig) -> NumberRegistrationService {
+    match config.backend {
+        NumberRegistrationBackend::Twilio => Box::new(TwilioAuthenticator {
+            config: config.twilio.clone(),
+        }),
+        NumberRegistrationBackend::Testing => set_testing_auth(),
+    }
 }
 
 #[allow(dead_code)]
@@ -49,32 +49,76 @@ pub(crate) fn set_testing_notification() -> NotificationService {
     Box::new(TestingNotificationService)
 }
 
-#[allow(dead_code)]
-pub(crate) fn get_ratelimits() -> RateLimitWrapper {
-    RateLimitWrapper::new(Box::new(DefaultRateLimitPolicy))
+pub(crate) fn get_ratelimits(pool: Pool, config: &LimitsConfig) -> RateLimitWrapper {
+    let rate_limits = config.rate_limits.clone();
+
+    match config.policy {
+        RateLimitPolicyKind::Memory => {
+            RateLimitWrapper::new(Box::new(SlidingWindowRateLimitPolicy::new(rate_limits)))
+        }
+        RateLimitPolicyKind::Analytics => {
+            RateLimitWrapper::new(Box::new(DefaultRateLimitPolicy::new(pool, rate_limits)))
+        }
+    }
 }
 
-#[allow(dead_code)]
-pub(crate) fn get_firebase_notification_service() -> NotificationService {
-    let api_token = std::env::var("FCM_TOKEN").expect("No FCM_TOKEN configured");
-
-    let config = FirebaseConfiguration {
-        fcm_token: api_token,
-    };
+/// Selects the configured backend
+pub(crate) fn get_notification_service(config: &NotificationConfig) -> NotificationService {
+    match config.backend {
+        NotificationBackend::OneSignal => Box::new(OneSignalService {
+            config: config.one_signal.clone(),
+        }),
+        NotificationBackend::Firebase => Box::new(FirebaseNotificationService {
+            config: config.firebase.clone(),
+        }),
+        NotificationBackend::Testing => set_testing_notification(),
+    }
+}
 
-    Box::new(FirebaseNotificationService { config })
+/// Delivers the notifications of the outbox with the configured backend
+pub(crate) fn spawn_notification_worker(pool: Pool, config: NotificationConfig) {
+    std::thread::Builder::new()
+        .name("notification-worker".to_string())
+        .spawn(move || {
+            let dao_factory = crate::dao_factory::DaoFactory::new(pool);
+            let sender = get_notification_service(&config);
+
+            run_worker(
+                dao_factory.get_outbox_dao(),
+                dao_factory.get_user_dao(),
+                dao_factory.get_device_dao(),
+                sender,
+                config.outbox,
+            );
+        })
+        .expect("Cannot start the notification worker");
 }
 
-#[allow(dead_code)]
-pub(crate) fn get_onesignal_notification_service() -> NotificationService {
-    let id = std::env::var("ONE_SIGNAL_ID").expect("No ONE_SIGNAL_ID configured");
-    let key = std::env::var("ONE_SIGNAL_KEY").expect("No ONE_SIGNAL_KEY configured");
+/// Switches the expired leds off
+pub(crate) fn spawn_led_expiry_worker(pool: Pool, config: LedExpiryConfig) {
+    std::thread::Builder::new()
+        .name("led-expiry-worker".to_string())
+        .spawn(move || {
+            let dao_factory = crate::dao_factory::DaoFactory::new(pool);
 
-    let config = OneSignalConfiguration { id, key };
+            led_expiry::run_worker(dao_factory.get_user_dao(), config);
+        })
+        .expect("Cannot start the led expiry worker");
+}
 
-    Box::new(OneSignalService { config })
+/// Purges the expired analytics and usage statistics
+pub(crate) fn spawn_retention_worker(pool: Pool, config: RetentionConfig) {
+    std::thread::Builder::new()
+        .name("retention-worker".to_string())
+        .spawn(move || {
+            let dao_factory = crate::dao_factory::DaoFactory::new(pool);
+
+            retention::run_worker(dao_factory.get_retention_dao(), config);
+        })
+        .expect("Cannot start the retention worker");
 }
 
+/// Stateless session service for the tests
 #[allow(dead_code)]
 pub(crate) fn get_session_service() -> SessionService {
     let secret = std::env::var("SESSION_KEY").expect("No SESSION_KEY configured");
@@ -82,6 +126,18 @@ pub(crate) fn get_session_service() -> SessionService {
     Box::new(SessionServicePriv::new(secret))
 }
 
+/// Session service, which allows to revoke sessions
+pub(crate) fn get_session_service_with_store(
+    config: &SessionConfig,
+    store: SessionStore,
+) -> SessionService {
+    Box::new(
+        SessionServicePriv::new(config.key.clone())
+            .with_durations(config.duration, config.refresh_token_duration)
+            .with_store(store),
+    )
+}
+
 macro_rules! response {
     ($payload:expr) => {{
         use actix_web::{HttpResponse};
@@ -0,0 +1,93 @@
+use core::hashing::pepper_hash;
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use diesel::QueryableByName;
+use dotenv::dotenv;
+use std::env;
+
+/// Columns with hashes of phone numbers. `users` comes first, because its
+/// foreign keys cascade the update to the blacklist, the votes and the broadcasts.
+const COLUMNS: &[(&str, &str)] = &[
+    ("users", "hash_tele_num"),
+    ("contacts", "target_hash_tele_num"),
+    ("pending_contacts", "target_hash_tele_num"),
+    ("contact_tombstones", "target_hash_tele_num"),
+    ("tele_num_changes", "old_hash_tele_num"),
+    ("blacklist", "hash_blocker"),
+    ("blacklist", "hash_blocked"),
+    ("muted_contacts", "hash_muted"),
+    ("contact_group_members", "hash_tele_num"),
+    ("votes", "hash_tele_num"),
+    ("broadcast", "display_user"),
+];
+
+#[derive(QueryableByName)]
+struct Hash {
+    #[sql_type = "Text"]
+    hash: String,
+}
+
+fn establish_connection() -> PgConnection {
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    PgConnection::establish(&database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+}
+
+fn main() {
+    dotenv().ok();
+
+    let pepper = env::var("HASH_PEPPER").expect("HASH_PEPPER must be set");
+
+    if pepper.is_empty() {
+        eprintln!("HASH_PEPPER is empty");
+        std::process::exit(1);
+    }
+
+    let connection = establish_connection();
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|| {
+        for (table, column) in COLUMNS {
+            let updated = rehash_column(&connection, pepper.as_bytes(), table, column)?;
+            println!("{}.{}: {} hashes", table, column, updated);
+        }
+
+        Ok(())
+    });
+
+    if let Err(ref err) = result {
+        eprintln!("err {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Peppers the unversioned hashes of `table.column`
+fn rehash_column(
+    connection: &PgConnection,
+    pepper: &[u8],
+    table: &str,
+    column: &str,
+) -> Result<usize, diesel::result::Error> {
+    let hashes = diesel::sql_query(format!(
+        "SELECT DISTINCT TRIM({column}) AS hash FROM {table} WHERE {column} NOT LIKE '%$%'",
+        table = table,
+        column = column
+    ))
+    .load::<Hash>(connection)?;
+
+    let update = format!(
+        "UPDATE {table} SET {column} = $1 WHERE {column} = $2",
+        table = table,
+        column = column
+    );
+
+    for hash in hashes.iter() {
+        diesel::sql_query(update.as_str())
+            .bind::<Text, _>(pepper_hash(pepper, &hash.hash))
+            .bind::<Text, _>(&hash.hash)
+            .execute(connection)?;
+    }
+
+    Ok(hashes.len())
+}
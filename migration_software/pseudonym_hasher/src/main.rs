@@ -0,0 +1,77 @@
+use core::pseudonym::keyed_pseudonym;
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use diesel::QueryableByName;
+use dotenv::dotenv;
+use std::env;
+
+/// Tables with pseudonyms of the users
+const TABLES: &[&str] = &["analytics", "usage_statistics", "usage_statistics_monthly"];
+
+#[derive(QueryableByName)]
+struct Pseudonym {
+    #[sql_type = "Text"]
+    pseudonym: String,
+}
+
+fn establish_connection() -> PgConnection {
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    PgConnection::establish(&database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+}
+
+fn main() {
+    dotenv().ok();
+
+    let key = env::var("PSEUDONYM_KEY").expect("PSEUDONYM_KEY must be set");
+
+    if key.is_empty() {
+        eprintln!("PSEUDONYM_KEY is empty");
+        std::process::exit(1);
+    }
+
+    let connection = establish_connection();
+
+    let result = connection.transaction::<_, diesel::result::Error, _>(|| {
+        for table in TABLES {
+            let updated = rehash_table(&connection, key.as_bytes(), table)?;
+            println!("{}: {} pseudonyms", table, updated);
+        }
+
+        Ok(())
+    });
+
+    if let Err(ref err) = result {
+        eprintln!("err {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Keys the pseudonyms of `table`, which are ids of users
+fn rehash_table(
+    connection: &PgConnection,
+    key: &[u8],
+    table: &str,
+) -> Result<usize, diesel::result::Error> {
+    let pseudonyms = diesel::sql_query(format!(
+        "SELECT DISTINCT pseudonym FROM {table} WHERE pseudonym IN (SELECT id::text FROM users)",
+        table = table
+    ))
+    .load::<Pseudonym>(connection)?;
+
+    let update = format!(
+        "UPDATE {table} SET pseudonym = $1 WHERE pseudonym = $2",
+        table = table
+    );
+
+    for pseudonym in pseudonyms.iter() {
+        diesel::sql_query(update.as_str())
+            .bind::<Text, _>(keyed_pseudonym(key, &pseudonym.pseudonym))
+            .bind::<Text, _>(&pseudonym.pseudonym)
+            .execute(connection)?;
+    }
+
+    Ok(pseudonyms.len())
+}
@@ -0,0 +1,50 @@
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use std::env;
+use uuid::Uuid;
+
+fn establish_connection() -> PgConnection {
+    dotenv().ok();
+
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    PgConnection::establish(&database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+}
+
+fn main() {
+    let ids: Vec<Uuid> = env::args()
+        .skip(1)
+        .map(|w| Uuid::parse_str(&w).unwrap_or_else(|_| panic!("{} is not a user id", w)))
+        .collect();
+
+    let connection = establish_connection();
+
+    match reset(&connection, &ids) {
+        Ok(count) => println!("Reset the access tokens of {} users", count),
+        Err(err) => {
+            eprintln!("err {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Resets the tokens of `ids` or of all users, when `ids` is empty
+fn reset(connection: &PgConnection, ids: &[Uuid]) -> Result<usize, diesel::result::Error> {
+    use core::schema::users::dsl::{access_token as token, id, users};
+
+    connection.transaction(|| {
+        let targets: Vec<Uuid> = if ids.is_empty() {
+            users.select(id).load(connection)?
+        } else {
+            users.select(id).filter(id.eq_any(ids)).load(connection)?
+        };
+
+        // Users without a token get a new one, when they verify their number
+        diesel::update(users.filter(id.eq_any(&targets)))
+            .set(token.eq(""))
+            .execute(connection)?;
+
+        Ok(targets.len())
+    })
+}
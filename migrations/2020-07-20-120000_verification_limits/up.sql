@@ -0,0 +1,8 @@
+CREATE TABLE verification_limits (
+	limit_key VARCHAR PRIMARY KEY,
+	requests INTEGER NOT NULL DEFAULT 0,
+	failed_checks INTEGER NOT NULL DEFAULT 0,
+	blocked_until TIMESTAMP,
+	locked_until TIMESTAMP,
+	updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,14 @@
+CREATE TABLE notification_outbox (
+	id SERIAL PRIMARY KEY,
+	recipient_name VARCHAR NOT NULL,
+	token VARCHAR NOT NULL,
+	-- pending, delivered or dead
+	status VARCHAR NOT NULL DEFAULT 'pending',
+	attempts INTEGER NOT NULL DEFAULT 0,
+	next_attempt_at TIMESTAMP NOT NULL,
+	last_error TEXT,
+	created_at TIMESTAMP NOT NULL,
+	updated_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX notification_outbox_due ON notification_outbox (status, next_attempt_at);
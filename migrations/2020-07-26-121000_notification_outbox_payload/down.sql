@@ -0,0 +1,5 @@
+ALTER TABLE notification_outbox DROP COLUMN data;
+ALTER TABLE notification_outbox DROP COLUMN text;
+ALTER TABLE notification_outbox DROP COLUMN country_code;
+ALTER TABLE notification_outbox DROP COLUMN kind;
+ALTER TABLE notification_outbox RENAME COLUMN name TO recipient_name;
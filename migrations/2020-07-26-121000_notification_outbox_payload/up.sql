@@ -0,0 +1,7 @@
+-- The name is the originator in the contacts of the recipient
+ALTER TABLE notification_outbox RENAME COLUMN recipient_name TO name;
+ALTER TABLE notification_outbox ADD COLUMN kind VARCHAR NOT NULL DEFAULT 'led_on';
+ALTER TABLE notification_outbox ADD COLUMN country_code VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE notification_outbox ADD COLUMN text TEXT NOT NULL DEFAULT '';
+-- Deep-link data as json object
+ALTER TABLE notification_outbox ADD COLUMN data TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1,19 @@
+CREATE TABLE devices (
+	id SERIAL PRIMARY KEY,
+	user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	-- A token belongs to one installation of the app
+	token VARCHAR NOT NULL UNIQUE,
+	platform VARCHAR NOT NULL,
+	app_version VARCHAR NOT NULL,
+	last_seen TIMESTAMP NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX devices_user_id ON devices (user_id, last_seen);
+
+-- The tokens of the released clients. They count as seen now, because `changed_at`
+-- is not refreshed by the signin.
+INSERT INTO devices (user_id, token, platform, app_version, last_seen, created_at)
+	SELECT DISTINCT ON (firebase_token) id, firebase_token, 'unknown', client_version, NOW(), NOW() FROM users
+	WHERE firebase_token IS NOT NULL AND firebase_token <> ''
+	ORDER BY firebase_token, changed_at DESC;
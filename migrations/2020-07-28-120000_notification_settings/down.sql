@@ -0,0 +1,3 @@
+DROP FUNCTION in_quiet_hours(TIME, TIME, VARCHAR);
+DROP TABLE muted_contacts;
+DROP TABLE notification_settings;
@@ -0,0 +1,28 @@
+CREATE TABLE notification_settings (
+	user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+	-- Nothing is pushed until then
+	muted_until TIMESTAMP,
+	-- Local time of `timezone`. The quiet hours may span midnight.
+	quiet_hours_start TIME,
+	quiet_hours_end TIME,
+	timezone VARCHAR NOT NULL DEFAULT 'UTC',
+	updated_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE muted_contacts (
+	user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	hash_muted VARCHAR NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	PRIMARY KEY (user_id, hash_muted)
+);
+
+-- Whether the current time of `tz` is between `start_time` and `end_time`
+CREATE FUNCTION in_quiet_hours(start_time TIME, end_time TIME, tz VARCHAR) RETURNS BOOLEAN AS $$
+	SELECT CASE
+		WHEN start_time IS NULL OR end_time IS NULL THEN FALSE
+		WHEN start_time <= end_time THEN
+			(NOW() AT TIME ZONE tz)::time >= start_time AND (NOW() AT TIME ZONE tz)::time < end_time
+		ELSE
+			(NOW() AT TIME ZONE tz)::time >= start_time OR (NOW() AT TIME ZONE tz)::time < end_time
+	END
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,2 @@
+DROP INDEX users_led_expires_at;
+ALTER TABLE users DROP COLUMN led_expires_at;
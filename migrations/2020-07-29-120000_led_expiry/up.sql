@@ -0,0 +1,4 @@
+-- The led is switched off automatically afterwards
+ALTER TABLE users ADD COLUMN led_expires_at TIMESTAMP;
+
+CREATE INDEX users_led_expires_at ON users (led_expires_at) WHERE led_expires_at IS NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE users DROP COLUMN led_audience;
+DROP TABLE contact_group_members;
+DROP TABLE contact_groups;
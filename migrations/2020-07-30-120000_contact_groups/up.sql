@@ -0,0 +1,17 @@
+CREATE TABLE contact_groups (
+	id SERIAL PRIMARY KEY,
+	owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	name VARCHAR NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	updated_at TIMESTAMP NOT NULL,
+	UNIQUE (owner_id, name)
+);
+
+CREATE TABLE contact_group_members (
+	group_id INT NOT NULL REFERENCES contact_groups(id) ON DELETE CASCADE,
+	hash_tele_num CHAR(64) NOT NULL,
+	PRIMARY KEY (group_id, hash_tele_num)
+);
+
+-- Only the members of the group see the led and the description. NULL is everyone.
+ALTER TABLE users ADD COLUMN led_audience INT REFERENCES contact_groups(id) ON DELETE SET NULL;
@@ -0,0 +1,6 @@
+-- The cursor is increased with every upload of the contacts
+CREATE TABLE contact_sync (
+	user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+	cursor BIGINT NOT NULL,
+	updated_at TIMESTAMP NOT NULL
+);
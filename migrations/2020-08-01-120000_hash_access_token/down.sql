@@ -0,0 +1,5 @@
+-- The plain tokens cannot be restored. Every user has to verify the number again.
+UPDATE users SET access_token = md5(random()::text || id::text);
+
+ALTER TABLE users ALTER COLUMN access_token TYPE VARCHAR(32);
+ALTER TABLE users ALTER COLUMN access_token SET DEFAULT 'init';
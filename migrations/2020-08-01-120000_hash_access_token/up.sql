@@ -0,0 +1,11 @@
+-- Access tokens are stored as `1$<salt>$<sha256(salt || token)>`
+ALTER TABLE users ALTER COLUMN access_token DROP DEFAULT;
+ALTER TABLE users ALTER COLUMN access_token TYPE VARCHAR(128);
+
+-- `init` was the default, these users have no token yet
+UPDATE users SET access_token = '' WHERE access_token = 'init';
+
+UPDATE users u
+SET access_token = '1$' || s.salt || '$' || encode(sha256(convert_to(s.salt || u.access_token, 'UTF8')), 'hex')
+FROM (SELECT id, substr(md5(random()::text || id::text), 1, 16) AS salt FROM users) s
+WHERE s.id = u.id AND u.access_token NOT LIKE '%$%';
@@ -0,0 +1,18 @@
+-- The monthly counts and the statistics of deleted users are lost
+DROP VIEW stat_monthly;
+DROP TABLE usage_statistics_monthly;
+
+UPDATE analytics a SET pseudonym = u.tele_num FROM users u WHERE u.id::text = a.pseudonym;
+UPDATE usage_statistics s SET pseudonym = u.tele_num FROM users u WHERE u.id::text = s.pseudonym;
+
+DELETE FROM analytics WHERE pseudonym NOT IN (SELECT tele_num FROM users);
+DELETE FROM usage_statistics WHERE pseudonym NOT IN (SELECT tele_num FROM users);
+
+ALTER TABLE usage_statistics RENAME COLUMN pseudonym TO tele_num;
+ALTER TABLE analytics RENAME COLUMN pseudonym TO tele_num;
+ALTER TABLE analytics ADD CONSTRAINT analytics_tele_num_fkey FOREIGN KEY (tele_num) REFERENCES users(tele_num) ON DELETE CASCADE ON UPDATE CASCADE;
+
+CREATE OR REPLACE VIEW stat_monthly AS (
+    SELECT tele_num, date_part('month', usage_statistics.created_at::date) as monthly, date_part('year', usage_statistics.created_at::date) as yearly, COUNT(usage_statistics.created_at::date), (dense_rank() over (ORDER BY date_part('year', usage_statistics.created_at::date) ASC, date_part('month', usage_statistics.created_at::date) ASC)) as period FROM usage_statistics  GROUP BY tele_num, monthly, yearly ORDER
+    BY yearly ASC, monthly ASC
+);
@@ -0,0 +1,34 @@
+-- The analytics and the usage statistics store a pseudonym (the id of the user) instead of the number.
+-- migration_software/pseudonym_hasher keys the ids afterwards.
+ALTER TABLE analytics DROP CONSTRAINT IF EXISTS analytics_tele_num_fkey;
+ALTER TABLE analytics RENAME COLUMN tele_num TO pseudonym;
+ALTER TABLE usage_statistics RENAME COLUMN tele_num TO pseudonym;
+
+-- Numbers without a user were deleted
+WITH salt AS (SELECT md5(random()::text) AS value)
+UPDATE usage_statistics
+SET pseudonym = 'deleted:' || md5(salt.value || pseudonym)
+FROM salt
+WHERE pseudonym NOT LIKE 'deleted:%' AND pseudonym NOT IN (SELECT tele_num FROM users);
+
+UPDATE analytics a SET pseudonym = u.id::text FROM users u WHERE u.tele_num = a.pseudonym;
+UPDATE usage_statistics s SET pseudonym = u.id::text FROM users u WHERE u.tele_num = s.pseudonym;
+
+-- Monthly counts of the purged usage statistics
+CREATE TABLE usage_statistics_monthly (
+	pseudonym VARCHAR(100) NOT NULL,
+	yearly INT NOT NULL,
+	monthly INT NOT NULL,
+	count BIGINT NOT NULL,
+	PRIMARY KEY (pseudonym, yearly, monthly)
+);
+
+DROP VIEW stat_monthly;
+
+CREATE VIEW stat_monthly AS (
+    SELECT pseudonym, monthly, yearly, SUM(count)::BIGINT AS count, (dense_rank() over (ORDER BY yearly ASC, monthly ASC)) as period FROM (
+        SELECT pseudonym, date_part('month', created_at::date)::INT AS monthly, date_part('year', created_at::date)::INT AS yearly, COUNT(*) AS count FROM usage_statistics GROUP BY pseudonym, monthly, yearly
+        UNION ALL
+        SELECT pseudonym, monthly, yearly, count FROM usage_statistics_monthly
+    ) entries GROUP BY pseudonym, monthly, yearly ORDER BY yearly ASC, monthly ASC
+);
@@ -0,0 +1,3 @@
+-- Renames and changes of the blacklist or of the audiences don't change the status of
+-- the contact, but have to be found by `since`
+ALTER TABLE contacts ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
@@ -0,0 +1,11 @@
+-- Contacts, whose numbers don't belong to a user yet. They are moved to `contacts`,
+-- when the number is registered.
+CREATE TABLE pending_contacts (
+	from_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	target_hash_tele_num CHAR(64) NOT NULL,
+	name VARCHAR NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	PRIMARY KEY (from_id, target_hash_tele_num)
+);
+
+CREATE INDEX pending_contacts_target_hash ON pending_contacts (target_hash_tele_num);
@@ -0,0 +1,9 @@
+-- Removed contacts, so `since` finds them. `hash_tele_num` is the hash of the client,
+-- because the number might not belong to a user anymore.
+CREATE TABLE contact_tombstones (
+	from_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	target_hash_tele_num CHAR(64) NOT NULL,
+	hash_tele_num CHAR(64) NOT NULL,
+	removed_at TIMESTAMP NOT NULL,
+	PRIMARY KEY (from_id, target_hash_tele_num)
+);
@@ -0,0 +1 @@
+DROP TABLE tele_num_changes;
@@ -0,0 +1,9 @@
+-- Old numbers of the users, which changed their number. The phones of their contacts
+-- still hold the old number, so uploads of it are kept for the user.
+CREATE TABLE tele_num_changes (
+	old_hash_tele_num CHAR(64) PRIMARY KEY,
+	hash_tele_num CHAR(64) NOT NULL REFERENCES users(hash_tele_num) ON UPDATE CASCADE ON DELETE CASCADE,
+	changed_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX tele_num_changes_hash ON tele_num_changes (hash_tele_num);
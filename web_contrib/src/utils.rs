@@ -32,22 +32,22 @@ pub fn set_response_headers(response: &mut actix_web::HttpResponse) {
     );
 }
 
+/// Semantic version of a client. The patch is ignored in comparisons.
 #[derive(Debug, Clone, Eq)]
-pub(crate) struct Version {
+pub struct Version {
     minor: usize,
     major: usize,
     patch: usize,
 }
 
 impl Version {
-    #[allow(dead_code)]
     pub fn new(raw: impl Into<String>) -> Result<Self, ()> {
         let s = raw.into();
 
         let splitted = s
             .split('.')
-            .map(|w| w.parse().expect("Version can only contain numbers"))
-            .collect::<Vec<_>>();
+            .map(|w| w.parse().map_err(|_| ()))
+            .collect::<Result<Vec<usize>, ()>>()?;
 
         let (major, minor, patch) = match splitted.len() {
             2 => {
@@ -76,6 +76,12 @@ impl Version {
     }
 }
 
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
 use std::cmp::Ordering;
 impl Ord for Version {
     fn cmp(&self, other: &Version) -> Ordering {
@@ -170,4 +176,11 @@ mod tests {
     
         assert!(v1 != v2);
     }
+
+    #[test]
+    fn test_version_invalid() {
+        assert!(Version::new("2.a").is_err());
+        assert!(Version::new("2").is_err());
+        assert!(Version::new("").is_err());
+    }
 }